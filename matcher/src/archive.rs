@@ -0,0 +1,301 @@
+//! 事件归档 - 把同步器处理的每个合约事件追加到 JSONL 文件
+//!
+//! 每行一个 `ArchiveRecord`：区块号、区块哈希、区块时间戳、交易哈希、日志索引、交易对和解码后的事件；
+//! 被链重组移除的日志同样记录（removed = true），按原顺序重放即可复现同步器看到的事件流。
+//!
+//! 文件名为 `events-<序号>-<首个区块号>.jsonl`：序号在目录内单调递增（重启后接着目录中最大的序号），
+//! 按序号排序即为写入顺序；区块号只作参考，重组或从 start_block 重新同步时可能比之前的文件小。
+//! 单个文件超过 max_file_mb 后切换到新文件，max_files 限制保留的文件数。
+
+use crate::config::ArchiveConfig;
+use crate::events::{ChainEvent, DecodedLog, EventMeta};
+use anyhow::{Context, Result};
+use ethers::types::H256;
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, LineWriter, Write};
+use std::path::{Path, PathBuf};
+use tracing::{info, warn};
+
+const FILE_PREFIX: &str = "events-";
+const FILE_SUFFIX: &str = ".jsonl";
+
+/// 归档中的一条事件
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ArchiveRecord {
+    #[serde(flatten)]
+    pub meta: EventMeta,
+    /// 事件所属的交易对（余额、配置类事件为 None）
+    pub trading_pair: Option<H256>,
+    /// 日志被链重组移除
+    #[serde(default)]
+    pub removed: bool,
+    pub event: ChainEvent,
+}
+
+impl ArchiveRecord {
+    pub fn new(log: &DecodedLog, removed: bool) -> Self {
+        Self {
+            meta: log.meta,
+            trading_pair: log.event.trading_pair().map(H256::from),
+            removed,
+            event: log.event.clone(),
+        }
+    }
+
+    pub fn to_decoded_log(&self) -> DecodedLog {
+        DecodedLog {
+            meta: self.meta,
+            event: self.event.clone(),
+        }
+    }
+}
+
+/// 正在写入的归档文件
+struct ArchiveFile {
+    path: PathBuf,
+    writer: LineWriter<File>,
+    bytes: u64,
+}
+
+/// 滚动写入的事件归档
+pub struct EventArchive {
+    dir: PathBuf,
+    max_file_bytes: u64,
+    max_files: usize,
+    /// 下一个文件的序号
+    next_seq: u64,
+    current: Option<ArchiveFile>,
+}
+
+impl EventArchive {
+    /// 按配置创建；未配置 archive.dir 时返回 None
+    pub fn open(config: &ArchiveConfig) -> Result<Option<Self>> {
+        let Some(dir) = &config.dir else {
+            return Ok(None);
+        };
+        Self::new(dir, config.max_file_mb.saturating_mul(1024 * 1024), config.max_files).map(Some)
+    }
+
+    /// max_file_bytes: 单个文件的大小上限；max_files: 保留的文件数（0 = 全部保留）
+    pub fn new(dir: impl Into<PathBuf>, max_file_bytes: u64, max_files: usize) -> Result<Self> {
+        let dir = dir.into();
+        fs::create_dir_all(&dir)
+            .with_context(|| format!("Failed to create archive dir {}", dir.display()))?;
+        let next_seq = archive_files(&dir)?
+            .last()
+            .and_then(|path| file_seq(path))
+            .map_or(0, |seq| seq + 1);
+        Ok(Self {
+            dir,
+            max_file_bytes,
+            max_files,
+            next_seq,
+            current: None,
+        })
+    }
+
+    /// 追加一条事件；当前文件已满时先切换到新文件
+    pub fn append(&mut self, record: &ArchiveRecord) -> Result<()> {
+        let line = serde_json::to_string(record)?;
+
+        let full = self
+            .current
+            .as_ref()
+            .is_some_and(|file| file.bytes >= self.max_file_bytes);
+        if self.current.is_none() || full {
+            self.rotate(record.meta.block_number)?;
+        }
+
+        let file = self.current.as_mut().expect("archive file opened by rotate");
+        writeln!(file.writer, "{}", line)
+            .with_context(|| format!("Failed to write archive {}", file.path.display()))?;
+        file.bytes += line.len() as u64 + 1;
+        Ok(())
+    }
+
+    /// 切换到下一个序号的新文件（首个区块号 first_block），并删除超出保留数量的旧文件
+    fn rotate(&mut self, first_block: u64) -> Result<()> {
+        let path = self.dir.join(format!(
+            "{}{:08}-{:012}{}",
+            FILE_PREFIX, self.next_seq, first_block, FILE_SUFFIX
+        ));
+        self.next_seq += 1;
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .with_context(|| format!("Failed to create archive {}", path.display()))?;
+        info!("🗄️  Archiving events to {}", path.display());
+        self.current = Some(ArchiveFile {
+            path,
+            writer: LineWriter::new(file),
+            bytes: 0,
+        });
+
+        if self.max_files > 0 {
+            let files = archive_files(&self.dir)?;
+            let excess = files.len().saturating_sub(self.max_files);
+            for old in &files[..excess] {
+                if let Err(e) = fs::remove_file(old) {
+                    warn!("Failed to remove old archive {}: {}", old.display(), e);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// 目录中的归档文件，按序号（即写入顺序）排列
+pub fn archive_files(dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut files: Vec<(u64, PathBuf)> = fs::read_dir(dir)
+        .with_context(|| format!("Failed to read archive dir {}", dir.display()))?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter_map(|path| Some((file_seq(&path)?, path)))
+        .collect();
+    files.sort();
+    Ok(files.into_iter().map(|(_, path)| path).collect())
+}
+
+/// 归档文件名中的序号；不是归档文件时为 None
+fn file_seq(path: &Path) -> Option<u64> {
+    let name = path.file_name()?.to_str()?;
+    let stem = name.strip_prefix(FILE_PREFIX)?.strip_suffix(FILE_SUFFIX)?;
+    let (seq, _first_block) = stem.split_once('-')?;
+    seq.parse().ok()
+}
+
+/// 读取一个归档文件；无法解析的行（如写到一半时进程退出）跳过并输出警告
+pub fn read_archive_file(path: &Path) -> Result<Vec<ArchiveRecord>> {
+    let file = File::open(path).with_context(|| format!("Failed to open archive {}", path.display()))?;
+    let mut records = Vec::new();
+    for (number, line) in BufReader::new(file).lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        match serde_json::from_str(&line) {
+            Ok(record) => records.push(record),
+            Err(e) => warn!("Skipping invalid archive line {}:{}: {}", path.display(), number + 1, e),
+        }
+    }
+    Ok(records)
+}
+
+/// 按写入顺序读取目录中的全部归档事件
+pub fn read_archive(dir: &Path) -> Result<Vec<ArchiveRecord>> {
+    let mut records = Vec::new();
+    for path in archive_files(dir)? {
+        records.extend(read_archive_file(&path)?);
+    }
+    Ok(records)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::contracts::account::{AccountEvents, DepositFilter};
+    use crate::contracts::order_book::{OrderBookEvents, TradeFilter};
+    use ethers::types::{Address, U256};
+
+    fn log(block_number: u64, event: ChainEvent) -> DecodedLog {
+        DecodedLog {
+            meta: EventMeta {
+                block_number,
+                block_hash: H256::from_low_u64_be(block_number),
+                tx_hash: H256::from_low_u64_be(block_number * 1000),
+                log_index: U256::from(block_number % 7),
+                block_timestamp: 1_700_000_000 + block_number,
+            },
+            event,
+        }
+    }
+
+    fn trade(block_number: u64) -> DecodedLog {
+        log(
+            block_number,
+            ChainEvent::OrderBook(OrderBookEvents::TradeFilter(TradeFilter {
+                trading_pair: [7; 32],
+                buy_order_id: U256::from(1),
+                sell_order_id: U256::from(2),
+                buyer: Address::from_low_u64_be(1),
+                seller: Address::from_low_u64_be(2),
+                price: U256::from(100),
+                amount: U256::from(3),
+            })),
+        )
+    }
+
+    #[test]
+    fn test_rotation_and_roundtrip() {
+        let dir = std::env::temp_dir().join(format!("matcher-archive-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+
+        // 每个文件写满一条即切换，最多保留 3 个文件
+        let mut archive = EventArchive::new(&dir, 1, 3).unwrap();
+        let deposit = log(
+            5,
+            ChainEvent::Account(AccountEvents::DepositFilter(DepositFilter {
+                user: Address::from_low_u64_be(1),
+                token: Address::from_low_u64_be(2),
+                amount: U256::from(10),
+            })),
+        );
+        let logs = [trade(1), trade(2), trade(3), trade(3), deposit];
+        for (index, log) in logs.iter().enumerate() {
+            archive.append(&ArchiveRecord::new(log, index == 3)).unwrap();
+        }
+
+        let names: Vec<_> = archive_files(&dir)
+            .unwrap()
+            .iter()
+            .map(|path| path.file_name().unwrap().to_string_lossy().into_owned())
+            .collect();
+        assert_eq!(
+            names,
+            vec![
+                "events-00000002-000000000003.jsonl",
+                "events-00000003-000000000003.jsonl",
+                "events-00000004-000000000005.jsonl",
+            ]
+        );
+
+        let records = read_archive(&dir).unwrap();
+        assert_eq!(records.len(), 3);
+        assert_eq!(records[0].to_decoded_log().event, trade(3).event);
+        assert_eq!(records[0].trading_pair, Some(H256::from([7; 32])));
+        assert!(!records[0].removed && records[1].removed);
+        assert_eq!(records[2].trading_pair, None);
+        assert_eq!(records[2].meta, logs[4].meta);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_order_survives_lower_block_numbers() {
+        let dir = std::env::temp_dir().join(format!("matcher-archive-order-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+
+        // 区块号倒退：重组移除的日志，以及重启后从更早的区块重新同步
+        let mut archive = EventArchive::new(&dir, 1, 0).unwrap();
+        archive.append(&ArchiveRecord::new(&trade(20), false)).unwrap();
+        archive.append(&ArchiveRecord::new(&trade(19), true)).unwrap();
+        drop(archive);
+        let mut archive = EventArchive::new(&dir, 1, 0).unwrap();
+        archive.append(&ArchiveRecord::new(&trade(3), false)).unwrap();
+
+        let blocks: Vec<_> = read_archive(&dir)
+            .unwrap()
+            .iter()
+            .map(|record| (record.meta.block_number, record.removed))
+            .collect();
+        assert_eq!(blocks, vec![(20, false), (19, true), (3, false)]);
+
+        // 序号超过 8 位后仍按数值排序
+        fs::write(dir.join("events-100000000-000000000001.jsonl"), "").unwrap();
+        let last = archive_files(&dir).unwrap().pop().unwrap();
+        assert!(last.ends_with("events-100000000-000000000001.jsonl"));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
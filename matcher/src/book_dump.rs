@@ -0,0 +1,308 @@
+//! 订单簿导出 - 模拟器状态的稳定序列化格式与可读的价格阶梯
+//!
+//! - JSON / CBOR：价格层级按价格排序、订单按 id 排序，同一订单簿总是得到相同的输出，
+//!   可以直接 diff；不包含检查点日志等临时状态
+//! - 价格阶梯：用 fixed_point 的 Price / Amount 换算成小数显示，
+//!   不遍历链表，订单簿损坏时也能输出
+
+use crate::fixed_point::{Amount, OrderSize, Price};
+use crate::orderbook_simulator::{MatchLimits, OrderBookSimulator, SimOrder, SimPriceLevel};
+use ethers::types::U256;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::collections::HashMap;
+use std::fmt::Write;
+use thiserror::Error;
+
+/// 导出格式版本，格式不兼容地变化时递增
+pub const DUMP_VERSION: u32 = 1;
+
+/// 导出 / 导入订单簿时的错误
+#[derive(Debug, Error)]
+pub enum DumpError {
+    #[error("json: {0}")]
+    Json(#[from] serde_json::Error),
+
+    #[error("cbor encode: {0}")]
+    CborEncode(#[from] ciborium::ser::Error<std::io::Error>),
+
+    #[error("cbor decode: {0}")]
+    CborDecode(#[from] ciborium::de::Error<std::io::Error>),
+
+    #[error("unsupported dump version {0} (expected {DUMP_VERSION})")]
+    UnsupportedVersion(u32),
+}
+
+/// OrderBookSimulator 的序列化形式
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SimulatorState {
+    pub version: u32,
+    pub ask_head: U256,
+    pub ask_tail: U256,
+    pub bid_head: U256,
+    pub bid_tail: U256,
+    pub market_ask_head: U256,
+    pub market_ask_tail: U256,
+    pub market_bid_head: U256,
+    pub market_bid_tail: U256,
+    pub match_limits: MatchLimits,
+    /// 卖方价格层级，价格升序
+    pub asks: Vec<SimPriceLevel>,
+    /// 买方价格层级，价格降序
+    pub bids: Vec<SimPriceLevel>,
+    /// 全部订单（限价单与市价单），id 升序
+    pub orders: Vec<SimOrder>,
+}
+
+impl From<&OrderBookSimulator> for SimulatorState {
+    fn from(sim: &OrderBookSimulator) -> Self {
+        let bid_flag = U256::one() << 255;
+        let (mut asks, mut bids): (Vec<_>, Vec<_>) = sim
+            .price_levels
+            .iter()
+            .partition(|(key, _)| **key & bid_flag == U256::zero());
+        asks.sort_by_key(|(key, _)| **key);
+        bids.sort_by_key(|(key, _)| std::cmp::Reverse(**key));
+
+        let mut orders: Vec<SimOrder> = sim.orders.values().cloned().collect();
+        orders.sort_by_key(|order| order.id);
+
+        Self {
+            version: DUMP_VERSION,
+            ask_head: sim.ask_head,
+            ask_tail: sim.ask_tail,
+            bid_head: sim.bid_head,
+            bid_tail: sim.bid_tail,
+            market_ask_head: sim.market_ask_head,
+            market_ask_tail: sim.market_ask_tail,
+            market_bid_head: sim.market_bid_head,
+            market_bid_tail: sim.market_bid_tail,
+            match_limits: sim.match_limits,
+            asks: asks.into_iter().map(|(_, level)| level.clone()).collect(),
+            bids: bids.into_iter().map(|(_, level)| level.clone()).collect(),
+            orders,
+        }
+    }
+}
+
+impl TryFrom<SimulatorState> for OrderBookSimulator {
+    type Error = DumpError;
+
+    fn try_from(state: SimulatorState) -> Result<Self, DumpError> {
+        if state.version != DUMP_VERSION {
+            return Err(DumpError::UnsupportedVersion(state.version));
+        }
+
+        let mut sim = OrderBookSimulator::from_chain_state(
+            state.ask_head,
+            state.ask_tail,
+            state.bid_head,
+            state.bid_tail,
+        );
+        sim.market_ask_head = state.market_ask_head;
+        sim.market_ask_tail = state.market_ask_tail;
+        sim.market_bid_head = state.market_bid_head;
+        sim.market_bid_tail = state.market_bid_tail;
+        sim.match_limits = state.match_limits;
+
+        for level in state.asks {
+            sim.add_existing_price_level(level, true);
+        }
+        for level in state.bids {
+            sim.add_existing_price_level(level, false);
+        }
+        for order in state.orders {
+            sim.add_existing_order(order);
+        }
+        Ok(sim)
+    }
+}
+
+impl Serialize for OrderBookSimulator {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        SimulatorState::from(self).serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for OrderBookSimulator {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let state = SimulatorState::deserialize(deserializer)?;
+        OrderBookSimulator::try_from(state).map_err(serde::de::Error::custom)
+    }
+}
+
+impl OrderBookSimulator {
+    /// 导出为格式化的 JSON
+    pub fn to_json(&self) -> Result<String, DumpError> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, DumpError> {
+        let state: SimulatorState = serde_json::from_str(json)?;
+        Self::try_from(state)
+    }
+
+    /// 导出为 CBOR
+    pub fn to_cbor(&self) -> Result<Vec<u8>, DumpError> {
+        let mut bytes = Vec::new();
+        ciborium::into_writer(self, &mut bytes)?;
+        Ok(bytes)
+    }
+
+    pub fn from_cbor(bytes: &[u8]) -> Result<Self, DumpError> {
+        let state: SimulatorState = ciborium::from_reader(bytes)?;
+        Self::try_from(state)
+    }
+
+    /// 可读的价格阶梯：卖盘价格从高到低，买盘价格从高到低，中间为价差，最后是市价单队列
+    /// depth 限制每侧显示的层级数（从最优价起算）
+    pub fn format_ladder(&self, depth: Option<usize>) -> String {
+        let state = SimulatorState::from(self);
+        let depth = depth.unwrap_or(usize::MAX);
+
+        // 每个价格层级的订单数（按订单数据统计，不遍历链表）
+        let mut order_counts: HashMap<(U256, bool), usize> = HashMap::new();
+        for order in state.orders.iter().filter(|order| !order.is_market_order) {
+            *order_counts.entry((order.price_level, order.is_ask)).or_default() += 1;
+        }
+
+        let mut out = String::new();
+        let _ = writeln!(out, "{:<6} {:>24} {:>24} {:>7}", "side", "price", "amount", "orders");
+        let row = |out: &mut String, side: &str, level: &SimPriceLevel, is_ask: bool| {
+            let _ = writeln!(
+                out,
+                "{:<6} {:>24} {:>24} {:>7}",
+                side,
+                Price::from_raw(level.price),
+                Amount::from_raw(level.total_volume),
+                order_counts.get(&(level.price, is_ask)).copied().unwrap_or(0)
+            );
+        };
+
+        let asks: Vec<_> = state.asks.iter().take(depth).collect();
+        for level in asks.iter().rev() {
+            row(&mut out, "ask", level, true);
+        }
+
+        let spread = match (state.asks.first(), state.bids.first()) {
+            (Some(ask), Some(bid)) if ask.price >= bid.price => {
+                format!("spread {}", Price::from_raw(ask.price - bid.price))
+            }
+            (Some(_), Some(_)) => "crossed".to_string(),
+            _ => "no spread".to_string(),
+        };
+        let _ = writeln!(out, "{:-^64}", format!(" {} ", spread));
+
+        for level in state.bids.iter().take(depth) {
+            row(&mut out, "bid", level, false);
+        }
+
+        for (is_ask, label) in [(true, "market ask"), (false, "market bid")] {
+            let queued: Vec<_> = state
+                .orders
+                .iter()
+                .filter(|order| order.is_market_order && order.is_ask == is_ask)
+                .collect();
+            let remaining = queued.iter().fold(U256::zero(), |acc, order| {
+                acc.saturating_add(order.amount.saturating_sub(order.filled_amount))
+            });
+            let _ = writeln!(
+                out,
+                "{}: {} orders, {} remaining",
+                label,
+                queued.len(),
+                OrderSize::new(true, is_ask, remaining)
+            );
+        }
+
+        out
+    }
+}
+
+/// 按精度（10 的幂，如 PRICE_DECIMALS）把定点数格式化为小数，保留全部小数位
+pub fn format_scaled(value: U256, scale: U256) -> String {
+    let places = scale.to_string().len() - 1;
+    if places == 0 {
+        return value.to_string();
+    }
+    format!("{}.{:0>width$}", value / scale, (value % scale).to_string(), width = places)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::constants::{AMOUNT_DECIMALS, PRICE_DECIMALS};
+    use ethers::types::Address;
+
+    fn price(whole: u64) -> U256 {
+        U256::from(whole) * PRICE_DECIMALS
+    }
+
+    fn sample_book() -> OrderBookSimulator {
+        let mut sim = OrderBookSimulator::new();
+        sim.simulate_insert_order(U256::from(1), Address::zero(), price(2010), AMOUNT_DECIMALS, true).unwrap();
+        sim.simulate_insert_order(U256::from(2), Address::zero(), price(2010), AMOUNT_DECIMALS / 2, true).unwrap();
+        sim.simulate_insert_order(U256::from(3), Address::zero(), price(2030), AMOUNT_DECIMALS * 2, true).unwrap();
+        sim.simulate_insert_order(U256::from(4), Address::zero(), price(2000), AMOUNT_DECIMALS / 4, false).unwrap();
+        sim.simulate_insert_order(U256::from(5), Address::zero(), price(1990), AMOUNT_DECIMALS, false).unwrap();
+        // 市价单留在队列中不撮合
+        sim.match_limits.market_iterations = 0;
+        sim.simulate_insert_market_order(U256::from(6), Address::zero(), AMOUNT_DECIMALS / 10, true).unwrap();
+        sim.match_limits = MatchLimits::default();
+        sim
+    }
+
+    #[test]
+    fn test_json_and_cbor_roundtrip() {
+        let sim = sample_book();
+        let json = sim.to_json().unwrap();
+
+        let from_json = OrderBookSimulator::from_json(&json).unwrap();
+        assert_eq!(SimulatorState::from(&from_json), SimulatorState::from(&sim));
+        assert!(from_json.validate().is_empty());
+        // 导出是确定的：再次导出得到相同文本
+        assert_eq!(from_json.to_json().unwrap(), json);
+
+        let from_cbor = OrderBookSimulator::from_cbor(&sim.to_cbor().unwrap()).unwrap();
+        assert_eq!(SimulatorState::from(&from_cbor), SimulatorState::from(&sim));
+        assert!(from_cbor.validate().is_empty());
+    }
+
+    #[test]
+    fn test_unsupported_version_is_rejected() {
+        let mut state = SimulatorState::from(&sample_book());
+        state.version = DUMP_VERSION + 1;
+        let json = serde_json::to_string(&state).unwrap();
+        assert!(matches!(
+            OrderBookSimulator::from_json(&json),
+            Err(DumpError::UnsupportedVersion(v)) if v == DUMP_VERSION + 1
+        ));
+    }
+
+    #[test]
+    fn test_format_scaled() {
+        assert_eq!(format_scaled(price(2010), PRICE_DECIMALS), "2010.00000000");
+        assert_eq!(format_scaled(U256::from(1_234_567u64), AMOUNT_DECIMALS), "0.01234567");
+        assert_eq!(format_scaled(U256::from(7), U256::one()), "7");
+    }
+
+    #[test]
+    fn test_format_ladder() {
+        let sim = sample_book();
+        let expected = [
+            "side                      price                   amount  orders",
+            "ask               2030.00000000               2.00000000       1",
+            "ask               2010.00000000               1.50000000       2",
+            "---------------------- spread 10.00000000 ----------------------",
+            "bid               2000.00000000               0.25000000       1",
+            "bid               1990.00000000               1.00000000       1",
+            "market ask: 1 orders, 0.10000000 base remaining",
+            "market bid: 0 orders, 0.00000000 quote remaining",
+        ];
+        assert_eq!(sim.format_ladder(None), expected.join("\n") + "\n");
+
+        let top = sim.format_ladder(Some(1));
+        assert!(!top.contains("2030.00000000"));
+        assert!(!top.contains("1990.00000000"));
+        assert!(top.contains("2010.00000000"));
+    }
+}
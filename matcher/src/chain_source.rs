@@ -0,0 +1,597 @@
+//! 事件来源 - 把“事件从哪里来”与同步器的事件处理（EventProcessor）分开
+//!
+//! - `LiveSource`：连接节点，WebSocket 订阅日志和区块头，或 HTTP 轮询 eth_getLogs；
+//!   后台任务在连接断开 / 切换节点后从已同步的区块继续。
+//!   HTTP 模式下节点不会推送 removed 日志，轮询时重新核对最近区块的哈希，
+//!   发现重组后为被孤立区块中的日志补发 removed 事件
+//! - `FileSource`：读取事件归档（见 archive.rs），按原顺序产生事件，
+//!   并在每个区块的最后一条事件之后产生 NewHead，用于离线重放
+//!
+//! 两者产生同样的 `SourceEvent`；日志已解码并带有区块时间戳。
+
+use crate::archive::{read_archive, read_archive_file, ArchiveRecord};
+use crate::config::Transport;
+use crate::events::{decode_log, ContractAddresses, DecodedLog};
+use crate::transport::{EndpointClient, MultiClient, RpcClient};
+use anyhow::Result;
+use async_trait::async_trait;
+use ethers::prelude::*;
+use futures::future::join_all;
+use futures::stream::StreamExt;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tracing::{debug, info, warn};
+
+/// 最近区块时间戳缓存保留的区块数
+const TIMESTAMP_CACHE_BLOCKS: u64 = 128;
+
+/// HTTP 轮询模式下核对区块哈希的范围（链头之前的区块数），更深的重组无法发现
+const REORG_WINDOW_BLOCKS: u64 = 64;
+
+/// LiveSource 后台任务与消费者之间的缓冲
+const LIVE_CHANNEL_CAPACITY: usize = 1024;
+
+/// 事件来源产生的一项
+#[derive(Debug, Clone)]
+pub enum SourceEvent {
+    /// 一条合约日志；removed 表示被链重组移除
+    Log { log: Box<DecodedLog>, removed: bool },
+    /// 链头推进到该区块
+    NewHead(u64),
+}
+
+/// 同步器的事件来源
+#[async_trait]
+pub trait ChainSource: Send {
+    /// 下一项事件；None 表示来源已结束（如归档读完）
+    async fn next_event(&mut self) -> Result<Option<SourceEvent>>;
+}
+
+/// 从节点拉取并解码日志，补全区块时间戳（每个区块只查询一次）
+#[derive(Clone)]
+pub struct LogFetcher<C = RpcClient> {
+    provider: Arc<Provider<MultiClient<C>>>,
+    contracts: ContractAddresses,
+    max_block_range: u64,
+    /// 区块号 -> 时间戳
+    timestamps: HashMap<u64, u64>,
+}
+
+impl<C: EndpointClient> LogFetcher<C> {
+    pub fn new(provider: Arc<Provider<MultiClient<C>>>, contracts: ContractAddresses, max_block_range: u64) -> Self {
+        Self {
+            provider,
+            contracts,
+            max_block_range: max_block_range.max(1),
+            timestamps: HashMap::new(),
+        }
+    }
+
+    pub fn provider(&self) -> &Arc<Provider<MultiClient<C>>> {
+        &self.provider
+    }
+
+    /// Sequencer / OrderBook / Account 合约的日志过滤器
+    pub fn event_filter(&self) -> Filter {
+        Filter::new().address(self.contracts.all())
+    }
+
+    /// 按 max_block_range 分段调用 eth_getLogs，返回 [from_block, to_block] 内匹配 filter 的原始日志
+    pub async fn fetch_raw(&self, filter: &Filter, from_block: u64, to_block: u64) -> Result<Vec<Log>> {
+        let mut logs = Vec::new();
+        let mut start = from_block;
+
+        while start <= to_block {
+            let end = to_block.min(start + self.max_block_range - 1);
+            let filter = filter.clone().from_block(start).to_block(end);
+            logs.extend(self.provider.get_logs(&filter).await?);
+            start = end + 1;
+        }
+
+        Ok(logs)
+    }
+
+    /// [from_block, to_block] 内三个合约的全部日志，已解码并带时间戳
+    pub async fn fetch(&mut self, from_block: u64, to_block: u64) -> Result<Vec<SourceEvent>> {
+        let logs = self.fetch_raw(&self.event_filter(), from_block, to_block).await?;
+        let mut events = Vec::with_capacity(logs.len());
+        for log in &logs {
+            events.extend(self.decode(log).await);
+        }
+        Ok(events)
+    }
+
+    /// 解码一条日志并补全时间戳；非目标合约或无法识别的日志返回 None
+    pub async fn decode(&mut self, log: &Log) -> Option<SourceEvent> {
+        let mut decoded = decode_log(log, &self.contracts)?;
+        decoded.meta.block_timestamp = self.block_timestamp(decoded.meta.block_number).await;
+        Some(SourceEvent::Log {
+            log: Box::new(decoded),
+            removed: log.removed == Some(true),
+        })
+    }
+
+    /// 区块时间戳；查询失败时为 0（时间未知）
+    async fn block_timestamp(&mut self, block_number: u64) -> u64 {
+        if let Some(timestamp) = self.timestamps.get(&block_number) {
+            return *timestamp;
+        }
+
+        match self.provider.get_block(block_number).await {
+            Ok(Some(block)) => {
+                let timestamp = block.timestamp.as_u64();
+                self.timestamps.insert(block_number, timestamp);
+                timestamp
+            }
+            Ok(None) => {
+                warn!("Block {} not found, timestamp unknown", block_number);
+                0
+            }
+            Err(e) => {
+                warn!("Failed to fetch block {}: {}", block_number, e);
+                0
+            }
+        }
+    }
+
+    /// 丢弃较早区块的时间戳
+    pub fn prune(&mut self, head_block: u64) {
+        self.timestamps
+            .retain(|block, _| *block + TIMESTAMP_CACHE_BLOCKS > head_block);
+    }
+
+    /// 丢弃 from_block 及之后区块的时间戳（重组后这些区块已被替换）
+    pub fn invalidate_from(&mut self, from_block: u64) {
+        self.timestamps.retain(|block, _| *block < from_block);
+    }
+}
+
+/// HTTP 轮询模式下最近区块的哈希及已产生的日志
+///
+/// 记录产生过日志的区块（哈希取自日志本身）和最近一次轮询的链头；
+/// 每次轮询重新查询这些区块，哈希变化说明该区块已被重组替换
+#[derive(Debug, Default)]
+struct PolledBlocks {
+    /// 区块号 -> (区块哈希, 该区块已产生的日志)
+    blocks: BTreeMap<u64, (H256, Vec<DecodedLog>)>,
+}
+
+impl PolledBlocks {
+    fn record_log(&mut self, log: &DecodedLog) {
+        self.blocks
+            .entry(log.meta.block_number)
+            .or_insert_with(|| (log.meta.block_hash, Vec::new()))
+            .1
+            .push(log.clone());
+    }
+
+    /// 记录轮询到的链头；没有日志的旧链头不再需要核对（新链头的哈希已覆盖其祖先）
+    /// 已有日志的区块保留日志中的哈希：日志与区块头查询之间发生重组时，下次核对会发现
+    fn record_head(&mut self, block_number: u64, hash: H256) {
+        self.blocks.retain(|_, (_, logs)| !logs.is_empty());
+        self.blocks.entry(block_number).or_insert_with(|| (hash, Vec::new()));
+    }
+
+    /// 需要核对的 (区块号, 哈希)，按区块号升序
+    fn hashes(&self) -> Vec<(u64, H256)> {
+        self.blocks.iter().map(|(number, (hash, _))| (*number, *hash)).collect()
+    }
+
+    /// 移除 from_block 及之后的区块，返回其中日志对应的 removed 事件（后产生的日志先移除）
+    fn rollback(&mut self, from_block: u64) -> Vec<SourceEvent> {
+        let orphaned = self.blocks.split_off(&from_block);
+        orphaned
+            .into_values()
+            .rev()
+            .flat_map(|(_, logs)| logs.into_iter().rev())
+            .map(|log| SourceEvent::Log {
+                log: Box::new(log),
+                removed: true,
+            })
+            .collect()
+    }
+
+    fn prune(&mut self, head_block: u64) {
+        self.blocks
+            .retain(|block, _| *block + REORG_WINDOW_BLOCKS > head_block);
+    }
+}
+
+/// 节点事件来源：后台任务监听节点，经 channel 交给消费者
+pub struct LiveSource {
+    events: mpsc::Receiver<SourceEvent>,
+}
+
+impl LiveSource {
+    /// 从 from_block 之后开始监听（之前的区块已同步）
+    pub fn spawn(fetcher: LogFetcher, transport: Transport, poll_interval: Duration, from_block: u64) -> Self {
+        let (sender, events) = mpsc::channel(LIVE_CHANNEL_CAPACITY);
+        let watcher = LiveWatcher {
+            fetcher,
+            transport,
+            poll_interval,
+            synced_block: from_block,
+            polled: PolledBlocks::default(),
+            sender,
+        };
+        tokio::spawn(watcher.run());
+        Self { events }
+    }
+}
+
+#[async_trait]
+impl ChainSource for LiveSource {
+    async fn next_event(&mut self) -> Result<Option<SourceEvent>> {
+        Ok(self.events.recv().await)
+    }
+}
+
+/// 消费者已退出（LiveSource 被丢弃）
+struct ReceiverDropped;
+
+struct LiveWatcher<C = RpcClient> {
+    fetcher: LogFetcher<C>,
+    transport: Transport,
+    poll_interval: Duration,
+    /// 已产生 NewHead 的最高区块，重连后从这里继续
+    synced_block: u64,
+    /// HTTP 模式下用于发现重组的最近区块
+    polled: PolledBlocks,
+    sender: mpsc::Sender<SourceEvent>,
+}
+
+impl<C: EndpointClient> LiveWatcher<C> {
+    async fn run(mut self) {
+        loop {
+            debug!(
+                "Event watcher starting from block {} (transport={:?})",
+                self.synced_block, self.transport
+            );
+            let result = match self.transport {
+                Transport::Ws => self.subscribe().await,
+                Transport::Http => self.poll().await,
+            };
+            match result {
+                Ok(Ok(())) => {}
+                Ok(Err(e)) => warn!("Event watcher failed: {:#}", e),
+                Err(ReceiverDropped) => return,
+            }
+
+            tokio::time::sleep(Duration::from_secs(1)).await;
+            info!(
+                "🔁 Reconnecting event watcher via {} from block {}",
+                self.fetcher.provider().as_ref().as_ref().active_url(),
+                self.synced_block
+            );
+        }
+    }
+
+    async fn emit(&self, event: SourceEvent) -> Result<(), ReceiverDropped> {
+        self.sender.send(event).await.map_err(|_| ReceiverDropped)
+    }
+
+    async fn emit_head(&mut self, head_block: u64) -> Result<(), ReceiverDropped> {
+        self.synced_block = self.synced_block.max(head_block);
+        self.fetcher.prune(head_block);
+        self.emit(SourceEvent::NewHead(head_block)).await
+    }
+
+    /// [from_block, to_block] 的日志补齐后推进链头
+    async fn backfill(&mut self, from_block: u64, to_block: u64) -> Result<Result<()>, ReceiverDropped> {
+        let events = match self.fetcher.fetch(from_block, to_block).await {
+            Ok(events) => events,
+            Err(e) => return Ok(Err(e)),
+        };
+        debug!(
+            "Fetched {} logs from blocks {}..={}",
+            events.len(),
+            from_block,
+            to_block
+        );
+        for event in events {
+            if let (Transport::Http, SourceEvent::Log { log, removed: false }) = (self.transport, &event) {
+                self.polled.record_log(log);
+            }
+            self.emit(event).await?;
+        }
+        self.emit_head(to_block).await?;
+        Ok(Ok(()))
+    }
+
+    /// WebSocket 模式：日志订阅驱动 head 视图，区块头订阅驱动 confirmed 视图的推进
+    /// 订阅建立后先用 eth_getLogs 补齐 synced_block 之后错过的区块（重连 / 切换节点时）
+    async fn subscribe(&mut self) -> Result<Result<()>, ReceiverDropped> {
+        let provider = self.fetcher.provider().clone();
+        let filter = self.fetcher.event_filter().from_block(self.synced_block + 1);
+
+        let mut log_stream = match provider.subscribe_logs(&filter).await {
+            Ok(stream) => stream,
+            Err(e) => return Ok(Err(e.into())),
+        };
+        let mut block_stream = match provider.subscribe_blocks().await {
+            Ok(stream) => stream,
+            Err(e) => return Ok(Err(e.into())),
+        };
+
+        let head_block = match provider.get_block_number().await {
+            Ok(number) => number.as_u64(),
+            Err(e) => return Ok(Err(e.into())),
+        };
+        if head_block > self.synced_block {
+            if let Err(e) = self.backfill(self.synced_block + 1, head_block).await? {
+                return Ok(Err(e));
+            }
+        }
+
+        loop {
+            tokio::select! {
+                Some(log) = log_stream.next() => {
+                    if let Some(event) = self.fetcher.decode(&log).await {
+                        self.emit(event).await?;
+                    }
+                }
+
+                Some(block) = block_stream.next() => {
+                    if let Some(number) = block.number {
+                        self.emit_head(number.as_u64()).await?;
+                    }
+                }
+
+                else => {
+                    warn!("All event streams ended, restarting...");
+                    return Ok(Ok(()));
+                }
+            }
+        }
+    }
+
+    /// HTTP 模式：定期查询最新区块，用 eth_getLogs 拉取新区块的日志
+    /// 轮询拿不到节点的 removed 日志，每次轮询先核对最近区块的哈希（见 check_reorg）
+    async fn poll(&mut self) -> Result<Result<()>, ReceiverDropped> {
+        let mut ticker = tokio::time::interval(self.poll_interval);
+
+        loop {
+            ticker.tick().await;
+
+            let head_block = match self.fetcher.provider().get_block_number().await {
+                Ok(number) => number.as_u64(),
+                Err(e) => {
+                    warn!("Error polling block number: {}", e);
+                    continue;
+                }
+            };
+
+            if let Err(e) = self.check_reorg().await? {
+                warn!("Error checking block hashes: {}", e);
+                continue;
+            }
+
+            if head_block <= self.synced_block {
+                continue;
+            }
+
+            if let Err(e) = self.backfill(self.synced_block + 1, head_block).await? {
+                warn!("Error polling logs: {}", e);
+                continue;
+            }
+
+            match self.fetcher.provider().get_block(head_block).await {
+                Ok(Some(Block { hash: Some(hash), .. })) => self.polled.record_head(head_block, hash),
+                Ok(_) => debug!("Block {} not found, head hash not recorded", head_block),
+                Err(e) => debug!("Failed to fetch block {}: {}", head_block, e),
+            }
+            self.polled.prune(head_block);
+        }
+    }
+
+    /// 重新查询已记录区块的哈希；从第一个哈希变化的区块开始视为被重组，
+    /// 为其中的日志产生 removed 事件，并把 synced_block 退回到最后一个未变化的已记录区块，
+    /// 下一次拉取会从那里重新获取新链上的日志
+    async fn check_reorg(&mut self) -> Result<Result<()>, ReceiverDropped> {
+        let recorded = self.polled.hashes();
+        if recorded.is_empty() {
+            return Ok(Ok(()));
+        }
+
+        let provider = self.fetcher.provider().clone();
+        let results = join_all(recorded.iter().map(|(number, _)| provider.get_block(*number))).await;
+
+        let mut last_unchanged = None;
+        let mut fork_block = None;
+        for ((number, hash), result) in recorded.iter().zip(results) {
+            let current = match result {
+                Ok(block) => block.and_then(|block| block.hash),
+                Err(e) => return Ok(Err(e.into())),
+            };
+            if current == Some(*hash) {
+                last_unchanged = Some(*number);
+            } else {
+                fork_block = Some(*number);
+                break;
+            }
+        }
+
+        let Some(fork_block) = fork_block else {
+            return Ok(Ok(()));
+        };
+        // 在已记录区块之间的区块没有产生过日志，只需从上一个未变化的区块之后重新拉取
+        let resume_block = last_unchanged.unwrap_or(fork_block.saturating_sub(1));
+        let removed = self.polled.rollback(resume_block + 1);
+        warn!(
+            "↩️  Reorg detected at block {} by polling, {} logs removed, resyncing from block {}",
+            fork_block,
+            removed.len(),
+            resume_block + 1
+        );
+        for event in removed {
+            self.emit(event).await?;
+        }
+        self.fetcher.invalidate_from(resume_block + 1);
+        self.synced_block = self.synced_block.min(resume_block);
+        Ok(Ok(()))
+    }
+}
+
+/// 事件归档来源：按写入顺序重放归档中的事件
+pub struct FileSource {
+    records: VecDeque<ArchiveRecord>,
+    /// 当前区块，在它的最后一条事件之后产生 NewHead
+    current_block: Option<u64>,
+}
+
+impl FileSource {
+    /// path 可以是归档目录（读取其中全部归档文件）或单个归档文件
+    pub fn open(path: &Path) -> Result<Self> {
+        let records = if path.is_dir() {
+            read_archive(path)?
+        } else {
+            read_archive_file(path)?
+        };
+        Ok(Self::from_records(records))
+    }
+
+    pub fn from_records(records: impl IntoIterator<Item = ArchiveRecord>) -> Self {
+        Self {
+            records: records.into_iter().collect(),
+            current_block: None,
+        }
+    }
+
+    /// 剩余的归档事件数
+    pub fn remaining(&self) -> usize {
+        self.records.len()
+    }
+}
+
+#[async_trait]
+impl ChainSource for FileSource {
+    async fn next_event(&mut self) -> Result<Option<SourceEvent>> {
+        let next_block = self.records.front().map(|record| record.meta.block_number);
+        if let Some(block) = self.current_block {
+            if next_block != Some(block) {
+                self.current_block = None;
+                return Ok(Some(SourceEvent::NewHead(block)));
+            }
+        }
+
+        let Some(record) = self.records.pop_front() else {
+            return Ok(None);
+        };
+        self.current_block = Some(record.meta.block_number);
+        Ok(Some(SourceEvent::Log {
+            log: Box::new(record.to_decoded_log()),
+            removed: record.removed,
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::contracts::order_book::{OrderBookEvents, OrderRemovedFilter};
+    use crate::events::{ChainEvent, EventMeta};
+    use ethers::providers::MockProvider;
+
+    fn log(block_number: u64, log_index: u64) -> DecodedLog {
+        DecodedLog {
+            meta: EventMeta {
+                block_number,
+                block_hash: H256::from_low_u64_be(block_number),
+                tx_hash: H256::from_low_u64_be(block_number),
+                log_index: U256::from(log_index),
+                block_timestamp: block_number * 12,
+            },
+            event: ChainEvent::OrderBook(OrderBookEvents::OrderRemovedFilter(OrderRemovedFilter {
+                trading_pair: [0; 32],
+                order_id: U256::from(log_index),
+            })),
+        }
+    }
+
+    fn record(block_number: u64, log_index: u64, removed: bool) -> ArchiveRecord {
+        ArchiveRecord::new(&log(block_number, log_index), removed)
+    }
+
+    fn block(number: u64, hash: H256) -> Block<TxHash> {
+        Block {
+            number: Some(number.into()),
+            hash: Some(hash),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_file_source_emits_head_after_each_block() {
+        let mut source = FileSource::from_records([
+            record(5, 0, false),
+            record(5, 1, false),
+            record(6, 0, false),
+            record(6, 0, true),
+        ]);
+
+        let mut items = Vec::new();
+        while let Some(event) = source.next_event().await.unwrap() {
+            items.push(match event {
+                SourceEvent::Log { log, removed } => {
+                    format!("log {}:{}{}", log.meta.block_number, log.meta.log_index, if removed { " removed" } else { "" })
+                }
+                SourceEvent::NewHead(block) => format!("head {}", block),
+            });
+        }
+        assert_eq!(
+            items,
+            vec!["log 5:0", "log 5:1", "head 5", "log 6:0", "log 6:0 removed", "head 6"]
+        );
+        assert_eq!(source.remaining(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_polling_detects_reorg() {
+        let mock = MockProvider::new();
+        let provider = Arc::new(Provider::new(MultiClient::mock(std::slice::from_ref(&mock))));
+        let contracts = ContractAddresses {
+            sequencer: Address::zero(),
+            orderbook: Address::zero(),
+            account: Address::zero(),
+        };
+        let (sender, mut events) = mpsc::channel(16);
+        let mut watcher = LiveWatcher {
+            fetcher: LogFetcher::new(provider, contracts, 100),
+            transport: Transport::Http,
+            poll_interval: Duration::from_secs(1),
+            synced_block: 6,
+            polled: PolledBlocks::default(),
+            sender,
+        };
+
+        // 区块 3、5 产生过日志，上次轮询的链头是 6
+        watcher.polled.record_log(&log(3, 0));
+        watcher.polled.record_log(&log(5, 0));
+        watcher.polled.record_log(&log(5, 1));
+        watcher.polled.record_head(6, H256::from_low_u64_be(6));
+
+        // 区块 5、6 被替换（MockProvider 从队尾取响应，按请求的逆序压入）
+        mock.push(block(6, H256::repeat_byte(0xee))).unwrap();
+        mock.push(block(5, H256::repeat_byte(0xee))).unwrap();
+        mock.push(block(3, H256::from_low_u64_be(3))).unwrap();
+
+        assert!(watcher.check_reorg().await.ok().unwrap().is_ok());
+
+        let mut removed = Vec::new();
+        while let Ok(event) = events.try_recv() {
+            match event {
+                SourceEvent::Log { log, removed: true } => {
+                    removed.push((log.meta.block_number, log.meta.log_index.as_u64()))
+                }
+                other => panic!("unexpected event: {:?}", other),
+            }
+        }
+        assert_eq!(removed, vec![(5, 1), (5, 0)]);
+        // 从最后一个未变化的已记录区块之后重新拉取
+        assert_eq!(watcher.synced_block, 3);
+        assert_eq!(watcher.polled.hashes(), vec![(3, H256::from_low_u64_be(3))]);
+    }
+}
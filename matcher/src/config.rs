@@ -1,5 +1,9 @@
-use anyhow::Result;
+use crate::orderbook_simulator::MatchLimits;
+use crate::self_trade::SelfTradePolicy;
+use anyhow::{bail, Context, Result};
+use ethers::utils::keccak256;
 use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
 use std::fs;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -9,12 +13,83 @@ pub struct Config {
     pub sync: SyncConfig,
     pub matching: MatchingConfig,
     pub executor: ExecutorConfig,
+    #[serde(default)]
+    pub history: HistoryConfig,
+    #[serde(default)]
+    pub archive: ArchiveConfig,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct NetworkConfig {
     pub rpc_url: String,
     pub chain_id: u64,
+    /// 传输方式；不填则按 rpc_url 的 scheme 推断（ws/wss 或 http/https）
+    #[serde(default)]
+    pub transport: Option<Transport>,
+    /// 备用 RPC 节点；主节点不可用时按顺序切换
+    #[serde(default)]
+    pub fallback_rpc_urls: Vec<String>,
+    /// 节点健康检查间隔（毫秒），只在配置了多个节点时启用
+    #[serde(default = "default_health_check_interval_ms")]
+    pub health_check_interval_ms: u64,
+    /// 启动快照读取需要一致的节点数（1 = 不做交叉校验）
+    #[serde(default = "default_snapshot_quorum")]
+    pub snapshot_quorum: usize,
+}
+
+fn default_health_check_interval_ms() -> u64 {
+    5000
+}
+
+fn default_snapshot_quorum() -> usize {
+    1
+}
+
+impl NetworkConfig {
+    /// 所有 RPC 节点：主节点在前，备用节点在后
+    pub fn endpoints(&self) -> impl Iterator<Item = &str> {
+        std::iter::once(self.rpc_url.as_str()).chain(self.fallback_rpc_urls.iter().map(String::as_str))
+    }
+
+    /// 所有节点共同的传输方式
+    ///
+    /// 节点切换后订阅发往新的当前节点，HTTP 节点无法订阅；
+    /// 因此不允许混用 WebSocket 与 HTTP 节点（显式配置 transport 时全部按该方式连接）。
+    /// snapshot_quorum 超过节点数时启动快照永远无法达成一致，同样在这里拒绝
+    pub fn resolve_transport(&self) -> Result<Transport> {
+        let endpoints = self.endpoints().count();
+        if self.snapshot_quorum > endpoints {
+            bail!(
+                "snapshot_quorum {} exceeds the number of RPC endpoints ({})",
+                self.snapshot_quorum,
+                endpoints
+            );
+        }
+
+        let transport = Transport::resolve(self.transport, &self.rpc_url)?;
+        for url in &self.fallback_rpc_urls {
+            let fallback = Transport::resolve(self.transport, url)?;
+            if fallback != transport {
+                bail!(
+                    "RPC endpoints must use the same transport: {} is {:?} but rpc_url is {:?}",
+                    url,
+                    fallback,
+                    transport
+                );
+            }
+        }
+        Ok(transport)
+    }
+}
+
+/// RPC 传输方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Transport {
+    /// WebSocket：eth_subscribe 推送日志和区块头
+    Ws,
+    /// HTTP：按 poll_interval_ms 轮询 eth_getLogs
+    Http,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -28,12 +103,118 @@ pub struct ContractsConfig {
 pub struct SyncConfig {
     pub start_block: u64,
     pub sync_historical: bool,
+    /// 确认深度：事件所在区块之上再出 N 个块才进入 confirmed 视图（0 = 立即确认）
+    #[serde(default)]
+    pub confirmations: u64,
+    /// HTTP 模式下的轮询间隔（毫秒）
+    #[serde(default = "default_poll_interval_ms")]
+    pub poll_interval_ms: u64,
+    /// 单次 eth_getLogs 查询的最大区块跨度
+    #[serde(default = "default_max_block_range")]
+    pub max_block_range: u64,
+    /// 交易对白名单；为空则加载 Account 中注册的全部交易对
+    /// 每项可以是 0x 开头的 bytes32，或交易对名称（如 "WETH/USDC"，取 keccak256）
+    #[serde(default)]
+    pub trading_pairs: Vec<String>,
+    /// 每应用一个 OrderBook 事件后检查订单簿结构不变量（仅 debug 构建生效）
+    #[serde(default)]
+    pub validate_orderbook: bool,
+}
+
+impl SyncConfig {
+    /// 解析交易对白名单；未配置时返回 None（不过滤）
+    pub fn trading_pair_allow_list(&self) -> Result<Option<HashSet<[u8; 32]>>> {
+        if self.trading_pairs.is_empty() {
+            return Ok(None);
+        }
+        self.trading_pairs
+            .iter()
+            .map(|pair| parse_trading_pair(pair))
+            .collect::<Result<HashSet<_>>>()
+            .map(Some)
+    }
+}
+
+/// 解析交易对 ID：0x 开头按 bytes32 解析，否则按名称取 keccak256（与部署脚本一致）
+pub fn parse_trading_pair(value: &str) -> Result<[u8; 32]> {
+    match value.strip_prefix("0x") {
+        Some(hex) => {
+            let bytes = ethers::utils::hex::decode(hex)
+                .with_context(|| format!("Invalid trading pair id: {}", value))?;
+            <[u8; 32]>::try_from(bytes.as_slice())
+                .map_err(|_| anyhow::anyhow!("Trading pair id must be 32 bytes: {}", value))
+        }
+        None => Ok(keccak256(value.as_bytes())),
+    }
+}
+
+fn default_poll_interval_ms() -> u64 {
+    1000
+}
+
+fn default_max_block_range() -> u64 {
+    1000
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct MatchingConfig {
     pub max_batch_size: usize,
     pub matching_interval_ms: u64,
+    /// 计算 insertAfterPrice 时使用的订单簿视图
+    #[serde(default)]
+    pub view: StateView,
+    /// 每次插入后限价单撮合的最大次数；合约硬编码为 50，其他值在加载配置时拒绝
+    #[serde(default = "default_max_iterations")]
+    pub max_iterations: usize,
+    /// 每次插入后市价单撮合的最大次数；合约硬编码为 50，其他值在加载配置时拒绝
+    #[serde(default = "default_max_iterations")]
+    pub market_max_iterations: usize,
+    /// 预测到自成交时的处理方式
+    #[serde(default)]
+    pub self_trade_policy: SelfTradePolicy,
+}
+
+fn default_max_iterations() -> usize {
+    crate::orderbook_simulator::DEFAULT_MAX_ITERATIONS
+}
+
+impl MatchingConfig {
+    /// 撮合上限必须与合约 _tryMatchAfterInsertion 一致，否则预测的成交和 hints 与链上不符
+    pub fn validate(&self) -> Result<()> {
+        let expected = default_max_iterations();
+        for (name, value) in [
+            ("max_iterations", self.max_iterations),
+            ("market_max_iterations", self.market_max_iterations),
+        ] {
+            if value != expected {
+                bail!(
+                    "matching.{} = {} does not match the contract's maxIterations ({})",
+                    name,
+                    value,
+                    expected
+                );
+            }
+        }
+        Ok(())
+    }
+
+    pub fn match_limits(&self) -> MatchLimits {
+        MatchLimits {
+            limit_iterations: self.max_iterations,
+            market_iterations: self.market_max_iterations,
+        }
+    }
+}
+
+/// 订单簿视图
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StateView {
+    /// 乐观视图：收到事件立即生效
+    #[default]
+    Head,
+    /// 确认视图：只包含达到确认深度的事件
+    Confirmed,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -43,10 +224,101 @@ pub struct ExecutorConfig {
     pub gas_limit: u64,
 }
 
+/// 成交记录与 K 线
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HistoryConfig {
+    /// 每个交易对在内存中保留的最近成交数（0 = 不限制），K 线不受影响
+    #[serde(default = "default_max_trades_per_pair")]
+    pub max_trades_per_pair: usize,
+    /// 成交记录文件（JSONL）：启动时加载，之后追加确认的成交；不填则只保存在内存中
+    #[serde(default)]
+    pub trade_file: Option<String>,
+}
+
+fn default_max_trades_per_pair() -> usize {
+    100_000
+}
+
+impl Default for HistoryConfig {
+    fn default() -> Self {
+        Self {
+            max_trades_per_pair: default_max_trades_per_pair(),
+            trade_file: None,
+        }
+    }
+}
+
+/// 事件归档（JSONL）
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ArchiveConfig {
+    /// 归档目录；不填则不归档
+    #[serde(default)]
+    pub dir: Option<String>,
+    /// 单个文件的大小上限（MB），超过后切换到新文件
+    #[serde(default = "default_archive_max_file_mb")]
+    pub max_file_mb: u64,
+    /// 保留的文件数（0 = 全部保留）
+    #[serde(default)]
+    pub max_files: usize,
+}
+
+fn default_archive_max_file_mb() -> u64 {
+    64
+}
+
+impl Default for ArchiveConfig {
+    fn default() -> Self {
+        Self {
+            dir: None,
+            max_file_mb: default_archive_max_file_mb(),
+            max_files: 0,
+        }
+    }
+}
+
 impl Config {
     pub fn from_file(path: &str) -> Result<Self> {
         let content = fs::read_to_string(path)?;
         let config: Config = toml::from_str(&content)?;
+        config.network.resolve_transport()?;
+        config.matching.validate()?;
         Ok(config)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_trading_pair() {
+        let by_name = parse_trading_pair("WETH/USDC").unwrap();
+        assert_eq!(by_name, keccak256(b"WETH/USDC"));
+
+        let hex = format!("0x{}", ethers::utils::hex::encode(by_name));
+        assert_eq!(parse_trading_pair(&hex).unwrap(), by_name);
+
+        assert!(parse_trading_pair("0x1234").is_err());
+        assert!(parse_trading_pair("0xzz").is_err());
+    }
+
+    #[test]
+    fn test_match_limits_must_match_contract() {
+        let mut matching: MatchingConfig = toml::from_str(
+            r#"
+            max_batch_size = 10
+            matching_interval_ms = 1000
+            "#,
+        )
+        .unwrap();
+        assert!(matching.validate().is_ok());
+        assert_eq!(matching.match_limits(), MatchLimits::default());
+
+        matching.max_iterations = 100;
+        assert!(matching.validate().is_err());
+
+        matching.max_iterations = default_max_iterations();
+        matching.market_max_iterations = 10;
+        assert!(matching.validate().is_err());
+    }
+}
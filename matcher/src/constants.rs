@@ -0,0 +1,13 @@
+//! 交易常量 - 对应链上 TradingConstants.sol
+
+use ethers::types::U256;
+
+/// 数量精度 (10^8) - 对应 TradingConstants.AMOUNT_DECIMALS
+pub const AMOUNT_DECIMALS: U256 = U256([100_000_000, 0, 0, 0]);
+
+/// 价格精度 (10^8) - 对应 TradingConstants.PRICE_DECIMALS
+pub const PRICE_DECIMALS: U256 = U256([100_000_000, 0, 0, 0]);
+
+/// 交易费率 (千分之一 = 0.1%) - 对应 TradingConstants.FEE_RATE / FEE_BASE
+pub const FEE_RATE: U256 = U256([1, 0, 0, 0]);
+pub const FEE_BASE: U256 = U256([1000, 0, 0, 0]);
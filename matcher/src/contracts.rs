@@ -18,3 +18,10 @@ abigen!(
     "./abi/Account.json",
     event_derives(serde::Deserialize, serde::Serialize)
 );
+
+abigen!(
+    Erc20,
+    r#"[
+        function decimals() external view returns (uint8)
+    ]"#
+);
@@ -0,0 +1,220 @@
+//! 行情查询 - 在模拟器上计算盘口、聚合深度（L2）和逐笔订单（L3）
+//!
+//! 与 get_price_levels / get_orders_at_price 一样按链表顺序遍历：
+//! 价格从最优价起（ask 升序、bid 降序），层级内从 head 起（即链上的撮合顺序）。
+//! 只包含限价单，市价单队列见 get_market_orders。
+//!
+//! 撮合次数达到上限时订单簿可能暂时交叉（best_bid >= best_ask），此时价差为 0。
+
+use crate::fixed_point::{Amount, Price};
+use crate::orderbook_simulator::OrderBookSimulator;
+use ethers::types::{Address, U256};
+use serde::{Deserialize, Serialize};
+
+/// 一个价格层级的聚合深度（L2）
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DepthLevel {
+    pub price: Price,
+    /// 层级内订单剩余量之和（链上 total_volume）
+    pub amount: Amount,
+    pub order_count: usize,
+}
+
+/// 双边聚合深度
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DepthSnapshot {
+    /// 买盘，价格从高到低
+    pub bids: Vec<DepthLevel>,
+    /// 卖盘，价格从低到高
+    pub asks: Vec<DepthLevel>,
+}
+
+/// 盘口
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TopOfBook {
+    pub best_bid: Option<DepthLevel>,
+    pub best_ask: Option<DepthLevel>,
+}
+
+impl TopOfBook {
+    /// 卖一价 - 买一价，任一侧为空时为 None，交叉时为 0
+    pub fn spread(&self) -> Option<Price> {
+        let (bid, ask) = (self.best_bid?, self.best_ask?);
+        Some(ask.price.saturating_sub(bid.price))
+    }
+
+    /// (买一价 + 卖一价) / 2，向下取整，任一侧为空时为 None
+    pub fn mid_price(&self) -> Option<Price> {
+        let (bid, ask) = (self.best_bid?, self.best_ask?);
+        // (a + b) / 2 不经过 a + b，避免溢出
+        let (bid, ask) = (bid.price.raw(), ask.price.raw());
+        Some(Price::from_raw(bid / 2 + ask / 2 + (bid % 2 + ask % 2) / 2))
+    }
+}
+
+/// 簿上的一个限价单（L3）
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BookOrder {
+    pub order_id: U256,
+    pub trader: Address,
+    pub is_ask: bool,
+    pub price: Price,
+    /// 下单数量
+    pub amount: Amount,
+    /// 剩余未成交数量
+    pub remaining: Amount,
+}
+
+impl OrderBookSimulator {
+    /// 某一侧从最优价起的前 levels 个价格层级（None 表示全部）
+    pub fn depth(&self, is_ask: bool, levels: Option<usize>) -> Vec<DepthLevel> {
+        self.get_price_levels(is_ask)
+            .into_iter()
+            .take(levels.unwrap_or(usize::MAX))
+            .map(|price| self.depth_level(price, is_ask))
+            .collect()
+    }
+
+    /// 双边聚合深度，每侧最多 levels 个价格层级（None 表示全部）
+    pub fn depth_snapshot(&self, levels: Option<usize>) -> DepthSnapshot {
+        DepthSnapshot {
+            bids: self.depth(false, levels),
+            asks: self.depth(true, levels),
+        }
+    }
+
+    pub fn best_bid(&self) -> Option<DepthLevel> {
+        (!self.bid_head.is_zero()).then(|| self.depth_level(self.bid_head, false))
+    }
+
+    pub fn best_ask(&self) -> Option<DepthLevel> {
+        (!self.ask_head.is_zero()).then(|| self.depth_level(self.ask_head, true))
+    }
+
+    pub fn top_of_book(&self) -> TopOfBook {
+        TopOfBook {
+            best_bid: self.best_bid(),
+            best_ask: self.best_ask(),
+        }
+    }
+
+    /// 某一侧价格不差于 price 的全部挂单量（ask 为 <= price，bid 为 >= price），
+    /// 即以 price 为限价吃单最多能成交的 base tokens
+    pub fn cumulative_depth(&self, is_ask: bool, price: Price) -> Amount {
+        self.get_price_levels(is_ask)
+            .into_iter()
+            .take_while(|&level| if is_ask { level <= price.raw() } else { level >= price.raw() })
+            .map(|level| self.depth_level(level, is_ask).amount)
+            .sum()
+    }
+
+    /// 某一侧的全部限价单，按撮合顺序排列
+    pub fn l3_orders(&self, is_ask: bool) -> Vec<BookOrder> {
+        self.get_price_levels(is_ask)
+            .into_iter()
+            .flat_map(|price| self.get_orders_at_price(price, is_ask))
+            .filter_map(|order_id| {
+                let order = self.orders.get(&order_id)?;
+                Some(BookOrder {
+                    order_id,
+                    trader: order.trader,
+                    is_ask,
+                    price: Price::from_raw(order.price_level),
+                    amount: Amount::from_raw(order.amount),
+                    remaining: Amount::from_raw(order.amount.saturating_sub(order.filled_amount)),
+                })
+            })
+            .collect()
+    }
+
+    fn depth_level(&self, price: U256, is_ask: bool) -> DepthLevel {
+        let total_volume = self
+            .level(price, is_ask)
+            .map(|level| level.total_volume)
+            .unwrap_or_default();
+        DepthLevel {
+            price: Price::from_raw(price),
+            amount: Amount::from_raw(total_volume),
+            order_count: self.get_orders_at_price(price, is_ask).len(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::orderbook_simulator::MatchLimits;
+
+    fn book() -> OrderBookSimulator {
+        let alice = Address::from_low_u64_be(1);
+        let mut sim = OrderBookSimulator::new();
+        sim.simulate_insert_order(U256::from(1), alice, U256::from(110), U256::from(4), true).unwrap();
+        sim.simulate_insert_order(U256::from(2), alice, U256::from(100), U256::from(5), true).unwrap();
+        sim.simulate_insert_order(U256::from(3), alice, U256::from(100), U256::from(2), true).unwrap();
+        sim.simulate_insert_order(U256::from(4), alice, U256::from(95), U256::from(3), false).unwrap();
+        sim.simulate_insert_order(U256::from(5), alice, U256::from(90), U256::from(6), false).unwrap();
+        // 部分成交：买单吃掉层级 100 头部订单 3 的 1
+        sim.simulate_insert_order(U256::from(6), alice, U256::from(100), U256::from(1), false).unwrap();
+        sim
+    }
+
+    fn level(price: u64, amount: u64, order_count: usize) -> DepthLevel {
+        DepthLevel {
+            price: Price::from_raw(U256::from(price)),
+            amount: Amount::from_raw(U256::from(amount)),
+            order_count,
+        }
+    }
+
+    #[test]
+    fn test_depth_and_top_of_book() {
+        let sim = book();
+        assert_eq!(sim.depth(true, None), vec![level(100, 6, 2), level(110, 4, 1)]);
+        assert_eq!(sim.depth(false, Some(1)), vec![level(95, 3, 1)]);
+        assert_eq!(sim.depth_snapshot(Some(5)).bids, vec![level(95, 3, 1), level(90, 6, 1)]);
+
+        let top = sim.top_of_book();
+        assert_eq!(top.best_ask, Some(level(100, 6, 2)));
+        assert_eq!(top.spread(), Some(Price::from_raw(U256::from(5))));
+        assert_eq!(top.mid_price(), Some(Price::from_raw(U256::from(97))));
+        assert_eq!(OrderBookSimulator::new().top_of_book().spread(), None);
+
+        let crossed = TopOfBook { best_bid: Some(level(101, 1, 1)), ..top };
+        assert_eq!(crossed.spread(), Some(Price::zero()));
+        let max = TopOfBook {
+            best_bid: Some(DepthLevel { price: Price::from_raw(U256::MAX), ..level(0, 1, 1) }),
+            best_ask: Some(DepthLevel { price: Price::from_raw(U256::MAX), ..level(0, 1, 1) }),
+        };
+        assert_eq!(max.mid_price(), Some(Price::from_raw(U256::MAX)));
+    }
+
+    #[test]
+    fn test_cumulative_depth() {
+        let sim = book();
+        let at = |price: u64| Price::from_raw(U256::from(price));
+        assert_eq!(sim.cumulative_depth(true, at(99)), Amount::zero());
+        assert_eq!(sim.cumulative_depth(true, at(100)), Amount::from_raw(U256::from(6)));
+        assert_eq!(sim.cumulative_depth(true, at(200)), Amount::from_raw(U256::from(10)));
+        assert_eq!(sim.cumulative_depth(false, at(91)), Amount::from_raw(U256::from(3)));
+        assert_eq!(sim.cumulative_depth(false, at(1)), Amount::from_raw(U256::from(9)));
+    }
+
+    #[test]
+    fn test_l3_orders_in_matching_order() {
+        let mut sim = book();
+        let asks = sim.l3_orders(true);
+        // 层级内新订单在头部：3 在 2 之前
+        let ids: Vec<_> = asks.iter().map(|order| order.order_id.as_u64()).collect();
+        assert_eq!(ids, vec![3, 2, 1]);
+        assert_eq!(asks[0].amount, Amount::from_raw(U256::from(2)));
+        assert_eq!(asks[0].remaining, Amount::from_raw(U256::one()));
+        let total: Amount = asks.iter().map(|order| order.remaining).sum();
+        assert_eq!(total, sim.depth(true, None).iter().map(|level| level.amount).sum());
+
+        // 市价单不在 L3 中
+        sim.match_limits.market_iterations = 0;
+        sim.simulate_insert_market_order(U256::from(7), Address::zero(), U256::from(1), true).unwrap();
+        sim.match_limits = MatchLimits::default();
+        assert_eq!(sim.l3_orders(false).len(), 2);
+    }
+}
@@ -0,0 +1,658 @@
+//! 差分测试 - 在内嵌 EVM（revm）中运行真实的 Sequencer / OrderBook / Account 字节码，
+//! 用同一请求流同时驱动合约与 OrderBookSimulator，比较：
+//! - insertAfterPrice：使用模拟器计算的 hints 调用 batchProcessRequests，合约会校验插入位置
+//! - 成交：合约发出的 Trade 事件与模拟器返回的 SimEffects.trades 逐笔一致
+//! - 链表状态：从合约存储读出头尾指针、价格层级和订单，重建的模拟器与本地模拟器完全一致
+//!
+//! 不需要外部节点，但需要合约编译产物：在仓库根目录执行 `make differential`
+//! （即 `forge build` 后运行 `cargo test -- --ignored differential`；产物位于 ../out，
+//! 也可用 MATCHER_ARTIFACTS_DIR 指定）。
+//! 不依赖产物的测试（字节码执行、Trade 日志解析与比较）随 `cargo test` 一起运行。
+
+use crate::constants::{AMOUNT_DECIMALS, PRICE_DECIMALS};
+use crate::contracts::order_book::OrderBookEvents;
+use crate::orderbook_simulator::{OrderBookSimulator, SimOrder, SimPriceLevel, SimTrade};
+use ethers::abi::{self, ParamType, RawLog, Token};
+use ethers::contract::EthLogDecode;
+use ethers::types::{Address, H256, U256};
+use ethers::utils::{id, keccak256};
+use revm::db::{CacheDB, EmptyDB};
+use revm::primitives::{Address as EvmAddress, Bytes, ExecutionResult, Log as EvmLog, Output, TxKind};
+use revm::Evm;
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+
+/// 单笔交易的 gas 上限（batchProcessRequests 中的撮合可能很耗 gas）
+const TX_GAS_LIMIT: u64 = 1_000_000_000;
+
+fn to_evm(address: Address) -> EvmAddress {
+    EvmAddress::from(address.0)
+}
+
+fn from_evm(address: EvmAddress) -> Address {
+    Address::from(address.into_array())
+}
+
+/// 函数选择器 + ABI 编码参数
+fn calldata(signature: &str, args: &[Token]) -> Vec<u8> {
+    let mut data = id(signature).to_vec();
+    data.extend(abi::encode(args));
+    data
+}
+
+/// 解析 Error(string) 形式的 revert 原因
+fn revert_reason(output: &[u8]) -> String {
+    if output.len() > 4 && output[..4] == id("Error(string)") {
+        if let Ok(tokens) = abi::decode(&[ParamType::String], &output[4..]) {
+            if let Some(Token::String(reason)) = tokens.into_iter().next() {
+                return reason;
+            }
+        }
+    }
+    format!("0x{}", ethers::utils::hex::encode(output))
+}
+
+/// 内嵌的 EVM 链
+struct Chain {
+    evm: Evm<'static, (), CacheDB<EmptyDB>>,
+}
+
+impl Chain {
+    fn new() -> Self {
+        let evm = Evm::builder()
+            .with_db(CacheDB::new(EmptyDB::default()))
+            // via_ir 编译的 OrderBook 可能超过 EIP-170 的 24KB 限制
+            .modify_cfg_env(|cfg| cfg.limit_contract_code_size = Some(usize::MAX))
+            .build();
+        Self { evm }
+    }
+
+    fn execute(&mut self, caller: Address, to: TxKind, data: Vec<u8>, commit: bool) -> Result<(Output, Vec<EvmLog>), String> {
+        let tx = self.evm.tx_mut();
+        tx.caller = to_evm(caller);
+        tx.transact_to = to;
+        tx.data = Bytes::from(data);
+        tx.gas_limit = TX_GAS_LIMIT;
+
+        let result = if commit {
+            self.evm.transact_commit().map_err(|e| format!("{:?}", e))?
+        } else {
+            self.evm.transact().map_err(|e| format!("{:?}", e))?.result
+        };
+        match result {
+            ExecutionResult::Success { output, logs, .. } => Ok((output, logs)),
+            ExecutionResult::Revert { output, .. } => Err(revert_reason(&output)),
+            ExecutionResult::Halt { reason, .. } => Err(format!("halted: {:?}", reason)),
+        }
+    }
+
+    /// 部署合约（init code = 字节码 + 构造参数）
+    fn deploy(&mut self, caller: Address, init_code: Vec<u8>) -> Result<Address, String> {
+        match self.execute(caller, TxKind::Create, init_code, true)?.0 {
+            Output::Create(_, Some(address)) => Ok(from_evm(address)),
+            output => Err(format!("unexpected create output: {:?}", output)),
+        }
+    }
+
+    /// 发送交易，返回输出与日志
+    fn send(&mut self, caller: Address, to: Address, data: Vec<u8>) -> Result<(Vec<u8>, Vec<EvmLog>), String> {
+        let (output, logs) = self.execute(caller, TxKind::Call(to_evm(to)), data, true)?;
+        Ok((output.into_data().to_vec(), logs))
+    }
+
+    /// 只读调用（不提交状态）
+    fn view(&mut self, to: Address, data: Vec<u8>) -> Result<Vec<u8>, String> {
+        let (output, _) = self.execute(Address::zero(), TxKind::Call(to_evm(to)), data, false)?;
+        Ok(output.into_data().to_vec())
+    }
+}
+
+/// 合约产物目录
+fn artifacts_dir() -> PathBuf {
+    std::env::var("MATCHER_ARTIFACTS_DIR")
+        .map(PathBuf::from)
+        .unwrap_or_else(|_| PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("../out"))
+}
+
+/// 读取 forge 产物中的部署字节码（out/<File>.sol/<Contract>.json 的 bytecode.object）
+fn load_bytecode(file: &str, contract: &str) -> Vec<u8> {
+    let path = artifacts_dir().join(format!("{}.sol", file)).join(format!("{}.json", contract));
+    let json: serde_json::Value = serde_json::from_str(
+        &std::fs::read_to_string(&path)
+            .unwrap_or_else(|e| panic!("{}: {} (run `make differential` in the repository root)", path.display(), e)),
+    )
+    .expect("invalid artifact json");
+    let object = json["bytecode"]["object"]
+        .as_str()
+        .expect("artifact has no bytecode.object");
+    ethers::utils::hex::decode(object.trim_start_matches("0x")).expect("invalid bytecode hex")
+}
+
+fn decode_uints(output: &[u8], count: usize) -> Vec<U256> {
+    abi::decode(&vec![ParamType::Uint(256); count], output)
+        .expect("unexpected return data")
+        .into_iter()
+        .map(|token| token.into_uint().expect("uint"))
+        .collect()
+}
+
+/// 从交易日志中解析 orderbook 合约的 Trade 事件
+fn trades_in(orderbook: Address, logs: &[EvmLog]) -> Vec<SimTrade> {
+    logs.iter()
+        .filter(|log| from_evm(log.address) == orderbook)
+        .filter_map(|log| {
+            let raw = RawLog {
+                topics: log.data.topics().iter().map(|t| H256::from(t.0)).collect(),
+                data: log.data.data.to_vec(),
+            };
+            match OrderBookEvents::decode_log(&raw).ok()? {
+                OrderBookEvents::TradeFilter(trade) => Some(SimTrade {
+                    buy_order_id: trade.buy_order_id,
+                    sell_order_id: trade.sell_order_id,
+                    price: trade.price,
+                    amount: trade.amount,
+                }),
+                _ => None,
+            }
+        })
+        .collect()
+}
+
+/// 请求流中的一个请求
+#[derive(Debug, Clone, Copy)]
+enum Request {
+    Limit { is_ask: bool, price: U256, amount: U256 },
+    Market { is_ask: bool, amount: U256 },
+    Remove { order_id: U256 },
+}
+
+/// 已提交到 Sequencer、等待批处理的请求
+#[derive(Debug, Clone, Copy)]
+struct Pending {
+    request_id: U256,
+    request: Request,
+}
+
+/// 部署好的合约与对应的模拟器
+struct Harness {
+    chain: Chain,
+    sequencer: Address,
+    orderbook: Address,
+    trading_pair: [u8; 32],
+    traders: Vec<Address>,
+    sim: OrderBookSimulator,
+    pending: Vec<Pending>,
+    owners: HashMap<U256, Address>,
+}
+
+impl Harness {
+    fn deploy() -> Self {
+        let mut chain = Chain::new();
+        let deployer = Address::from_low_u64_be(0x1000);
+        let traders = vec![Address::from_low_u64_be(0x2000), Address::from_low_u64_be(0x2001)];
+
+        let token = |chain: &mut Chain, name: &str, decimals: u8| {
+            let mut init_code = load_bytecode("MockERC20", "MockERC20");
+            init_code.extend(abi::encode(&[
+                Token::String(name.to_string()),
+                Token::String(name.to_string()),
+                Token::Uint(decimals.into()),
+            ]));
+            chain.deploy(deployer, init_code).expect("deploy MockERC20")
+        };
+        let weth = token(&mut chain, "WETH", 18);
+        let usdc = token(&mut chain, "USDC", 6);
+
+        let account = chain
+            .deploy(deployer, load_bytecode("Account", "Account"))
+            .expect("deploy Account");
+        let orderbook = chain
+            .deploy(deployer, load_bytecode("OrderBook", "OrderBook"))
+            .expect("deploy OrderBook");
+        let sequencer = chain
+            .deploy(deployer, load_bytecode("Sequencer", "Sequencer"))
+            .expect("deploy Sequencer");
+
+        let wiring = [
+            (account, "setOrderBook(address)", orderbook),
+            (account, "setSequencer(address)", sequencer),
+            (orderbook, "setSequencer(address)", sequencer),
+            (orderbook, "setAccount(address)", account),
+            (sequencer, "setAccount(address)", account),
+            (sequencer, "setOrderBook(address)", orderbook),
+        ];
+        for (target, signature, value) in wiring {
+            chain
+                .send(deployer, target, calldata(signature, &[Token::Address(value)]))
+                .unwrap_or_else(|e| panic!("{}: {}", signature, e));
+        }
+
+        let trading_pair = keccak256("WETH/USDC");
+        chain
+            .send(
+                deployer,
+                account,
+                calldata(
+                    "registerTradingPair(bytes32,address,address)",
+                    &[
+                        Token::FixedBytes(trading_pair.to_vec()),
+                        Token::Address(weth),
+                        Token::Address(usdc),
+                    ],
+                ),
+            )
+            .expect("registerTradingPair");
+
+        // 每个交易者充足的两种代币
+        let funds = U256::exp10(40);
+        for trader in &traders {
+            for token in [weth, usdc] {
+                let steps = [
+                    (token, calldata("mint(address,uint256)", &[Token::Address(*trader), Token::Uint(funds)])),
+                    (token, calldata("approve(address,uint256)", &[Token::Address(account), Token::Uint(funds)])),
+                    (account, calldata("deposit(address,uint256)", &[Token::Address(token), Token::Uint(funds)])),
+                ];
+                for (target, data) in steps {
+                    chain.send(*trader, target, data).expect("fund trader");
+                }
+            }
+        }
+
+        Self {
+            chain,
+            sequencer,
+            orderbook,
+            trading_pair,
+            traders,
+            sim: OrderBookSimulator::new(),
+            pending: Vec::new(),
+            owners: HashMap::new(),
+        }
+    }
+
+    fn trader(&self, index: usize) -> Address {
+        self.traders[index % self.traders.len()]
+    }
+
+    /// 提交请求到 Sequencer（尚未处理）
+    fn submit(&mut self, trader: Address, request: Request) {
+        let data = match request {
+            Request::Limit { is_ask, price, amount } => calldata(
+                "placeLimitOrder(bytes32,bool,uint256,uint256)",
+                &[
+                    Token::FixedBytes(self.trading_pair.to_vec()),
+                    Token::Bool(is_ask),
+                    Token::Uint(price),
+                    Token::Uint(amount),
+                ],
+            ),
+            Request::Market { is_ask, amount } => calldata(
+                "placeMarketOrder(bytes32,bool,uint256)",
+                &[
+                    Token::FixedBytes(self.trading_pair.to_vec()),
+                    Token::Bool(is_ask),
+                    Token::Uint(amount),
+                ],
+            ),
+            Request::Remove { order_id } => {
+                calldata("requestRemoveOrder(uint256)", &[Token::Uint(order_id)])
+            }
+        };
+        let (output, _) = self
+            .chain
+            .send(trader, self.sequencer, data)
+            .unwrap_or_else(|e| panic!("submit {:?}: {}", request, e));
+        let request_id = decode_uints(&output, 1)[0];
+        if !matches!(request, Request::Remove { .. }) {
+            self.owners.insert(request_id, trader);
+        }
+        self.pending.push(Pending { request_id, request });
+    }
+
+    /// 用模拟器计算 hints 与预期成交，调用 batchProcessRequests 并比较结果
+    fn process_batch(&mut self) {
+        if self.pending.is_empty() {
+            return;
+        }
+        let batch = std::mem::take(&mut self.pending);
+
+        let mut hints = Vec::with_capacity(batch.len());
+        let mut expected_trades = Vec::new();
+        for pending in &batch {
+            let trader = self.owners.get(&pending.request_id).copied().unwrap_or_default();
+            let effects = match pending.request {
+                Request::Limit { is_ask, price, amount } => {
+                    self.sim.simulate_insert_order(pending.request_id, trader, price, amount, is_ask)
+                }
+                Request::Market { is_ask, amount } => {
+                    self.sim.simulate_insert_market_order(pending.request_id, trader, amount, is_ask)
+                }
+                Request::Remove { order_id } => self.sim.simulate_remove_order(order_id, trader),
+            }
+            .unwrap_or_else(|e| panic!("simulating {:?}: {}", pending, e));
+            hints.push(Token::Uint(effects.insert_after_price));
+            expected_trades.extend(effects.trades);
+        }
+
+        let ids = batch.iter().map(|p| Token::Uint(p.request_id)).collect();
+        let zeros = vec![Token::Uint(U256::zero()); batch.len()];
+        let (output, logs) = self
+            .chain
+            .send(
+                self.traders[0],
+                self.orderbook,
+                calldata(
+                    "batchProcessRequests(uint256[],uint256[],uint256[])",
+                    &[Token::Array(ids), Token::Array(hints), Token::Array(zeros)],
+                ),
+            )
+            .unwrap_or_else(|e| panic!("batchProcessRequests reverted: {} (batch {:?})", e, batch));
+        assert_eq!(decode_uints(&output, 1)[0], U256::from(batch.len()), "not all requests processed");
+
+        assert_eq!(trades_in(self.orderbook, &logs), expected_trades, "trades differ for batch {:?}", batch);
+        self.assert_state_matches();
+    }
+
+    fn view_uints(&mut self, signature: &str, arg: Token, count: usize) -> Vec<U256> {
+        let output = self
+            .chain
+            .view(self.orderbook, calldata(signature, &[arg]))
+            .unwrap_or_else(|e| panic!("{}: {}", signature, e));
+        decode_uints(&output, count)
+    }
+
+    fn read_order(&mut self, order_id: U256, is_ask: bool) -> SimOrder {
+        let output = self
+            .chain
+            .view(self.orderbook, calldata("orders(uint256)", &[Token::Uint(order_id)]))
+            .expect("orders");
+        let tokens = abi::decode(
+            &[
+                ParamType::Uint(256),
+                ParamType::Address,
+                ParamType::Uint(256),
+                ParamType::Uint(256),
+                ParamType::Bool,
+                ParamType::Uint(256),
+                ParamType::Uint(256),
+                ParamType::Uint(256),
+            ],
+            &output,
+        )
+        .expect("orders return data");
+        let uint = |i: usize| tokens[i].clone().into_uint().expect("uint");
+        SimOrder {
+            id: uint(0),
+            trader: tokens[1].clone().into_address().expect("address"),
+            amount: uint(2),
+            filled_amount: uint(3),
+            is_market_order: tokens[4].clone().into_bool().expect("bool"),
+            is_ask,
+            price_level: uint(5),
+            next_order_id: uint(6),
+            prev_order_id: uint(7),
+            requested: Default::default(),
+            inserted: Default::default(),
+        }
+    }
+
+    /// 从合约存储重建模拟器（与同步器加载链上状态的方式相同）
+    fn read_chain_book(&mut self) -> OrderBookSimulator {
+        let heads = self.view_uints(
+            "orderBooks(bytes32)",
+            Token::FixedBytes(self.trading_pair.to_vec()),
+            8,
+        );
+        let mut book = OrderBookSimulator::from_chain_state(heads[0], heads[1], heads[2], heads[3]);
+        book.market_ask_head = heads[4];
+        book.market_ask_tail = heads[5];
+        book.market_bid_head = heads[6];
+        book.market_bid_tail = heads[7];
+
+        for (is_ask, head) in [(true, heads[0]), (false, heads[2])] {
+            let mut price = head;
+            while !price.is_zero() {
+                let key = if is_ask { price } else { price | (U256::one() << 255) };
+                let level = self.view_uints("priceLevels(uint256)", Token::Uint(key), 6);
+                let level = SimPriceLevel {
+                    price: level[0],
+                    total_volume: level[1],
+                    head_order_id: level[2],
+                    tail_order_id: level[3],
+                    next_price: level[4],
+                    prev_price: level[5],
+                };
+                let mut order_id = level.head_order_id;
+                while !order_id.is_zero() {
+                    let order = self.read_order(order_id, is_ask);
+                    order_id = order.next_order_id;
+                    book.add_existing_order(order);
+                }
+                price = level.next_price;
+                book.add_existing_price_level(level, is_ask);
+            }
+        }
+
+        for (is_ask, head) in [(true, heads[4]), (false, heads[6])] {
+            let mut order_id = head;
+            while !order_id.is_zero() {
+                let order = self.read_order(order_id, is_ask);
+                order_id = order.next_order_id;
+                book.add_existing_order(order);
+            }
+        }
+
+        book
+    }
+
+    fn assert_state_matches(&mut self) {
+        let chain = self.read_chain_book();
+        assert_eq!(chain.validate(), vec![], "contract state violates simulator invariants");
+
+        let heads = |book: &OrderBookSimulator| {
+            [
+                book.ask_head,
+                book.ask_tail,
+                book.bid_head,
+                book.bid_tail,
+                book.market_ask_head,
+                book.market_ask_tail,
+                book.market_bid_head,
+                book.market_bid_tail,
+            ]
+        };
+        assert_eq!(heads(&chain), heads(&self.sim), "list heads differ");
+        assert_eq!(chain.price_levels, self.sim.price_levels, "price levels differ");
+        assert_eq!(chain.orders, self.sim.orders, "orders differ");
+    }
+
+    /// 簿上可撤销且尚未在队列中等待撤销的订单
+    fn removable_orders(&self) -> Vec<U256> {
+        let queued: HashSet<U256> = self
+            .pending
+            .iter()
+            .filter_map(|p| match p.request {
+                Request::Remove { order_id } => Some(order_id),
+                _ => None,
+            })
+            .collect();
+        let mut ids: Vec<U256> = self
+            .sim
+            .orders
+            .keys()
+            .filter(|id| !queued.contains(*id))
+            .copied()
+            .collect();
+        ids.sort();
+        ids
+    }
+}
+
+fn price(whole: u64) -> U256 {
+    U256::from(whole) * PRICE_DECIMALS
+}
+
+/// 0.01 WETH 的整数倍
+fn lots(count: u64) -> U256 {
+    AMOUNT_DECIMALS / 100 * count
+}
+
+#[test]
+fn test_raw_bytecode_roundtrip() {
+    // init code 复制并返回 runtime；runtime 返回 uint256(42)
+    let runtime = "602a60005260206000f3";
+    let init = format!("600a600c600039600a6000f3{}", runtime);
+    let mut chain = Chain::new();
+    let caller = Address::from_low_u64_be(1);
+
+    let contract = chain
+        .deploy(caller, ethers::utils::hex::decode(init).unwrap())
+        .unwrap();
+    assert_eq!(chain.view(contract, vec![]).unwrap(), {
+        let mut word = [0u8; 32];
+        word[31] = 42;
+        word.to_vec()
+    });
+}
+
+#[test]
+fn test_emitted_trades_compare_with_simulator() {
+    // 不依赖合约产物：部署一个把 calldata 原样作为 LOG4 发出的合约
+    // （前 4 个字为 topics，其余为 data），按 OrderBook 的 Trade 事件格式发出模拟器预期的成交，
+    // 经 revm 执行后由 trades_in 解析，走与差分测试相同的比较路径
+    let runtime = "366000600037606051604051602051600051608036036080a400";
+    let init = format!("60{:02x}600c60003960{:02x}6000f3{}", runtime.len() / 2, runtime.len() / 2, runtime);
+    let mut chain = Chain::new();
+    let caller = Address::from_low_u64_be(1);
+    let emitter = chain
+        .deploy(caller, ethers::utils::hex::decode(init).unwrap())
+        .unwrap();
+
+    let (seller, buyer) = (Address::from_low_u64_be(0x2000), Address::from_low_u64_be(0x2001));
+    let mut sim = OrderBookSimulator::new();
+    sim.simulate_insert_order(U256::from(1), seller, price(2000), lots(5), true).unwrap();
+    let expected = sim
+        .simulate_insert_order(U256::from(2), buyer, price(2010), lots(3), false)
+        .unwrap()
+        .trades;
+    assert_eq!(expected.len(), 1);
+
+    let trading_pair = keccak256("WETH/USDC");
+    let mut logs = Vec::new();
+    for trade in &expected {
+        let mut data = abi::encode(&[
+            Token::FixedBytes(keccak256("Trade(bytes32,uint256,uint256,address,address,uint256,uint256)").to_vec()),
+            Token::FixedBytes(trading_pair.to_vec()),
+            Token::Uint(trade.buy_order_id),
+            Token::Uint(trade.sell_order_id),
+        ]);
+        data.extend(abi::encode(&[
+            Token::Address(buyer),
+            Token::Address(seller),
+            Token::Uint(trade.price),
+            Token::Uint(trade.amount),
+        ]));
+        logs.extend(chain.send(caller, emitter, data).unwrap().1);
+    }
+
+    assert_eq!(trades_in(emitter, &logs), expected);
+    // 其他合约发出的日志不计入
+    assert_eq!(trades_in(Address::from_low_u64_be(2), &logs), vec![]);
+}
+
+#[test]
+fn test_revert_reason() {
+    let mut output = id("Error(string)").to_vec();
+    output.extend(abi::encode(&[Token::String("Order does not exist".into())]));
+    assert_eq!(revert_reason(&output), "Order does not exist");
+    assert_eq!(revert_reason(&[0xde, 0xad]), "0xdead");
+}
+
+#[test]
+#[ignore = "needs contract artifacts: run `make differential` in the repository root"]
+fn differential_scripted_book() {
+    let mut h = Harness::deploy();
+    let (alice, bob) = (h.trader(0), h.trader(1));
+
+    // 两侧价格阶梯（价格层级从头部、中间、尾部插入）
+    for (i, p) in [2010, 2030, 2020, 2050, 2040].into_iter().enumerate() {
+        h.submit(alice, Request::Limit { is_ask: true, price: price(p), amount: lots(5 + i as u64) });
+    }
+    for (i, p) in [2000, 1980, 1990, 1960, 1970].into_iter().enumerate() {
+        h.submit(bob, Request::Limit { is_ask: false, price: price(p), amount: lots(5 + i as u64) });
+    }
+    h.process_batch();
+
+    // 同价位追加、交叉限价单
+    h.submit(alice, Request::Limit { is_ask: true, price: price(2010), amount: lots(3) });
+    h.submit(bob, Request::Limit { is_ask: false, price: price(2025), amount: lots(20) });
+    h.submit(alice, Request::Limit { is_ask: true, price: price(1985), amount: lots(12) });
+    h.process_batch();
+
+    // 市价单：买单按计价代币数量、卖单按基础代币数量
+    h.submit(bob, Request::Market { is_ask: false, amount: AMOUNT_DECIMALS * 100 });
+    h.submit(alice, Request::Market { is_ask: true, amount: lots(4) });
+    h.process_batch();
+
+    // 撤销限价单
+    let target = h.removable_orders()[0];
+    let owner = h.owners[&target];
+    h.submit(owner, Request::Remove { order_id: target });
+    h.process_batch();
+
+    // 单次插入超过 50 次成交：剩余部分在下一次插入时继续撮合
+    for _ in 0..60 {
+        h.submit(alice, Request::Limit { is_ask: true, price: price(2100), amount: lots(1) });
+    }
+    h.process_batch();
+    h.submit(bob, Request::Limit { is_ask: false, price: price(2100), amount: lots(200) });
+    h.process_batch();
+    h.submit(bob, Request::Limit { is_ask: false, price: price(1000), amount: lots(1) });
+    h.process_batch();
+}
+
+#[test]
+#[ignore = "needs contract artifacts: run `make differential` in the repository root"]
+fn differential_random_stream() {
+    let mut h = Harness::deploy();
+    let mut seed: u64 = 0x9e37_79b9_7f4a_7c15;
+    let mut next = |bound: u64| {
+        seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+        (seed >> 33) % bound
+    };
+
+    for _ in 0..80 {
+        for _ in 0..1 + next(10) {
+            let trader = h.trader(next(2) as usize);
+            let is_ask = next(2) == 0;
+            match next(8) {
+                0..=4 => {
+                    // 买卖价格区间部分重叠
+                    let ticks = if is_ask { 8 + next(12) } else { next(12) };
+                    let request = Request::Limit { is_ask, price: price(1990 + ticks * 5), amount: lots(1 + next(10)) };
+                    h.submit(trader, request);
+                }
+                5 | 6 => {
+                    let amount = if is_ask {
+                        lots(1 + next(10))
+                    } else {
+                        AMOUNT_DECIMALS * (1 + next(300))
+                    };
+                    h.submit(trader, Request::Market { is_ask, amount });
+                }
+                _ => {
+                    // 撤单目标必须在处理时仍在簿上：先处理已排队的请求，再从模拟器中挑选
+                    h.process_batch();
+                    let removable = h.removable_orders();
+                    if removable.is_empty() {
+                        continue;
+                    }
+                    let target = removable[next(removable.len() as u64) as usize];
+                    let owner = h.owners[&target];
+                    h.submit(owner, Request::Remove { order_id: target });
+                }
+            }
+        }
+        h.process_batch();
+    }
+}
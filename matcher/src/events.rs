@@ -0,0 +1,122 @@
+//! 链上事件解码
+//!
+//! 将 Sequencer / OrderBook / Account 合约的原始日志统一解码为 `DecodedLog`，
+//! 并附带区块号、交易哈希、日志索引等元数据，供同步器按区块处理。
+
+use crate::contracts::account::AccountEvents;
+use crate::contracts::order_book::OrderBookEvents;
+use crate::contracts::sequencer::SequencerEvents;
+use crate::types::BlockStamp;
+use ethers::abi::RawLog;
+use ethers::contract::EthLogDecode;
+use ethers::types::{Address, Log, H256, U256};
+use serde::{Deserialize, Serialize};
+
+/// 解码后的合约事件
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChainEvent {
+    Sequencer(SequencerEvents),
+    OrderBook(OrderBookEvents),
+    Account(AccountEvents),
+}
+
+impl ChainEvent {
+    /// 事件所属的交易对；余额、配置类事件没有交易对
+    pub fn trading_pair(&self) -> Option<[u8; 32]> {
+        match self {
+            ChainEvent::Sequencer(event) => match event {
+                SequencerEvents::PlaceOrderRequestedFilter(e) => Some(e.trading_pair),
+                SequencerEvents::RemoveOrderRequestedFilter(e) => Some(e.trading_pair),
+                _ => None,
+            },
+            ChainEvent::OrderBook(event) => match event {
+                OrderBookEvents::MarketOrderInsertedFilter(e) => Some(e.trading_pair),
+                OrderBookEvents::MarketOrderRemovedFilter(e) => Some(e.trading_pair),
+                OrderBookEvents::OrderFilledFilter(e) => Some(e.trading_pair),
+                OrderBookEvents::OrderInsertedFilter(e) => Some(e.trading_pair),
+                OrderBookEvents::OrderRemovedFilter(e) => Some(e.trading_pair),
+                OrderBookEvents::PriceLevelCreatedFilter(e) => Some(e.trading_pair),
+                OrderBookEvents::PriceLevelRemovedFilter(e) => Some(e.trading_pair),
+                OrderBookEvents::TradeFilter(e) => Some(e.trading_pair),
+                _ => None,
+            },
+            ChainEvent::Account(AccountEvents::TradingPairRegisteredFilter(e)) => Some(e.trading_pair),
+            ChainEvent::Account(_) => None,
+        }
+    }
+}
+
+/// 需要监听的合约地址
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ContractAddresses {
+    pub sequencer: Address,
+    pub orderbook: Address,
+    pub account: Address,
+}
+
+impl ContractAddresses {
+    pub fn all(&self) -> Vec<Address> {
+        vec![self.sequencer, self.orderbook, self.account]
+    }
+}
+
+/// 事件所在的链上位置
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EventMeta {
+    pub block_number: u64,
+    pub block_hash: H256,
+    pub tx_hash: H256,
+    pub log_index: U256,
+    /// 区块时间戳（秒），日志本身不带，由同步器查询区块后填入，未知时为 0
+    #[serde(default)]
+    pub block_timestamp: u64,
+}
+
+impl EventMeta {
+    pub fn stamp(&self) -> BlockStamp {
+        BlockStamp {
+            timestamp: self.block_timestamp,
+            block_number: self.block_number,
+            tx_hash: self.tx_hash,
+        }
+    }
+}
+
+/// 带元数据的事件
+#[derive(Debug, Clone)]
+pub struct DecodedLog {
+    pub meta: EventMeta,
+    pub event: ChainEvent,
+}
+
+impl DecodedLog {
+    /// 同一条日志（reorg 时用于匹配被移除的日志）
+    pub fn is_same_log(&self, other: &EventMeta) -> bool {
+        self.meta.tx_hash == other.tx_hash && self.meta.log_index == other.log_index
+    }
+}
+
+/// 解码单条日志；非目标合约、未上链或无法识别的日志返回 None
+pub fn decode_log(log: &Log, contracts: &ContractAddresses) -> Option<DecodedLog> {
+    let meta = EventMeta {
+        block_number: log.block_number?.as_u64(),
+        block_hash: log.block_hash?,
+        tx_hash: log.transaction_hash?,
+        log_index: log.log_index?,
+        block_timestamp: 0,
+    };
+
+    let raw = RawLog::from(log.clone());
+    let event = if log.address == contracts.sequencer {
+        ChainEvent::Sequencer(SequencerEvents::decode_log(&raw).ok()?)
+    } else if log.address == contracts.orderbook {
+        ChainEvent::OrderBook(OrderBookEvents::decode_log(&raw).ok()?)
+    } else if log.address == contracts.account {
+        ChainEvent::Account(AccountEvents::decode_log(&raw).ok()?)
+    } else {
+        return None;
+    };
+
+    Some(DecodedLog { meta, event })
+}
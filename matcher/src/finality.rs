@@ -0,0 +1,136 @@
+//! 确认深度跟踪
+//!
+//! 事件到达后立即作用于乐观（head）视图，同时缓存在这里；
+//! 当事件所在区块之上累计 `confirmations` 个区块后，才交给确认（confirmed）视图。
+//! 若节点推送了 `removed = true` 的日志（链重组），对应事件从缓存中剔除，
+//! head 视图由 confirmed 视图加上剩余缓存事件重建。
+
+use crate::events::{DecodedLog, EventMeta};
+use std::collections::VecDeque;
+
+/// 等待确认的事件缓存（按到达顺序）
+#[derive(Debug)]
+pub struct ConfirmationTracker {
+    confirmations: u64,
+    pending: VecDeque<DecodedLog>,
+}
+
+impl ConfirmationTracker {
+    pub fn new(confirmations: u64) -> Self {
+        Self {
+            confirmations,
+            pending: VecDeque::new(),
+        }
+    }
+
+    pub fn confirmations(&self) -> u64 {
+        self.confirmations
+    }
+
+    /// 缓存一条新事件
+    pub fn push(&mut self, log: DecodedLog) {
+        self.pending.push_back(log);
+    }
+
+    /// 移除被重组掉的事件，返回是否在缓存中找到
+    /// 找不到说明重组深度超过了 confirmations，confirmed 视图已无法回滚
+    pub fn remove(&mut self, meta: &EventMeta) -> bool {
+        let before = self.pending.len();
+        self.pending.retain(|log| !log.is_same_log(meta));
+        self.pending.len() != before
+    }
+
+    /// 给定最新区块高度，取出所有已达到确认深度的事件（保持原有顺序）
+    pub fn drain_confirmed(&mut self, head_block: u64) -> Vec<DecodedLog> {
+        let Some(confirmed_block) = self.confirmed_block(head_block) else {
+            return Vec::new();
+        };
+
+        let mut confirmed = Vec::new();
+        while let Some(log) = self.pending.front() {
+            if log.meta.block_number > confirmed_block {
+                break;
+            }
+            confirmed.extend(self.pending.pop_front());
+        }
+        confirmed
+    }
+
+    /// head 高度对应的已确认区块高度
+    pub fn confirmed_block(&self, head_block: u64) -> Option<u64> {
+        head_block.checked_sub(self.confirmations)
+    }
+
+    /// 尚未确认的事件
+    pub fn pending(&self) -> impl Iterator<Item = &DecodedLog> {
+        self.pending.iter()
+    }
+
+    pub fn pending_len(&self) -> usize {
+        self.pending.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::contracts::order_book::{OrderBookEvents, OrderRemovedFilter};
+    use crate::events::ChainEvent;
+    use ethers::types::{H256, U256};
+
+    fn log_at(block_number: u64, log_index: u64) -> DecodedLog {
+        DecodedLog {
+            meta: EventMeta {
+                block_number,
+                block_hash: H256::from_low_u64_be(block_number),
+                tx_hash: H256::from_low_u64_be(block_number * 1000 + log_index),
+                log_index: U256::from(log_index),
+                block_timestamp: 0,
+            },
+            event: ChainEvent::OrderBook(OrderBookEvents::OrderRemovedFilter(OrderRemovedFilter {
+                trading_pair: [0u8; 32],
+                order_id: U256::from(log_index),
+            })),
+        }
+    }
+
+    #[test]
+    fn test_drain_respects_depth() {
+        let mut tracker = ConfirmationTracker::new(2);
+        tracker.push(log_at(10, 0));
+        tracker.push(log_at(11, 0));
+        tracker.push(log_at(12, 0));
+
+        // head=11 -> confirmed=9，没有事件确认
+        assert!(tracker.drain_confirmed(11).is_empty());
+
+        // head=13 -> confirmed=11
+        let confirmed = tracker.drain_confirmed(13);
+        assert_eq!(confirmed.len(), 2);
+        assert_eq!(confirmed[0].meta.block_number, 10);
+        assert_eq!(confirmed[1].meta.block_number, 11);
+        assert_eq!(tracker.pending_len(), 1);
+    }
+
+    #[test]
+    fn test_zero_confirmations_drains_immediately() {
+        let mut tracker = ConfirmationTracker::new(0);
+        tracker.push(log_at(5, 0));
+        assert_eq!(tracker.drain_confirmed(5).len(), 1);
+        assert_eq!(tracker.pending_len(), 0);
+    }
+
+    #[test]
+    fn test_remove_reorged_log() {
+        let mut tracker = ConfirmationTracker::new(3);
+        let reorged = log_at(20, 1);
+        tracker.push(log_at(20, 0));
+        tracker.push(reorged.clone());
+
+        assert!(tracker.remove(&reorged.meta));
+        assert_eq!(tracker.pending_len(), 1);
+
+        // 已经不在缓存中：重组深度超过确认深度
+        assert!(!tracker.remove(&reorged.meta));
+    }
+}
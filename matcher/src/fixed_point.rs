@@ -0,0 +1,310 @@
+//! 定点数类型 - 带 TradingConstants 精度的价格与数量
+//!
+//! - Price：价格，精度 PRICE_DECIMALS（10^8）
+//! - Amount：基础代币数量，精度 AMOUNT_DECIMALS（10^8）
+//! - QuoteAmount：计价代币数量（市价买单的 amount），精度 AMOUNT_DECIMALS
+//!
+//! 三者只能与同类型相加减；换算只能通过 Amount * Price 和 QuoteAmount / Price，
+//! 运算顺序与取整方式和 OrderBook._executeTrade 一致（先乘后除，向下取整）；
+//! 市价单由 _matchMarketOrdersInternal 以对手价调用 _executeTrade 成交。
+//! Display / FromStr 使用小数形式，如 "2010.5"。
+
+use crate::constants::{AMOUNT_DECIMALS, PRICE_DECIMALS};
+use ethers::types::U256;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::ops::{Add, Sub};
+use std::str::FromStr;
+use thiserror::Error;
+
+/// 小数位数（PRICE_DECIMALS 与 AMOUNT_DECIMALS 都是 10^8）
+pub const DECIMAL_PLACES: usize = 8;
+
+/// 定点数运算失败的原因
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Error)]
+pub enum FixedPointError {
+    #[error("arithmetic overflow")]
+    Overflow,
+
+    #[error("division by zero")]
+    DivisionByZero,
+}
+
+/// 解析小数字符串失败的原因
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum ParseFixedError {
+    #[error("empty number")]
+    Empty,
+
+    #[error("invalid digit in {0:?}")]
+    InvalidDigit(String),
+
+    #[error("{0:?} has more than {DECIMAL_PLACES} decimal places")]
+    TooManyDecimals(String),
+
+    #[error("{0:?} is out of range")]
+    Overflow(String),
+}
+
+/// a * b / c，先乘后除，向下取整（与 Solidity 的整数运算一致）
+fn mul_div(a: U256, b: U256, c: U256) -> Result<U256, FixedPointError> {
+    a.checked_mul(b)
+        .ok_or(FixedPointError::Overflow)?
+        .checked_div(c)
+        .ok_or(FixedPointError::DivisionByZero)
+}
+
+/// 把带 DECIMAL_PLACES 位小数的定点数格式化为小数，保留全部小数位
+fn format_fixed(raw: U256, scale: U256, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    let text = format!(
+        "{}.{:0>width$}",
+        raw / scale,
+        (raw % scale).to_string(),
+        width = DECIMAL_PLACES
+    );
+    f.pad(&text)
+}
+
+/// 解析 "123"、"123.45"、".5" 形式的非负小数
+fn parse_fixed(s: &str, scale: U256) -> Result<U256, ParseFixedError> {
+    let (whole, fraction) = s.split_once('.').unwrap_or((s, ""));
+    if whole.is_empty() && fraction.is_empty() {
+        return Err(ParseFixedError::Empty);
+    }
+    if !whole.chars().chain(fraction.chars()).all(|c| c.is_ascii_digit()) {
+        return Err(ParseFixedError::InvalidDigit(s.to_string()));
+    }
+    if fraction.len() > DECIMAL_PLACES {
+        return Err(ParseFixedError::TooManyDecimals(s.to_string()));
+    }
+
+    let overflow = || ParseFixedError::Overflow(s.to_string());
+    let whole = if whole.is_empty() {
+        U256::zero()
+    } else {
+        U256::from_dec_str(whole).map_err(|_| overflow())?
+    };
+    let fraction = if fraction.is_empty() {
+        U256::zero()
+    } else {
+        let padded = format!("{:0<width$}", fraction, width = DECIMAL_PLACES);
+        U256::from_dec_str(&padded).map_err(|_| overflow())?
+    };
+
+    whole
+        .checked_mul(scale)
+        .and_then(|whole| whole.checked_add(fraction))
+        .ok_or_else(overflow)
+}
+
+macro_rules! fixed_point_type {
+    ($(#[$meta:meta])* $name:ident, $scale:expr) => {
+        $(#[$meta])*
+        #[derive(
+            Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize,
+        )]
+        #[serde(transparent)]
+        pub struct $name(U256);
+
+        impl $name {
+            /// 精度（1.0 对应的原始值）
+            pub const SCALE: U256 = $scale;
+
+            /// 从链上的原始整数构造
+            pub const fn from_raw(raw: U256) -> Self {
+                Self(raw)
+            }
+
+            /// 链上的原始整数
+            pub const fn raw(self) -> U256 {
+                self.0
+            }
+
+            pub fn zero() -> Self {
+                Self(U256::zero())
+            }
+
+            pub fn is_zero(self) -> bool {
+                self.0.is_zero()
+            }
+
+            /// 整数个单位（如 Price::from_units(2010) 即 2010.0）
+            pub fn from_units(units: u64) -> Self {
+                Self(U256::from(units) * $scale)
+            }
+
+            pub fn checked_add(self, other: Self) -> Option<Self> {
+                self.0.checked_add(other.0).map(Self)
+            }
+
+            pub fn checked_sub(self, other: Self) -> Option<Self> {
+                self.0.checked_sub(other.0).map(Self)
+            }
+
+            pub fn saturating_sub(self, other: Self) -> Self {
+                Self(self.0.saturating_sub(other.0))
+            }
+        }
+
+        impl Add for $name {
+            type Output = Self;
+
+            fn add(self, other: Self) -> Self {
+                Self(self.0 + other.0)
+            }
+        }
+
+        impl Sub for $name {
+            type Output = Self;
+
+            fn sub(self, other: Self) -> Self {
+                Self(self.0 - other.0)
+            }
+        }
+
+        impl std::iter::Sum for $name {
+            fn sum<I: Iterator<Item = Self>>(iter: I) -> Self {
+                iter.fold(Self::zero(), Add::add)
+            }
+        }
+
+        impl fmt::Display for $name {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                format_fixed(self.0, $scale, f)
+            }
+        }
+
+        impl FromStr for $name {
+            type Err = ParseFixedError;
+
+            fn from_str(s: &str) -> Result<Self, ParseFixedError> {
+                parse_fixed(s, $scale).map(Self)
+            }
+        }
+    };
+}
+
+fixed_point_type!(
+    /// 价格（每个基础代币值多少计价代币），精度 PRICE_DECIMALS
+    Price,
+    PRICE_DECIMALS
+);
+
+fixed_point_type!(
+    /// 基础代币数量，精度 AMOUNT_DECIMALS
+    Amount,
+    AMOUNT_DECIMALS
+);
+
+fixed_point_type!(
+    /// 计价代币数量，精度 AMOUNT_DECIMALS
+    QuoteAmount,
+    AMOUNT_DECIMALS
+);
+
+impl Amount {
+    /// 按价格换算成计价代币：amount * price / PRICE_DECIMALS，向下取整
+    /// （对应 _executeTrade 中市价买单的 quoteSpent = tradeAmount * tradePrice / PRICE_DECIMALS）
+    pub fn checked_mul_price(self, price: Price) -> Result<QuoteAmount, FixedPointError> {
+        mul_div(self.0, price.0, PRICE_DECIMALS).map(QuoteAmount)
+    }
+}
+
+impl QuoteAmount {
+    /// 按价格能买到的基础代币：quote * PRICE_DECIMALS / price，向下取整
+    /// （对应 _executeTrade 中市价买单的 bidRemaining = quoteRemaining * PRICE_DECIMALS / tradePrice）
+    pub fn checked_div_price(self, price: Price) -> Result<Amount, FixedPointError> {
+        mul_div(self.0, PRICE_DECIMALS, price.0).map(Amount)
+    }
+
+    /// 成交均价：quote * PRICE_DECIMALS / amount，向下取整
+    pub fn checked_div_amount(self, amount: Amount) -> Result<Price, FixedPointError> {
+        mul_div(self.0, PRICE_DECIMALS, amount.0).map(Price)
+    }
+}
+
+/// 下单数量：限价单与市价卖单为基础代币，市价买单为要花费的计价代币
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OrderSize {
+    Base(Amount),
+    Quote(QuoteAmount),
+}
+
+impl OrderSize {
+    /// 按订单类型解释链上的 amount
+    pub fn new(is_market_order: bool, is_ask: bool, raw: U256) -> Self {
+        if is_market_order && !is_ask {
+            Self::Quote(QuoteAmount::from_raw(raw))
+        } else {
+            Self::Base(Amount::from_raw(raw))
+        }
+    }
+
+    /// 链上的原始整数
+    pub fn raw(self) -> U256 {
+        match self {
+            Self::Base(amount) => amount.raw(),
+            Self::Quote(quote) => quote.raw(),
+        }
+    }
+}
+
+impl fmt::Display for OrderSize {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Base(amount) => write!(f, "{} base", amount),
+            Self::Quote(quote) => write!(f, "{} quote", quote),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_display_and_parse() {
+        let price: Price = "2010.5".parse().unwrap();
+        assert_eq!(price.raw(), U256::from(201_050_000_000u64));
+        assert_eq!(price.to_string(), "2010.50000000");
+        assert_eq!(format!("{:>16}", Amount::from_raw(U256::one())), "      0.00000001");
+
+        assert_eq!(".25".parse::<Amount>().unwrap(), Amount::from_raw(U256::from(25_000_000)));
+        assert_eq!("7".parse::<QuoteAmount>().unwrap(), QuoteAmount::from_units(7));
+        assert_eq!(
+            Price::from_str(&Price::from_raw(U256::MAX).to_string()).unwrap(),
+            Price::from_raw(U256::MAX)
+        );
+
+        assert_eq!("".parse::<Price>(), Err(ParseFixedError::Empty));
+        assert_eq!(".".parse::<Price>(), Err(ParseFixedError::Empty));
+        assert!(matches!("-1".parse::<Price>(), Err(ParseFixedError::InvalidDigit(_))));
+        assert!(matches!("1.2.3".parse::<Price>(), Err(ParseFixedError::InvalidDigit(_))));
+        assert!(matches!("0.000000001".parse::<Price>(), Err(ParseFixedError::TooManyDecimals(_))));
+        let too_big = format!("{}0", U256::MAX);
+        assert!(matches!(too_big.parse::<Price>(), Err(ParseFixedError::Overflow(_))));
+    }
+
+    #[test]
+    fn test_conversions_round_down_like_the_contract() {
+        let price: Price = "3".parse().unwrap();
+        let quote: QuoteAmount = "10".parse().unwrap();
+
+        // 10 / 3 = 3.33333333（向下取整）
+        let base = quote.checked_div_price(price).unwrap();
+        assert_eq!(base.to_string(), "3.33333333");
+        // 3.33333333 * 3 = 9.99999999，剩余 0.00000001 quote
+        let spent = base.checked_mul_price(price).unwrap();
+        assert_eq!(quote - spent, QuoteAmount::from_raw(U256::one()));
+
+        assert_eq!(spent.checked_div_amount(base).unwrap(), price);
+        assert_eq!(
+            quote.checked_div_price(Price::zero()),
+            Err(FixedPointError::DivisionByZero)
+        );
+        assert_eq!(
+            Amount::from_raw(U256::MAX).checked_mul_price(price),
+            Err(FixedPointError::Overflow)
+        );
+    }
+}
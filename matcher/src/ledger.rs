@@ -0,0 +1,485 @@
+//! 账户余额镜像
+//!
+//! 镜像 Account 合约的 `balances[user][token]`（available / locked），
+//! 启动时从快照读取，之后按 Deposit / Withdraw / FundsLocked / FundsUnlocked /
+//! FundsTransferred / FeeCollected 事件增量更新。
+//!
+//! `transferFunds` 的事件不能单独解释：
+//! - 第一条 FundsTransferred(buyer, seller, baseToken, amount) 中的 amount 是带
+//!   AMOUNT_DECIMALS 精度的成交数量，需要按 baseToken 的 decimals 换算成代币数量
+//! - 第二条 FundsTransferred(buyer, seller, quoteToken, sellerReceives) 是卖方实收
+//! - 随后的 FeeCollected 依次为买方费用和卖方费用：买方 locked 共需扣除
+//!   quoteAmount + buyerFee = sellerReceives + sellerFee + buyerFee，两笔费用都从买方 locked 扣除
+//!
+//! 因此这里按事件顺序维护一个进行中的成交（`PendingTransfer`）。
+
+use crate::contracts::account::AccountEvents;
+use crate::settlement::{token_amount, PairDecimals};
+use ethers::types::{Address, U256};
+use std::collections::HashMap;
+use tracing::warn;
+
+/// 单个用户在单个代币上的余额
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Balance {
+    pub available: U256,
+    pub locked: U256,
+}
+
+impl Balance {
+    /// available + locked；溢出（事件流异常）时返回 None
+    pub fn total(&self) -> Option<U256> {
+        self.available.checked_add(self.locked)
+    }
+}
+
+/// 正在解析的 transferFunds 事件组
+#[derive(Debug, Clone, Copy)]
+struct PendingTransfer {
+    buyer: Address,
+    seller: Address,
+    /// 是否已收到计价代币的 FundsTransferred
+    quote_seen: bool,
+    /// 是否已收到买方的 FeeCollected
+    buyer_fee_seen: bool,
+}
+
+/// Account 合约余额的本地镜像
+#[derive(Debug, Clone, Default)]
+pub struct Ledger {
+    /// (user, token) -> Balance
+    balances: HashMap<(Address, Address), Balance>,
+    /// token -> decimals
+    decimals: HashMap<Address, u8>,
+    /// token -> 已收取的交易费用
+    collected_fees: HashMap<Address, U256>,
+    pending_transfer: Option<PendingTransfer>,
+}
+
+impl Ledger {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 记录代币精度（换算 FundsTransferred 中的成交数量需要）
+    pub fn set_decimals(&mut self, token: Address, decimals: u8) {
+        self.decimals.insert(token, decimals);
+    }
+
+    pub fn decimals(&self, token: &Address) -> Option<u8> {
+        self.decimals.get(token).copied()
+    }
+
+    /// 交易对两种代币的精度（用于结算模拟）
+    pub fn pair_decimals(&self, base_token: &Address, quote_token: &Address) -> Option<PairDecimals> {
+        Some(PairDecimals {
+            base: self.decimals(base_token)?,
+            quote: self.decimals(quote_token)?,
+        })
+    }
+
+    /// 设置快照余额
+    pub fn set_balance(&mut self, user: Address, token: Address, balance: Balance) {
+        self.balances.insert((user, token), balance);
+    }
+
+    /// 设置快照中的已收取费用
+    pub fn set_collected_fees(&mut self, token: Address, amount: U256) {
+        self.collected_fees.insert(token, amount);
+    }
+
+    /// 查询余额（未出现过的用户返回 0）
+    pub fn balance(&self, user: Address, token: Address) -> Balance {
+        self.balances
+            .get(&(user, token))
+            .copied()
+            .unwrap_or_default()
+    }
+
+    pub fn available(&self, user: Address, token: Address) -> U256 {
+        self.balance(user, token).available
+    }
+
+    pub fn locked(&self, user: Address, token: Address) -> U256 {
+        self.balance(user, token).locked
+    }
+
+    /// 某个用户的全部余额
+    pub fn balances_of(&self, user: Address) -> Vec<(Address, Balance)> {
+        self.balances
+            .iter()
+            .filter(|((owner, _), _)| *owner == user)
+            .map(|((_, token), balance)| (*token, *balance))
+            .collect()
+    }
+
+    pub fn collected_fees(&self, token: Address) -> U256 {
+        self.collected_fees.get(&token).copied().unwrap_or_default()
+    }
+
+    /// 应用一条 Account 事件
+    pub fn apply_event(&mut self, event: &AccountEvents) {
+        match event {
+            AccountEvents::DepositFilter(deposit) => {
+                self.credit_available(deposit.user, deposit.token, deposit.amount);
+            }
+
+            AccountEvents::WithdrawFilter(withdraw) => {
+                self.debit_available(withdraw.user, withdraw.token, withdraw.amount);
+            }
+
+            AccountEvents::FundsLockedFilter(locked) => {
+                self.debit_available(locked.user, locked.token, locked.amount);
+                self.credit_locked(locked.user, locked.token, locked.amount);
+            }
+
+            AccountEvents::FundsUnlockedFilter(unlocked) => {
+                self.debit_locked(unlocked.user, unlocked.token, unlocked.amount);
+                self.credit_available(unlocked.user, unlocked.token, unlocked.amount);
+            }
+
+            AccountEvents::FundsTransferredFilter(transferred) => {
+                let quote_leg = matches!(
+                    self.pending_transfer,
+                    Some(p) if !p.quote_seen && p.buyer == transferred.from && p.seller == transferred.to
+                );
+
+                if quote_leg {
+                    // 计价代币：卖方实收进入 available，买方 locked 扣除同等数量（两笔费用稍后扣除）
+                    self.debit_locked(transferred.from, transferred.token, transferred.amount);
+                    self.credit_available(transferred.to, transferred.token, transferred.amount);
+                    if let Some(pending) = self.pending_transfer.as_mut() {
+                        pending.quote_seen = true;
+                    }
+                } else {
+                    // 基础代币：amount 为带精度的成交数量
+                    let base_amount = self.scale_amount(transferred.token, transferred.amount);
+                    self.debit_locked(transferred.to, transferred.token, base_amount);
+                    self.credit_available(transferred.from, transferred.token, base_amount);
+                    self.pending_transfer = Some(PendingTransfer {
+                        buyer: transferred.from,
+                        seller: transferred.to,
+                        quote_seen: false,
+                        buyer_fee_seen: false,
+                    });
+                }
+            }
+
+            AccountEvents::FeeCollectedFilter(fee) => {
+                let fees = self.collected_fees.entry(fee.token).or_default();
+                match fees.checked_add(fee.amount) {
+                    Some(total) => *fees = total,
+                    None => warn!(
+                        "Collected fees overflow: token={:?}, have={}, add={}, skipped",
+                        fee.token, fees, fee.amount
+                    ),
+                }
+
+                match self.pending_transfer.as_mut() {
+                    // 买方费用：从买方 locked 扣除
+                    Some(pending) if !pending.buyer_fee_seen && pending.buyer == fee.payer => {
+                        pending.buyer_fee_seen = true;
+                        let buyer = pending.buyer;
+                        self.debit_locked(buyer, fee.token, fee.amount);
+                    }
+                    // 卖方费用：卖方实收中已扣除，这部分 quoteAmount 同样从买方 locked 支付
+                    Some(pending) if pending.seller == fee.payer => {
+                        let buyer = pending.buyer;
+                        self.pending_transfer = None;
+                        self.debit_locked(buyer, fee.token, fee.amount);
+                    }
+                    _ => warn!("FeeCollected without matching transfer: payer={:?}", fee.payer),
+                }
+            }
+
+            AccountEvents::FeeWithdrawnFilter(withdrawn) => {
+                let fees = self.collected_fees.entry(withdrawn.token).or_default();
+                *fees = fees.saturating_sub(withdrawn.amount);
+            }
+
+            _ => {}
+        }
+    }
+
+    /// 带 AMOUNT_DECIMALS 精度的数量换算为代币最小单位
+    fn scale_amount(&self, token: Address, amount: U256) -> U256 {
+        match self.decimals(&token) {
+            Some(decimals) => token_amount(amount, decimals).unwrap_or_else(|| {
+                warn!("Amount {} of token {:?} overflows when scaled, ledger may be inaccurate", amount, token);
+                U256::MAX
+            }),
+            None => {
+                warn!("Unknown decimals for token {:?}, ledger may be inaccurate", token);
+                amount
+            }
+        }
+    }
+
+    fn entry(&mut self, user: Address, token: Address) -> &mut Balance {
+        self.balances.entry((user, token)).or_default()
+    }
+
+    fn credit_available(&mut self, user: Address, token: Address, amount: U256) {
+        let balance = self.entry(user, token);
+        match balance.available.checked_add(amount) {
+            Some(available) => balance.available = available,
+            None => warn!(
+                "Ledger available overflow: user={:?}, token={:?}, have={}, add={}, skipped",
+                user, token, balance.available, amount
+            ),
+        }
+    }
+
+    fn credit_locked(&mut self, user: Address, token: Address, amount: U256) {
+        let balance = self.entry(user, token);
+        match balance.locked.checked_add(amount) {
+            Some(locked) => balance.locked = locked,
+            None => warn!(
+                "Ledger locked overflow: user={:?}, token={:?}, have={}, add={}, skipped",
+                user, token, balance.locked, amount
+            ),
+        }
+    }
+
+    fn debit_available(&mut self, user: Address, token: Address, amount: U256) {
+        let balance = self.entry(user, token);
+        if balance.available < amount {
+            warn!(
+                "Ledger available underflow: user={:?}, token={:?}, have={}, need={}",
+                user, token, balance.available, amount
+            );
+        }
+        balance.available = balance.available.saturating_sub(amount);
+    }
+
+    fn debit_locked(&mut self, user: Address, token: Address, amount: U256) {
+        let balance = self.entry(user, token);
+        if balance.locked < amount {
+            warn!(
+                "Ledger locked underflow: user={:?}, token={:?}, have={}, need={}",
+                user, token, balance.locked, amount
+            );
+        }
+        balance.locked = balance.locked.saturating_sub(amount);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::constants::AMOUNT_DECIMALS;
+    use crate::contracts::account::{
+        DepositFilter, FeeCollectedFilter, FundsLockedFilter, FundsTransferredFilter,
+        FundsUnlockedFilter, WithdrawFilter,
+    };
+
+    fn addr(n: u64) -> Address {
+        Address::from_low_u64_be(n)
+    }
+
+    const WETH: u64 = 100;
+    const USDC: u64 = 200;
+
+    fn ledger() -> Ledger {
+        let mut ledger = Ledger::new();
+        ledger.set_decimals(addr(WETH), 18);
+        ledger.set_decimals(addr(USDC), 6);
+        ledger
+    }
+
+    #[test]
+    fn test_deposit_lock_unlock_withdraw() {
+        let mut ledger = ledger();
+        let user = addr(1);
+        let usdc = addr(USDC);
+
+        ledger.apply_event(&AccountEvents::DepositFilter(DepositFilter {
+            user,
+            token: usdc,
+            amount: U256::from(1_000_000),
+        }));
+        ledger.apply_event(&AccountEvents::FundsLockedFilter(FundsLockedFilter {
+            user,
+            token: usdc,
+            amount: U256::from(400_000),
+            order_id: U256::from(1),
+        }));
+        assert_eq!(ledger.available(user, usdc), U256::from(600_000));
+        assert_eq!(ledger.locked(user, usdc), U256::from(400_000));
+
+        ledger.apply_event(&AccountEvents::FundsUnlockedFilter(FundsUnlockedFilter {
+            user,
+            token: usdc,
+            amount: U256::from(100_000),
+            order_id: U256::from(1),
+        }));
+        ledger.apply_event(&AccountEvents::WithdrawFilter(WithdrawFilter {
+            user,
+            token: usdc,
+            amount: U256::from(200_000),
+        }));
+        assert_eq!(ledger.available(user, usdc), U256::from(500_000));
+        assert_eq!(ledger.locked(user, usdc), U256::from(300_000));
+        assert_eq!(ledger.balance(user, usdc).total(), Some(U256::from(800_000)));
+    }
+
+    #[test]
+    fn test_transfer_funds_sequence() {
+        let mut ledger = ledger();
+        let buyer = addr(1);
+        let seller = addr(2);
+        let weth = addr(WETH);
+        let usdc = addr(USDC);
+
+        // 成交 1 WETH @ 2000 USDC：quoteAmount = 2000e6，双方费用各 2e6
+        let quote_amount = U256::from(2_000_000_000u64);
+        let fee = U256::from(2_000_000u64);
+        ledger.set_balance(
+            buyer,
+            usdc,
+            Balance {
+                available: U256::zero(),
+                locked: quote_amount + fee,
+            },
+        );
+        ledger.set_balance(
+            seller,
+            weth,
+            Balance {
+                available: U256::zero(),
+                locked: U256::exp10(18),
+            },
+        );
+
+        ledger.apply_event(&AccountEvents::FundsTransferredFilter(FundsTransferredFilter {
+            from: buyer,
+            to: seller,
+            token: weth,
+            amount: AMOUNT_DECIMALS,
+        }));
+        ledger.apply_event(&AccountEvents::FundsTransferredFilter(FundsTransferredFilter {
+            from: buyer,
+            to: seller,
+            token: usdc,
+            amount: quote_amount - fee,
+        }));
+        ledger.apply_event(&AccountEvents::FeeCollectedFilter(FeeCollectedFilter {
+            token: usdc,
+            amount: fee,
+            payer: buyer,
+        }));
+        ledger.apply_event(&AccountEvents::FeeCollectedFilter(FeeCollectedFilter {
+            token: usdc,
+            amount: fee,
+            payer: seller,
+        }));
+
+        assert_eq!(ledger.balance(buyer, usdc), Balance::default());
+        assert_eq!(ledger.available(buyer, weth), U256::exp10(18));
+        assert_eq!(ledger.balance(seller, weth), Balance::default());
+        assert_eq!(ledger.available(seller, usdc), quote_amount - fee);
+        assert_eq!(ledger.collected_fees(usdc), fee * 2);
+    }
+
+    #[test]
+    fn test_self_trade_transfer() {
+        let mut ledger = ledger();
+        let trader = addr(1);
+        let weth = addr(WETH);
+        let usdc = addr(USDC);
+
+        let quote_amount = U256::from(1_000_000u64);
+        let fee = U256::from(1_000u64);
+        ledger.set_balance(
+            trader,
+            usdc,
+            Balance {
+                available: U256::zero(),
+                locked: quote_amount + fee + U256::from(5),
+            },
+        );
+        ledger.set_balance(
+            trader,
+            weth,
+            Balance {
+                available: U256::zero(),
+                locked: U256::exp10(18),
+            },
+        );
+
+        for event in [
+            AccountEvents::FundsTransferredFilter(FundsTransferredFilter {
+                from: trader,
+                to: trader,
+                token: weth,
+                amount: AMOUNT_DECIMALS,
+            }),
+            AccountEvents::FundsTransferredFilter(FundsTransferredFilter {
+                from: trader,
+                to: trader,
+                token: usdc,
+                amount: quote_amount - fee,
+            }),
+            AccountEvents::FeeCollectedFilter(FeeCollectedFilter {
+                token: usdc,
+                amount: fee,
+                payer: trader,
+            }),
+            AccountEvents::FeeCollectedFilter(FeeCollectedFilter {
+                token: usdc,
+                amount: fee,
+                payer: trader,
+            }),
+        ] {
+            ledger.apply_event(&event);
+        }
+
+        // 买卖双方是同一地址，两笔费用按顺序区分
+        assert_eq!(ledger.locked(trader, usdc), U256::from(5));
+        assert_eq!(ledger.available(trader, usdc), quote_amount - fee);
+        assert_eq!(ledger.available(trader, weth), U256::exp10(18));
+        assert_eq!(ledger.collected_fees(usdc), fee * 2);
+    }
+
+    #[test]
+    fn test_overflowing_credit_is_skipped() {
+        let mut ledger = ledger();
+        let user = addr(1);
+        let usdc = addr(USDC);
+
+        ledger.apply_event(&AccountEvents::DepositFilter(DepositFilter {
+            user,
+            token: usdc,
+            amount: U256::MAX,
+        }));
+        ledger.apply_event(&AccountEvents::DepositFilter(DepositFilter {
+            user,
+            token: usdc,
+            amount: U256::one(),
+        }));
+        assert_eq!(ledger.available(user, usdc), U256::MAX);
+
+        ledger.apply_event(&AccountEvents::FundsLockedFilter(FundsLockedFilter {
+            user,
+            token: usdc,
+            amount: U256::from(10),
+            order_id: U256::from(1),
+        }));
+        assert_eq!(ledger.balance(user, usdc).total(), Some(U256::MAX));
+        ledger.apply_event(&AccountEvents::DepositFilter(DepositFilter {
+            user,
+            token: usdc,
+            amount: U256::from(10),
+        }));
+        assert_eq!(ledger.available(user, usdc), U256::MAX);
+        assert_eq!(ledger.balance(user, usdc).total(), None);
+
+        ledger.set_collected_fees(usdc, U256::MAX);
+        ledger.apply_event(&AccountEvents::FeeCollectedFilter(FeeCollectedFilter {
+            token: usdc,
+            amount: U256::one(),
+            payer: user,
+        }));
+        assert_eq!(ledger.collected_fees(usdc), U256::MAX);
+    }
+}
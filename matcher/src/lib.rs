@@ -0,0 +1,27 @@
+pub mod archive;
+pub mod book_dump;
+pub mod chain_source;
+pub mod config;
+pub mod constants;
+pub mod contracts;
+pub mod depth;
+#[cfg(test)]
+mod differential;
+pub mod events;
+pub mod finality;
+pub mod fixed_point;
+pub mod ledger;
+pub mod matcher;
+pub mod orderbook_simulator;
+pub mod quote;
+#[cfg(test)]
+mod reference_matcher;
+pub mod replay;
+pub mod request_queue;
+pub mod self_trade;
+pub mod settlement;
+pub mod state;
+pub mod sync;
+pub mod trade_history;
+pub mod transport;
+pub mod types;
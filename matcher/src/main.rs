@@ -1,19 +1,16 @@
-mod config;
-mod contracts;
-mod matcher;
-mod orderbook_simulator;
-mod state;
-mod sync;
-mod types;
-
 use anyhow::Result;
 use clap::Parser;
 use tracing::{info, Level};
-use tracing_subscriber;
 
-use crate::config::Config;
-use crate::matcher::MatchingEngine;
-use crate::sync::StateSynchronizer;
+use matcher::chain_source::FileSource;
+use matcher::config::{parse_trading_pair, Config, StateView};
+use matcher::matcher::MatchingEngine;
+use matcher::orderbook_simulator::OrderBookSimulator;
+use matcher::replay::Replayer;
+use matcher::state::GlobalState;
+use matcher::sync::StateSynchronizer;
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
 
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
@@ -29,6 +26,72 @@ struct Args {
     /// 起始区块号（覆盖配置文件）
     #[arg(short, long)]
     start_block: Option<u64>,
+
+    /// 收到 SIGUSR1 时导出订单簿的目录
+    #[arg(long, default_value = ".")]
+    dump_dir: PathBuf,
+
+    /// 离线重放事件归档（目录或单个文件），不连接节点；结束后输出统计并导出订单簿到 dump_dir
+    #[arg(long)]
+    replay: Option<PathBuf>,
+
+    /// 重放的起始订单簿（导出的 JSON），默认为空订单簿
+    #[arg(long, requires_all = ["replay", "replay_pair"])]
+    replay_orderbook: Option<PathBuf>,
+
+    /// 起始订单簿所属的交易对（0x 开头的 bytes32 或交易对名称）
+    #[arg(long, requires = "replay_orderbook")]
+    replay_pair: Option<String>,
+}
+
+/// 离线重放：同步器的事件处理和匹配引擎的批处理参数计算运行在归档事件上
+async fn replay(config: Config, args: &Args, path: &std::path::Path) -> Result<()> {
+    info!("⏪ Replaying archived events from {}", path.display());
+
+    let mut replayer = Replayer::new(config, FileSource::open(path)?)?;
+    if let (Some(orderbook), Some(pair)) = (&args.replay_orderbook, &args.replay_pair) {
+        let json = std::fs::read_to_string(orderbook)?;
+        replayer = replayer.with_orderbook(parse_trading_pair(pair)?, OrderBookSimulator::from_json(&json)?);
+        info!("  Starting from orderbook {} for pair {}", orderbook.display(), pair);
+    }
+
+    let state = replayer.state();
+    let report = replayer.run().await?;
+
+    info!("📊 Replay finished:\n{}", report);
+    for batch in report.mismatches() {
+        info!(
+            "  Mismatch in tx {:?} (block {}): computed {:?}, processed {:?}",
+            batch.tx_hash, batch.block_number, batch.predicted.order_ids, batch.processed
+        );
+    }
+    dump_orderbooks(&state, &args.dump_dir);
+    Ok(())
+}
+
+/// 导出每个交易对的 head / confirmed 订单簿：JSON 写入 dump_dir，价格阶梯输出到日志
+fn dump_orderbooks(state: &GlobalState, dump_dir: &std::path::Path) {
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default();
+
+    for (name, view) in [("head", StateView::Head), ("confirmed", StateView::Confirmed)] {
+        let mut books: Vec<_> = state.clone_orderbooks(view).into_iter().collect();
+        books.sort_by_key(|(pair, _)| *pair);
+        for (pair, book) in books {
+            let pair = format!("0x{}", ethers::utils::hex::encode(pair));
+            let path = dump_dir.join(format!("orderbook-{}-{}-{}.json", name, pair, timestamp));
+            match book.to_json().map_err(anyhow::Error::from).and_then(|json| {
+                std::fs::write(&path, json)?;
+                Ok(())
+            }) {
+                Ok(()) => info!("📖 {} orderbook {} dumped to {}", name, pair, path.display()),
+                Err(e) => tracing::error!("Failed to dump {} orderbook {}: {}", name, pair, e),
+            }
+            info!("📖 {} orderbook {} ladder:\n{}", name, pair, book.format_ladder(Some(20)));
+        }
+    }
 }
 
 #[tokio::main]
@@ -58,8 +121,17 @@ async fn main() -> Result<()> {
         config.sync.start_block = start_block;
     }
 
+    if let Some(path) = &args.replay {
+        return replay(config, &args, path).await;
+    }
+
     info!("📋 Configuration loaded:");
     info!("  RPC: {}", config.network.rpc_url);
+    info!(
+        "  Transport: {:?}",
+        config.network.resolve_transport()?
+    );
+    info!("  Fallback RPCs: {}", config.network.fallback_rpc_urls.len());
     info!("  Sequencer: {}", config.contracts.sequencer);
     info!("  OrderBook: {}", config.contracts.orderbook);
     info!("  Start Block: {}", config.sync.start_block);
@@ -71,6 +143,20 @@ async fn main() -> Result<()> {
     // 获取共享状态
     let state = synchronizer.state();
 
+    // SIGUSR1：导出订单簿（用于排查问题）
+    #[cfg(unix)]
+    {
+        let state = state.clone();
+        let dump_dir = args.dump_dir.clone();
+        let mut signals =
+            tokio::signal::unix::signal(tokio::signal::unix::SignalKind::user_defined1())?;
+        tokio::spawn(async move {
+            while signals.recv().await.is_some() {
+                dump_orderbooks(&state, &dump_dir);
+            }
+        });
+    }
+
     // 创建匹配引擎（从 GlobalState 获取订单簿状态）
     let matcher = MatchingEngine::new(config.clone(), state).await?;
 
@@ -1,26 +1,37 @@
 use crate::config::Config;
+use crate::contracts::sequencer::RequestProcessedFilter;
 use crate::contracts::OrderBook;
-use crate::state::GlobalState;
+use crate::fixed_point::{Amount, Price};
+use crate::orderbook_simulator::SimSelfTrade;
+use crate::self_trade::{SelfTradePolicy, SelfTradeRecord};
+use crate::settlement::PairDecimals;
+use crate::state::{GlobalState, OrderBooks};
+use crate::transport::{self, RpcProvider};
 use crate::types::*;
 use anyhow::{Context, Result};
+use ethers::abi::RawLog;
 use ethers::prelude::*;
+use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use tracing::{debug, error, info, warn};
 
 pub struct MatchingEngine {
     config: Config,
     state: GlobalState,
-    orderbook: OrderBook<SignerMiddleware<Arc<Provider<Ws>>, LocalWallet>>,
+    /// 交易对白名单（None = 全部）
+    allowed_pairs: Option<HashSet<[u8; 32]>>,
+    /// Sequencer 合约地址：从交易回执的 RequestProcessed 事件确认实际处理的请求
+    sequencer: Address,
+    /// 提交批处理的合约实例；离线模式（重放）下为 None，只计算参数
+    orderbook: Option<OrderBook<SignerMiddleware<Arc<RpcProvider>, LocalWallet>>>,
 }
 
 impl MatchingEngine {
     pub async fn new(config: Config, state: GlobalState) -> Result<Self> {
         // 连接到节点
-        let ws = Ws::connect(&config.network.rpc_url)
-            .await
-            .context("Failed to connect to WebSocket")?;
-        let provider = Arc::new(Provider::new(ws));
+        let (provider, _) = transport::connect(&config.network).await?;
+        let provider = Arc::new(provider);
 
         // 创建钱包
         let wallet: LocalWallet = config
@@ -37,12 +48,31 @@ impl MatchingEngine {
         let orderbook = OrderBook::new(orderbook_addr, Arc::new(client));
 
         Ok(Self {
+            allowed_pairs: config.sync.trading_pair_allow_list()?,
+            sequencer: config.contracts.sequencer.parse()?,
             config,
             state,
-            orderbook,
+            orderbook: Some(orderbook),
         })
     }
 
+    /// 离线模式：不连接节点，只用 compute_batch 计算批处理参数（见 replay.rs）
+    pub fn offline(config: Config, state: GlobalState) -> Result<Self> {
+        Ok(Self {
+            allowed_pairs: config.sync.trading_pair_allow_list()?,
+            sequencer: config.contracts.sequencer.parse()?,
+            config,
+            state,
+            orderbook: None,
+        })
+    }
+
+    fn is_pair_allowed(&self, trading_pair: &[u8; 32]) -> bool {
+        self.allowed_pairs
+            .as_ref()
+            .is_none_or(|allowed| allowed.contains(trading_pair))
+    }
+
     /// 运行匹配引擎
     pub async fn run(self) -> Result<()> {
         info!("🎯 Starting matching engine");
@@ -51,6 +81,7 @@ impl MatchingEngine {
             "  Interval: {}ms",
             self.config.matching.matching_interval_ms
         );
+        info!("  View: {:?}", self.config.matching.view);
 
         let interval = Duration::from_millis(self.config.matching.matching_interval_ms);
         let mut ticker = tokio::time::interval(interval);
@@ -73,132 +104,271 @@ impl MatchingEngine {
 
     /// 处理一批请求
     async fn process_batch(&self) -> Result<usize> {
+        let match_result = self.compute_batch()?;
+
+        if match_result.is_empty() {
+            debug!("No valid orders to insert");
+            return Ok(0);
+        }
+
+        // 执行批量处理
+        self.execute_batch(&match_result).await
+    }
+
+    /// 计算下一批 batchProcessRequests 的参数（不发送交易）：队列头部的请求及其插入位置
+    pub fn compute_batch(&self) -> Result<MatchResult> {
         // 获取队列中的请求
-        let requests = self
+        let mut requests = self
             .state
             .get_head_requests(self.config.matching.max_batch_size);
 
+        // 白名单之外的交易对不由本撮合器处理；链上批处理在第一个不是队列头部的请求处停止，
+        // 因此批次截止到第一个这样的请求之前
+        if let Some(pos) = requests
+            .iter()
+            .position(|request| !self.is_pair_allowed(&request.trading_pair))
+        {
+            debug!(
+                "Request {} belongs to a trading pair outside the allow list, batch stops before it",
+                requests[pos].request_id
+            );
+            requests.truncate(pos);
+        }
+
         if requests.is_empty() {
             debug!("No requests to process");
-            return Ok(0);
+            return Ok(MatchResult::new());
         }
 
         debug!("Processing {} requests", requests.len());
 
         // 使用 Simulator 计算每个订单的 insertAfterPrice
         // Simulator 从 GlobalState 获取当前状态，不再从链上同步
-        let match_result = self.calculate_insert_positions_with_simulator(&requests)?;
+        self.calculate_insert_positions_with_simulator(&requests)
+    }
 
-        if match_result.is_empty() {
-            debug!("No valid orders to insert");
-            return Ok(0);
-        }
+    /// 使用 Simulator 计算插入位置（严格按照链上逻辑）
+    /// 直接在 GlobalState 的订单簿上模拟，结束后回滚（不复制订单簿）
+    fn calculate_insert_positions_with_simulator(
+        &self,
+        requests: &[QueuedRequest],
+    ) -> Result<MatchResult> {
+        // 模拟期间持有订单簿写锁，先读取交易对精度，避免同时持有账本锁
+        let decimals: HashMap<[u8; 32], PairDecimals> = requests
+            .iter()
+            .filter_map(|request| {
+                let pair_decimals = self.state.pair_decimals(&request.trading_pair)?;
+                Some((request.trading_pair, pair_decimals))
+            })
+            .collect();
+
+        // 在配置所选视图（head / confirmed）中各请求所属交易对的订单簿上模拟
+        let pairs = requests.iter().map(|request| request.trading_pair);
+        self.state
+            .simulate_on_orderbooks(self.config.matching.view, pairs, |orderbooks| {
+                for sim in orderbooks.values_mut() {
+                    sim.match_limits = self.config.matching.match_limits();
+                }
+                self.simulate_requests(orderbooks, requests, &decimals)
+            })
+    }
 
-        // 执行批量处理
-        self.execute_batch(&match_result).await?;
+    /// 记录预测到的自成交并按策略输出日志，返回是否拒绝处理该请求
+    /// 同一请求每个批次都会重新模拟，只有首次检测到时输出日志
+    fn handle_self_trades(&self, request: &QueuedRequest, self_trades: &[SimSelfTrade]) -> bool {
+        let policy = self.config.matching.self_trade_policy;
+        let refused = policy == SelfTradePolicy::Refuse;
+        let mut log = self.state.self_trades.write();
+
+        for trade in self_trades {
+            let record = SelfTradeRecord::new(request.request_id, request.trading_pair, trade, refused);
+            if !log.record(record) {
+                continue;
+            }
+            match policy {
+                SelfTradePolicy::Report => info!(
+                    "Self-trade: request {} trader {:?} buy={} sell={} price={} amount={}",
+                    request.request_id,
+                    trade.trader,
+                    trade.buy_order_id,
+                    trade.sell_order_id,
+                    Price::from_raw(trade.price),
+                    Amount::from_raw(trade.amount)
+                ),
+                SelfTradePolicy::Alert => warn!(
+                    "⚠️  Self-trade: request {} trader {:?} buy={} sell={} price={} amount={}",
+                    request.request_id,
+                    trade.trader,
+                    trade.buy_order_id,
+                    trade.sell_order_id,
+                    Price::from_raw(trade.price),
+                    Amount::from_raw(trade.amount)
+                ),
+                SelfTradePolicy::Refuse => error!(
+                    "⛔ Self-trade refused: request {} trader {:?} buy={} sell={}, batching stops before it",
+                    request.request_id,
+                    trade.trader,
+                    trade.buy_order_id,
+                    trade.sell_order_id
+                ),
+            }
+        }
 
-        Ok(match_result.len())
+        refused
     }
 
-    /// 使用 Simulator 计算插入位置（严格按照链上逻辑）
-    /// Simulator 从 GlobalState 获取当前订单簿状态，不再从链上同步
-    fn calculate_insert_positions_with_simulator(
+    /// 依次在各请求所属交易对的订单簿上模拟每个请求，计算 batchProcessRequests 所需参数
+    fn simulate_requests(
         &self,
+        orderbooks: &mut OrderBooks,
         requests: &[QueuedRequest],
+        decimals: &HashMap<[u8; 32], PairDecimals>,
     ) -> Result<MatchResult> {
         let mut result = MatchResult::new();
 
-        // 从 GlobalState 克隆当前 orderbook 状态
-        let mut sim = self.state.clone_orderbook();
-
         debug!(
-            "📊 Simulator state: ask_head={}, bid_head={}, {} price_levels, {} orders",
-            sim.ask_head,
-            sim.bid_head,
-            sim.price_levels.len(),
-            sim.orders.len()
+            "📊 Simulator state: {} trading pairs, {} price_levels, {} orders",
+            orderbooks.len(),
+            orderbooks.values().map(|sim| sim.price_levels.len()).sum::<usize>(),
+            orderbooks.values().map(|sim| sim.orders.len()).sum::<usize>()
         );
 
+        // 预计收取的交易费用（计价代币最小单位，仅统计精度已知的交易对）
+        let mut forecast_fees = U256::zero();
+        // 被拒绝的队列头部请求（批次为空，队列停滞）
+        let mut refused_head = None;
+
         // 对每个请求，模拟执行并获取必要参数
         for request in requests {
-            match request.request_type {
-                RequestType::RemoveOrder => {
-                    // 模拟移除订单，更新本地状态
-                    // 这样后续的 insert 订单基于正确的状态计算 insertAfterPrice
-                    let removed = sim.simulate_remove_order(
-                        request.order_id_to_remove,
-                        request.is_ask,
-                    );
-                    debug!(
-                        "RemoveOrder {}: order_id={}, removed={}",
-                        request.request_id, request.order_id_to_remove, removed
-                    );
-                    // RemoveOrder 不需要 insertAfterPrice，但仍需加入批处理
-                    result.add_order(
-                        request.request_id,
-                        U256::zero(),
-                        U256::zero(),
+            // RemoveOrder 更新本地状态，后续的 insert 基于正确的状态计算 insertAfterPrice；
+            // 限价单得到 insertAfterPrice；市价单不需要它，但需要模拟以更新订单簿状态
+            let sim = orderbooks
+                .get_mut(&request.trading_pair)
+                .context("No orderbook for request trading pair")?;
+            let simulated = sim.simulate_request(request);
+
+            // 模拟失败的请求不加入批处理（模拟器已撤销它的修改）
+            // 链上只处理队列头部的请求，其后的请求也要等到下一批
+            let effects = match simulated {
+                Ok(effects) => effects,
+                Err(e) => {
+                    error!(
+                        "❌ Request {} dropped from batch, simulation failed: {}",
+                        request.request_id, e
                     );
+                    break;
                 }
-                RequestType::PlaceOrder => {
-                    if request.order_type == OrderType::Limit {
-                        // 限价单：使用 simulator 模拟插入，获取 insertAfterPrice
-                        let insert_after_price = sim.simulate_insert_order(
-                            request.request_id,
-                            request.price,
-                            request.amount,
-                            request.is_ask,
-                        );
-
-                        debug!(
-                            "PlaceOrder {} (limit, price={}, is_ask={}): insertAfterPrice={}",
-                            request.request_id, request.price, request.is_ask, insert_after_price
-                        );
-
-                        // 添加到结果中
-                        result.add_order(
-                            request.request_id,
-                            insert_after_price,
-                            U256::zero(), // insertAfterOrder 设为 0（插入到价格层级头部）
-                        );
-                    } else {
-                        // 市价单：模拟插入市价单队列并撮合
-                        // 市价单不需要 insertAfterPrice，但需要模拟以更新订单簿状态
-                        sim.simulate_insert_market_order(
-                            request.request_id,
-                            request.amount,
-                            request.is_ask,
-                        );
-
-                        debug!(
-                            "PlaceOrder {} (market, amount={}, is_ask={}): simulated",
-                            request.request_id, request.amount, request.is_ask
-                        );
-
-                        // 市价单的 insertAfterPrice 和 insertAfterOrder 都设为 0
-                        result.add_order(
-                            request.request_id,
-                            U256::zero(),
-                            U256::zero(),
-                        );
-                    }
+            };
+
+            if let Some(pair_decimals) = decimals.get(&request.trading_pair) {
+                // 结算溢出时链上会 revert；预计费用只是统计，跳过该请求的费用
+                match effects
+                    .total_fees(*pair_decimals)
+                    .and_then(|fees| forecast_fees.checked_add(fees))
+                {
+                    Some(total) => forecast_fees = total,
+                    None => warn!(
+                        "Request {}: settlement overflows, fee forecast skipped",
+                        request.request_id
+                    ),
+                }
+            }
+            if effects.match_cap_reached {
+                warn!(
+                    "PlaceOrder {}: matching hit the iteration cap, crossing carried over",
+                    request.request_id
+                );
+            }
+            if !effects.self_trades.is_empty() && self.handle_self_trades(request, &effects.self_trades) {
+                // 拒绝策略：批次在该请求之前截止，它和其后的请求留在队列中
+                debug!("Request {} refused: self-trade", request.request_id);
+                if result.is_empty() {
+                    refused_head = Some(request.request_id);
                 }
+                break;
             }
+
+            match request.request_type {
+                RequestType::RemoveOrder => debug!(
+                    "RemoveOrder {}: order_id={}",
+                    request.request_id, request.order_id_to_remove
+                ),
+                RequestType::PlaceOrder if request.order_type == OrderType::Limit => debug!(
+                    "PlaceOrder {} (limit, price={}, is_ask={}): insertAfterPrice={}, trades={}",
+                    request.request_id,
+                    request.price,
+                    request.is_ask,
+                    effects.insert_after_price,
+                    effects.trades.len()
+                ),
+                RequestType::PlaceOrder => debug!(
+                    "PlaceOrder {} (market, amount={}, is_ask={}): trades={}",
+                    request.request_id,
+                    request.size(),
+                    request.is_ask,
+                    effects.trades.len()
+                ),
+            }
+
+            // RemoveOrder 和市价单的 insertAfterPrice 为 0；
+            // insertAfterOrder 总是 0（插入到价格层级头部）
+            result.add_order(
+                request.request_id,
+                effects.insert_after_price,
+                U256::zero(),
+            );
         }
 
+        if !forecast_fees.is_zero() {
+            debug!("💰 Forecast fees for batch: {}", forecast_fees);
+        }
+        self.report_stall(refused_head);
+
         Ok(result)
     }
 
-    /// 执行批量处理
-    async fn execute_batch(&self, match_result: &MatchResult) -> Result<()> {
+    /// 拒绝策略下队列头部的请求被拒绝时批次为空、整个队列停滞：
+    /// 每隔 STALL_REPORT_INTERVAL 输出一次警告，停滞结束时输出恢复日志
+    fn report_stall(&self, refused_head: Option<U256>) {
+        let mut log = self.state.self_trades.write();
+        match refused_head {
+            Some(request_id) => {
+                if let Some(stall) = log.record_stall(request_id, Instant::now()) {
+                    warn!(
+                        "⛔ Queue stalled: head request {} refused for self-trade, {} empty batches over {:?}; \
+                         manual intervention required (matching.self_trade_policy)",
+                        stall.request_id,
+                        stall.ticks,
+                        stall.since.elapsed()
+                    );
+                }
+            }
+            None => {
+                if let Some(stall) = log.clear_stall() {
+                    info!(
+                        "Queue resumed after request {} stalled {} batches over {:?}",
+                        stall.request_id,
+                        stall.ticks,
+                        stall.since.elapsed()
+                    );
+                }
+            }
+        }
+    }
+
+    /// 执行批量处理，返回链上实际处理的请求数
+    async fn execute_batch(&self, match_result: &MatchResult) -> Result<usize> {
         info!(
             "📤 Executing batch with {} orders",
             match_result.order_ids.len()
         );
 
+        let Some(orderbook) = &self.orderbook else {
+            anyhow::bail!("Matching engine is offline, cannot send transactions");
+        };
+
         // 调用合约的 batchProcessRequests 函数
-        let tx = self
-            .orderbook
+        let tx = orderbook
             .batch_process_requests(
                 match_result.order_ids.clone(),
                 match_result.insert_after_price_levels.clone(),
@@ -214,7 +384,7 @@ impl MatchingEngine {
         info!("📝 Transaction sent: {:?}", tx_hash);
 
         // 等待交易确认
-        match pending_tx.await {
+        let receipt = match pending_tx.await {
             Ok(Some(receipt)) => {
                 if receipt.status != Some(1.into()) {
                     error!("❌ Transaction {:?} failed", tx_hash);
@@ -225,6 +395,7 @@ impl MatchingEngine {
                         tx_hash,
                         receipt.logs.len()
                     );
+                    receipt
                 }
             }
             Ok(None) => {
@@ -235,21 +406,213 @@ impl MatchingEngine {
                 error!("❌ Error waiting for transaction {:?}: {}", tx_hash, e);
                 return Err(e.into());
             }
+        };
+
+        // 更新本地状态：只移除链上实际处理的请求
+        // 批次第一个请求不是链上队列头部时合约不 revert，而是一个请求也不处理
+        let processed = processed_requests(&receipt.logs, self.sequencer);
+        if processed.len() < match_result.order_ids.len() {
+            warn!(
+                "Transaction {:?} processed {} of {} requests: {:?}",
+                tx_hash,
+                processed.len(),
+                match_result.order_ids.len(),
+                processed
+            );
+        }
+        self.state.complete_requests(&processed);
+
+        Ok(processed.len())
+    }
+}
+
+/// 交易回执中 Sequencer 的 RequestProcessed 事件对应的请求 ID（即 batchProcessRequests 实际处理的请求）
+fn processed_requests(logs: &[Log], sequencer: Address) -> Vec<U256> {
+    logs.iter()
+        .filter(|log| log.address == sequencer)
+        .filter_map(|log| <RequestProcessedFilter as EthEvent>::decode_log(&RawLog::from(log.clone())).ok())
+        .map(|processed| processed.request_id)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::contracts::order_book::{OrderBookEvents, OrderInsertedFilter, PriceLevelCreatedFilter};
+    use crate::events::{ChainEvent, DecodedLog, EventMeta};
+    use crate::sync::apply_event;
+
+    const CONFIG: &str = r#"
+        [network]
+        rpc_url = "http://localhost:8545"
+        chain_id = 31337
+
+        [contracts]
+        sequencer = "0x0000000000000000000000000000000000000001"
+        orderbook = "0x0000000000000000000000000000000000000002"
+        account = "0x0000000000000000000000000000000000000003"
+
+        [sync]
+        start_block = 1
+        sync_historical = false
+
+        [matching]
+        max_batch_size = 10
+        matching_interval_ms = 1000
+        self_trade_policy = "refuse"
+
+        [executor]
+        private_key = "0x0000000000000000000000000000000000000000000000000000000000000001"
+        gas_price_gwei = 1
+        gas_limit = 1000000
+    "#;
+
+    fn buy_request(request_id: u64, trader: Address) -> QueuedRequest {
+        QueuedRequest {
+            request_id: U256::from(request_id),
+            request_type: RequestType::PlaceOrder,
+            trading_pair: [0; 32],
+            trader,
+            order_type: OrderType::Limit,
+            is_ask: false,
+            price: Price::from_raw(U256::from(100)),
+            amount: U256::from(5),
+            order_id_to_remove: U256::zero(),
+            next_request_id: U256::zero(),
+            requested: BlockStamp::default(),
+        }
+    }
+
+    #[test]
+    fn test_refused_head_request_stalls_queue() {
+        let alice = Address::from_low_u64_be(1);
+        let state = GlobalState::new();
+        state
+            .orderbooks
+            .write()
+            .entry([0; 32])
+            .or_default()
+            .simulate_insert_order(U256::from(1), alice, U256::from(100), U256::from(5), true)
+            .unwrap();
+        state.add_request(buy_request(2, alice));
+
+        let engine = MatchingEngine::offline(toml::from_str(CONFIG).unwrap(), state.clone()).unwrap();
+        for tick in 1..=3 {
+            assert!(engine.compute_batch().unwrap().is_empty());
+            let stall = state.self_trades.read().stall().unwrap();
+            assert_eq!((stall.request_id, stall.ticks), (U256::from(2), tick));
         }
+        assert_eq!(state.self_trades.read().len(), 1);
+        assert_eq!(state.orderbooks.read()[&[0; 32]].orders.len(), 1);
+
+        // 自成交的对手单被撤销后批次恢复，停滞结束
+        state
+            .orderbooks
+            .write()
+            .get_mut(&[0; 32])
+            .unwrap()
+            .simulate_remove_order(U256::from(1), alice)
+            .unwrap();
+        assert_eq!(engine.compute_batch().unwrap().order_ids, vec![U256::from(2)]);
+        assert_eq!(state.self_trades.read().stall(), None);
+    }
 
-        // 更新本地状态：移除已处理的请求
-        for request_id in &match_result.order_ids {
-            self.state.remove_request(request_id);
-            debug!("  Removed request {} from local state", request_id);
+    /// 在交易对 pair 的 price 买价层级插入订单（层级不存在时先创建）
+    fn insert_bid(state: &GlobalState, pair: [u8; 32], order_id: u64, price: u64, create_level: bool) {
+        let meta = EventMeta {
+            block_number: 1,
+            block_hash: H256::zero(),
+            tx_hash: H256::from_low_u64_be(order_id),
+            log_index: U256::zero(),
+            block_timestamp: 0,
+        };
+        let mut events = Vec::new();
+        if create_level {
+            events.push(OrderBookEvents::PriceLevelCreatedFilter(PriceLevelCreatedFilter {
+                trading_pair: pair,
+                price: U256::from(price),
+                is_ask: false,
+            }));
         }
+        events.push(OrderBookEvents::OrderInsertedFilter(OrderInsertedFilter {
+            trading_pair: pair,
+            order_id: U256::from(order_id),
+            is_ask: false,
+            price: U256::from(price),
+            amount: U256::from(5),
+        }));
+        for event in events {
+            apply_event(state, &DecodedLog { meta, event: ChainEvent::OrderBook(event) });
+        }
+    }
 
-        // 更新队列头部
-        if let Some(first_remaining) = self.state.get_head_requests(1).first() {
-            self.state.update_queue_head(first_remaining.request_id);
-        } else {
-            self.state.update_queue_head(U256::zero());
+    #[test]
+    fn test_trading_pairs_use_separate_orderbooks() {
+        let (pair_a, pair_b) = ([1; 32], [2; 32]);
+        let state = GlobalState::new();
+        // 两个交易对都有 100 的买价层级，A 另有 105
+        insert_bid(&state, pair_a, 1, 100, true);
+        insert_bid(&state, pair_b, 2, 100, true);
+        insert_bid(&state, pair_a, 3, 105, true);
+
+        {
+            let orderbooks = state.orderbooks.read();
+            assert_eq!(orderbooks[&pair_a].get_price_levels(false), vec![U256::from(105), U256::from(100)]);
+            assert_eq!(orderbooks[&pair_b].get_price_levels(false), vec![U256::from(100)]);
+            assert_eq!(orderbooks[&pair_b].orders.len(), 1);
+            assert!(orderbooks.values().all(|sim| sim.validate().is_empty()));
         }
 
-        Ok(())
+        // 101 的买单在 B 中是最优价（插入到头部），在 A 中排在 105 之后
+        let mut request_b = buy_request(4, Address::zero());
+        request_b.trading_pair = pair_b;
+        request_b.price = Price::from_raw(U256::from(101));
+        let mut request_a = buy_request(5, Address::zero());
+        request_a.trading_pair = pair_a;
+        request_a.price = Price::from_raw(U256::from(101));
+        state.add_request(request_b);
+        state.add_request(request_a);
+
+        let engine = MatchingEngine::offline(toml::from_str(CONFIG).unwrap(), state.clone()).unwrap();
+        let batch = engine.compute_batch().unwrap();
+        assert_eq!(batch.order_ids, vec![U256::from(4), U256::from(5)]);
+        assert_eq!(batch.insert_after_price_levels, vec![U256::zero(), U256::from(105)]);
+        assert_eq!(state.orderbooks.read()[&pair_b].orders.len(), 1);
+    }
+
+    #[test]
+    fn test_batch_stops_before_disallowed_pair() {
+        let state = GlobalState::new();
+        let mut disallowed = buy_request(3, Address::zero());
+        disallowed.trading_pair = [2; 32];
+        let mut allowed = buy_request(2, Address::zero());
+        allowed.trading_pair = [1; 32];
+        state.add_request(allowed);
+        state.add_request(disallowed);
+
+        let config = CONFIG.replace(
+            "start_block = 1",
+            &format!("start_block = 1\ntrading_pairs = [\"0x{}\"]", "01".repeat(32)),
+        );
+        let engine = MatchingEngine::offline(toml::from_str(&config).unwrap(), state.clone()).unwrap();
+        assert_eq!(engine.compute_batch().unwrap().order_ids, vec![U256::from(2)]);
+        // 模拟没有为白名单之外的交易对留下订单簿
+        assert!(!state.orderbooks.read().contains_key(&[2; 32]));
+    }
+
+    #[test]
+    fn test_processed_requests_from_receipt_logs() {
+        let sequencer = Address::from_low_u64_be(1);
+        let processed = |address: Address, request_id: u64| Log {
+            address,
+            topics: vec![RequestProcessedFilter::signature(), H256::from_low_u64_be(request_id)],
+            data: ethers::abi::encode(&[ethers::abi::Token::Uint(U256::zero())]).into(),
+            ..Default::default()
+        };
+
+        // 其他合约的同名事件不计入；没有 RequestProcessed 时说明一个请求也没有处理
+        let logs = vec![processed(sequencer, 4), processed(Address::from_low_u64_be(2), 5), processed(sequencer, 6)];
+        assert_eq!(processed_requests(&logs, sequencer), vec![U256::from(4), U256::from(6)]);
+        assert!(processed_requests(&[], sequencer).is_empty());
     }
 }
@@ -9,21 +9,51 @@
 //! 1. 计算 insertAfterPrice
 //! 2. 插入订单到价格层级
 //! 3. 执行撮合（best bid vs best ask）
-
-use ethers::types::U256;
-use std::collections::HashMap;
+//!
+//! 除链表外，每一侧还维护一个按价格排序的索引（BTreeSet），
+//! 计算 insertAfterPrice 时用它在 O(log n) 内找到前驱价格，结果与链上遍历链表一致。
+
+use crate::fixed_point::{Amount, FixedPointError, Price, QuoteAmount};
+use crate::types::{BlockStamp, OrderType, QueuedRequest, RequestType};
+use ethers::types::{Address, U256};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap, HashSet};
+use thiserror::Error;
 use tracing::debug;
 
 /// 常量：空节点
 const EMPTY: U256 = U256::zero();
 
-/// 常量：价格精度 (10^8) - 对应 TradingConstants.PRICE_DECIMALS
-const PRICE_DECIMALS: U256 = U256([100_000_000, 0, 0, 0]);
+/// 常量：每次插入后的最大撮合次数 - 对应链上 _tryMatchAfterInsertion 中硬编码的 maxIterations
+/// 限价撮合与市价撮合都使用这个值；其他上限只用于模拟工具（如 depth / quote 中关闭市价撮合）
+pub const DEFAULT_MAX_ITERATIONS: usize = 50;
+
+/// 插入后撮合的次数上限
+/// 链上 _tryMatchAfterInsertion 先调用 _matchOrdersInternal(maxIterations)，
+/// 再调用 _matchMarketOrdersInternal(maxIterations)，两者各自计数
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MatchLimits {
+    /// 限价单撮合（_matchOrdersInternal）的最大成交次数
+    pub limit_iterations: usize,
+    /// 市价单撮合（_matchMarketOrdersInternal）的最大循环次数，买卖两侧共用
+    pub market_iterations: usize,
+}
+
+impl Default for MatchLimits {
+    fn default() -> Self {
+        Self {
+            limit_iterations: DEFAULT_MAX_ITERATIONS,
+            market_iterations: DEFAULT_MAX_ITERATIONS,
+        }
+    }
+}
 
 /// 模拟订单 - 对应链上 Order 结构
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct SimOrder {
     pub id: U256,
+    #[serde(default)]
+    pub trader: Address,
     pub amount: U256,
     pub filled_amount: U256,
     pub is_market_order: bool,
@@ -31,10 +61,30 @@ pub struct SimOrder {
     pub price_level: U256,     // 该订单所属的价格
     pub next_order_id: U256,
     pub prev_order_id: U256,
+    /// 下单请求提交的时间与位置（链上 Order 不保存，来自 PlaceOrderRequested）
+    #[serde(default)]
+    pub requested: BlockStamp,
+    /// 订单进入订单簿的时间与位置（OrderInserted 所在交易；模拟插入的订单未知）
+    #[serde(default)]
+    pub inserted: BlockStamp,
+}
+
+impl SimOrder {
+    /// 在订单簿中停留的秒数；插入时间未知时为 None
+    pub fn time_in_book(&self, now: u64) -> Option<u64> {
+        self.inserted.elapsed(now)
+    }
+
+    /// 从提交请求到进入订单簿的秒数；任一时间未知时为 None
+    pub fn insertion_latency(&self) -> Option<u64> {
+        (self.inserted.timestamp != 0)
+            .then(|| self.requested.elapsed(self.inserted.timestamp))
+            .flatten()
+    }
 }
 
 /// 模拟价格层级 - 对应链上 PriceLevel 结构
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct SimPriceLevel {
     pub price: U256,
     pub total_volume: U256,
@@ -44,6 +94,262 @@ pub struct SimPriceLevel {
     pub prev_price: U256, // 上一个价格
 }
 
+/// 模拟成交 - 对应链上 Trade 事件
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SimTrade {
+    pub buy_order_id: U256,
+    pub sell_order_id: U256,
+    /// 成交价格（链上取卖方价格层级的价格）
+    pub price: U256,
+    /// 成交数量（base tokens）
+    pub amount: U256,
+}
+
+/// 自成交：同一交易者的买单与卖单互相成交（链上不阻止）
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SimSelfTrade {
+    pub trader: Address,
+    pub buy_order_id: U256,
+    pub sell_order_id: U256,
+    pub price: U256,
+    pub amount: U256,
+}
+
+/// 订单成交 - 对应链上 OrderFilled 事件
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SimFill {
+    pub order_id: U256,
+    /// 本次成交数量（base tokens）
+    pub filled_amount: U256,
+    pub is_fully_filled: bool,
+}
+
+/// 价格层级变化 - 对应链上 PriceLevelCreated / PriceLevelRemoved 事件
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SimPriceLevelChange {
+    pub price: U256,
+    pub is_ask: bool,
+}
+
+/// 一次模拟操作产生的全部影响（顺序与链上事件顺序一致）
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SimEffects {
+    /// 限价单的 insertAfterPrice（其他操作为 0）
+    pub insert_after_price: U256,
+    pub trades: Vec<SimTrade>,
+    pub fills: Vec<SimFill>,
+    /// 被移除的订单（完全成交或被撤销）
+    pub removed_orders: Vec<U256>,
+    pub levels_created: Vec<SimPriceLevelChange>,
+    pub levels_removed: Vec<SimPriceLevelChange>,
+    /// trades 中买卖双方为同一交易者的成交（trader 未知的订单不计）
+    pub self_trades: Vec<SimSelfTrade>,
+    /// 撮合因达到次数上限而停止，且订单簿仍可成交
+    /// 剩余部分会在下一次插入订单时继续撮合（与链上一致）
+    pub match_cap_reached: bool,
+}
+
+impl SimEffects {
+    /// 总成交量（base tokens），溢出时取 U256::MAX
+    pub fn traded_amount(&self) -> U256 {
+        self.trades
+            .iter()
+            .fold(EMPTY, |acc, trade| acc.saturating_add(trade.amount))
+    }
+
+    /// 指定订单在本次操作中的成交量，溢出时取 U256::MAX
+    pub fn filled_amount_of(&self, order_id: U256) -> U256 {
+        self.fills
+            .iter()
+            .filter(|fill| fill.order_id == order_id)
+            .fold(EMPTY, |acc, fill| acc.saturating_add(fill.filled_amount))
+    }
+}
+
+fn side(is_ask: bool) -> &'static str {
+    if is_ask {
+        "ask"
+    } else {
+        "bid"
+    }
+}
+
+fn checked_add(a: U256, b: U256, context: &'static str) -> Result<U256, SimulatorError> {
+    a.checked_add(b).ok_or(SimulatorError::Overflow { context })
+}
+
+fn checked_sub(a: U256, b: U256, context: &'static str) -> Result<U256, SimulatorError> {
+    a.checked_sub(b).ok_or(SimulatorError::Underflow { context })
+}
+
+impl FixedPointError {
+    /// 转换为带上下文的 SimulatorError
+    pub fn in_context(self, context: &'static str) -> SimulatorError {
+        match self {
+            FixedPointError::Overflow => SimulatorError::Overflow { context },
+            FixedPointError::DivisionByZero => SimulatorError::DivisionByZero { context },
+        }
+    }
+}
+
+/// 模拟操作失败的原因
+///
+/// 出错的 simulate_* / match_* 调用不会修改订单簿（见 atomically）。
+/// 链上对应的情况要么会 revert，要么说明本地订单簿已与链上不一致
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum SimulatorError {
+    #[error("order {order_id} is not on the book")]
+    UnknownOrder { order_id: U256 },
+
+    #[error("order {order_id} already exists")]
+    DuplicateOrder { order_id: U256 },
+
+    #[error("order {order_id} has zero price")]
+    ZeroPrice { order_id: U256 },
+
+    #[error("order {order_id} has zero amount")]
+    ZeroAmount { order_id: U256 },
+
+    #[error("order {order_id} is not owned by {trader:?}")]
+    NotOrderOwner { order_id: U256, trader: Address },
+
+    #[error("linked list references missing {} price level {price}", side(*is_ask))]
+    MissingPriceLevel { price: U256, is_ask: bool },
+
+    #[error("linked list references missing order {order_id}")]
+    MissingOrder { order_id: U256 },
+
+    #[error("arithmetic overflow in {context}")]
+    Overflow { context: &'static str },
+
+    #[error("arithmetic underflow in {context}")]
+    Underflow { context: &'static str },
+
+    #[error("division by zero in {context}")]
+    DivisionByZero { context: &'static str },
+}
+
+/// 订单簿结构不变量被破坏的情况（由 validate 返回）
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum InvariantViolation {
+    #[error("{} list references missing price level {price}", side(*is_ask))]
+    MissingPriceLevel { price: U256, is_ask: bool },
+
+    #[error("{} price level {price} has prev_price {actual}, expected {expected}", side(*is_ask))]
+    BrokenPriceLink {
+        price: U256,
+        is_ask: bool,
+        expected: U256,
+        actual: U256,
+    },
+
+    #[error("{} price {next_price} follows {price}, list is not strictly sorted", side(*is_ask))]
+    PriceOrder {
+        price: U256,
+        next_price: U256,
+        is_ask: bool,
+    },
+
+    #[error("{} price list contains a cycle", side(*is_ask))]
+    PriceCycle { is_ask: bool },
+
+    #[error("{} tail is {actual}, expected {expected}", side(*is_ask))]
+    PriceTailMismatch {
+        is_ask: bool,
+        expected: U256,
+        actual: U256,
+    },
+
+    #[error("{} price level {price} is not reachable from head", side(*is_ask))]
+    UnlinkedPriceLevel { price: U256, is_ask: bool },
+
+    #[error("{} price index does not match the linked list", side(*is_ask))]
+    PriceIndexMismatch { is_ask: bool },
+
+    #[error("order list at {} price {price} references missing order {order_id}", side(*is_ask))]
+    MissingOrder {
+        order_id: U256,
+        price: U256,
+        is_ask: bool,
+    },
+
+    #[error("order {order_id} has prev_order_id {actual}, expected {expected}")]
+    BrokenOrderLink {
+        order_id: U256,
+        expected: U256,
+        actual: U256,
+    },
+
+    #[error("order {order_id} is linked into the wrong list")]
+    OrderListMismatch { order_id: U256 },
+
+    #[error("order list at {} price {price} contains a cycle", side(*is_ask))]
+    OrderCycle { price: U256, is_ask: bool },
+
+    #[error("order list at {} price {price} has tail {actual}, expected {expected}", side(*is_ask))]
+    OrderTailMismatch {
+        price: U256,
+        is_ask: bool,
+        expected: U256,
+        actual: U256,
+    },
+
+    #[error("{} price level {price} has total_volume {recorded}, remaining orders sum to {expected}", side(*is_ask))]
+    TotalVolumeMismatch {
+        price: U256,
+        is_ask: bool,
+        recorded: U256,
+        expected: U256,
+    },
+
+    #[error("order {order_id} is still on the book with filled_amount {filled_amount} >= amount {amount}")]
+    FilledOrderOnBook {
+        order_id: U256,
+        amount: U256,
+        filled_amount: U256,
+    },
+
+    #[error("market {} queue references missing order {order_id}", side(*is_ask))]
+    MissingMarketOrder { order_id: U256, is_ask: bool },
+
+    #[error("market {} queue contains a cycle", side(*is_ask))]
+    MarketQueueCycle { is_ask: bool },
+
+    #[error("market {} queue tail is {actual}, expected {expected}", side(*is_ask))]
+    MarketTailMismatch {
+        is_ask: bool,
+        expected: U256,
+        actual: U256,
+    },
+
+    #[error("order {order_id} is not reachable from any list")]
+    UnlinkedOrder { order_id: U256 },
+
+    #[error("open order index of trader {trader:?} does not match the orders")]
+    TraderIndexMismatch { trader: Address },
+}
+
+/// 链表头尾指针快照
+#[derive(Debug, Clone, Copy)]
+struct ListHeads {
+    ask_head: U256,
+    ask_tail: U256,
+    bid_head: U256,
+    bid_tail: U256,
+    market_ask_head: U256,
+    market_ask_tail: U256,
+    market_bid_head: U256,
+    market_bid_tail: U256,
+}
+
+/// 检查点日志：记录检查点之后首次被修改的价格层级 / 订单的原值（None 表示原本不存在）
+#[derive(Debug, Clone)]
+struct Journal {
+    heads: ListHeads,
+    levels: HashMap<U256, Option<SimPriceLevel>>,
+    orders: HashMap<U256, Option<SimOrder>>,
+}
+
 /// 模拟订单簿 - 严格按照链上 OrderBook 合约实现
 #[derive(Debug, Clone)]
 pub struct OrderBookSimulator {
@@ -65,6 +371,28 @@ pub struct OrderBookSimulator {
 
     /// 订单: order_id -> SimOrder
     pub orders: HashMap<U256, SimOrder>,
+
+    /// 插入后撮合的次数上限
+    pub match_limits: MatchLimits,
+
+    /// 价格索引：与链表中的价格层级一一对应，按价格升序
+    ask_prices: BTreeSet<U256>,
+    bid_prices: BTreeSet<U256>,
+
+    /// 交易者索引：trader -> 簿上订单 id（限价单与市价单）
+    trader_orders: HashMap<Address, BTreeSet<U256>>,
+
+    /// 检查点日志栈（见 checkpoint / rollback / commit）
+    journals: Vec<Journal>,
+
+    /// 当前 simulate_* 调用累积的影响，调用结束时取出
+    effects: SimEffects,
+}
+
+impl Default for OrderBookSimulator {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl OrderBookSimulator {
@@ -80,6 +408,12 @@ impl OrderBookSimulator {
             market_bid_tail: EMPTY,
             price_levels: HashMap::new(),
             orders: HashMap::new(),
+            match_limits: MatchLimits::default(),
+            ask_prices: BTreeSet::new(),
+            bid_prices: BTreeSet::new(),
+            trader_orders: HashMap::new(),
+            journals: Vec::new(),
+            effects: SimEffects::default(),
         }
     }
 
@@ -101,6 +435,12 @@ impl OrderBookSimulator {
             market_bid_tail: EMPTY,
             price_levels: HashMap::new(),
             orders: HashMap::new(),
+            match_limits: MatchLimits::default(),
+            ask_prices: BTreeSet::new(),
+            bid_prices: BTreeSet::new(),
+            trader_orders: HashMap::new(),
+            journals: Vec::new(),
+            effects: SimEffects::default(),
         }
     }
 
@@ -115,18 +455,318 @@ impl OrderBookSimulator {
         }
     }
 
+    fn price_index(&self, is_ask: bool) -> &BTreeSet<U256> {
+        if is_ask {
+            &self.ask_prices
+        } else {
+            &self.bid_prices
+        }
+    }
+
+    fn price_index_mut(&mut self, is_ask: bool) -> &mut BTreeSet<U256> {
+        if is_ask {
+            &mut self.ask_prices
+        } else {
+            &mut self.bid_prices
+        }
+    }
+
+    /// 价格层级的 composite key 还原为 (price, is_ask)
+    fn split_price_level_key(key: U256) -> (U256, bool) {
+        let bid_flag = U256::one() << 255;
+        if key & bid_flag == EMPTY {
+            (key, true)
+        } else {
+            (key ^ bid_flag, false)
+        }
+    }
+
+    fn list_heads(&self) -> ListHeads {
+        ListHeads {
+            ask_head: self.ask_head,
+            ask_tail: self.ask_tail,
+            bid_head: self.bid_head,
+            bid_tail: self.bid_tail,
+            market_ask_head: self.market_ask_head,
+            market_ask_tail: self.market_ask_tail,
+            market_bid_head: self.market_bid_head,
+            market_bid_tail: self.market_bid_tail,
+        }
+    }
+
+    fn set_list_heads(&mut self, heads: ListHeads) {
+        self.ask_head = heads.ask_head;
+        self.ask_tail = heads.ask_tail;
+        self.bid_head = heads.bid_head;
+        self.bid_tail = heads.bid_tail;
+        self.market_ask_head = heads.market_ask_head;
+        self.market_ask_tail = heads.market_ask_tail;
+        self.market_bid_head = heads.market_bid_head;
+        self.market_bid_tail = heads.market_bid_tail;
+    }
+
+    /// 修改价格层级前记录原值（仅在有检查点时）
+    fn journal_level(&mut self, key: U256) {
+        if let Some(journal) = self.journals.last_mut() {
+            journal
+                .levels
+                .entry(key)
+                .or_insert_with(|| self.price_levels.get(&key).cloned());
+        }
+    }
+
+    /// 修改订单前记录原值（仅在有检查点时）
+    fn journal_order(&mut self, order_id: U256) {
+        if let Some(journal) = self.journals.last_mut() {
+            journal
+                .orders
+                .entry(order_id)
+                .or_insert_with(|| self.orders.get(&order_id).cloned());
+        }
+    }
+
+    /// 链表引用的价格层级，不存在说明链表已损坏
+    pub(crate) fn level(&self, price: U256, is_ask: bool) -> Result<&SimPriceLevel, SimulatorError> {
+        self.price_levels
+            .get(&Self::get_price_level_key(price, is_ask))
+            .ok_or(SimulatorError::MissingPriceLevel { price, is_ask })
+    }
+
+    /// 链表引用的订单，不存在说明链表已损坏
+    pub(crate) fn order(&self, order_id: U256) -> Result<&SimOrder, SimulatorError> {
+        self.orders
+            .get(&order_id)
+            .ok_or(SimulatorError::MissingOrder { order_id })
+    }
+
+    fn level_mut(&mut self, price: U256, is_ask: bool) -> Result<&mut SimPriceLevel, SimulatorError> {
+        let key = Self::get_price_level_key(price, is_ask);
+        self.journal_level(key);
+        self.price_levels
+            .get_mut(&key)
+            .ok_or(SimulatorError::MissingPriceLevel { price, is_ask })
+    }
+
+    fn order_mut(&mut self, order_id: U256) -> Result<&mut SimOrder, SimulatorError> {
+        self.journal_order(order_id);
+        self.orders
+            .get_mut(&order_id)
+            .ok_or(SimulatorError::MissingOrder { order_id })
+    }
+
+    fn insert_level_data(&mut self, key: U256, level: SimPriceLevel) {
+        self.journal_level(key);
+        let (price, is_ask) = Self::split_price_level_key(key);
+        self.price_index_mut(is_ask).insert(price);
+        self.price_levels.insert(key, level);
+    }
+
+    fn remove_level_data(&mut self, key: U256) -> Option<SimPriceLevel> {
+        self.journal_level(key);
+        let (price, is_ask) = Self::split_price_level_key(key);
+        self.price_index_mut(is_ask).remove(&price);
+        self.price_levels.remove(&key)
+    }
+
+    fn insert_order_data(&mut self, order: SimOrder) {
+        self.journal_order(order.id);
+        self.put_order(order);
+    }
+
+    fn remove_order_data(&mut self, order_id: U256) -> Option<SimOrder> {
+        self.journal_order(order_id);
+        self.take_order(order_id)
+    }
+
+    /// 写入订单并维护交易者索引（不记录日志）
+    fn put_order(&mut self, order: SimOrder) {
+        self.take_order(order.id);
+        self.trader_orders
+            .entry(order.trader)
+            .or_default()
+            .insert(order.id);
+        self.orders.insert(order.id, order);
+    }
+
+    /// 删除订单并维护交易者索引（不记录日志）
+    fn take_order(&mut self, order_id: U256) -> Option<SimOrder> {
+        let order = self.orders.remove(&order_id)?;
+        if let Some(ids) = self.trader_orders.get_mut(&order.trader) {
+            ids.remove(&order_id);
+            if ids.is_empty() {
+                self.trader_orders.remove(&order.trader);
+            }
+        }
+        Some(order)
+    }
+
+    /// 创建检查点：之后的修改只记录被触及条目的原值，不复制整个订单簿
+    /// 检查点可以嵌套，每个检查点必须以 rollback 或 commit 结束
+    ///
+    /// 注意：只有通过模拟器方法的修改会被记录，直接修改 price_levels / orders 字段不会
+    pub fn checkpoint(&mut self) {
+        self.journals.push(Journal {
+            heads: self.list_heads(),
+            levels: HashMap::new(),
+            orders: HashMap::new(),
+        });
+    }
+
+    /// 撤销最近一个检查点之后的所有修改
+    pub fn rollback(&mut self) {
+        let Some(journal) = self.journals.pop() else {
+            debug!("No checkpoint to roll back");
+            return;
+        };
+
+        self.set_list_heads(journal.heads);
+        for (key, original) in journal.levels {
+            let (price, is_ask) = Self::split_price_level_key(key);
+            match original {
+                Some(level) => {
+                    self.price_index_mut(is_ask).insert(price);
+                    self.price_levels.insert(key, level);
+                }
+                None => {
+                    self.price_index_mut(is_ask).remove(&price);
+                    self.price_levels.remove(&key);
+                }
+            }
+        }
+        for (order_id, original) in journal.orders {
+            match original {
+                Some(order) => self.put_order(order),
+                None => {
+                    self.take_order(order_id);
+                }
+            }
+        }
+    }
+
+    /// 保留最近一个检查点之后的修改
+    /// 嵌套时并入外层检查点，外层 rollback 仍会撤销这些修改
+    pub fn commit(&mut self) {
+        let Some(journal) = self.journals.pop() else {
+            debug!("No checkpoint to commit");
+            return;
+        };
+
+        if let Some(parent) = self.journals.last_mut() {
+            for (key, original) in journal.levels {
+                parent.levels.entry(key).or_insert(original);
+            }
+            for (order_id, original) in journal.orders {
+                parent.orders.entry(order_id).or_insert(original);
+            }
+        }
+    }
+
+    /// 当前未结束的检查点数量
+    pub fn checkpoint_depth(&self) -> usize {
+        self.journals.len()
+    }
+
     /// 添加链上已存在的价格层级（用于初始化同步）
     pub fn add_existing_price_level(&mut self, level: SimPriceLevel, is_ask: bool) {
         let key = Self::get_price_level_key(level.price, is_ask);
-        self.price_levels.insert(key, level);
+        self.insert_level_data(key, level);
+    }
+
+    /// 创建价格层级并按价格索引接入链表（用于同步 PriceLevelCreated 事件）
+    ///
+    /// 链上的插入位置来自 insertAfterPrice hint，合约校验了它在价格顺序中的位置，
+    /// 因此等价于价格索引中的前一个价格；层级已存在时不做任何修改
+    pub fn create_existing_price_level(&mut self, price: U256, is_ask: bool) -> Result<(), SimulatorError> {
+        let key = Self::get_price_level_key(price, is_ask);
+        if self.price_levels.contains_key(&key) {
+            return Ok(());
+        }
+
+        let insert_after_price = self.find_insert_position(price, is_ask);
+        self.insert_level_data(
+            key,
+            SimPriceLevel {
+                price,
+                total_volume: EMPTY,
+                head_order_id: EMPTY,
+                tail_order_id: EMPTY,
+                next_price: EMPTY,
+                prev_price: EMPTY,
+            },
+        );
+        self.insert_price_level_into_list(price, is_ask, insert_after_price)
+    }
+
+    /// 删除价格层级数据（链表指针由调用方维护，用于同步 PriceLevelRemoved 事件）
+    /// 层级不存在时返回 None
+    pub fn remove_existing_price_level(&mut self, price: U256, is_ask: bool) -> Option<SimPriceLevel> {
+        let key = Self::get_price_level_key(price, is_ask);
+        self.remove_level_data(key)
     }
 
     /// 添加链上已存在的订单（用于初始化同步）
     pub fn add_existing_order(&mut self, order: SimOrder) {
-        self.orders.insert(order.id, order);
+        self.insert_order_data(order);
+    }
+
+    /// 删除订单数据（链表指针由调用方维护，用于同步 OrderRemoved / OrderFilled 事件）
+    /// 订单不存在时返回 None
+    pub fn remove_existing_order(&mut self, order_id: U256) -> Option<SimOrder> {
+        self.remove_order_data(order_id)
+    }
+
+    /// 指定交易者在簿上的订单 id（升序）
+    pub fn open_orders_of(&self, trader: Address) -> Vec<U256> {
+        self.trader_orders
+            .get(&trader)
+            .map(|ids| ids.iter().copied().collect())
+            .unwrap_or_default()
+    }
+
+    /// 有挂单的交易者
+    pub fn traders(&self) -> Vec<Address> {
+        let mut traders: Vec<Address> = self.trader_orders.keys().copied().collect();
+        traders.sort();
+        traders
+    }
+
+    /// 执行一次模拟操作：成功时保留修改并返回累积的影响；
+    /// 出错时撤销本次操作的全部修改，订单簿保持调用前的状态
+    fn atomically<T>(
+        &mut self,
+        op: impl FnOnce(&mut Self) -> Result<T, SimulatorError>,
+    ) -> Result<(T, SimEffects), SimulatorError> {
+        self.effects = SimEffects::default();
+        self.checkpoint();
+        let result = op(self);
+        let effects = std::mem::take(&mut self.effects);
+        match result {
+            Ok(value) => {
+                self.commit();
+                Ok((value, effects))
+            }
+            Err(error) => {
+                self.rollback();
+                Err(error)
+            }
+        }
+    }
+
+    /// 新订单的基本检查（对应 Sequencer 下单时的 require）
+    fn check_new_order(&self, order_id: U256, price: Option<U256>, amount: U256) -> Result<(), SimulatorError> {
+        if price.is_some_and(|price| price.is_zero()) {
+            return Err(SimulatorError::ZeroPrice { order_id });
+        }
+        if amount.is_zero() {
+            return Err(SimulatorError::ZeroAmount { order_id });
+        }
+        if self.orders.contains_key(&order_id) {
+            return Err(SimulatorError::DuplicateOrder { order_id });
+        }
+        Ok(())
     }
 
-    /// 模拟插入限价单并执行撮合，返回 insertAfterPrice
+    /// 模拟插入限价单并执行撮合，返回 insertAfterPrice 及撮合产生的成交
     ///
     /// 严格按照链上逻辑：
     /// 1. 计算 insertAfterPrice（基于当前状态）
@@ -136,134 +776,195 @@ impl OrderBookSimulator {
     pub fn simulate_insert_order(
         &mut self,
         order_id: U256,
+        trader: Address,
         price: U256,
         amount: U256,
         is_ask: bool,
-    ) -> U256 {
-        // 1. 计算 insertAfterPrice（在当前状态下）
-        let insert_after_price = self.find_insert_position(price, is_ask);
-
-        debug!(
-            "Order {} (price={}, is_ask={}): insertAfterPrice={}",
-            order_id, price, is_ask, insert_after_price
-        );
-
-        // 2. 查找或创建价格层级（对应链上 _findOrCreatePriceLevel）
-        self.find_or_create_price_level(price, is_ask, insert_after_price);
+    ) -> Result<SimEffects, SimulatorError> {
+        self.check_new_order(order_id, Some(price), amount)?;
+
+        let (insert_after_price, mut effects) = self.atomically(|sim| {
+            // 1. 计算 insertAfterPrice（在当前状态下）
+            let insert_after_price = sim.find_insert_position(price, is_ask);
+
+            debug!(
+                "Order {} (price={}, is_ask={}): insertAfterPrice={}",
+                order_id, price, is_ask, insert_after_price
+            );
+
+            // 2. 查找或创建价格层级（对应链上 _findOrCreatePriceLevel）
+            sim.find_or_create_price_level(price, is_ask, insert_after_price)?;
+
+            // 3. 创建并插入订单（对应链上的订单创建和 _insertOrderIntoPriceLevel）
+            let order = SimOrder {
+                id: order_id,
+                trader,
+                amount,
+                filled_amount: EMPTY,
+                is_market_order: false,
+                is_ask,
+                price_level: price,
+                next_order_id: EMPTY,
+                prev_order_id: EMPTY,
+                requested: BlockStamp::default(),
+                inserted: BlockStamp::default(),
+            };
+            sim.insert_order_data(order);
 
-        // 3. 创建并插入订单（对应链上的订单创建和 _insertOrderIntoPriceLevel）
-        let order = SimOrder {
-            id: order_id,
-            amount,
-            filled_amount: EMPTY,
-            is_market_order: false,
-            is_ask,
-            price_level: price,
-            next_order_id: EMPTY,
-            prev_order_id: EMPTY,
-        };
-        self.orders.insert(order_id, order);
+            // 插入订单到价格层级的头部（链上支持 insertAfterOrder 参数，撮合器总是传 0）
+            sim.insert_order_into_price_level(price, order_id, EMPTY, is_ask)?;
 
-        // 插入订单到价格层级的尾部（简化版，链上支持 insertAfterOrder 参数）
-        self.insert_order_into_price_level(price, order_id, EMPTY, is_ask);
+            // 4. 执行撮合（对应链上 _tryMatchAfterInsertion）
+            sim.try_match_after_insertion()?;
 
-        // 4. 执行撮合（对应链上 _tryMatchAfterInsertion）
-        self.try_match_after_insertion();
+            Ok(insert_after_price)
+        })?;
 
-        insert_after_price
+        effects.insert_after_price = insert_after_price;
+        Ok(effects)
     }
 
-    /// 模拟移除订单（对应链上 removeOrder）
-    /// 用于处理 RemoveOrder 类型的请求
-    /// 注意：is_ask 参数现在被忽略，从订单本身获取
-    pub fn simulate_remove_order(&mut self, order_id: U256, _is_ask: bool) -> bool {
+    /// 模拟移除订单（对应链上 processRemoveOrder）
+    /// 用于处理 RemoveOrder 类型的请求，trader 为撤单请求的提交者，买卖方向从订单本身获取
+    /// 订单不存在时返回 SimulatorError::UnknownOrder；与链上 require(order.trader == trader) 一致，
+    /// 不是订单所有者时返回 SimulatorError::NotOrderOwner（trader 未知的订单不检查）
+    pub fn simulate_remove_order(&mut self, order_id: U256, trader: Address) -> Result<SimEffects, SimulatorError> {
         // 检查订单是否存在并获取信息
-        let (price_level_id, is_ask) = if let Some(order) = self.orders.get(&order_id) {
-            (order.price_level, order.is_ask)
-        } else {
-            debug!("Order {} not found, skip removal", order_id);
-            return false;
+        let (price_level_id, is_ask, is_market_order) = match self.orders.get(&order_id) {
+            Some(order) if !order.trader.is_zero() && order.trader != trader => {
+                return Err(SimulatorError::NotOrderOwner { order_id, trader })
+            }
+            Some(order) => (order.price_level, order.is_ask, order.is_market_order),
+            None => return Err(SimulatorError::UnknownOrder { order_id }),
         };
 
-        debug!(
-            "Removing order {} from price level {} (is_ask={})",
-            order_id, price_level_id, is_ask
-        );
+        let ((), effects) = self.atomically(|sim| {
+            if is_market_order {
+                // 市价单：从市价单队列中移除（对应链上 _removeMarketOrderFromList）
+                debug!("Removing market order {} (is_ask={})", order_id, is_ask);
+                sim.remove_market_order_from_list(order_id, is_ask)?;
+            } else {
+                debug!(
+                    "Removing order {} from price level {} (is_ask={})",
+                    order_id, price_level_id, is_ask
+                );
 
-        // 从价格层级中移除订单
-        self.remove_order_from_price_level(price_level_id, order_id, is_ask);
+                // 从价格层级中移除订单
+                sim.remove_order_from_price_level(price_level_id, order_id, is_ask)?;
 
-        // 检查价格层级是否为空，如果为空则删除
-        let level_key = Self::get_price_level_key(price_level_id, is_ask);
-        let should_remove_level = if let Some(level) = self.price_levels.get(&level_key) {
-            level.head_order_id.is_zero()
-        } else {
-            false
-        };
+                // 检查价格层级是否为空，如果为空则删除
+                if sim.level(price_level_id, is_ask)?.head_order_id.is_zero() {
+                    sim.remove_price_level(price_level_id, is_ask)?;
+                }
+            }
 
-        if should_remove_level {
-            self.remove_price_level(price_level_id, is_ask);
-        }
+            // 删除订单数据
+            sim.remove_order_data(order_id);
+            sim.effects.removed_orders.push(order_id);
+            Ok(())
+        })?;
 
-        // 删除订单数据
-        self.orders.remove(&order_id);
+        Ok(effects)
+    }
 
-        true
+    /// 模拟 Sequencer 队列中的一个请求（对应 batchProcessRequests 对单个请求的处理）
+    /// 下单请求的 order_id 即 request_id，留在簿上的订单带上请求的提交时间
+    pub fn simulate_request(&mut self, request: &QueuedRequest) -> Result<SimEffects, SimulatorError> {
+        let effects = match request.request_type {
+            RequestType::RemoveOrder => {
+                self.simulate_remove_order(request.order_id_to_remove, request.trader)
+            }
+            RequestType::PlaceOrder if request.order_type == OrderType::Limit => self
+                .simulate_insert_order(
+                    request.request_id,
+                    request.trader,
+                    request.price.raw(),
+                    request.amount,
+                    request.is_ask,
+                ),
+            RequestType::PlaceOrder => self.simulate_insert_market_order(
+                request.request_id,
+                request.trader,
+                request.amount,
+                request.is_ask,
+            ),
+        }?;
+
+        if request.request_type == RequestType::PlaceOrder && self.orders.contains_key(&request.request_id) {
+            self.journal_order(request.request_id);
+            if let Some(order) = self.orders.get_mut(&request.request_id) {
+                order.requested = request.requested;
+            }
+        }
+        Ok(effects)
     }
 
     /// 找到正确的插入位置（返回 insertAfterPrice）
+    ///
+    /// 链表按 ask 升序 / bid 降序排列，新价格应插入到最后一个"优于"它的价格之后：
+    /// - Ask: 小于 price 的最大价格
+    /// - Bid: 大于 price 的最小价格
+    ///
+    /// 不存在这样的价格时返回 0（插入到头部）
     fn find_insert_position(&self, price: U256, is_ask: bool) -> U256 {
-        let key = Self::get_price_level_key(price, is_ask);
+        let index = self.price_index(is_ask);
 
         // 如果价格层级已存在，直接返回该价格
-        if self.price_levels.contains_key(&key) {
+        if index.contains(&price) {
             return price;
         }
 
-        let head = if is_ask { self.ask_head } else { self.bid_head };
+        let prev_price = if is_ask {
+            index.range(..price).next_back()
+        } else {
+            index.range(price..).next()
+        };
+        prev_price.copied().unwrap_or(EMPTY)
+    }
 
-        // 如果订单簿为空，返回 0（插入到头部）
-        if head.is_zero() {
-            return EMPTY;
+    /// 按链上方式从头遍历链表计算 insertAfterPrice（用于校验价格索引）
+    #[cfg(test)]
+    fn find_insert_position_by_walk(&self, price: U256, is_ask: bool) -> U256 {
+        let key = Self::get_price_level_key(price, is_ask);
+        if self.price_levels.contains_key(&key) {
+            return price;
         }
 
-        // 遍历价格层级找到正确位置
-        let mut current_price = head;
+        let mut current_price = if is_ask { self.ask_head } else { self.bid_head };
         let mut prev_price = EMPTY;
 
         while !current_price.is_zero() {
             let current_key = Self::get_price_level_key(current_price, is_ask);
-            if let Some(level) = self.price_levels.get(&current_key) {
-                let should_insert_here = if is_ask {
-                    // Ask: 价格从低到高，如果 price <= current，应插入到 current 之前
-                    price <= level.price
-                } else {
-                    // Bid: 价格从高到低，如果 price >= current，应插入到 current 之前
-                    price >= level.price
-                };
-
-                if should_insert_here {
-                    return prev_price;
-                }
-
-                prev_price = current_price;
-                current_price = level.next_price;
-            } else {
+            let Some(level) = self.price_levels.get(&current_key) else {
                 break;
+            };
+            let should_insert_here = if is_ask {
+                price <= level.price
+            } else {
+                price >= level.price
+            };
+            if should_insert_here {
+                return prev_price;
             }
+            prev_price = current_price;
+            current_price = level.next_price;
         }
 
-        // 插入到末尾
         prev_price
     }
 
     /// 查找或创建价格层级（对应链上 _findOrCreatePriceLevel）
-    fn find_or_create_price_level(&mut self, price: U256, is_ask: bool, insert_after_price: U256) {
+    fn find_or_create_price_level(
+        &mut self,
+        price: U256,
+        is_ask: bool,
+        insert_after_price: U256,
+    ) -> Result<(), SimulatorError> {
         let key = Self::get_price_level_key(price, is_ask);
 
         // 如果已存在，直接返回
         if self.price_levels.contains_key(&key) {
-            return;
+            return Ok(());
         }
 
         // 创建新价格层级
@@ -275,31 +976,31 @@ impl OrderBookSimulator {
             next_price: EMPTY,
             prev_price: EMPTY,
         };
-        self.price_levels.insert(key, new_level);
+        self.insert_level_data(key, new_level);
+        self.effects
+            .levels_created
+            .push(SimPriceLevelChange { price, is_ask });
 
         // 插入到链表中（对应链上 _insertPriceLevelIntoList）
-        self.insert_price_level_into_list(price, is_ask, insert_after_price);
+        self.insert_price_level_into_list(price, is_ask, insert_after_price)
     }
 
     /// 将价格层级插入到链表中（对应链上 _insertPriceLevelIntoList）
-    fn insert_price_level_into_list(&mut self, price: U256, is_ask: bool, insert_after_price: U256) {
-        let key = Self::get_price_level_key(price, is_ask);
-
+    fn insert_price_level_into_list(
+        &mut self,
+        price: U256,
+        is_ask: bool,
+        insert_after_price: U256,
+    ) -> Result<(), SimulatorError> {
         if insert_after_price.is_zero() {
             // 插入到头部
             let old_head = if is_ask { self.ask_head } else { self.bid_head };
 
             if !old_head.is_zero() {
-                let old_head_key = Self::get_price_level_key(old_head, is_ask);
-
                 // 更新旧头部的 prev_price
-                if let Some(old_head_level) = self.price_levels.get_mut(&old_head_key) {
-                    old_head_level.prev_price = price;
-                }
+                self.level_mut(old_head, is_ask)?.prev_price = price;
                 // 设置新头部的 next_price
-                if let Some(new_level) = self.price_levels.get_mut(&key) {
-                    new_level.next_price = old_head;
-                }
+                self.level_mut(price, is_ask)?.next_price = old_head;
             } else {
                 // 列表为空，同时设置 tail
                 if is_ask {
@@ -317,31 +1018,19 @@ impl OrderBookSimulator {
             }
         } else {
             // 插入到 insert_after_price 之后
-            let insert_after_key = Self::get_price_level_key(insert_after_price, is_ask);
-
-            let next_price = if let Some(prev_level) = self.price_levels.get(&insert_after_key) {
-                prev_level.next_price
-            } else {
-                EMPTY
-            };
+            let next_price = self.level(insert_after_price, is_ask)?.next_price;
 
             // 更新新节点的指针
-            if let Some(new_level) = self.price_levels.get_mut(&key) {
-                new_level.prev_price = insert_after_price;
-                new_level.next_price = next_price;
-            }
+            let new_level = self.level_mut(price, is_ask)?;
+            new_level.prev_price = insert_after_price;
+            new_level.next_price = next_price;
 
             // 更新前一个节点的 next_price
-            if let Some(prev_level) = self.price_levels.get_mut(&insert_after_key) {
-                prev_level.next_price = price;
-            }
+            self.level_mut(insert_after_price, is_ask)?.next_price = price;
 
             // 更新后一个节点的 prev_price
             if !next_price.is_zero() {
-                let next_key = Self::get_price_level_key(next_price, is_ask);
-                if let Some(next_level) = self.price_levels.get_mut(&next_key) {
-                    next_level.prev_price = price;
-                }
+                self.level_mut(next_price, is_ask)?.prev_price = price;
             } else {
                 // 插入到尾部
                 if is_ask {
@@ -351,6 +1040,8 @@ impl OrderBookSimulator {
                 }
             }
         }
+
+        Ok(())
     }
 
     /// 将订单插入到价格层级的订单列表中（对应链上 _insertOrderIntoPriceLevel）
@@ -360,92 +1051,99 @@ impl OrderBookSimulator {
         order_id: U256,
         insert_after_order: U256,
         is_ask: bool,
-    ) {
-        let level_key = Self::get_price_level_key(price_level_id, is_ask);
-
-        let order_amount = if let Some(order) = self.orders.get(&order_id) {
-            order.amount
-        } else {
-            return;
-        };
+    ) -> Result<(), SimulatorError> {
+        let order_amount = self.order(order_id)?.amount;
 
         if insert_after_order.is_zero() {
             // 插入到头部
-            let old_head = if let Some(level) = self.price_levels.get(&level_key) {
-                level.head_order_id
-            } else {
-                EMPTY
-            };
+            let old_head = self.level(price_level_id, is_ask)?.head_order_id;
 
             if !old_head.is_zero() {
                 // 更新旧头部的 prev
-                if let Some(old_head_order) = self.orders.get_mut(&old_head) {
-                    old_head_order.prev_order_id = order_id;
-                }
+                self.order_mut(old_head)?.prev_order_id = order_id;
                 // 设置新头部的 next
-                if let Some(order) = self.orders.get_mut(&order_id) {
-                    order.next_order_id = old_head;
-                }
+                self.order_mut(order_id)?.next_order_id = old_head;
             } else {
                 // 列表为空，设置 tail
-                if let Some(level) = self.price_levels.get_mut(&level_key) {
-                    level.tail_order_id = order_id;
-                }
+                self.level_mut(price_level_id, is_ask)?.tail_order_id = order_id;
             }
 
             // 更新 head
-            if let Some(level) = self.price_levels.get_mut(&level_key) {
-                level.head_order_id = order_id;
-            }
+            self.level_mut(price_level_id, is_ask)?.head_order_id = order_id;
         } else {
             // 插入到指定订单后面
-            let next_order_id = if let Some(prev_order) = self.orders.get(&insert_after_order) {
-                prev_order.next_order_id
-            } else {
-                EMPTY
-            };
+            let next_order_id = self.order(insert_after_order)?.next_order_id;
 
             // 更新新订单的指针
-            if let Some(order) = self.orders.get_mut(&order_id) {
-                order.prev_order_id = insert_after_order;
-                order.next_order_id = next_order_id;
-            }
+            let order = self.order_mut(order_id)?;
+            order.prev_order_id = insert_after_order;
+            order.next_order_id = next_order_id;
 
             // 更新前一个订单的 next
-            if let Some(prev_order) = self.orders.get_mut(&insert_after_order) {
-                prev_order.next_order_id = order_id;
-            }
+            self.order_mut(insert_after_order)?.next_order_id = order_id;
 
             // 更新后一个订单的 prev
             if !next_order_id.is_zero() {
-                if let Some(next_order) = self.orders.get_mut(&next_order_id) {
-                    next_order.prev_order_id = order_id;
-                }
+                self.order_mut(next_order_id)?.prev_order_id = order_id;
             } else {
                 // 插入到尾部
-                if let Some(level) = self.price_levels.get_mut(&level_key) {
-                    level.tail_order_id = order_id;
-                }
+                self.level_mut(price_level_id, is_ask)?.tail_order_id = order_id;
             }
         }
 
         // 更新价格层级的总挂单量
-        if let Some(level) = self.price_levels.get_mut(&level_key) {
-            level.total_volume = level.total_volume + order_amount;
-        }
+        let level = self.level_mut(price_level_id, is_ask)?;
+        level.total_volume = checked_add(level.total_volume, order_amount, "price level total_volume")?;
+        Ok(())
     }
 
     /// 插入后尝试撮合（对应链上 _tryMatchAfterInsertion）
-    fn try_match_after_insertion(&mut self) {
-        let max_iterations = 50;
+    fn try_match_after_insertion(&mut self) -> Result<(), SimulatorError> {
+        let limits = self.match_limits;
         // 先匹配限价单
-        self.match_orders_internal(max_iterations);
+        let limit_trades = self.match_orders_internal(limits.limit_iterations)?;
         // 再匹配市价单
-        self.match_market_orders_internal(max_iterations);
+        let market_trades = self.match_market_orders_internal(limits.market_iterations)?;
+
+        let limit_capped = limit_trades == limits.limit_iterations && self.has_limit_cross();
+        let market_capped = market_trades == limits.market_iterations && self.has_market_cross();
+        if limit_capped || market_capped {
+            debug!(
+                "Matching stopped at iteration cap (limit={}/{}, market={}/{}), book still crossed",
+                limit_trades, limits.limit_iterations, market_trades, limits.market_iterations
+            );
+            self.effects.match_cap_reached = true;
+        }
+        Ok(())
     }
 
-    /// 内部撮合逻辑（对应链上 _matchOrdersInternal）
-    fn match_orders_internal(&mut self, max_iterations: usize) {
+    /// 限价买卖盘是否交叉（best bid >= best ask）
+    pub fn has_limit_cross(&self) -> bool {
+        !self.bid_head.is_zero() && !self.ask_head.is_zero() && self.bid_head >= self.ask_head
+    }
+
+    /// 是否存在可与对手盘撮合的市价单
+    pub fn has_market_cross(&self) -> bool {
+        (!self.market_bid_head.is_zero() && !self.ask_head.is_zero())
+            || (!self.market_ask_head.is_zero() && !self.bid_head.is_zero())
+    }
+
+    /// 手动撮合限价单（对应链上 matchOrders）
+    pub fn match_orders(&mut self, max_iterations: usize) -> Result<SimEffects, SimulatorError> {
+        let (_, effects) = self.atomically(|sim| sim.match_orders_internal(max_iterations))?;
+        Ok(effects)
+    }
+
+    /// 手动撮合市价单（对应链上 matchMarketOrders）
+    pub fn match_market_orders(&mut self, max_iterations: usize) -> Result<SimEffects, SimulatorError> {
+        let (_, effects) = self.atomically(|sim| sim.match_market_orders_internal(max_iterations))?;
+        Ok(effects)
+    }
+
+    /// 内部撮合逻辑（对应链上 _matchOrdersInternal），返回成交次数
+    fn match_orders_internal(&mut self, max_iterations: usize) -> Result<usize, SimulatorError> {
+        let mut total_trades = 0;
+
         for _ in 0..max_iterations {
             // 获取最优买价和卖价
             let bid_price = self.bid_head;
@@ -456,21 +1154,11 @@ impl OrderBookSimulator {
                 break;
             }
 
-            let bid_key = Self::get_price_level_key(bid_price, false);
-            let ask_key = Self::get_price_level_key(ask_price, true);
-
             // 获取价格层级
-            let (bid_level_price, bid_head_order) = if let Some(level) = self.price_levels.get(&bid_key) {
-                (level.price, level.head_order_id)
-            } else {
-                break;
-            };
-
-            let (ask_level_price, ask_head_order) = if let Some(level) = self.price_levels.get(&ask_key) {
-                (level.price, level.head_order_id)
-            } else {
-                break;
-            };
+            let bid_level = self.level(bid_price, false)?;
+            let (bid_level_price, bid_head_order) = (bid_level.price, bid_level.head_order_id);
+            let ask_level = self.level(ask_price, true)?;
+            let (ask_level_price, ask_head_order) = (ask_level.price, ask_level.head_order_id);
 
             // 检查是否可以成交：买价 >= 卖价
             if bid_level_price < ask_level_price {
@@ -483,158 +1171,174 @@ impl OrderBookSimulator {
             }
 
             // 执行撮合
-            let traded = self.execute_trade(bid_head_order, ask_head_order);
+            let traded = self.execute_trade(bid_head_order, ask_head_order)?;
             if !traded {
                 break;
             }
+            total_trades += 1;
+        }
+
+        Ok(total_trades)
+    }
+
+    /// 订单剩余未成交数量
+    pub(crate) fn remaining_of(&self, order_id: U256) -> Result<U256, SimulatorError> {
+        let order = self.order(order_id)?;
+        checked_sub(order.amount, order.filled_amount, "order remaining amount")
+    }
+
+    /// 增加订单已成交数量，返回是否完全成交（链上以 == 判断）
+    fn fill_order(&mut self, order_id: U256, amount: U256) -> Result<bool, SimulatorError> {
+        let order = self.order_mut(order_id)?;
+        order.filled_amount = checked_add(order.filled_amount, amount, "order filled_amount")?;
+        Ok(order.filled_amount == order.amount)
+    }
+
+    /// 扣减价格层级的总挂单量
+    fn reduce_total_volume(&mut self, price: U256, is_ask: bool, amount: U256) -> Result<(), SimulatorError> {
+        let level = self.level_mut(price, is_ask)?;
+        level.total_volume = checked_sub(level.total_volume, amount, "price level total_volume")?;
+        Ok(())
+    }
+
+    /// 记录一笔成交（在更新订单之前调用），买卖双方为同一交易者时同时记为自成交
+    /// trader 为零地址表示未知（如同步时无法确定下单者），不计为自成交
+    fn record_trade(&mut self, trade: SimTrade) -> Result<(), SimulatorError> {
+        let buyer = self.order(trade.buy_order_id)?.trader;
+        let seller = self.order(trade.sell_order_id)?.trader;
+        if buyer == seller && !buyer.is_zero() {
+            self.effects.self_trades.push(SimSelfTrade {
+                trader: buyer,
+                buy_order_id: trade.buy_order_id,
+                sell_order_id: trade.sell_order_id,
+                price: trade.price,
+                amount: trade.amount,
+            });
         }
+        self.effects.trades.push(trade);
+        Ok(())
     }
 
     /// 执行单笔交易（对应链上 _executeTrade）
-    fn execute_trade(&mut self, bid_order_id: U256, ask_order_id: U256) -> bool {
+    fn execute_trade(&mut self, bid_order_id: U256, ask_order_id: U256) -> Result<bool, SimulatorError> {
         // 获取订单信息
-        let (bid_remaining, bid_price_level) = if let Some(order) = self.orders.get(&bid_order_id) {
-            (order.amount - order.filled_amount, order.price_level)
-        } else {
-            return false;
-        };
-
-        let (ask_remaining, ask_price_level) = if let Some(order) = self.orders.get(&ask_order_id) {
-            (order.amount - order.filled_amount, order.price_level)
-        } else {
-            return false;
-        };
+        let bid_remaining = self.remaining_of(bid_order_id)?;
+        let bid_price_level = self.order(bid_order_id)?.price_level;
+        let ask_remaining = self.remaining_of(ask_order_id)?;
+        let ask_price_level = self.order(ask_order_id)?.price_level;
 
         // 计算成交数量
         let trade_amount = bid_remaining.min(ask_remaining);
         if trade_amount.is_zero() {
-            return false;
+            return Ok(false);
         }
 
+        // 成交价格：取卖单价格（与链上一致）
+        self.record_trade(SimTrade {
+            buy_order_id: bid_order_id,
+            sell_order_id: ask_order_id,
+            price: ask_price_level,
+            amount: trade_amount,
+        })?;
+
         // 更新订单已成交数量
-        if let Some(bid_order) = self.orders.get_mut(&bid_order_id) {
-            bid_order.filled_amount = bid_order.filled_amount + trade_amount;
-        }
-        if let Some(ask_order) = self.orders.get_mut(&ask_order_id) {
-            ask_order.filled_amount = ask_order.filled_amount + trade_amount;
-        }
+        let bid_fully_filled = self.fill_order(bid_order_id, trade_amount)?;
+        let ask_fully_filled = self.fill_order(ask_order_id, trade_amount)?;
 
         // 更新价格层级的总挂单量
-        let bid_key = Self::get_price_level_key(bid_price_level, false);
-        if let Some(level) = self.price_levels.get_mut(&bid_key) {
-            level.total_volume = level.total_volume.saturating_sub(trade_amount);
-        }
-
-        let ask_key = Self::get_price_level_key(ask_price_level, true);
-        if let Some(level) = self.price_levels.get_mut(&ask_key) {
-            level.total_volume = level.total_volume.saturating_sub(trade_amount);
-        }
-
-        // 检查买单是否完全成交
-        let bid_fully_filled = if let Some(order) = self.orders.get(&bid_order_id) {
-            order.filled_amount >= order.amount
-        } else {
-            false
-        };
+        self.reduce_total_volume(bid_price_level, false, trade_amount)?;
+        self.reduce_total_volume(ask_price_level, true, trade_amount)?;
 
+        // 买单完全成交则移除
         if bid_fully_filled {
-            self.remove_filled_order(bid_order_id, false);
+            self.remove_filled_order(bid_order_id, false)?;
         }
+        self.effects.fills.push(SimFill {
+            order_id: bid_order_id,
+            filled_amount: trade_amount,
+            is_fully_filled: bid_fully_filled,
+        });
 
-        // 检查卖单是否完全成交
-        let ask_fully_filled = if let Some(order) = self.orders.get(&ask_order_id) {
-            order.filled_amount >= order.amount
-        } else {
-            false
-        };
-
+        // 卖单完全成交则移除
         if ask_fully_filled {
-            self.remove_filled_order(ask_order_id, true);
+            self.remove_filled_order(ask_order_id, true)?;
         }
+        self.effects.fills.push(SimFill {
+            order_id: ask_order_id,
+            filled_amount: trade_amount,
+            is_fully_filled: ask_fully_filled,
+        });
 
-        true
+        Ok(true)
     }
 
     /// 移除已完全成交的订单（对应链上 _removeFilledOrder）
-    fn remove_filled_order(&mut self, order_id: U256, is_ask: bool) {
-        let price_level_id = if let Some(order) = self.orders.get(&order_id) {
-            order.price_level
-        } else {
-            return;
-        };
+    fn remove_filled_order(&mut self, order_id: U256, is_ask: bool) -> Result<(), SimulatorError> {
+        let price_level_id = self.order(order_id)?.price_level;
 
         // 从价格层级中移除订单
-        self.remove_order_from_price_level(price_level_id, order_id, is_ask);
+        self.remove_order_from_price_level(price_level_id, order_id, is_ask)?;
 
         // 如果价格层级没有订单了，删除该价格层级
-        let level_key = Self::get_price_level_key(price_level_id, is_ask);
-        let should_remove_level = if let Some(level) = self.price_levels.get(&level_key) {
-            level.head_order_id.is_zero()
-        } else {
-            false
-        };
-
-        if should_remove_level {
-            self.remove_price_level(price_level_id, is_ask);
+        if self.level(price_level_id, is_ask)?.head_order_id.is_zero() {
+            self.remove_price_level(price_level_id, is_ask)?;
         }
 
         // 删除订单数据
-        self.orders.remove(&order_id);
+        self.remove_order_data(order_id);
+        self.effects.removed_orders.push(order_id);
+        Ok(())
+    }
+
+    /// 移除已完全成交的市价单：从市价单列表中移除并删除订单数据
+    fn remove_filled_market_order(&mut self, order_id: U256, is_ask: bool) -> Result<(), SimulatorError> {
+        self.remove_market_order_from_list(order_id, is_ask)?;
+        self.remove_order_data(order_id);
+        self.effects.removed_orders.push(order_id);
+        Ok(())
     }
 
     /// 从价格层级的订单列表中移除订单（对应链上 _removeOrderFromPriceLevel）
-    fn remove_order_from_price_level(&mut self, price_level_id: U256, order_id: U256, is_ask: bool) {
-        let (prev_order_id, next_order_id) = if let Some(order) = self.orders.get(&order_id) {
-            (order.prev_order_id, order.next_order_id)
-        } else {
-            return;
-        };
+    fn remove_order_from_price_level(
+        &mut self,
+        price_level_id: U256,
+        order_id: U256,
+        is_ask: bool,
+    ) -> Result<(), SimulatorError> {
+        let remaining = self.remaining_of(order_id)?;
+        let order = self.order(order_id)?;
+        let (prev_order_id, next_order_id) = (order.prev_order_id, order.next_order_id);
 
         // 更新前一个订单的 next
         if !prev_order_id.is_zero() {
-            if let Some(prev_order) = self.orders.get_mut(&prev_order_id) {
-                prev_order.next_order_id = next_order_id;
-            }
+            self.order_mut(prev_order_id)?.next_order_id = next_order_id;
         } else {
             // 这是头节点
-            let level_key = Self::get_price_level_key(price_level_id, is_ask);
-            if let Some(level) = self.price_levels.get_mut(&level_key) {
-                level.head_order_id = next_order_id;
-            }
+            self.level_mut(price_level_id, is_ask)?.head_order_id = next_order_id;
         }
 
         // 更新后一个订单的 prev
         if !next_order_id.is_zero() {
-            if let Some(next_order) = self.orders.get_mut(&next_order_id) {
-                next_order.prev_order_id = prev_order_id;
-            }
+            self.order_mut(next_order_id)?.prev_order_id = prev_order_id;
         } else {
             // 这是尾节点
-            let level_key = Self::get_price_level_key(price_level_id, is_ask);
-            if let Some(level) = self.price_levels.get_mut(&level_key) {
-                level.tail_order_id = prev_order_id;
-            }
+            self.level_mut(price_level_id, is_ask)?.tail_order_id = prev_order_id;
         }
+
+        // 更新价格层级的总挂单量（已完全成交的订单剩余量为 0）
+        self.reduce_total_volume(price_level_id, is_ask, remaining)
     }
 
     /// 从列表中移除价格层级（对应链上 _removePriceLevel）
-    fn remove_price_level(&mut self, price_level_id: U256, is_ask: bool) {
-        let level_key = Self::get_price_level_key(price_level_id, is_ask);
-
-        let (prev_price, next_price) = if let Some(level) = self.price_levels.get(&level_key) {
-            (level.prev_price, level.next_price)
-        } else {
-            return;
-        };
+    fn remove_price_level(&mut self, price_level_id: U256, is_ask: bool) -> Result<(), SimulatorError> {
+        let level = self.level(price_level_id, is_ask)?;
+        let (prev_price, next_price) = (level.prev_price, level.next_price);
 
         debug!("Removing empty price level: price={}, is_ask={}", price_level_id, is_ask);
 
         // 更新前一个价格层级的 next
         if !prev_price.is_zero() {
-            let prev_key = Self::get_price_level_key(prev_price, is_ask);
-            if let Some(prev_level) = self.price_levels.get_mut(&prev_key) {
-                prev_level.next_price = next_price;
-            }
+            self.level_mut(prev_price, is_ask)?.next_price = next_price;
         } else {
             // 这是头节点
             if is_ask {
@@ -646,10 +1350,7 @@ impl OrderBookSimulator {
 
         // 更新后一个价格层级的 prev
         if !next_price.is_zero() {
-            let next_key = Self::get_price_level_key(next_price, is_ask);
-            if let Some(next_level) = self.price_levels.get_mut(&next_key) {
-                next_level.prev_price = prev_price;
-            }
+            self.level_mut(next_price, is_ask)?.prev_price = prev_price;
         } else {
             // 这是尾节点
             if is_ask {
@@ -660,7 +1361,12 @@ impl OrderBookSimulator {
         }
 
         // 删除价格层级
-        self.price_levels.remove(&level_key);
+        self.remove_level_data(Self::get_price_level_key(price_level_id, is_ask));
+        self.effects.levels_removed.push(SimPriceLevelChange {
+            price: price_level_id,
+            is_ask,
+        });
+        Ok(())
     }
 
     /// 获取所有价格层级（用于调试）
@@ -705,34 +1411,49 @@ impl OrderBookSimulator {
 
     /// 模拟插入市价单（对应链上 insertMarketOrder）
     /// 市价单总是插入到队尾（FIFO），不需要 insertAfterPrice
-    pub fn simulate_insert_market_order(&mut self, order_id: U256, amount: U256, is_ask: bool) {
+    pub fn simulate_insert_market_order(
+        &mut self,
+        order_id: U256,
+        trader: Address,
+        amount: U256,
+        is_ask: bool,
+    ) -> Result<SimEffects, SimulatorError> {
+        self.check_new_order(order_id, None, amount)?;
+
         debug!(
             "Inserting market order {} (amount={}, is_ask={})",
             order_id, amount, is_ask
         );
 
-        // 创建市价单
-        let order = SimOrder {
-            id: order_id,
-            amount,
-            filled_amount: EMPTY,
-            is_market_order: true,
-            is_ask,
-            price_level: EMPTY, // 市价单不需要价格层级
-            next_order_id: EMPTY,
-            prev_order_id: EMPTY,
-        };
-        self.orders.insert(order_id, order);
+        let ((), effects) = self.atomically(|sim| {
+            // 创建市价单
+            let order = SimOrder {
+                id: order_id,
+                trader,
+                amount,
+                filled_amount: EMPTY,
+                is_market_order: true,
+                is_ask,
+                price_level: EMPTY, // 市价单不需要价格层级
+                next_order_id: EMPTY,
+                prev_order_id: EMPTY,
+                requested: BlockStamp::default(),
+                inserted: BlockStamp::default(),
+            };
+            sim.insert_order_data(order);
 
-        // 插入到市价单队列尾部
-        self.insert_market_order_at_tail(order_id, is_ask);
+            // 插入到市价单队列尾部
+            sim.insert_market_order_at_tail(order_id, is_ask)?;
 
-        // 执行撮合
-        self.try_match_after_insertion();
+            // 执行撮合
+            sim.try_match_after_insertion()
+        })?;
+
+        Ok(effects)
     }
 
     /// 将市价单插入到队尾（对应链上 _insertMarketOrderAtTail）
-    fn insert_market_order_at_tail(&mut self, order_id: U256, is_ask: bool) {
+    fn insert_market_order_at_tail(&mut self, order_id: U256, is_ask: bool) -> Result<(), SimulatorError> {
         let old_tail = if is_ask {
             self.market_ask_tail
         } else {
@@ -750,12 +1471,8 @@ impl OrderBookSimulator {
             }
         } else {
             // 插入到尾部
-            if let Some(tail_order) = self.orders.get_mut(&old_tail) {
-                tail_order.next_order_id = order_id;
-            }
-            if let Some(new_order) = self.orders.get_mut(&order_id) {
-                new_order.prev_order_id = old_tail;
-            }
+            self.order_mut(old_tail)?.next_order_id = order_id;
+            self.order_mut(order_id)?.prev_order_id = old_tail;
 
             // 更新 tail
             if is_ask {
@@ -764,21 +1481,18 @@ impl OrderBookSimulator {
                 self.market_bid_tail = order_id;
             }
         }
+
+        Ok(())
     }
 
     /// 从市价单列表中移除订单（对应链上 _removeMarketOrderFromList）
-    fn remove_market_order_from_list(&mut self, order_id: U256, is_ask: bool) {
-        let (prev_order_id, next_order_id) = if let Some(order) = self.orders.get(&order_id) {
-            (order.prev_order_id, order.next_order_id)
-        } else {
-            return;
-        };
+    fn remove_market_order_from_list(&mut self, order_id: U256, is_ask: bool) -> Result<(), SimulatorError> {
+        let order = self.order(order_id)?;
+        let (prev_order_id, next_order_id) = (order.prev_order_id, order.next_order_id);
 
         // 更新前一个订单的 next
         if !prev_order_id.is_zero() {
-            if let Some(prev_order) = self.orders.get_mut(&prev_order_id) {
-                prev_order.next_order_id = next_order_id;
-            }
+            self.order_mut(prev_order_id)?.next_order_id = next_order_id;
         } else {
             // 这是头节点
             if is_ask {
@@ -790,9 +1504,7 @@ impl OrderBookSimulator {
 
         // 更新后一个订单的 prev
         if !next_order_id.is_zero() {
-            if let Some(next_order) = self.orders.get_mut(&next_order_id) {
-                next_order.prev_order_id = prev_order_id;
-            }
+            self.order_mut(next_order_id)?.prev_order_id = prev_order_id;
         } else {
             // 这是尾节点
             if is_ask {
@@ -801,73 +1513,49 @@ impl OrderBookSimulator {
                 self.market_bid_tail = prev_order_id;
             }
         }
+
+        Ok(())
     }
 
-    /// 市价单撮合逻辑（对应链上 _matchMarketOrdersInternal）
-    fn match_market_orders_internal(&mut self, max_iterations: usize) {
-        let mut iterations = 0;
+    /// 市价单撮合逻辑（对应链上 _matchMarketOrdersInternal），返回成交次数
+    ///
+    /// 每次循环优先撮合市价买单与最优卖价，成交则进入下一次循环；
+    /// 否则尝试市价卖单与最优买价；两者都未成交则退出
+    fn match_market_orders_internal(&mut self, max_iterations: usize) -> Result<usize, SimulatorError> {
+        let mut total_trades = 0;
 
-        // 1. 匹配市价买单与最优卖价（限价单）
-        while iterations < max_iterations {
+        for _ in 0..max_iterations {
+            // 1. 市价买单与最优卖价（限价单）
             let market_bid_head = self.market_bid_head;
-            let ask_head = self.ask_head;
-
-            // 如果任意一方为空，跳出
-            if market_bid_head.is_zero() || ask_head.is_zero() {
-                break;
-            }
-
-            // 获取限价卖单队列头部订单
-            let ask_key = Self::get_price_level_key(ask_head, true);
-            let ask_head_order = if let Some(level) = self.price_levels.get(&ask_key) {
-                level.head_order_id
-            } else {
-                break;
-            };
-
-            if ask_head_order.is_zero() {
-                break;
-            }
-
-            // 执行市价买单与限价卖单的撮合
-            let traded = self.execute_market_trade(market_bid_head, ask_head_order, false);
-            if !traded {
-                break;
+            if !market_bid_head.is_zero() && !self.ask_head.is_zero() {
+                let ask_head_order = self.level(self.ask_head, true)?.head_order_id;
+
+                if !ask_head_order.is_zero()
+                    && self.execute_market_trade(market_bid_head, ask_head_order, false)?
+                {
+                    total_trades += 1;
+                    continue;
+                }
             }
 
-            iterations += 1;
-        }
-
-        // 2. 匹配市价卖单与最优买价（限价单）
-        while iterations < max_iterations {
+            // 2. 市价卖单与最优买价（限价单）
             let market_ask_head = self.market_ask_head;
-            let bid_head = self.bid_head;
-
-            // 如果任意一方为空，跳出
-            if market_ask_head.is_zero() || bid_head.is_zero() {
-                break;
-            }
-
-            // 获取限价买单队列头部订单
-            let bid_key = Self::get_price_level_key(bid_head, false);
-            let bid_head_order = if let Some(level) = self.price_levels.get(&bid_key) {
-                level.head_order_id
-            } else {
-                break;
-            };
-
-            if bid_head_order.is_zero() {
-                break;
-            }
-
-            // 执行市价卖单与限价买单的撮合
-            let traded = self.execute_market_trade(market_ask_head, bid_head_order, true);
-            if !traded {
-                break;
+            if !market_ask_head.is_zero() && !self.bid_head.is_zero() {
+                let bid_head_order = self.level(self.bid_head, false)?.head_order_id;
+
+                if !bid_head_order.is_zero()
+                    && self.execute_market_trade(market_ask_head, bid_head_order, true)?
+                {
+                    total_trades += 1;
+                    continue;
+                }
             }
 
-            iterations += 1;
+            // 没有成交，退出循环
+            break;
         }
+
+        Ok(total_trades)
     }
 
     /// 执行市价单与限价单的交易
@@ -881,37 +1569,29 @@ impl OrderBookSimulator {
         market_order_id: U256,
         limit_order_id: U256,
         is_market_ask: bool,
-    ) -> bool {
-        // 获取市价单信息
-        let (market_amount, market_filled) = if let Some(order) = self.orders.get(&market_order_id) {
-            (order.amount, order.filled_amount)
-        } else {
-            return false;
-        };
+    ) -> Result<bool, SimulatorError> {
+        // 市价单剩余数量（卖单为 base tokens，买单为 quote tokens）
+        let market_remaining = self.remaining_of(market_order_id)?;
 
-        // 获取限价单信息
-        let (limit_remaining, limit_price_level) = if let Some(order) = self.orders.get(&limit_order_id) {
-            (order.amount - order.filled_amount, order.price_level)
-        } else {
-            return false;
-        };
+        // 获取限价单信息
+        let limit_remaining = self.remaining_of(limit_order_id)?;
+        let limit_price_level = self.order(limit_order_id)?.price_level;
 
         // 计算成交数量（以 base tokens 为单位）
         let trade_amount = if is_market_ask {
             // 市价卖单：amount 是 base tokens，直接比较
-            let market_remaining = market_amount - market_filled;
             market_remaining.min(limit_remaining)
         } else {
             // 市价买单：amount 是 quote tokens（计价代币），需要转换成 base tokens
-            // quote_remaining = 剩余可花费的计价代币数量
-            let quote_remaining = market_amount - market_filled;
             // base = quote * PRICE_DECIMALS / price
-            let market_remaining_base = quote_remaining * PRICE_DECIMALS / limit_price_level;
-            market_remaining_base.min(limit_remaining)
+            let market_remaining_base = QuoteAmount::from_raw(market_remaining)
+                .checked_div_price(Price::from_raw(limit_price_level))
+                .map_err(|e| e.in_context("market bid base amount"))?;
+            market_remaining_base.raw().min(limit_remaining)
         };
 
         if trade_amount.is_zero() {
-            return false;
+            return Ok(false);
         }
 
         debug!(
@@ -919,57 +1599,75 @@ impl OrderBookSimulator {
             market_order_id, limit_order_id, trade_amount, is_market_ask
         );
 
+        // 市价单使用对手价成交
+        let (buy_order_id, sell_order_id) = if is_market_ask {
+            (limit_order_id, market_order_id)
+        } else {
+            (market_order_id, limit_order_id)
+        };
+        self.record_trade(SimTrade {
+            buy_order_id,
+            sell_order_id,
+            price: limit_price_level,
+            amount: trade_amount,
+        })?;
+
         // 更新市价单已成交数量
-        if let Some(order) = self.orders.get_mut(&market_order_id) {
-            if is_market_ask {
-                // 市价卖单：filled_amount 是 base tokens
-                order.filled_amount = order.filled_amount + trade_amount;
-            } else {
-                // 市价买单：filled_amount 是 quote tokens（追踪花费的计价代币）
-                // quote_spent = trade_amount * price / PRICE_DECIMALS
-                let quote_spent = trade_amount * limit_price_level / PRICE_DECIMALS;
-                order.filled_amount = order.filled_amount + quote_spent;
-            }
-        }
+        let market_filled = if is_market_ask {
+            // 市价卖单：filled_amount 是 base tokens
+            trade_amount
+        } else {
+            // 市价买单：filled_amount 是 quote tokens（追踪花费的计价代币）
+            // quote_spent = trade_amount * price / PRICE_DECIMALS
+            Amount::from_raw(trade_amount)
+                .checked_mul_price(Price::from_raw(limit_price_level))
+                .map_err(|e| e.in_context("market bid quote spent"))?
+                .raw()
+        };
+        let market_fully_filled = self.fill_order(market_order_id, market_filled)?;
 
         // 更新限价单已成交数量 (always in base tokens)
-        if let Some(order) = self.orders.get_mut(&limit_order_id) {
-            order.filled_amount = order.filled_amount + trade_amount;
-        }
+        let limit_fully_filled = self.fill_order(limit_order_id, trade_amount)?;
 
         // 更新限价单所在价格层级的总挂单量
         let limit_is_ask = !is_market_ask;
-        let limit_key = Self::get_price_level_key(limit_price_level, limit_is_ask);
-        if let Some(level) = self.price_levels.get_mut(&limit_key) {
-            level.total_volume = level.total_volume.saturating_sub(trade_amount);
-        }
+        self.reduce_total_volume(limit_price_level, limit_is_ask, trade_amount)?;
 
-        // 检查市价单是否完全成交
-        let market_fully_filled = if let Some(order) = self.orders.get(&market_order_id) {
-            order.filled_amount >= order.amount
+        // 与链上 _executeTrade 一致：先移除完全成交的买单，再移除卖单
+        if is_market_ask {
+            if limit_fully_filled {
+                self.remove_filled_order(limit_order_id, limit_is_ask)?;
+            }
+            if market_fully_filled {
+                self.remove_filled_market_order(market_order_id, is_market_ask)?;
+            }
         } else {
-            false
-        };
-
-        if market_fully_filled {
-            // 从市价单列表中移除
-            self.remove_market_order_from_list(market_order_id, is_market_ask);
-            // 删除订单数据
-            self.orders.remove(&market_order_id);
+            if market_fully_filled {
+                self.remove_filled_market_order(market_order_id, is_market_ask)?;
+            }
+            if limit_fully_filled {
+                self.remove_filled_order(limit_order_id, limit_is_ask)?;
+            }
         }
 
-        // 检查限价单是否完全成交
-        let limit_fully_filled = if let Some(order) = self.orders.get(&limit_order_id) {
-            order.filled_amount >= order.amount
-        } else {
-            false
+        // 链上先发出买单的 OrderFilled，再发出卖单的
+        let market_fill = SimFill {
+            order_id: market_order_id,
+            filled_amount: trade_amount,
+            is_fully_filled: market_fully_filled,
         };
-
-        if limit_fully_filled {
-            self.remove_filled_order(limit_order_id, limit_is_ask);
+        let limit_fill = SimFill {
+            order_id: limit_order_id,
+            filled_amount: trade_amount,
+            is_fully_filled: limit_fully_filled,
+        };
+        if is_market_ask {
+            self.effects.fills.extend([limit_fill, market_fill]);
+        } else {
+            self.effects.fills.extend([market_fill, limit_fill]);
         }
 
-        true
+        Ok(true)
     }
 
     /// 获取市价单列表（用于调试）
@@ -992,11 +1690,281 @@ impl OrderBookSimulator {
 
         order_ids
     }
+
+    /// 检查订单簿结构不变量，返回全部违反项（为空表示一致）
+    ///
+    /// - 价格链表：双向指针一致、ask 严格升序 / bid 严格降序、无环、tail 正确、
+    ///   所有价格层级都可从 head 到达，且与价格索引一致
+    /// - 订单链表：双向指针一致、无环、tail 正确、订单的价格与方向与所在层级一致，
+    ///   total_volume 等于层级内订单剩余量之和，已完全成交的订单不在簿上
+    /// - 市价单队列：同上（不含价格与 total_volume）
+    /// - 每个订单都恰好能从某个链表到达
+    /// - 交易者索引与订单数据一致
+    pub fn validate(&self) -> Vec<InvariantViolation> {
+        let mut violations = Vec::new();
+        let mut reached_orders = HashSet::new();
+
+        for is_ask in [true, false] {
+            let prices = self.validate_price_list(is_ask, &mut violations);
+            for price in prices {
+                self.validate_order_list(price, is_ask, &mut reached_orders, &mut violations);
+            }
+            self.validate_market_queue(is_ask, &mut reached_orders, &mut violations);
+        }
+
+        let mut unlinked: Vec<U256> = self
+            .orders
+            .keys()
+            .filter(|order_id| !reached_orders.contains(*order_id))
+            .copied()
+            .collect();
+        unlinked.sort();
+        violations.extend(
+            unlinked
+                .into_iter()
+                .map(|order_id| InvariantViolation::UnlinkedOrder { order_id }),
+        );
+
+        // 交易者索引与订单数据一致
+        let mut expected: HashMap<Address, BTreeSet<U256>> = HashMap::new();
+        for order in self.orders.values() {
+            expected.entry(order.trader).or_default().insert(order.id);
+        }
+        let mut traders: Vec<Address> = expected
+            .keys()
+            .chain(self.trader_orders.keys())
+            .copied()
+            .collect::<HashSet<_>>()
+            .into_iter()
+            .filter(|trader| expected.get(trader) != self.trader_orders.get(trader))
+            .collect();
+        traders.sort();
+        violations.extend(
+            traders
+                .into_iter()
+                .map(|trader| InvariantViolation::TraderIndexMismatch { trader }),
+        );
+
+        violations
+    }
+
+    /// 检查一侧的价格链表，返回从 head 可到达的价格
+    fn validate_price_list(&self, is_ask: bool, violations: &mut Vec<InvariantViolation>) -> Vec<U256> {
+        let (head, tail) = if is_ask {
+            (self.ask_head, self.ask_tail)
+        } else {
+            (self.bid_head, self.bid_tail)
+        };
+
+        let mut prices = Vec::new();
+        let mut prev_price = EMPTY;
+        let mut current = head;
+        while !current.is_zero() {
+            if prices.len() > self.price_levels.len() {
+                violations.push(InvariantViolation::PriceCycle { is_ask });
+                break;
+            }
+            let key = Self::get_price_level_key(current, is_ask);
+            let Some(level) = self.price_levels.get(&key) else {
+                violations.push(InvariantViolation::MissingPriceLevel {
+                    price: current,
+                    is_ask,
+                });
+                break;
+            };
+            if level.prev_price != prev_price {
+                violations.push(InvariantViolation::BrokenPriceLink {
+                    price: current,
+                    is_ask,
+                    expected: prev_price,
+                    actual: level.prev_price,
+                });
+            }
+            if !prev_price.is_zero() {
+                let sorted = if is_ask {
+                    prev_price < current
+                } else {
+                    prev_price > current
+                };
+                if !sorted {
+                    violations.push(InvariantViolation::PriceOrder {
+                        price: prev_price,
+                        next_price: current,
+                        is_ask,
+                    });
+                }
+            }
+            prices.push(current);
+            prev_price = current;
+            current = level.next_price;
+        }
+
+        if tail != prev_price {
+            violations.push(InvariantViolation::PriceTailMismatch {
+                is_ask,
+                expected: prev_price,
+                actual: tail,
+            });
+        }
+
+        let reachable: HashSet<U256> = prices.iter().copied().collect();
+        let mut unlinked: Vec<U256> = self
+            .price_levels
+            .keys()
+            .map(|key| Self::split_price_level_key(*key))
+            .filter(|(price, level_is_ask)| *level_is_ask == is_ask && !reachable.contains(price))
+            .map(|(price, _)| price)
+            .collect();
+        unlinked.sort();
+        violations.extend(
+            unlinked
+                .into_iter()
+                .map(|price| InvariantViolation::UnlinkedPriceLevel { price, is_ask }),
+        );
+
+        let index_matches = self.price_index(is_ask).len() == reachable.len()
+            && self.price_index(is_ask).iter().all(|price| reachable.contains(price));
+        if !index_matches {
+            violations.push(InvariantViolation::PriceIndexMismatch { is_ask });
+        }
+
+        prices
+    }
+
+    /// 检查一个价格层级内的订单链表与 total_volume
+    fn validate_order_list(
+        &self,
+        price: U256,
+        is_ask: bool,
+        reached_orders: &mut HashSet<U256>,
+        violations: &mut Vec<InvariantViolation>,
+    ) {
+        let key = Self::get_price_level_key(price, is_ask);
+        let Some(level) = self.price_levels.get(&key) else {
+            return;
+        };
+
+        let mut remaining = EMPTY;
+        let mut count = 0;
+        let mut prev_order_id = EMPTY;
+        let mut current = level.head_order_id;
+        while !current.is_zero() {
+            if count > self.orders.len() {
+                violations.push(InvariantViolation::OrderCycle { price, is_ask });
+                break;
+            }
+            let Some(order) = self.orders.get(&current) else {
+                violations.push(InvariantViolation::MissingOrder {
+                    order_id: current,
+                    price,
+                    is_ask,
+                });
+                break;
+            };
+            reached_orders.insert(current);
+            self.validate_order_node(order, prev_order_id, violations);
+            if order.is_market_order || order.is_ask != is_ask || order.price_level != price {
+                violations.push(InvariantViolation::OrderListMismatch { order_id: current });
+            }
+            remaining = remaining.saturating_add(order.amount.saturating_sub(order.filled_amount));
+            count += 1;
+            prev_order_id = current;
+            current = order.next_order_id;
+        }
+
+        if level.tail_order_id != prev_order_id {
+            violations.push(InvariantViolation::OrderTailMismatch {
+                price,
+                is_ask,
+                expected: prev_order_id,
+                actual: level.tail_order_id,
+            });
+        }
+        if level.total_volume != remaining {
+            violations.push(InvariantViolation::TotalVolumeMismatch {
+                price,
+                is_ask,
+                recorded: level.total_volume,
+                expected: remaining,
+            });
+        }
+    }
+
+    /// 检查一侧的市价单队列
+    fn validate_market_queue(
+        &self,
+        is_ask: bool,
+        reached_orders: &mut HashSet<U256>,
+        violations: &mut Vec<InvariantViolation>,
+    ) {
+        let (head, tail) = if is_ask {
+            (self.market_ask_head, self.market_ask_tail)
+        } else {
+            (self.market_bid_head, self.market_bid_tail)
+        };
+
+        let mut count = 0;
+        let mut prev_order_id = EMPTY;
+        let mut current = head;
+        while !current.is_zero() {
+            if count > self.orders.len() {
+                violations.push(InvariantViolation::MarketQueueCycle { is_ask });
+                break;
+            }
+            let Some(order) = self.orders.get(&current) else {
+                violations.push(InvariantViolation::MissingMarketOrder {
+                    order_id: current,
+                    is_ask,
+                });
+                break;
+            };
+            reached_orders.insert(current);
+            self.validate_order_node(order, prev_order_id, violations);
+            if !order.is_market_order || order.is_ask != is_ask {
+                violations.push(InvariantViolation::OrderListMismatch { order_id: current });
+            }
+            count += 1;
+            prev_order_id = current;
+            current = order.next_order_id;
+        }
+
+        if tail != prev_order_id {
+            violations.push(InvariantViolation::MarketTailMismatch {
+                is_ask,
+                expected: prev_order_id,
+                actual: tail,
+            });
+        }
+    }
+
+    /// 订单节点本身的检查：prev 指针与成交状态
+    fn validate_order_node(
+        &self,
+        order: &SimOrder,
+        prev_order_id: U256,
+        violations: &mut Vec<InvariantViolation>,
+    ) {
+        if order.prev_order_id != prev_order_id {
+            violations.push(InvariantViolation::BrokenOrderLink {
+                order_id: order.id,
+                expected: prev_order_id,
+                actual: order.prev_order_id,
+            });
+        }
+        if order.filled_amount >= order.amount {
+            violations.push(InvariantViolation::FilledOrderOnBook {
+                order_id: order.id,
+                amount: order.amount,
+                filled_amount: order.filled_amount,
+            });
+        }
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::constants::PRICE_DECIMALS;
 
     #[test]
     fn test_insert_single_order() {
@@ -1005,12 +1973,13 @@ mod tests {
         // 插入一个买单
         let insert_after = sim.simulate_insert_order(
             U256::from(1),
+            Address::zero(),
             U256::from(100),
             U256::from(10),
             false, // bid
-        );
+        ).unwrap();
 
-        assert_eq!(insert_after, U256::zero()); // 空订单簿，插入头部
+        assert_eq!(insert_after.insert_after_price, U256::zero()); // 空订单簿，插入头部
         assert_eq!(sim.bid_head, U256::from(100));
         assert_eq!(sim.get_price_levels(false), vec![U256::from(100)]);
     }
@@ -1022,29 +1991,32 @@ mod tests {
         // 插入买单1: price=100
         let insert1 = sim.simulate_insert_order(
             U256::from(1),
+            Address::zero(),
             U256::from(100),
             U256::from(10),
             false,
-        );
-        assert_eq!(insert1, U256::zero());
+        ).unwrap();
+        assert_eq!(insert1.insert_after_price, U256::zero());
 
         // 插入买单2: price=90 (低于100，应该在100之后)
         let insert2 = sim.simulate_insert_order(
             U256::from(2),
+            Address::zero(),
             U256::from(90),
             U256::from(10),
             false,
-        );
-        assert_eq!(insert2, U256::from(100)); // 插入到100之后
+        ).unwrap();
+        assert_eq!(insert2.insert_after_price, U256::from(100)); // 插入到100之后
 
         // 插入买单3: price=110 (高于100，应该成为新头部)
         let insert3 = sim.simulate_insert_order(
             U256::from(3),
+            Address::zero(),
             U256::from(110),
             U256::from(10),
             false,
-        );
-        assert_eq!(insert3, U256::zero()); // 插入到头部
+        ).unwrap();
+        assert_eq!(insert3.insert_after_price, U256::zero()); // 插入到头部
 
         // 验证顺序: 110 -> 100 -> 90
         assert_eq!(sim.get_price_levels(false), vec![
@@ -1061,29 +2033,32 @@ mod tests {
         // 插入卖单1: price=100
         let insert1 = sim.simulate_insert_order(
             U256::from(1),
+            Address::zero(),
             U256::from(100),
             U256::from(10),
             true, // ask
-        );
-        assert_eq!(insert1, U256::zero());
+        ).unwrap();
+        assert_eq!(insert1.insert_after_price, U256::zero());
 
         // 插入卖单2: price=110 (高于100，应该在100之后)
         let insert2 = sim.simulate_insert_order(
             U256::from(2),
+            Address::zero(),
             U256::from(110),
             U256::from(10),
             true,
-        );
-        assert_eq!(insert2, U256::from(100)); // 插入到100之后
+        ).unwrap();
+        assert_eq!(insert2.insert_after_price, U256::from(100)); // 插入到100之后
 
         // 插入卖单3: price=90 (低于100，应该成为新头部)
         let insert3 = sim.simulate_insert_order(
             U256::from(3),
+            Address::zero(),
             U256::from(90),
             U256::from(10),
             true,
-        );
-        assert_eq!(insert3, U256::zero()); // 插入到头部
+        ).unwrap();
+        assert_eq!(insert3.insert_after_price, U256::zero()); // 插入到头部
 
         // 验证顺序: 90 -> 100 -> 110 (ask 从低到高)
         assert_eq!(sim.get_price_levels(true), vec![
@@ -1100,18 +2075,20 @@ mod tests {
         // 先插入一个买单: price=100, amount=10
         sim.simulate_insert_order(
             U256::from(1),
+            Address::zero(),
             U256::from(100),
             U256::from(10),
             false,
-        );
+        ).unwrap();
 
         // 插入一个卖单: price=100, amount=5 (应该匹配)
         sim.simulate_insert_order(
             U256::from(2),
+            Address::zero(),
             U256::from(100),
             U256::from(5),
             true,
-        );
+        ).unwrap();
 
         // 卖单完全成交，不应该在订单簿中
         assert!(!sim.orders.contains_key(&U256::from(2)));
@@ -1128,18 +2105,20 @@ mod tests {
         // 插入买单: price=100, amount=10
         sim.simulate_insert_order(
             U256::from(1),
+            Address::zero(),
             U256::from(100),
             U256::from(10),
             false,
-        );
+        ).unwrap();
 
         // 插入卖单: price=100, amount=10 (完全匹配)
         sim.simulate_insert_order(
             U256::from(2),
+            Address::zero(),
             U256::from(100),
             U256::from(10),
             true,
-        );
+        ).unwrap();
 
         // 买单价格层级应该被移除
         assert_eq!(sim.bid_head, U256::zero());
@@ -1157,21 +2136,23 @@ mod tests {
         // 插入买单: price=100, amount=10
         sim.simulate_insert_order(
             U256::from(1),
+            Address::zero(),
             U256::from(100),
             U256::from(10),
             false,
-        );
+        ).unwrap();
 
         // 插入卖单: price=90 (低于买单价格，会被撮合)
         let insert_after = sim.simulate_insert_order(
             U256::from(2),
+            Address::zero(),
             U256::from(90),
             U256::from(5),
             true,
-        );
+        ).unwrap();
 
         // insertAfterPrice 应该基于插入前的状态（ask 侧为空）
-        assert_eq!(insert_after, U256::zero());
+        assert_eq!(insert_after.insert_after_price, U256::zero());
 
         // 卖单完全成交
         assert!(!sim.orders.contains_key(&U256::from(2)));
@@ -1190,15 +2171,15 @@ mod tests {
         // 2. 卖单 @ 100 (会匹配)
         // 3. 买单 @ 95 (应该正确计算 insertAfterPrice)
 
-        sim.simulate_insert_order(U256::from(1), U256::from(100), U256::from(10), false);
-        sim.simulate_insert_order(U256::from(2), U256::from(100), U256::from(10), true);
+        sim.simulate_insert_order(U256::from(1), Address::zero(), U256::from(100), U256::from(10), false).unwrap();
+        sim.simulate_insert_order(U256::from(2), Address::zero(), U256::from(100), U256::from(10), true).unwrap();
 
         // 买单和卖单完全匹配后，订单簿为空
         assert!(sim.get_price_levels(false).is_empty());
 
         // 新买单应该插入到头部
-        let insert_after = sim.simulate_insert_order(U256::from(3), U256::from(95), U256::from(10), false);
-        assert_eq!(insert_after, U256::zero());
+        let insert_after = sim.simulate_insert_order(U256::from(3), Address::zero(), U256::from(95), U256::from(10), false).unwrap();
+        assert_eq!(insert_after.insert_after_price, U256::zero());
     }
 
     // ============ 市价单测试 ============
@@ -1217,11 +2198,11 @@ mod tests {
         let price = PRICE_DECIMALS;
 
         // 插入一个限价卖单: price=PRICE_DECIMALS, amount=10
-        sim.simulate_insert_order(U256::from(1), price, U256::from(10), true);
+        sim.simulate_insert_order(U256::from(1), Address::zero(), price, U256::from(10), true).unwrap();
 
         // 插入一个市价买单，花费 5 quote tokens
         // 由于 price = PRICE_DECIMALS，5 quote = 5 base
-        sim.simulate_insert_market_order(U256::from(2), U256::from(5), false);
+        sim.simulate_insert_market_order(U256::from(2), Address::zero(), U256::from(5), false).unwrap();
 
         // 市价买单完全成交，不应该在订单簿中
         assert!(!sim.orders.contains_key(&U256::from(2)));
@@ -1239,10 +2220,10 @@ mod tests {
         let price = PRICE_DECIMALS;
 
         // 插入限价卖单: price=PRICE_DECIMALS, amount=10
-        sim.simulate_insert_order(U256::from(1), price, U256::from(10), true);
+        sim.simulate_insert_order(U256::from(1), Address::zero(), price, U256::from(10), true).unwrap();
 
         // 插入市价买单，花费 10 quote tokens = 10 base tokens
-        sim.simulate_insert_market_order(U256::from(2), U256::from(10), false);
+        sim.simulate_insert_market_order(U256::from(2), Address::zero(), U256::from(10), false).unwrap();
 
         // 两个订单都应该被移除
         assert!(!sim.orders.contains_key(&U256::from(1)));
@@ -1260,11 +2241,11 @@ mod tests {
         let price = PRICE_DECIMALS;
 
         // 插入限价卖单: price=PRICE_DECIMALS, amount=5
-        sim.simulate_insert_order(U256::from(1), price, U256::from(5), true);
+        sim.simulate_insert_order(U256::from(1), Address::zero(), price, U256::from(5), true).unwrap();
 
         // 插入市价买单，花费 10 quote tokens
         // 但只有 5 base tokens 可买，所以只花费 5 quote tokens
-        sim.simulate_insert_market_order(U256::from(2), U256::from(10), false);
+        sim.simulate_insert_market_order(U256::from(2), Address::zero(), U256::from(10), false).unwrap();
 
         // 限价卖单完全成交，被移除
         assert!(!sim.orders.contains_key(&U256::from(1)));
@@ -1273,7 +2254,7 @@ mod tests {
         // filled_amount 是花费的 quote tokens = 5
         let market_order = sim.orders.get(&U256::from(2)).unwrap();
         assert_eq!(market_order.filled_amount, U256::from(5));
-        assert_eq!(market_order.is_market_order, true);
+        assert!(market_order.is_market_order);
 
         // 市价买单应该在队列中
         assert_eq!(sim.get_market_orders(false), vec![U256::from(2)]);
@@ -1284,10 +2265,10 @@ mod tests {
         let mut sim = OrderBookSimulator::new();
 
         // 插入限价买单: price=100, amount=10
-        sim.simulate_insert_order(U256::from(1), U256::from(100), U256::from(10), false);
+        sim.simulate_insert_order(U256::from(1), Address::zero(), U256::from(100), U256::from(10), false).unwrap();
 
         // 插入市价卖单
-        sim.simulate_insert_market_order(U256::from(2), U256::from(5), true);
+        sim.simulate_insert_market_order(U256::from(2), Address::zero(), U256::from(5), true).unwrap();
 
         // 市价卖单完全成交
         assert!(!sim.orders.contains_key(&U256::from(2)));
@@ -1315,9 +2296,9 @@ mod tests {
         let price_102 = PRICE_DECIMALS + U256::from(2);
 
         // 设置初始订单簿
-        sim.simulate_insert_order(U256::from(1), price_100, U256::from(10), true); // ask@PRICE_DECIMALS
-        sim.simulate_insert_order(U256::from(2), price_101, U256::from(10), true); // ask@PRICE_DECIMALS+1
-        sim.simulate_insert_order(U256::from(3), price_102, U256::from(10), true); // ask@PRICE_DECIMALS+2
+        sim.simulate_insert_order(U256::from(1), Address::zero(), price_100, U256::from(10), true).unwrap(); // ask@PRICE_DECIMALS
+        sim.simulate_insert_order(U256::from(2), Address::zero(), price_101, U256::from(10), true).unwrap(); // ask@PRICE_DECIMALS+1
+        sim.simulate_insert_order(U256::from(3), Address::zero(), price_102, U256::from(10), true).unwrap(); // ask@PRICE_DECIMALS+2
 
         assert_eq!(sim.get_price_levels(true), vec![
             price_100,
@@ -1327,7 +2308,7 @@ mod tests {
 
         // 市价买单，花费 10 quote tokens 消耗掉价格层的所有订单
         // 由于 price = PRICE_DECIMALS，10 quote = 10 base
-        sim.simulate_insert_market_order(U256::from(10), U256::from(10), false);
+        sim.simulate_insert_market_order(U256::from(10), Address::zero(), U256::from(10), false).unwrap();
 
         // 价格层 PRICE_DECIMALS 应该被移除
         assert_eq!(sim.get_price_levels(true), vec![
@@ -1339,11 +2320,12 @@ mod tests {
         // 应该 insertAfterPrice = 0（插入到头部）
         let insert_after = sim.simulate_insert_order(
             U256::from(11),
+            Address::zero(),
             price_100,
             U256::from(10),
             true,
-        );
-        assert_eq!(insert_after, U256::zero()); // 正确！插入到头部
+        ).unwrap();
+        assert_eq!(insert_after.insert_after_price, U256::zero()); // 正确！插入到头部
 
         // 验证新状态
         assert_eq!(sim.get_price_levels(true), vec![
@@ -1359,9 +2341,9 @@ mod tests {
 
         // 市价单应该按 FIFO 顺序排列
         // 先插入市价买单（没有卖单可撮合）
-        sim.simulate_insert_market_order(U256::from(1), U256::from(10), false);
-        sim.simulate_insert_market_order(U256::from(2), U256::from(10), false);
-        sim.simulate_insert_market_order(U256::from(3), U256::from(10), false);
+        sim.simulate_insert_market_order(U256::from(1), Address::zero(), U256::from(10), false).unwrap();
+        sim.simulate_insert_market_order(U256::from(2), Address::zero(), U256::from(10), false).unwrap();
+        sim.simulate_insert_market_order(U256::from(3), Address::zero(), U256::from(10), false).unwrap();
 
         // 验证 FIFO 顺序
         assert_eq!(sim.get_market_orders(false), vec![
@@ -1373,6 +2355,28 @@ mod tests {
         assert_eq!(sim.market_bid_tail, U256::from(3));
     }
 
+    #[test]
+    fn test_remove_market_order_from_queue() {
+        let mut sim = OrderBookSimulator::new();
+        for id in 1..=3 {
+            sim.simulate_insert_market_order(U256::from(id), Address::zero(), U256::from(10), false).unwrap();
+        }
+
+        // 市价单不在价格层级中，撤单从市价单队列移除（对应链上 _removeMarketOrderFromList）
+        let effects = sim.simulate_remove_order(U256::from(2), Address::zero()).unwrap();
+        assert_eq!(effects.removed_orders, vec![U256::from(2)]);
+        assert!(!sim.orders.contains_key(&U256::from(2)));
+        assert_eq!(sim.get_market_orders(false), vec![U256::from(1), U256::from(3)]);
+
+        // 移除队尾
+        sim.simulate_remove_order(U256::from(3), Address::zero()).unwrap();
+        assert_eq!(sim.get_market_orders(false), vec![U256::from(1)]);
+        assert_eq!(sim.market_bid_head, U256::from(1));
+        assert_eq!(sim.market_bid_tail, U256::from(1));
+        assert!(sim.price_levels.is_empty());
+        assert_eq!(sim.validate(), vec![]);
+    }
+
     #[test]
     fn test_multiple_market_orders_match_one_limit() {
         let mut sim = OrderBookSimulator::new();
@@ -1381,12 +2385,12 @@ mod tests {
         let price = PRICE_DECIMALS;
 
         // 插入一个大额限价卖单: 30 base tokens
-        sim.simulate_insert_order(U256::from(1), price, U256::from(30), true);
+        sim.simulate_insert_order(U256::from(1), Address::zero(), price, U256::from(30), true).unwrap();
 
         // 插入多个市价买单，每个花费 10 quote tokens = 10 base tokens
-        sim.simulate_insert_market_order(U256::from(10), U256::from(10), false);
-        sim.simulate_insert_market_order(U256::from(11), U256::from(10), false);
-        sim.simulate_insert_market_order(U256::from(12), U256::from(10), false);
+        sim.simulate_insert_market_order(U256::from(10), Address::zero(), U256::from(10), false).unwrap();
+        sim.simulate_insert_market_order(U256::from(11), Address::zero(), U256::from(10), false).unwrap();
+        sim.simulate_insert_market_order(U256::from(12), Address::zero(), U256::from(10), false).unwrap();
 
         // 所有市价买单应该已成交（共消费 30 base tokens）
         assert!(!sim.orders.contains_key(&U256::from(10)));
@@ -1399,4 +2403,594 @@ mod tests {
         // 价格层级也应该被移除
         assert!(sim.get_price_levels(true).is_empty());
     }
+
+    #[test]
+    fn test_effects_of_crossing_limit_order() {
+        let mut sim = OrderBookSimulator::new();
+
+        // 两个卖单：100 x 5, 110 x 5
+        sim.simulate_insert_order(U256::from(1), Address::zero(), U256::from(100), U256::from(5), true).unwrap();
+        sim.simulate_insert_order(U256::from(2), Address::zero(), U256::from(110), U256::from(5), true).unwrap();
+
+        // 买单 120 x 8：吃掉 100 档全部和 110 档 3 个
+        let effects = sim.simulate_insert_order(U256::from(3), Address::zero(), U256::from(120), U256::from(8), false).unwrap();
+
+        assert_eq!(effects.insert_after_price, U256::zero());
+        assert_eq!(
+            effects.trades,
+            vec![
+                SimTrade {
+                    buy_order_id: U256::from(3),
+                    sell_order_id: U256::from(1),
+                    price: U256::from(100),
+                    amount: U256::from(5),
+                },
+                SimTrade {
+                    buy_order_id: U256::from(3),
+                    sell_order_id: U256::from(2),
+                    price: U256::from(110),
+                    amount: U256::from(3),
+                },
+            ]
+        );
+        assert_eq!(effects.traded_amount(), U256::from(8));
+        assert_eq!(effects.filled_amount_of(U256::from(3)), U256::from(8));
+        assert_eq!(
+            effects.fills.last(),
+            Some(&SimFill {
+                order_id: U256::from(2),
+                filled_amount: U256::from(3),
+                is_fully_filled: false,
+            })
+        );
+        assert_eq!(effects.removed_orders, vec![U256::from(1), U256::from(3)]);
+        assert_eq!(
+            effects.levels_created,
+            vec![SimPriceLevelChange { price: U256::from(120), is_ask: false }]
+        );
+        assert_eq!(
+            effects.levels_removed,
+            vec![
+                SimPriceLevelChange { price: U256::from(100), is_ask: true },
+                SimPriceLevelChange { price: U256::from(120), is_ask: false },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_market_trade_removes_bid_before_ask() {
+        // 使用 price = PRICE_DECIMALS，这样 quote_amount = base_amount
+        let price = PRICE_DECIMALS;
+
+        // 市价卖单：限价买单先于市价卖单移除
+        let mut sim = OrderBookSimulator::new();
+        sim.simulate_insert_order(U256::from(1), Address::zero(), price, U256::from(10), false).unwrap();
+        let effects = sim.simulate_insert_market_order(U256::from(2), Address::zero(), U256::from(10), true).unwrap();
+        assert_eq!(effects.removed_orders, vec![U256::from(1), U256::from(2)]);
+
+        // 市价买单：市价买单先于限价卖单移除
+        let mut sim = OrderBookSimulator::new();
+        sim.simulate_insert_order(U256::from(1), Address::zero(), price, U256::from(10), true).unwrap();
+        let effects = sim.simulate_insert_market_order(U256::from(2), Address::zero(), U256::from(10), false).unwrap();
+        assert_eq!(effects.removed_orders, vec![U256::from(2), U256::from(1)]);
+    }
+
+    #[test]
+    fn test_effects_of_market_order() {
+        let mut sim = OrderBookSimulator::new();
+        sim.simulate_insert_order(U256::from(1), Address::zero(), U256::from(100), U256::from(10), false).unwrap();
+
+        // 市价卖单按买方价格成交，买单在前
+        let effects = sim.simulate_insert_market_order(U256::from(2), Address::zero(), U256::from(4), true).unwrap();
+        assert_eq!(
+            effects.trades,
+            vec![SimTrade {
+                buy_order_id: U256::from(1),
+                sell_order_id: U256::from(2),
+                price: U256::from(100),
+                amount: U256::from(4),
+            }]
+        );
+        assert_eq!(effects.fills[0].order_id, U256::from(1));
+        assert!(effects.fills[1].is_fully_filled);
+        assert_eq!(effects.removed_orders, vec![U256::from(2)]);
+
+        // 撤单
+        let effects = sim.simulate_remove_order(U256::from(1), Address::zero()).unwrap();
+        assert_eq!(effects.removed_orders, vec![U256::from(1)]);
+        assert_eq!(effects.levels_removed.len(), 1);
+        assert_eq!(
+            sim.simulate_remove_order(U256::from(1), Address::zero()),
+            Err(SimulatorError::UnknownOrder { order_id: U256::from(1) })
+        );
+    }
+
+    /// 挂 n 个 1 单位的卖单（价格相同，每笔成交一个订单）
+    fn sim_with_asks(n: u64, price: U256) -> OrderBookSimulator {
+        let mut sim = OrderBookSimulator::new();
+        for id in 1..=n {
+            sim.simulate_insert_order(U256::from(id), Address::zero(), price, U256::one(), true).unwrap();
+        }
+        sim
+    }
+
+    #[test]
+    fn test_limit_matching_cap_carries_over() {
+        let mut sim = sim_with_asks(60, U256::from(100));
+
+        // 需要 60 笔成交，但单次插入最多撮合 50 笔
+        let effects = sim.simulate_insert_order(U256::from(1000), Address::zero(), U256::from(100), U256::from(60), false).unwrap();
+        assert_eq!(effects.trades.len(), DEFAULT_MAX_ITERATIONS);
+        assert!(effects.match_cap_reached);
+        assert!(sim.has_limit_cross());
+        assert_eq!(sim.orders[&U256::from(1000)].filled_amount, U256::from(50));
+        assert_eq!(sim.get_orders_at_price(U256::from(100), true).len(), 10);
+
+        // 下一次插入（不相关的订单）时继续撮合剩余部分，与链上一致
+        let effects = sim.simulate_insert_order(U256::from(1001), Address::zero(), U256::from(10), U256::one(), false).unwrap();
+        assert_eq!(effects.trades.len(), 10);
+        assert!(!effects.match_cap_reached);
+        assert!(!sim.has_limit_cross());
+        assert!(!sim.orders.contains_key(&U256::from(1000)));
+        assert_eq!(sim.get_price_levels(true), Vec::<U256>::new());
+    }
+
+    #[test]
+    fn test_exact_cap_without_remaining_cross() {
+        let mut sim = sim_with_asks(50, U256::from(100));
+
+        // 恰好 50 笔成交后订单簿不再交叉，不算截断
+        let effects = sim.simulate_insert_order(U256::from(1000), Address::zero(), U256::from(100), U256::from(50), false).unwrap();
+        assert_eq!(effects.trades.len(), 50);
+        assert!(!effects.match_cap_reached);
+    }
+
+    #[test]
+    fn test_market_matching_cap() {
+        let price = PRICE_DECIMALS;
+        let mut sim = sim_with_asks(60, price);
+
+        // 市价买单花费 60 quote = 60 base，需要 60 笔成交
+        let effects = sim.simulate_insert_market_order(U256::from(1000), Address::zero(), U256::from(60), false).unwrap();
+        assert_eq!(effects.trades.len(), 50);
+        assert!(effects.match_cap_reached);
+        assert!(sim.has_market_cross());
+
+        // 手动触发 matchMarketOrders 完成剩余撮合
+        let effects = sim.match_market_orders(DEFAULT_MAX_ITERATIONS).unwrap();
+        assert_eq!(effects.trades.len(), 10);
+        assert!(sim.get_market_orders(false).is_empty());
+    }
+
+    #[test]
+    fn test_market_cap_shared_between_sides() {
+        let price = PRICE_DECIMALS;
+        let mut sim = OrderBookSimulator::new();
+        // 先关闭市价撮合，构造两侧市价单都在排队的状态
+        sim.match_limits = MatchLimits {
+            limit_iterations: 50,
+            market_iterations: 0,
+        };
+
+        for id in 1..=3 {
+            sim.simulate_insert_order(U256::from(id), Address::zero(), price * 2, U256::one(), true).unwrap();
+        }
+        sim.simulate_insert_order(U256::from(10), Address::zero(), price, U256::from(5), false).unwrap();
+        sim.simulate_insert_market_order(U256::from(20), Address::zero(), U256::from(2), true).unwrap();
+        let effects = sim.simulate_insert_market_order(U256::from(30), Address::zero(), U256::from(6), false).unwrap();
+        assert!(effects.trades.is_empty());
+        assert!(effects.match_cap_reached);
+
+        // 市价买单（可买 3 个）：每次循环优先撮合市价买单，3 次循环全部用于买单
+        sim.match_limits.market_iterations = 3;
+        let effects = sim.simulate_insert_order(U256::from(11), Address::zero(), U256::one(), U256::one(), false).unwrap();
+        assert_eq!(effects.trades.len(), 3);
+        assert!(effects.trades.iter().all(|trade| trade.buy_order_id == U256::from(30)));
+        assert!(effects.match_cap_reached);
+
+        // 市价卖单 20 仍可与买单 10 撮合，留待下一次
+        assert!(sim.has_market_cross());
+        let effects = sim.match_market_orders(3).unwrap();
+        assert_eq!(effects.trades.len(), 1);
+        assert_eq!(effects.trades[0].sell_order_id, U256::from(20));
+        assert!(!sim.has_market_cross());
+    }
+
+    #[test]
+    fn test_price_index_matches_linked_list_walk() {
+        let mut sim = OrderBookSimulator::new();
+        // 固定种子的线性同余序列，覆盖插入、撮合、撤单交织的情况
+        let mut seed: u64 = 0x2545_f491_4f6c_dd1d;
+        let mut next = |bound: u64| {
+            seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            (seed >> 33) % bound
+        };
+
+        for id in 1..=2000u64 {
+            let is_ask = next(2) == 0;
+            // 买卖价格区间部分重叠，使插入时经常发生撮合
+            let price = if is_ask { 90 + next(40) } else { 70 + next(40) };
+            let price = U256::from(price);
+
+            for probe in 60..130u64 {
+                for side in [true, false] {
+                    assert_eq!(
+                        sim.find_insert_position(U256::from(probe), side),
+                        sim.find_insert_position_by_walk(U256::from(probe), side),
+                    );
+                }
+            }
+
+            if next(5) == 0 && !sim.orders.is_empty() {
+                let victim = U256::from(1 + next(id));
+                let _ = sim.simulate_remove_order(victim, Address::zero());
+            } else {
+                let expected = sim.find_insert_position_by_walk(price, is_ask);
+                let effects = sim.simulate_insert_order(U256::from(id), Address::zero(), price, U256::from(1 + next(5)), is_ask).unwrap();
+                assert_eq!(effects.insert_after_price, expected);
+            }
+
+            assert_eq!(sim.validate(), vec![]);
+            assert_eq!(sim.get_price_levels(true), sim.ask_prices.iter().copied().collect::<Vec<_>>());
+            assert_eq!(sim.get_price_levels(false), sim.bid_prices.iter().rev().copied().collect::<Vec<_>>());
+        }
+    }
+
+    /// 订单簿完整状态：头尾指针、价格层级、订单、两侧价格索引
+    type BookState = (Vec<U256>, Vec<(U256, SimPriceLevel)>, Vec<(U256, SimOrder)>, Vec<U256>, Vec<U256>);
+
+    /// 订单簿完整状态（用于比较回滚前后是否一致）
+    fn book_state(sim: &OrderBookSimulator) -> BookState {
+        let heads = vec![
+            sim.ask_head,
+            sim.ask_tail,
+            sim.bid_head,
+            sim.bid_tail,
+            sim.market_ask_head,
+            sim.market_ask_tail,
+            sim.market_bid_head,
+            sim.market_bid_tail,
+        ];
+        let mut levels: Vec<_> = sim.price_levels.iter().map(|(k, v)| (*k, v.clone())).collect();
+        levels.sort_by_key(|(k, _)| *k);
+        let mut orders: Vec<_> = sim.orders.iter().map(|(k, v)| (*k, v.clone())).collect();
+        orders.sort_by_key(|(k, _)| *k);
+        let ask_index = sim.ask_prices.iter().copied().collect();
+        let bid_index = sim.bid_prices.iter().copied().collect();
+        (heads, levels, orders, ask_index, bid_index)
+    }
+
+    #[test]
+    fn test_checkpoint_rollback_restores_state() {
+        let mut sim = OrderBookSimulator::new();
+        for id in 1..=5u64 {
+            sim.simulate_insert_order(U256::from(id), Address::zero(), U256::from(100 + id), U256::from(10), true).unwrap();
+            sim.simulate_insert_order(U256::from(10 + id), Address::zero(), U256::from(90 + id), U256::from(10), false).unwrap();
+        }
+        sim.simulate_insert_market_order(U256::from(30), Address::zero(), U256::from(5), true).unwrap();
+        let before = book_state(&sim);
+
+        sim.checkpoint();
+        // 撮合、删除价格层级、撤单、市价单排队
+        sim.simulate_insert_order(U256::from(20), Address::zero(), U256::from(103), U256::from(25), false).unwrap();
+        sim.simulate_remove_order(U256::from(12), Address::zero()).unwrap();
+        sim.simulate_insert_order(U256::from(21), Address::zero(), U256::from(50), U256::from(1), true).unwrap();
+        sim.simulate_insert_market_order(U256::from(31), Address::zero(), U256::from(1000), false).unwrap();
+        sim.simulate_insert_market_order(U256::from(32), Address::zero(), U256::from(3), true).unwrap();
+        assert_ne!(book_state(&sim), before);
+
+        assert_eq!(sim.validate(), vec![]);
+
+        sim.rollback();
+        assert_eq!(book_state(&sim), before);
+        assert_eq!(sim.checkpoint_depth(), 0);
+        assert_eq!(sim.validate(), vec![]);
+
+        // 回滚后继续模拟的结果与从未修改过一致
+        let mut fresh = sim.clone();
+        let a = sim.simulate_insert_order(U256::from(20), Address::zero(), U256::from(103), U256::from(25), false).unwrap();
+        let b = fresh.simulate_insert_order(U256::from(20), Address::zero(), U256::from(103), U256::from(25), false).unwrap();
+        assert_eq!(a, b);
+    }
+
+    #[test]
+    fn test_nested_checkpoint_commit() {
+        let mut sim = OrderBookSimulator::new();
+        sim.simulate_insert_order(U256::from(1), Address::zero(), U256::from(100), U256::from(10), true).unwrap();
+        let before = book_state(&sim);
+
+        sim.checkpoint();
+        sim.simulate_insert_order(U256::from(2), Address::zero(), U256::from(110), U256::from(10), true).unwrap();
+        let after_outer = book_state(&sim);
+
+        // 内层提交：修改保留
+        sim.checkpoint();
+        sim.simulate_insert_order(U256::from(3), Address::zero(), U256::from(100), U256::from(4), false).unwrap();
+        sim.commit();
+        assert_eq!(sim.checkpoint_depth(), 1);
+        assert_eq!(sim.orders[&U256::from(1)].filled_amount, U256::from(4));
+
+        // 内层回滚：只撤销内层修改
+        sim.checkpoint();
+        sim.simulate_remove_order(U256::from(2), Address::zero()).unwrap();
+        sim.rollback();
+        assert!(sim.orders.contains_key(&U256::from(2)));
+        assert_ne!(book_state(&sim), after_outer);
+
+        // 外层回滚：撤销包括已提交内层在内的全部修改
+        sim.rollback();
+        assert_eq!(book_state(&sim), before);
+    }
+
+    #[test]
+    fn test_validate_market_and_limit_mix() {
+        let mut sim = OrderBookSimulator::new();
+        let price = PRICE_DECIMALS;
+        sim.simulate_insert_market_order(U256::from(1), Address::zero(), U256::from(3), true).unwrap();
+        sim.simulate_insert_market_order(U256::from(2), Address::zero(), U256::from(6), false).unwrap();
+        sim.simulate_insert_order(U256::from(3), Address::zero(), price, U256::from(2), false).unwrap();
+        sim.simulate_insert_order(U256::from(4), Address::zero(), price * 2, U256::from(4), true).unwrap();
+        sim.simulate_insert_order(U256::from(5), Address::zero(), price * 2, U256::from(4), true).unwrap();
+        sim.simulate_remove_order(U256::from(5), Address::zero()).unwrap();
+        assert_eq!(sim.validate(), vec![]);
+        // 撤单时 total_volume 扣除剩余量（与 _removeOrderFromPriceLevel 一致）
+        let level = &sim.price_levels[&(price * 2)];
+        assert_eq!(level.total_volume, U256::from(4) - sim.orders[&U256::from(4)].filled_amount);
+    }
+
+    #[test]
+    fn test_validate_reports_violations() {
+        let mut sim = OrderBookSimulator::new();
+        sim.simulate_insert_order(U256::from(1), Address::zero(), U256::from(100), U256::from(10), true).unwrap();
+        sim.simulate_insert_order(U256::from(2), Address::zero(), U256::from(100), U256::from(10), true).unwrap();
+        sim.simulate_insert_order(U256::from(3), Address::zero(), U256::from(110), U256::from(10), true).unwrap();
+        assert_eq!(sim.validate(), vec![]);
+
+        // 破坏：层级总量、订单 prev 指针、tail 指针，并加入一个游离订单
+        sim.price_levels.get_mut(&U256::from(100)).unwrap().total_volume = U256::from(5);
+        sim.orders.get_mut(&U256::from(2)).unwrap().prev_order_id = U256::from(9);
+        sim.ask_tail = U256::from(100);
+        let mut stray = sim.orders[&U256::from(3)].clone();
+        stray.id = U256::from(4);
+        sim.orders.insert(stray.id, stray);
+
+        let violations = sim.validate();
+        assert!(violations.contains(&InvariantViolation::TotalVolumeMismatch {
+            price: U256::from(100),
+            is_ask: true,
+            recorded: U256::from(5),
+            expected: U256::from(20),
+        }));
+        // insertAfterOrder = 0：订单 2 插入到层级头部
+        assert!(violations.contains(&InvariantViolation::BrokenOrderLink {
+            order_id: U256::from(2),
+            expected: U256::zero(),
+            actual: U256::from(9),
+        }));
+        assert!(violations.contains(&InvariantViolation::PriceTailMismatch {
+            is_ask: true,
+            expected: U256::from(110),
+            actual: U256::from(100),
+        }));
+        assert!(violations.contains(&InvariantViolation::UnlinkedOrder {
+            order_id: U256::from(4)
+        }));
+        // 直接写入 orders 的订单不在交易者索引中
+        assert!(violations.contains(&InvariantViolation::TraderIndexMismatch {
+            trader: Address::zero()
+        }));
+        assert_eq!(violations.len(), 5);
+    }
+
+    #[test]
+    fn test_validate_reports_unsorted_price_list() {
+        let mut sim = OrderBookSimulator::new();
+        sim.simulate_insert_order(U256::from(1), Address::zero(), U256::from(100), U256::one(), false).unwrap();
+        sim.simulate_insert_order(U256::from(2), Address::zero(), U256::from(90), U256::one(), false).unwrap();
+
+        // 交换两个买价层级的顺序
+        sim.bid_head = U256::from(90);
+        sim.bid_tail = U256::from(100);
+        let key = |price: u64| U256::from(price) | (U256::one() << 255);
+        let level = sim.price_levels.get_mut(&key(90)).unwrap();
+        level.prev_price = U256::zero();
+        level.next_price = U256::from(100);
+        let level = sim.price_levels.get_mut(&key(100)).unwrap();
+        level.prev_price = U256::from(90);
+        level.next_price = U256::zero();
+
+        assert_eq!(
+            sim.validate(),
+            vec![InvariantViolation::PriceOrder {
+                price: U256::from(90),
+                next_price: U256::from(100),
+                is_ask: false,
+            }]
+        );
+    }
+
+    #[test]
+    fn test_remove_order_requires_owner() {
+        let (alice, bob) = (Address::from_low_u64_be(1), Address::from_low_u64_be(2));
+        let mut sim = OrderBookSimulator::new();
+        sim.simulate_insert_order(U256::from(1), alice, U256::from(100), U256::from(5), true).unwrap();
+        let before = book_state(&sim);
+
+        // 与 processRemoveOrder 的 require(order.trader == trader) 一致
+        assert_eq!(
+            sim.simulate_remove_order(U256::from(1), bob),
+            Err(SimulatorError::NotOrderOwner { order_id: U256::from(1), trader: bob })
+        );
+        assert_eq!(book_state(&sim), before);
+        sim.simulate_remove_order(U256::from(1), alice).unwrap();
+        assert!(sim.orders.is_empty());
+    }
+
+    #[test]
+    fn test_invalid_requests_are_rejected() {
+        let mut sim = OrderBookSimulator::new();
+        sim.simulate_insert_order(U256::from(1), Address::zero(), U256::from(100), U256::from(5), true).unwrap();
+        let before = book_state(&sim);
+
+        assert_eq!(
+            sim.simulate_insert_order(U256::from(1), Address::zero(), U256::from(90), U256::one(), false),
+            Err(SimulatorError::DuplicateOrder { order_id: U256::from(1) })
+        );
+        assert_eq!(
+            sim.simulate_insert_order(U256::from(2), Address::zero(), U256::zero(), U256::one(), false),
+            Err(SimulatorError::ZeroPrice { order_id: U256::from(2) })
+        );
+        assert_eq!(
+            sim.simulate_insert_market_order(U256::from(2), Address::zero(), U256::zero(), false),
+            Err(SimulatorError::ZeroAmount { order_id: U256::from(2) })
+        );
+        assert_eq!(
+            sim.simulate_remove_order(U256::from(7), Address::zero()),
+            Err(SimulatorError::UnknownOrder { order_id: U256::from(7) })
+        );
+        assert_eq!(book_state(&sim), before);
+    }
+
+    #[test]
+    fn test_overflow_rolls_back_partial_changes() {
+        let mut sim = OrderBookSimulator::new();
+        sim.simulate_insert_order(U256::from(1), Address::zero(), U256::from(100), U256::MAX, true).unwrap();
+        sim.checkpoint();
+        let before = book_state(&sim);
+
+        // 同价位第二笔卖单使 total_volume 溢出：订单与链表指针已写入，需要整体撤销
+        assert_eq!(
+            sim.simulate_insert_order(U256::from(2), Address::zero(), U256::from(100), U256::one(), true),
+            Err(SimulatorError::Overflow { context: "price level total_volume" })
+        );
+        assert_eq!(book_state(&sim), before);
+        assert_eq!(sim.checkpoint_depth(), 1);
+        assert!(sim.validate().is_empty());
+
+        // 外层检查点不受影响，之后的请求正常模拟
+        let effects = sim
+            .simulate_insert_order(U256::from(3), Address::zero(), U256::from(100), U256::from(4), false)
+            .unwrap();
+        assert_eq!(effects.traded_amount(), U256::from(4));
+        sim.rollback();
+        assert_eq!(book_state(&sim), before);
+    }
+
+    #[test]
+    fn test_effects_totals_saturate() {
+        let trade = SimTrade {
+            buy_order_id: U256::from(1),
+            sell_order_id: U256::from(2),
+            price: U256::from(100),
+            amount: U256::MAX,
+        };
+        let fill = SimFill {
+            order_id: U256::from(1),
+            filled_amount: U256::MAX,
+            is_fully_filled: false,
+        };
+        let effects = SimEffects {
+            trades: vec![trade, trade],
+            fills: vec![fill, fill],
+            ..SimEffects::default()
+        };
+        assert_eq!(effects.traded_amount(), U256::MAX);
+        assert_eq!(effects.filled_amount_of(U256::from(1)), U256::MAX);
+        assert_eq!(effects.filled_amount_of(U256::from(2)), U256::zero());
+    }
+
+    #[test]
+    fn test_broken_link_is_reported() {
+        let mut sim = OrderBookSimulator::new();
+        sim.simulate_insert_order(U256::from(1), Address::zero(), U256::from(100), U256::from(5), true).unwrap();
+        sim.simulate_insert_order(U256::from(2), Address::zero(), U256::from(100), U256::from(5), true).unwrap();
+
+        // 价格层级的头部订单数据丢失
+        sim.orders.remove(&U256::from(2));
+        let before = book_state(&sim);
+
+        assert_eq!(
+            sim.simulate_insert_order(U256::from(3), Address::zero(), U256::from(100), U256::from(3), false),
+            Err(SimulatorError::MissingOrder { order_id: U256::from(2) })
+        );
+        assert_eq!(
+            sim.simulate_remove_order(U256::from(1), Address::zero()),
+            Err(SimulatorError::MissingOrder { order_id: U256::from(2) })
+        );
+        assert_eq!(book_state(&sim), before);
+    }
+
+    #[test]
+    fn test_trader_index() {
+        let alice = Address::from_low_u64_be(1);
+        let bob = Address::from_low_u64_be(2);
+        let mut sim = OrderBookSimulator::new();
+        sim.simulate_insert_order(U256::from(1), alice, U256::from(100), U256::from(5), true).unwrap();
+        sim.simulate_insert_order(U256::from(2), alice, U256::from(110), U256::from(5), true).unwrap();
+        sim.simulate_insert_order(U256::from(3), bob, U256::from(90), U256::from(5), false).unwrap();
+        sim.simulate_insert_market_order(U256::from(4), bob, U256::from(2), true).unwrap();
+        assert_eq!(sim.open_orders_of(alice), vec![U256::from(1), U256::from(2)]);
+        assert_eq!(sim.open_orders_of(bob), vec![U256::from(3)]);
+        assert_eq!(sim.orders[&U256::from(3)].trader, bob);
+        assert_eq!(sim.traders(), vec![alice, bob]);
+
+        // 检查点内：bob 吃掉 alice 的第一笔卖单，alice 撤掉第二笔
+        sim.checkpoint();
+        sim.simulate_insert_order(U256::from(5), bob, U256::from(100), U256::from(5), false).unwrap();
+        sim.simulate_remove_order(U256::from(2), alice).unwrap();
+        assert!(sim.open_orders_of(alice).is_empty());
+        assert_eq!(sim.traders(), vec![bob]);
+        assert!(sim.validate().is_empty());
+
+        sim.rollback();
+        assert_eq!(sim.open_orders_of(alice), vec![U256::from(1), U256::from(2)]);
+        assert_eq!(sim.open_orders_of(bob), vec![U256::from(3)]);
+        assert!(sim.validate().is_empty());
+
+        assert_eq!(sim.remove_existing_order(U256::from(3)).map(|order| order.trader), Some(bob));
+        assert!(sim.open_orders_of(bob).is_empty());
+    }
+
+    #[test]
+    fn test_request_timestamps() {
+        let requested = BlockStamp {
+            timestamp: 1_000,
+            block_number: 10,
+            tx_hash: ethers::types::H256::from_low_u64_be(10),
+        };
+        let request = |request_id: u64, amount: u64| QueuedRequest {
+            request_id: U256::from(request_id),
+            request_type: RequestType::PlaceOrder,
+            trading_pair: [0; 32],
+            trader: Address::from_low_u64_be(1),
+            order_type: OrderType::Limit,
+            is_ask: true,
+            price: Price::from_raw(U256::from(100)),
+            amount: U256::from(amount),
+            order_id_to_remove: EMPTY,
+            next_request_id: EMPTY,
+            requested,
+        };
+        assert_eq!(request(1, 5).queue_age(1_030), Some(30));
+        assert_eq!(QueuedRequest { requested: BlockStamp::default(), ..request(1, 5) }.queue_age(1_030), None);
+
+        let mut sim = OrderBookSimulator::new();
+        sim.simulate_insert_order(U256::from(1), Address::zero(), U256::from(100), U256::from(5), true).unwrap();
+
+        // 检查点内模拟的请求带上提交时间，回滚后订单簿上的订单不受影响
+        sim.checkpoint();
+        sim.simulate_request(&request(2, 5)).unwrap();
+        assert_eq!(sim.orders[&U256::from(2)].requested, requested);
+        assert!(!sim.orders[&U256::from(2)].inserted.is_known());
+        sim.rollback();
+        assert!(!sim.orders.contains_key(&U256::from(2)));
+
+        // 同步器应用 OrderInserted 时填入插入时间
+        let order = sim.orders.get_mut(&U256::from(1)).unwrap();
+        assert_eq!(order.time_in_book(2_000), None);
+        order.requested = requested;
+        order.inserted = BlockStamp { timestamp: 1_012, block_number: 12, ..requested };
+        assert_eq!(order.insertion_latency(), Some(12));
+        assert_eq!(order.time_in_book(1_100), Some(88));
+    }
 }
@@ -0,0 +1,647 @@
+//! 下单预估
+//!
+//! - quote_market：只读地遍历订单簿，估算市价单的成交结果
+//! - preview_order：在检查点上实际模拟一个假想订单（限价或市价），
+//!   得到它会成交还是挂单、在队列中的位置以及对手方，随后回滚
+//!
+//! quote_market 与 execute_market_trade 的整数运算一致：
+//! - 市价卖单：amount 为要卖出的 base tokens，逐笔成交 min(剩余, 限价单剩余)
+//! - 市价买单：amount 为要花费的 quote tokens，
+//!   每笔可买 base = 剩余 quote * PRICE_DECIMALS / price，花费 quote = base * price / PRICE_DECIMALS
+//!
+//! 新市价单排在同侧已有市价单之后（FIFO），这些市价单先消耗对手方流动性；
+//! 不考虑单次插入的撮合次数上限：超出上限的部分会在之后的插入中继续撮合，最终结果相同。
+
+use crate::fixed_point::{Amount, Price, QuoteAmount};
+use crate::orderbook_simulator::{OrderBookSimulator, SimTrade, SimulatorError};
+use crate::types::OrderType;
+use ethers::types::{Address, U256};
+
+/// 基点
+const BPS: U256 = U256([10_000, 0, 0, 0]);
+
+/// 市价单报价（quote_market 的结果）
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MarketQuote {
+    /// 成交的 base tokens
+    pub base_amount: Amount,
+    /// 成交的 quote tokens（买单为花费，卖单为所得，不含费用）
+    pub quote_amount: QuoteAmount,
+    /// 成交均价 quote_amount * PRICE_DECIMALS / base_amount，未成交时为 0
+    pub average_price: Price,
+    /// 第一笔成交的价格，未成交时为 0
+    pub best_price: Price,
+    /// 最后一笔成交的价格，未成交时为 0
+    pub worst_price: Price,
+    /// 均价相对第一笔成交价格的滑点（基点，向下取整）
+    pub slippage_bps: U256,
+    /// 未成交的剩余，单位与输入的 amount 相同（卖单为 base，买单为 quote）
+    pub unfilled: U256,
+}
+
+impl MarketQuote {
+    /// 是否能全部成交
+    pub fn is_fully_filled(&self) -> bool {
+        self.unfilled.is_zero()
+    }
+}
+
+/// 按撮合优先级（最优价格、层级内 FIFO）只读遍历某一侧的限价单，记录已被消耗的数量
+struct LiquidityCursor<'a> {
+    sim: &'a OrderBookSimulator,
+    is_ask: bool,
+    /// 当前限价单，0 表示流动性已耗尽
+    order_id: U256,
+    price: U256,
+    remaining: U256,
+}
+
+impl<'a> LiquidityCursor<'a> {
+    fn new(sim: &'a OrderBookSimulator, is_ask: bool) -> Result<Self, SimulatorError> {
+        let mut cursor = Self {
+            sim,
+            is_ask,
+            order_id: U256::zero(),
+            price: U256::zero(),
+            remaining: U256::zero(),
+        };
+        let head = if is_ask { sim.ask_head } else { sim.bid_head };
+        cursor.enter_level(head)?;
+        Ok(cursor)
+    }
+
+    /// 移动到价格层级的头部订单（链上撮合只看层级的 head_order_id）
+    fn enter_level(&mut self, price: U256) -> Result<(), SimulatorError> {
+        let head_order_id = if price.is_zero() {
+            U256::zero()
+        } else {
+            self.sim.level(price, self.is_ask)?.head_order_id
+        };
+        self.enter_order(head_order_id, price)
+    }
+
+    fn enter_order(&mut self, order_id: U256, price: U256) -> Result<(), SimulatorError> {
+        self.order_id = order_id;
+        self.price = price;
+        self.remaining = if order_id.is_zero() {
+            U256::zero()
+        } else {
+            self.sim.remaining_of(order_id)?
+        };
+        Ok(())
+    }
+
+    /// 消耗当前限价单的 amount，吃完后移动到下一个订单
+    fn consume(&mut self, amount: U256) -> Result<(), SimulatorError> {
+        self.remaining = self
+            .remaining
+            .checked_sub(amount)
+            .ok_or(SimulatorError::Underflow { context: "quote limit remaining" })?;
+        if !self.remaining.is_zero() {
+            return Ok(());
+        }
+
+        let next_order_id = self.sim.order(self.order_id)?.next_order_id;
+        if next_order_id.is_zero() {
+            let next_price = self.sim.level(self.price, self.is_ask)?.next_price;
+            self.enter_level(next_price)
+        } else {
+            self.enter_order(next_order_id, self.price)
+        }
+    }
+}
+
+/// 一个市价单按链上规则吃掉的流动性
+#[derive(Default)]
+struct Sweep {
+    base_amount: Amount,
+    quote_amount: QuoteAmount,
+    best_price: Price,
+    worst_price: Price,
+    unfilled: U256,
+    /// 剩余部分无法继续成交（对手方流动性耗尽或剩余 quote 不足以买 1 单位 base）
+    stalled: bool,
+}
+
+/// 对应 execute_market_trade 的循环：直到市价单完全成交或无法继续成交
+fn sweep(
+    cursor: &mut LiquidityCursor,
+    amount: U256,
+    is_market_ask: bool,
+) -> Result<Sweep, SimulatorError> {
+    let mut result = Sweep {
+        unfilled: amount,
+        ..Sweep::default()
+    };
+
+    while !result.unfilled.is_zero() {
+        if cursor.order_id.is_zero() {
+            result.stalled = true;
+            break;
+        }
+
+        let price = Price::from_raw(cursor.price);
+        let trade_amount = if is_market_ask {
+            result.unfilled.min(cursor.remaining)
+        } else {
+            QuoteAmount::from_raw(result.unfilled)
+                .checked_div_price(price)
+                .map_err(|e| e.in_context("quote market bid base amount"))?
+                .raw()
+                .min(cursor.remaining)
+        };
+        if trade_amount.is_zero() {
+            result.stalled = true;
+            break;
+        }
+
+        let base = Amount::from_raw(trade_amount);
+        let quote = base
+            .checked_mul_price(price)
+            .map_err(|e| e.in_context("quote market quote amount"))?;
+        let spent = if is_market_ask { base.raw() } else { quote.raw() };
+
+        if result.best_price.is_zero() {
+            result.best_price = price;
+        }
+        result.worst_price = price;
+        result.base_amount = result.base_amount + base;
+        result.quote_amount = result.quote_amount + quote;
+        result.unfilled -= spent;
+        cursor.consume(trade_amount)?;
+    }
+
+    Ok(result)
+}
+
+impl OrderBookSimulator {
+    /// 估算一个新市价单在当前订单簿上的成交结果（只读，不修改订单簿）
+    ///
+    /// - is_ask = true：市价卖单，amount 为要卖出的 base tokens
+    /// - is_ask = false：市价买单，amount 为要花费的 quote tokens
+    ///
+    /// 同侧已排队的市价单先成交。若排在前面的市价单卡住（剩余 quote 不足以买 1 单位 base），
+    /// 链上每次循环只尝试队首，新订单也无法成交。
+    /// 要把 Sequencer 中尚未处理的请求也算进去，使用 GlobalState::quote_market
+    pub fn quote_market(&self, is_ask: bool, amount: U256) -> Result<MarketQuote, SimulatorError> {
+        let mut cursor = LiquidityCursor::new(self, !is_ask)?;
+
+        for order_id in self.get_market_orders(is_ask) {
+            let ahead = sweep(&mut cursor, self.remaining_of(order_id)?, is_ask)?;
+            if ahead.stalled {
+                return Ok(MarketQuote {
+                    unfilled: amount,
+                    ..MarketQuote::default()
+                });
+            }
+        }
+
+        let fill = sweep(&mut cursor, amount, is_ask)?;
+        let mut quote = MarketQuote {
+            base_amount: fill.base_amount,
+            quote_amount: fill.quote_amount,
+            best_price: fill.best_price,
+            worst_price: fill.worst_price,
+            unfilled: fill.unfilled,
+            ..MarketQuote::default()
+        };
+
+        if !fill.base_amount.is_zero() {
+            quote.average_price = fill
+                .quote_amount
+                .checked_div_amount(fill.base_amount)
+                .map_err(|e| e.in_context("quote average price"))?;
+            let deviation = if is_ask {
+                fill.best_price.saturating_sub(quote.average_price)
+            } else {
+                quote.average_price.saturating_sub(fill.best_price)
+            };
+            quote.slippage_bps = deviation
+                .raw()
+                .checked_mul(BPS)
+                .ok_or(SimulatorError::Overflow { context: "quote slippage" })?
+                / fill.best_price.raw();
+        }
+
+        Ok(quote)
+    }
+}
+
+/// 假想订单（preview_order 的输入）
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HypotheticalOrder {
+    pub trader: Address,
+    pub order_type: OrderType,
+    pub is_ask: bool,
+    /// 限价单价格，市价单忽略
+    pub price: Price,
+    /// 限价单与市价卖单为 base tokens，市价买单为要花费的 quote tokens
+    pub amount: U256,
+}
+
+/// 假想订单的结果
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PreviewStatus {
+    /// 立即完全成交
+    Filled,
+    /// 部分成交，剩余部分留在簿上
+    PartiallyFilled,
+    /// 没有成交，整个订单留在簿上
+    Resting,
+}
+
+/// 假想订单与一个对手方订单的成交
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PreviewMatch {
+    pub order_id: U256,
+    pub trader: Address,
+    /// 成交价格（限价单为卖方价格，市价单为对手价）
+    pub price: Price,
+    /// 成交数量（base tokens）
+    pub amount: Amount,
+}
+
+/// 假想订单的模拟结果（preview_order 的结果）
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OrderPreview {
+    /// 模拟时使用的订单 id
+    pub order_id: U256,
+    /// 限价单的 insertAfterPrice（市价单为 0）
+    pub insert_after_price: U256,
+    pub status: PreviewStatus,
+    /// 已成交数量（base tokens）
+    pub filled_amount: Amount,
+    /// 留在簿上的剩余，单位与输入的 amount 相同
+    pub remaining: U256,
+    /// 留在簿上时在价格层级（市价单为市价单队列）中的位置，0 表示最先被撮合
+    pub queue_position: Option<usize>,
+    /// 排在前面的订单的剩余量之和，单位与这些订单的 amount 相同
+    pub amount_ahead: U256,
+    /// 按成交顺序排列的对手方
+    pub matches: Vec<PreviewMatch>,
+    /// 撮合达到次数上限而停止，剩余部分会在之后的插入中继续撮合
+    pub match_cap_reached: bool,
+}
+
+impl OrderBookSimulator {
+    /// 模拟插入一个假想订单并返回它的结果；订单簿保持调用前的状态
+    ///
+    /// order_id 不能与簿上订单重复。要排在 Sequencer 队列之后，使用 GlobalState::preview_order
+    pub fn preview_order(
+        &mut self,
+        order_id: U256,
+        order: &HypotheticalOrder,
+    ) -> Result<OrderPreview, SimulatorError> {
+        self.checkpoint();
+        let result = self.preview_order_in_checkpoint(order_id, order);
+        self.rollback();
+
+        // 回滚后被吃掉的对手方订单恢复，可以查到它们的 trader
+        let (mut preview, trades) = result?;
+        for trade in trades {
+            let counterparty = if trade.buy_order_id == order_id {
+                trade.sell_order_id
+            } else {
+                trade.buy_order_id
+            };
+            preview.matches.push(PreviewMatch {
+                order_id: counterparty,
+                trader: self
+                    .orders
+                    .get(&counterparty)
+                    .map(|order| order.trader)
+                    .unwrap_or_default(),
+                price: Price::from_raw(trade.price),
+                amount: Amount::from_raw(trade.amount),
+            });
+        }
+        Ok(preview)
+    }
+
+    fn preview_order_in_checkpoint(
+        &mut self,
+        order_id: U256,
+        order: &HypotheticalOrder,
+    ) -> Result<(OrderPreview, Vec<SimTrade>), SimulatorError> {
+        let effects = match order.order_type {
+            OrderType::Limit => self.simulate_insert_order(
+                order_id,
+                order.trader,
+                order.price.raw(),
+                order.amount,
+                order.is_ask,
+            )?,
+            OrderType::Market => {
+                self.simulate_insert_market_order(order_id, order.trader, order.amount, order.is_ask)?
+            }
+        };
+
+        // 同一次插入中其他订单之间的撮合（延续上次的交叉）不属于这个订单
+        let trades: Vec<SimTrade> = effects
+            .trades
+            .iter()
+            .filter(|trade| trade.buy_order_id == order_id || trade.sell_order_id == order_id)
+            .copied()
+            .collect();
+        let filled_amount = Amount::from_raw(effects.filled_amount_of(order_id));
+
+        let mut preview = OrderPreview {
+            order_id,
+            insert_after_price: effects.insert_after_price,
+            status: PreviewStatus::Filled,
+            filled_amount,
+            remaining: U256::zero(),
+            queue_position: None,
+            amount_ahead: U256::zero(),
+            matches: Vec::new(),
+            match_cap_reached: effects.match_cap_reached,
+        };
+
+        if self.orders.contains_key(&order_id) {
+            preview.remaining = self.remaining_of(order_id)?;
+            preview.status = if filled_amount.is_zero() {
+                PreviewStatus::Resting
+            } else {
+                PreviewStatus::PartiallyFilled
+            };
+
+            let queue = match order.order_type {
+                OrderType::Limit => self.get_orders_at_price(order.price.raw(), order.is_ask),
+                OrderType::Market => self.get_market_orders(order.is_ask),
+            };
+            let position = queue.iter().position(|id| *id == order_id);
+            if let Some(position) = position {
+                for ahead in &queue[..position] {
+                    preview.amount_ahead += self.remaining_of(*ahead)?;
+                }
+            }
+            preview.queue_position = position;
+        }
+
+        Ok((preview, trades))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::constants::PRICE_DECIMALS;
+    use ethers::types::Address;
+
+    fn price(whole: u64) -> U256 {
+        U256::from(whole) * PRICE_DECIMALS
+    }
+
+    fn trader(id: u64) -> Address {
+        Address::from_low_u64_be(id)
+    }
+
+    /// 卖：100 档 [2: 5, 1: 10]（新订单插入层级头部），110 档 [3: 10]；买：90 档 [4: 10]，80 档 [5: 10]
+    fn book() -> OrderBookSimulator {
+        let mut sim = OrderBookSimulator::new();
+        let orders = [
+            (1, 100, 10, true),
+            (2, 100, 5, true),
+            (3, 110, 10, true),
+            (4, 90, 10, false),
+            (5, 80, 10, false),
+        ];
+        for (id, px, amount, is_ask) in orders {
+            sim.simulate_insert_order(U256::from(id), trader(id), price(px), U256::from(amount), is_ask).unwrap();
+        }
+        sim
+    }
+
+    /// 报价应与在副本上实际插入市价单的结果一致
+    fn assert_matches_simulation(sim: &OrderBookSimulator, is_ask: bool, amount: U256) {
+        let quote = sim.quote_market(is_ask, amount).unwrap();
+        let mut copy = sim.clone();
+        let effects = copy
+            .simulate_insert_market_order(U256::from(1000), Address::zero(), amount, is_ask)
+            .unwrap();
+        // 只统计新订单的成交（排在前面的市价单也会在这次插入中撮合）
+        let id = U256::from(1000);
+        let trades: Vec<_> = effects
+            .trades
+            .iter()
+            .filter(|trade| trade.buy_order_id == id || trade.sell_order_id == id)
+            .collect();
+        let base = trades.iter().fold(U256::zero(), |acc, trade| acc + trade.amount);
+        let quote_amount = trades
+            .iter()
+            .fold(U256::zero(), |acc, trade| acc + trade.amount * trade.price / PRICE_DECIMALS);
+        assert_eq!(quote.base_amount.raw(), base);
+        assert_eq!(quote.quote_amount.raw(), quote_amount);
+        let spent = if is_ask { base } else { quote_amount };
+        assert_eq!(quote.unfilled, amount - spent);
+    }
+
+    #[test]
+    fn test_quote_market_buy_walks_levels() {
+        let sim = book();
+        // 花费 2100 quote：100 x 15 = 1500，剩余 600 在 110 买 5，剩下 50 不够买 1 个
+        let quote = sim.quote_market(false, U256::from(2100)).unwrap();
+        assert_eq!(quote.base_amount.raw(), U256::from(20));
+        assert_eq!(quote.quote_amount.raw(), U256::from(2050));
+        assert_eq!(quote.best_price.raw(), price(100));
+        assert_eq!(quote.worst_price.raw(), price(110));
+        assert_eq!(quote.average_price.raw(), price(2050) / 20);
+        // 均价 102.5，相对 100 滑点 250 bps
+        assert_eq!(quote.slippage_bps, U256::from(250));
+        assert_eq!(quote.unfilled, U256::from(50));
+        assert_matches_simulation(&sim, false, U256::from(2100));
+    }
+
+    #[test]
+    fn test_quote_market_sell_with_remainder() {
+        let sim = book();
+        let quote = sim.quote_market(true, U256::from(25)).unwrap();
+        assert_eq!(quote.base_amount.raw(), U256::from(20));
+        assert_eq!(quote.quote_amount.raw(), U256::from(1700));
+        assert_eq!(quote.best_price.raw(), price(90));
+        assert_eq!(quote.worst_price.raw(), price(80));
+        assert_eq!(quote.slippage_bps, U256::from(555));
+        assert_eq!(quote.unfilled, U256::from(5));
+        assert!(!quote.is_fully_filled());
+        assert_matches_simulation(&sim, true, U256::from(25));
+
+        // 只读：订单簿未被修改
+        assert_eq!(sim.get_orders_at_price(price(90), false), vec![U256::from(4)]);
+    }
+
+    #[test]
+    fn test_quote_market_behind_queued_market_orders() {
+        let mut sim = book();
+        // 排队的市价买单：撮合上限为 0，留在队列中
+        sim.match_limits.market_iterations = 0;
+        sim.simulate_insert_market_order(U256::from(10), Address::zero(), U256::from(1200), false).unwrap();
+
+        // 前面的市价买单先吃掉 100 档的 12 个
+        let quote = sim.quote_market(false, U256::from(300)).unwrap();
+        assert_eq!(quote.base_amount.raw(), U256::from(3));
+        assert_eq!(quote.best_price.raw(), price(100));
+        assert!(quote.is_fully_filled());
+
+        let quote = sim.quote_market(false, U256::from(1000)).unwrap();
+        // 100 档剩 3 个，110 档用 700 买 6 个
+        assert_eq!(quote.base_amount.raw(), U256::from(9));
+        assert_eq!(quote.worst_price.raw(), price(110));
+
+        sim.match_limits = Default::default();
+        assert_matches_simulation(&sim, false, U256::from(1000));
+    }
+
+    #[test]
+    fn test_global_state_quote_includes_sequencer_queue() {
+        use crate::config::StateView;
+        use crate::state::GlobalState;
+        use crate::types::{BlockStamp, OrderType, QueuedRequest, RequestType};
+
+        let state = GlobalState::new();
+        state.orderbooks.write().insert([0; 32], book());
+
+        // 队列中的市价买单先花掉 1500 quote，吃光 100 档
+        state.add_request(QueuedRequest {
+            request_id: U256::from(20),
+            request_type: RequestType::PlaceOrder,
+            trading_pair: [0; 32],
+            trader: Address::zero(),
+            order_type: OrderType::Market,
+            is_ask: false,
+            price: Price::zero(),
+            amount: U256::from(1500),
+            order_id_to_remove: U256::zero(),
+            next_request_id: U256::zero(),
+            requested: BlockStamp::default(),
+        });
+
+        let quote = state.quote_market(StateView::Head, [0; 32], false, U256::from(220)).unwrap();
+        assert_eq!(quote.base_amount.raw(), U256::from(2));
+        assert_eq!(quote.best_price.raw(), price(110));
+
+        // 订单簿本身未被修改
+        assert_eq!(state.orderbooks.read()[&[0; 32]].get_price_levels(true), vec![price(100), price(110)]);
+    }
+
+    #[test]
+    fn test_quote_market_on_empty_side() {
+        let sim = OrderBookSimulator::new();
+        let quote = sim.quote_market(false, U256::from(10)).unwrap();
+        assert_eq!(quote, MarketQuote { unfilled: U256::from(10), ..MarketQuote::default() });
+    }
+
+    fn limit(is_ask: bool, px: u64, amount: u64) -> HypotheticalOrder {
+        HypotheticalOrder {
+            trader: trader(9),
+            order_type: OrderType::Limit,
+            is_ask,
+            price: Price::from_units(px),
+            amount: U256::from(amount),
+        }
+    }
+
+    fn market(is_ask: bool, amount: u64) -> HypotheticalOrder {
+        HypotheticalOrder {
+            trader: trader(9),
+            order_type: OrderType::Market,
+            is_ask,
+            price: Price::zero(),
+            amount: U256::from(amount),
+        }
+    }
+
+    #[test]
+    fn test_preview_crossing_limit_order() {
+        let mut sim = book();
+        let before = sim.clone();
+
+        let preview = sim.preview_order(U256::from(100), &limit(false, 100, 20)).unwrap();
+        assert_eq!(preview.status, PreviewStatus::PartiallyFilled);
+        assert_eq!(preview.filled_amount.raw(), U256::from(15));
+        assert_eq!(preview.remaining, U256::from(5));
+        assert_eq!(preview.insert_after_price, U256::zero());
+        assert_eq!(preview.queue_position, Some(0));
+        assert_eq!(
+            preview.matches,
+            vec![
+                PreviewMatch { order_id: U256::from(2), trader: trader(2), price: Price::from_units(100), amount: Amount::from_raw(U256::from(5)) },
+                PreviewMatch { order_id: U256::from(1), trader: trader(1), price: Price::from_units(100), amount: Amount::from_raw(U256::from(10)) },
+            ]
+        );
+
+        // 订单簿保持不变
+        assert_eq!(sim.orders, before.orders);
+        assert_eq!(sim.price_levels, before.price_levels);
+        assert_eq!(sim.checkpoint_depth(), 0);
+    }
+
+    #[test]
+    fn test_preview_resting_and_filled_orders() {
+        let mut sim = book();
+
+        let preview = sim.preview_order(U256::from(100), &limit(true, 120, 3)).unwrap();
+        assert_eq!(preview.status, PreviewStatus::Resting);
+        assert_eq!(preview.insert_after_price, price(110));
+        assert_eq!(preview.remaining, U256::from(3));
+        assert!(preview.matches.is_empty());
+
+        let preview = sim.preview_order(U256::from(100), &market(true, 5)).unwrap();
+        assert_eq!(preview.status, PreviewStatus::Filled);
+        assert_eq!(preview.queue_position, None);
+        assert_eq!(preview.matches[0].trader, trader(4));
+        assert_eq!(preview.matches[0].price.raw(), price(90));
+    }
+
+    #[test]
+    fn test_preview_market_order_queue_position() {
+        let mut sim = OrderBookSimulator::new();
+        sim.simulate_insert_market_order(U256::from(1), trader(1), U256::from(50), false).unwrap();
+
+        // 没有卖单，市价买单排在已有的市价买单之后
+        let preview = sim.preview_order(U256::from(2), &market(false, 30)).unwrap();
+        assert_eq!(preview.status, PreviewStatus::Resting);
+        assert_eq!(preview.queue_position, Some(1));
+        assert_eq!(preview.amount_ahead, U256::from(50));
+        assert_eq!(preview.remaining, U256::from(30));
+
+        assert_eq!(
+            sim.preview_order(U256::from(1), &market(false, 30)),
+            Err(SimulatorError::DuplicateOrder { order_id: U256::from(1) })
+        );
+        assert_eq!(sim.get_market_orders(false), vec![U256::from(1)]);
+    }
+
+    #[test]
+    fn test_global_state_preview_after_queue() {
+        use crate::config::StateView;
+        use crate::state::GlobalState;
+        use crate::types::{BlockStamp, QueuedRequest, RequestType};
+
+        let state = GlobalState::new();
+        state.orderbooks.write().insert([0; 32], book());
+
+        // 队列中有一个 95 的卖单，新买单排在它后面会先和它成交
+        state.add_request(QueuedRequest {
+            request_id: U256::from(20),
+            request_type: RequestType::PlaceOrder,
+            trading_pair: [0; 32],
+            trader: trader(7),
+            order_type: OrderType::Limit,
+            is_ask: true,
+            price: Price::from_units(95),
+            amount: U256::from(5),
+            order_id_to_remove: U256::zero(),
+            next_request_id: U256::zero(),
+            requested: BlockStamp::default(),
+        });
+
+        let preview = state.preview_order(StateView::Head, [0; 32], &limit(false, 95, 2)).unwrap();
+        assert_eq!(preview.order_id, U256::from(21));
+        assert_eq!(preview.status, PreviewStatus::Filled);
+        assert_eq!(
+            preview.matches,
+            vec![PreviewMatch { order_id: U256::from(20), trader: trader(7), price: Price::from_units(95), amount: Amount::from_raw(U256::from(2)) }]
+        );
+        assert!(!state.orderbooks.read()[&[0; 32]].orders.contains_key(&U256::from(20)));
+    }
+}
@@ -0,0 +1,509 @@
+//! 朴素参考撮合器 - 用于对 OrderBookSimulator 做性质测试
+//!
+//! 不使用链表，价格层级和层级内订单都放在 Vec 里，每次撮合都线性扫描找最优价格，
+//! 规则直接按 OrderBook.sol 书写，不读取模拟器的状态：
+//! - 价格优先：ask 取最低价、bid 取最高价
+//! - 同价位顺序按 `_insertOrderIntoPriceLevel` 推导：insertAfterOrder = EMPTY 时插到 headOrderId 之前，
+//!   否则插到指定订单之后（该订单须在同一层级）；`_matchOrdersInternal` /
+//!   `_matchMarketOrdersInternal` 总是取层级的 headOrderId 成交
+//! - 撮合引擎提交的 insertAfterOrder 总是 0（见 matcher.rs），性质测试按 0 调用，
+//!   即同价位内最新的订单最先成交；模拟器不接受其它 insertAfterOrder，这条分支不在对照范围内
+//! - 限价单成交价为卖单价格；市价单成交价为对手限价单价格
+//! - 每次插入后先做最多 limit_iterations 次限价撮合，再做最多 market_iterations 次市价撮合，
+//!   市价循环每次优先尝试市价买单，成交后 continue，两侧都无法成交则退出
+//! - 市价买单的 amount 为计价代币数量，按 quote * PRICE_DECIMALS / price 换算为基础代币（向下取整）
+
+use crate::constants::PRICE_DECIMALS;
+use crate::orderbook_simulator::{MatchLimits, OrderBookSimulator, SimTrade};
+use ethers::types::U256;
+use std::collections::VecDeque;
+
+#[derive(Debug, Clone)]
+struct RefOrder {
+    id: U256,
+    amount: U256,
+    filled: U256,
+}
+
+impl RefOrder {
+    fn remaining(&self) -> U256 {
+        self.amount - self.filled
+    }
+}
+
+/// 参考撮合器的价格层级：订单按链表顺序（head 在前）
+#[derive(Debug, Clone)]
+struct RefLevel {
+    price: U256,
+    orders: Vec<RefOrder>,
+}
+
+/// 一个价格层级：价格与按链表顺序排列的 (order_id, 剩余量)
+type Level = (U256, Vec<(U256, U256)>);
+
+/// 订单簿快照：两侧价格层级（按链表顺序）与两侧市价单队列
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BookSnapshot {
+    pub asks: Vec<Level>,
+    pub bids: Vec<Level>,
+    pub market_asks: Vec<(U256, U256)>,
+    pub market_bids: Vec<(U256, U256)>,
+}
+
+impl BookSnapshot {
+    /// 通过模拟器的公开接口读取快照
+    pub fn of(sim: &OrderBookSimulator) -> Self {
+        let levels = |is_ask: bool| {
+            sim.get_price_levels(is_ask)
+                .into_iter()
+                .map(|price| {
+                    let orders = sim
+                        .get_orders_at_price(price, is_ask)
+                        .into_iter()
+                        .map(|id| (id, sim.orders[&id].amount - sim.orders[&id].filled_amount))
+                        .collect();
+                    (price, orders)
+                })
+                .collect()
+        };
+        let queue = |is_ask: bool| {
+            sim.get_market_orders(is_ask)
+                .into_iter()
+                .map(|id| (id, sim.orders[&id].amount - sim.orders[&id].filled_amount))
+                .collect()
+        };
+        Self {
+            asks: levels(true),
+            bids: levels(false),
+            market_asks: queue(true),
+            market_bids: queue(false),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct ReferenceMatcher {
+    /// 价格层级，顺序无关（撮合时线性扫描最优价格）
+    asks: Vec<RefLevel>,
+    bids: Vec<RefLevel>,
+    market_asks: VecDeque<RefOrder>,
+    market_bids: VecDeque<RefOrder>,
+    limits: MatchLimits,
+}
+
+impl ReferenceMatcher {
+    pub fn new(limits: MatchLimits) -> Self {
+        Self {
+            limits,
+            ..Default::default()
+        }
+    }
+
+    /// 插入限价单并撮合；insert_after_order 同链上 insertAfterOrder（0 = 插到层级头部）
+    pub fn insert_limit(
+        &mut self,
+        id: U256,
+        price: U256,
+        amount: U256,
+        is_ask: bool,
+        insert_after_order: U256,
+    ) -> Vec<SimTrade> {
+        let order = RefOrder {
+            id,
+            amount,
+            filled: U256::zero(),
+        };
+        let side = if is_ask { &mut self.asks } else { &mut self.bids };
+        let level = match side.iter().position(|level| level.price == price) {
+            Some(idx) => &mut side[idx],
+            None => {
+                side.push(RefLevel {
+                    price,
+                    orders: Vec::new(),
+                });
+                side.last_mut().unwrap()
+            }
+        };
+
+        // _insertOrderIntoPriceLevel
+        if insert_after_order.is_zero() {
+            level.orders.insert(0, order);
+        } else {
+            let prev = level
+                .orders
+                .iter()
+                .position(|order| order.id == insert_after_order)
+                .expect("Previous order not in same price level");
+            level.orders.insert(prev + 1, order);
+        }
+        self.match_after_insertion()
+    }
+
+    pub fn insert_market(&mut self, id: U256, amount: U256, is_ask: bool) -> Vec<SimTrade> {
+        let order = RefOrder {
+            id,
+            amount,
+            filled: U256::zero(),
+        };
+        if is_ask {
+            self.market_asks.push_back(order);
+        } else {
+            self.market_bids.push_back(order);
+        }
+        self.match_after_insertion()
+    }
+
+    /// 撤单（不触发撮合），订单不存在时返回 false
+    pub fn remove(&mut self, id: U256) -> bool {
+        let before = self.len();
+        for level in self.asks.iter_mut().chain(self.bids.iter_mut()) {
+            level.orders.retain(|order| order.id != id);
+        }
+        Self::drop_empty_levels(&mut self.asks);
+        Self::drop_empty_levels(&mut self.bids);
+        self.market_asks.retain(|order| order.id != id);
+        self.market_bids.retain(|order| order.id != id);
+        self.len() != before
+    }
+
+    fn len(&self) -> usize {
+        let limit_orders = |levels: &[RefLevel]| levels.iter().map(|level| level.orders.len()).sum::<usize>();
+        limit_orders(&self.asks) + limit_orders(&self.bids) + self.market_asks.len() + self.market_bids.len()
+    }
+
+    /// 移除完全成交的订单，以及因此变空的价格层级
+    fn drop_filled(levels: &mut Vec<RefLevel>) {
+        for level in levels.iter_mut() {
+            level.orders.retain(|order| order.filled != order.amount);
+        }
+        Self::drop_empty_levels(levels);
+    }
+
+    fn drop_empty_levels(levels: &mut Vec<RefLevel>) {
+        levels.retain(|level| !level.orders.is_empty());
+    }
+
+    /// 最优价格层级的下标：ask 取最低价、bid 取最高价
+    fn best(levels: &[RefLevel], is_ask: bool) -> Option<usize> {
+        (0..levels.len()).max_by(|&a, &b| {
+            let (a, b) = (levels[a].price, levels[b].price);
+            if is_ask {
+                b.cmp(&a)
+            } else {
+                a.cmp(&b)
+            }
+        })
+    }
+
+    fn match_after_insertion(&mut self) -> Vec<SimTrade> {
+        let mut trades = Vec::new();
+
+        for _ in 0..self.limits.limit_iterations {
+            let (Some(bid), Some(ask)) = (Self::best(&self.bids, false), Self::best(&self.asks, true)) else {
+                break;
+            };
+            let (bid_level, ask_level) = (&mut self.bids[bid], &mut self.asks[ask]);
+            if bid_level.price < ask_level.price {
+                break;
+            }
+            // 两侧都取层级的 headOrderId
+            let (bid_order, ask_order) = (&mut bid_level.orders[0], &mut ask_level.orders[0]);
+            let amount = bid_order.remaining().min(ask_order.remaining());
+            trades.push(SimTrade {
+                buy_order_id: bid_order.id,
+                sell_order_id: ask_order.id,
+                price: ask_level.price,
+                amount,
+            });
+            bid_order.filled += amount;
+            ask_order.filled += amount;
+            Self::drop_filled(&mut self.asks);
+            Self::drop_filled(&mut self.bids);
+        }
+
+        for _ in 0..self.limits.market_iterations {
+            if let Some(trade) = self.match_market_bid() {
+                trades.push(trade);
+                continue;
+            }
+            if let Some(trade) = self.match_market_ask() {
+                trades.push(trade);
+                continue;
+            }
+            break;
+        }
+
+        trades
+    }
+
+    fn match_market_bid(&mut self) -> Option<SimTrade> {
+        let ask = Self::best(&self.asks, true)?;
+        let market = self.market_bids.front_mut()?;
+        let price = self.asks[ask].price;
+        let limit = &mut self.asks[ask].orders[0];
+
+        let base = (market.amount - market.filled) * PRICE_DECIMALS / price;
+        let amount = base.min(limit.remaining());
+        if amount.is_zero() {
+            return None;
+        }
+        market.filled += amount * price / PRICE_DECIMALS;
+        limit.filled += amount;
+        let trade = SimTrade {
+            buy_order_id: market.id,
+            sell_order_id: limit.id,
+            price,
+            amount,
+        };
+
+        if market.filled == market.amount {
+            self.market_bids.pop_front();
+        }
+        Self::drop_filled(&mut self.asks);
+        Some(trade)
+    }
+
+    fn match_market_ask(&mut self) -> Option<SimTrade> {
+        let bid = Self::best(&self.bids, false)?;
+        let market = self.market_asks.front_mut()?;
+        let price = self.bids[bid].price;
+        let limit = &mut self.bids[bid].orders[0];
+
+        let amount = (market.amount - market.filled).min(limit.remaining());
+        if amount.is_zero() {
+            return None;
+        }
+        market.filled += amount;
+        limit.filled += amount;
+        let trade = SimTrade {
+            buy_order_id: limit.id,
+            sell_order_id: market.id,
+            price,
+            amount,
+        };
+
+        if market.filled == market.amount {
+            self.market_asks.pop_front();
+        }
+        Self::drop_filled(&mut self.bids);
+        Some(trade)
+    }
+
+    pub fn snapshot(&self) -> BookSnapshot {
+        let levels = |levels: &[RefLevel], is_ask: bool| {
+            let mut sorted: Vec<Level> = levels
+                .iter()
+                .map(|level| {
+                    let orders = level.orders.iter().map(|order| (order.id, order.remaining())).collect();
+                    (level.price, orders)
+                })
+                .collect();
+            sorted.sort_by(|a, b| if is_ask { a.0.cmp(&b.0) } else { b.0.cmp(&a.0) });
+            sorted
+        };
+        let queue = |orders: &VecDeque<RefOrder>| {
+            orders
+                .iter()
+                .map(|order| (order.id, order.remaining()))
+                .collect()
+        };
+        BookSnapshot {
+            asks: levels(&self.asks, true),
+            bids: levels(&self.bids, false),
+            market_asks: queue(&self.market_asks),
+            market_bids: queue(&self.market_bids),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ethers::types::Address;
+    use proptest::prelude::*;
+    use std::collections::HashMap;
+
+    #[derive(Debug, Clone)]
+    enum Op {
+        Limit { is_ask: bool, price: u64, amount: u64 },
+        Market { is_ask: bool, amount: u64 },
+        Remove { pick: usize },
+    }
+
+    fn op() -> impl Strategy<Value = Op> {
+        prop_oneof![
+            4 => (any::<bool>(), 1..=24u64, 1..=20u64)
+                .prop_map(|(is_ask, price, amount)| Op::Limit { is_ask, price, amount }),
+            2 => (any::<bool>(), 1..=60u64).prop_map(|(is_ask, amount)| Op::Market { is_ask, amount }),
+            1 => any::<usize>().prop_map(|pick| Op::Remove { pick }),
+        ]
+    }
+
+    fn limits() -> impl Strategy<Value = MatchLimits> {
+        (1..=6usize, 1..=6usize).prop_map(|(limit_iterations, market_iterations)| MatchLimits {
+            limit_iterations,
+            market_iterations,
+        })
+    }
+
+    /// 价格取 PRICE_DECIMALS / 4 的整数倍，使市价买单换算时出现向下取整
+    fn to_price(ticks: u64) -> U256 {
+        PRICE_DECIMALS * ticks / 4
+    }
+
+    /// 订单分属三个交易者（validate 会检查交易者索引）
+    fn trader(id: U256) -> Address {
+        Address::from_low_u64_be(id.low_u64() % 3 + 1)
+    }
+
+    /// 按成交记录累计的已成交量（市价买单为花费的计价代币）
+    #[derive(Default)]
+    struct Ledger {
+        filled: HashMap<U256, U256>,
+    }
+
+    impl Ledger {
+        fn record(&mut self, trade: &SimTrade, market_bids: &[U256]) {
+            let buy = if market_bids.contains(&trade.buy_order_id) {
+                trade.amount * trade.price / PRICE_DECIMALS
+            } else {
+                trade.amount
+            };
+            *self.filled.entry(trade.buy_order_id).or_default() += buy;
+            *self.filled.entry(trade.sell_order_id).or_default() += trade.amount;
+        }
+
+        fn filled(&self, id: U256) -> U256 {
+            self.filled.get(&id).copied().unwrap_or_default()
+        }
+    }
+
+    #[test]
+    fn test_level_order_follows_insert_after_order() {
+        let price = PRICE_DECIMALS;
+        let mut reference = ReferenceMatcher::new(MatchLimits::default());
+        reference.insert_limit(U256::from(1), price, U256::from(1), false, U256::zero());
+        reference.insert_limit(U256::from(2), price, U256::from(1), false, U256::zero());
+        reference.insert_limit(U256::from(3), price, U256::from(1), false, U256::from(2));
+
+        // 0 插到头部，非 0 插到指定订单之后：2 -> 3 -> 1
+        let ids = |reference: &ReferenceMatcher| -> Vec<U256> {
+            reference.snapshot().bids[0].1.iter().map(|(id, _)| *id).collect()
+        };
+        assert_eq!(ids(&reference), vec![U256::from(2), U256::from(3), U256::from(1)]);
+
+        // 对手单与层级头部成交
+        let trades = reference.insert_limit(U256::from(4), price, U256::from(1), true, U256::zero());
+        assert_eq!(trades[0].buy_order_id, U256::from(2));
+        assert_eq!(ids(&reference), vec![U256::from(3), U256::from(1)]);
+    }
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(256))]
+
+        #[test]
+        fn simulator_matches_reference(limits in limits(), ops in prop::collection::vec(op(), 1..120)) {
+            let mut sim = OrderBookSimulator::new();
+            sim.match_limits = limits;
+            let mut reference = ReferenceMatcher::new(limits);
+
+            let mut amounts: HashMap<U256, U256> = HashMap::new();
+            let mut limit_prices: HashMap<U256, (U256, bool)> = HashMap::new();
+            let mut market_bids = Vec::new();
+            let mut ledger = Ledger::default();
+
+            for (step, op) in ops.into_iter().enumerate() {
+                let id = U256::from(step + 1);
+                let (trades, expected) = match op {
+                    Op::Limit { is_ask, price, amount } => {
+                        let (price, amount) = (to_price(price), U256::from(amount));
+                        amounts.insert(id, amount);
+                        limit_prices.insert(id, (price, is_ask));
+                        let effects = sim.simulate_insert_order(id, trader(id), price, amount, is_ask).unwrap();
+                        // 撮合引擎总是提交 insertAfterOrder = 0
+                        (effects.trades, reference.insert_limit(id, price, amount, is_ask, U256::zero()))
+                    }
+                    Op::Market { is_ask, amount } => {
+                        let amount = U256::from(amount);
+                        amounts.insert(id, amount);
+                        if !is_ask {
+                            market_bids.push(id);
+                        }
+                        let effects = sim.simulate_insert_market_order(id, trader(id), amount, is_ask).unwrap();
+                        (effects.trades, reference.insert_market(id, amount, is_ask))
+                    }
+                    Op::Remove { pick } => {
+                        let target = U256::from(pick % (step + 1) + 1);
+                        let removed = sim.simulate_remove_order(target, trader(target)).is_ok();
+                        prop_assert_eq!(removed, reference.remove(target));
+                        (vec![], vec![])
+                    }
+                };
+
+                // 与参考撮合器的成交序列、订单簿完全一致
+                prop_assert_eq!(&trades, &expected);
+                prop_assert_eq!(BookSnapshot::of(&sim), reference.snapshot());
+                prop_assert_eq!(sim.validate(), vec![]);
+
+                for trade in &trades {
+                    // 限价单之间：买价 >= 卖价，成交价为卖价
+                    if let (Some((bid, _)), Some((ask, _))) =
+                        (limit_prices.get(&trade.buy_order_id), limit_prices.get(&trade.sell_order_id))
+                    {
+                        prop_assert!(bid >= ask);
+                        prop_assert_eq!(trade.price, *ask);
+                    }
+                    ledger.record(trade, &market_bids);
+                }
+
+                // 成交量守恒：簿上订单的 filled_amount 等于其成交记录之和，且不超过下单量
+                for (id, amount) in &amounts {
+                    let filled = ledger.filled(*id);
+                    prop_assert!(filled <= *amount);
+                    if let Some(order) = sim.orders.get(id) {
+                        prop_assert_eq!(order.filled_amount, filled);
+                        prop_assert!(order.filled_amount < order.amount);
+                    }
+                }
+            }
+        }
+
+        #[test]
+        fn rollback_restores_snapshot(
+            setup in prop::collection::vec(op(), 0..40),
+            speculative in prop::collection::vec(op(), 1..40),
+        ) {
+            let mut sim = OrderBookSimulator::new();
+            let apply = |sim: &mut OrderBookSimulator, ops: Vec<Op>, first_id: usize| {
+                for (offset, op) in ops.into_iter().enumerate() {
+                    let id = U256::from(first_id + offset);
+                    match op {
+                        Op::Limit { is_ask, price, amount } => {
+                            sim.simulate_insert_order(id, trader(id), to_price(price), U256::from(amount), is_ask).unwrap();
+                        }
+                        Op::Market { is_ask, amount } => {
+                            sim.simulate_insert_market_order(id, trader(id), U256::from(amount), is_ask).unwrap();
+                        }
+                        Op::Remove { pick } => {
+                            let target = U256::from(pick % (first_id + offset) + 1);
+                            let _ = sim.simulate_remove_order(target, trader(target));
+                        }
+                    }
+                }
+            };
+
+            let setup_len = setup.len();
+            apply(&mut sim, setup, 1);
+            let before = BookSnapshot::of(&sim);
+            let orders_before = sim.orders.len();
+
+            sim.checkpoint();
+            apply(&mut sim, speculative, setup_len + 1);
+            sim.rollback();
+
+            prop_assert_eq!(BookSnapshot::of(&sim), before);
+            prop_assert_eq!(sim.orders.len(), orders_before);
+            prop_assert_eq!(sim.validate(), vec![]);
+        }
+    }
+}
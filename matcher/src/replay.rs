@@ -0,0 +1,391 @@
+//! 离线重放 - 用事件归档代替节点，运行同步器的事件处理和匹配引擎的批处理参数计算
+//!
+//! 同一份归档总是得到同样的结果，用于确定性地复现线上问题，
+//! 以及对比模拟器改动前后的批处理参数与计算耗时。
+//!
+//! 事件按交易分组处理：含 RequestProcessed 的交易即一次 batchProcessRequests，
+//! 应用它之前先用当时的状态计算批处理参数（即线上匹配引擎发送这笔交易前看到的状态），
+//! 再与交易实际处理的请求对比；应用之后与线上一样从队列中移除已处理的请求。
+//!
+//! 归档开始之前的状态无法从归档得到：归档应从合约部署（start_block）开始记录，
+//! 或者用 with_orderbook 提供交易对的起始订单簿（book_dump 导出的 JSON）。
+
+use crate::chain_source::{ChainSource, SourceEvent};
+use crate::config::{Config, HistoryConfig};
+use crate::contracts::account::AccountEvents;
+use crate::contracts::sequencer::SequencerEvents;
+use crate::events::{ChainEvent, DecodedLog};
+use crate::matcher::MatchingEngine;
+use crate::orderbook_simulator::OrderBookSimulator;
+use crate::state::GlobalState;
+use crate::sync::EventProcessor;
+use crate::trade_history::TradeHistory;
+use crate::types::{MatchResult, TradingPairInfo};
+use anyhow::{Context, Result};
+use ethers::types::{H256, U256};
+use std::fmt;
+use std::time::{Duration, Instant};
+use tracing::warn;
+
+/// 一次 batchProcessRequests 的重放结果
+#[derive(Debug, Clone)]
+pub struct BatchReplay {
+    pub tx_hash: H256,
+    pub block_number: u64,
+    /// 重放时计算的批处理参数
+    pub predicted: MatchResult,
+    /// 交易实际处理的请求（按 RequestProcessed 事件顺序）
+    pub processed: Vec<U256>,
+    /// 计算批处理参数的耗时
+    pub elapsed: Duration,
+}
+
+impl BatchReplay {
+    /// 计算出的请求与交易实际处理的请求一致
+    pub fn matches(&self) -> bool {
+        self.predicted.order_ids == self.processed
+    }
+}
+
+/// 重放统计
+#[derive(Debug, Clone, Default)]
+pub struct ReplayReport {
+    /// 处理的日志数（不含重复和被重组移除的日志）
+    pub events: usize,
+    /// 被重组移除的日志数
+    pub removed_events: usize,
+    pub blocks: usize,
+    pub batches: Vec<BatchReplay>,
+}
+
+impl ReplayReport {
+    /// 计算结果与链上不一致的批次
+    pub fn mismatches(&self) -> impl Iterator<Item = &BatchReplay> {
+        self.batches.iter().filter(|batch| !batch.matches())
+    }
+
+    /// 全部批次计算批处理参数的总耗时
+    pub fn compute_time(&self) -> Duration {
+        self.batches.iter().map(|batch| batch.elapsed).sum()
+    }
+}
+
+impl fmt::Display for ReplayReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{} events ({} removed by reorg) over {} blocks",
+            self.events, self.removed_events, self.blocks
+        )?;
+        let average = self
+            .compute_time()
+            .checked_div(self.batches.len() as u32)
+            .unwrap_or_default();
+        write!(
+            f,
+            "{} batches, {} mismatched, compute time {:?} (avg {:?})",
+            self.batches.len(),
+            self.mismatches().count(),
+            self.compute_time(),
+            average
+        )
+    }
+}
+
+/// 从事件来源重放到一份新的 GlobalState 上
+pub struct Replayer<S: ChainSource> {
+    source: S,
+    state: GlobalState,
+    processor: EventProcessor,
+    engine: MatchingEngine,
+    /// 当前交易已读取的日志，交易结束后一起处理
+    pending_tx: Vec<DecodedLog>,
+    report: ReplayReport,
+}
+
+impl<S: ChainSource> Replayer<S> {
+    /// 状态从空开始；重放不写事件归档和成交记录文件
+    pub fn new(config: Config, source: S) -> Result<Self> {
+        let state = GlobalState::new();
+        *state.trade_history.write() = TradeHistory::open(&HistoryConfig {
+            trade_file: None,
+            ..config.history.clone()
+        })?;
+        let processor = EventProcessor::new(&config.sync, state.clone(), None)?;
+        let engine = MatchingEngine::offline(config, state.clone())?;
+
+        Ok(Self {
+            source,
+            state,
+            processor,
+            engine,
+            pending_tx: Vec::new(),
+            report: ReplayReport::default(),
+        })
+    }
+
+    /// 交易对从给定的订单簿开始（head 与 confirmed 视图相同）
+    pub fn with_orderbook(self, trading_pair: [u8; 32], orderbook: OrderBookSimulator) -> Self {
+        self.state
+            .confirmed_orderbooks
+            .write()
+            .insert(trading_pair, orderbook.clone());
+        self.state.orderbooks.write().insert(trading_pair, orderbook);
+        self
+    }
+
+    pub fn state(&self) -> GlobalState {
+        self.state.clone()
+    }
+
+    /// 重放到来源结束
+    pub async fn run(mut self) -> Result<ReplayReport> {
+        while let Some(event) = self.source.next_event().await? {
+            match event {
+                SourceEvent::Log { log, removed: false } => {
+                    if self
+                        .pending_tx
+                        .last()
+                        .is_some_and(|last| last.meta.tx_hash != log.meta.tx_hash)
+                    {
+                        self.flush_tx()?;
+                    }
+                    self.pending_tx.push(*log);
+                }
+                event => {
+                    self.flush_tx()?;
+                    self.process(event);
+                }
+            }
+        }
+        self.flush_tx()?;
+        Ok(self.report)
+    }
+
+    /// 处理当前交易的日志；批处理交易先计算批处理参数
+    fn flush_tx(&mut self) -> Result<()> {
+        let logs = std::mem::take(&mut self.pending_tx);
+        let Some(first) = logs.first() else {
+            return Ok(());
+        };
+
+        // 先在应用交易之前的状态上计算批处理参数
+        let is_batch = logs.iter().any(|log| processed_request(log).is_some());
+        let predicted = if is_batch {
+            let started = Instant::now();
+            let predicted = self
+                .engine
+                .compute_batch()
+                .with_context(|| format!("Failed to compute batch for tx {:?}", first.meta.tx_hash))?;
+            Some((predicted, started.elapsed()))
+        } else {
+            None
+        };
+        let (tx_hash, block_number) = (first.meta.tx_hash, first.meta.block_number);
+
+        // 实际处理的请求只取已应用的日志（重复的日志不计）
+        let mut processed = Vec::new();
+        for log in logs {
+            let applied = self.process(SourceEvent::Log {
+                log: Box::new(log),
+                removed: false,
+            });
+            processed.extend(applied.as_ref().and_then(processed_request));
+        }
+
+        if let (Some((predicted, elapsed)), false) = (predicted, processed.is_empty()) {
+            let batch = BatchReplay {
+                tx_hash,
+                block_number,
+                predicted,
+                processed,
+                elapsed,
+            };
+            if !batch.matches() {
+                warn!(
+                    "Batch mismatch in tx {:?} (block {}): computed {:?}, processed {:?}",
+                    batch.tx_hash, batch.block_number, batch.predicted.order_ids, batch.processed
+                );
+            }
+            self.state.complete_requests(&batch.processed);
+            self.report.batches.push(batch);
+        }
+        Ok(())
+    }
+
+    /// 交给 EventProcessor 处理，返回作用到 head 视图的日志
+    fn process(&mut self, event: SourceEvent) -> Option<DecodedLog> {
+        match &event {
+            SourceEvent::Log { removed: true, .. } => self.report.removed_events += 1,
+            SourceEvent::NewHead(_) => self.report.blocks += 1,
+            SourceEvent::Log { .. } => {}
+        }
+
+        let log = self.processor.process(event)?;
+        self.report.events += 1;
+
+        // 离线无法读取代币精度：交易对只登记代币，预计手续费等需要精度的统计跳过
+        if let ChainEvent::Account(AccountEvents::TradingPairRegisteredFilter(registered)) = &log.event {
+            if self.processor.is_pair_allowed(&registered.trading_pair) {
+                self.state.add_trading_pair(TradingPairInfo {
+                    trading_pair: registered.trading_pair,
+                    base_token: registered.base_token,
+                    quote_token: registered.quote_token,
+                });
+            }
+        }
+        Some(log)
+    }
+}
+
+/// RequestProcessed 事件中的请求 ID
+fn processed_request(log: &DecodedLog) -> Option<U256> {
+    match &log.event {
+        ChainEvent::Sequencer(SequencerEvents::RequestProcessedFilter(processed)) => Some(processed.request_id),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::archive::ArchiveRecord;
+    use crate::chain_source::FileSource;
+    use crate::config::StateView;
+    use crate::contracts::order_book::{OrderBookEvents, OrderInsertedFilter, PriceLevelCreatedFilter};
+    use crate::contracts::sequencer::{PlaceOrderRequestedFilter, RequestProcessedFilter};
+    use crate::events::EventMeta;
+    use ethers::types::Address;
+
+    const CONFIG: &str = r#"
+        [network]
+        rpc_url = "http://localhost:8545"
+        chain_id = 31337
+
+        [contracts]
+        sequencer = "0x0000000000000000000000000000000000000001"
+        orderbook = "0x0000000000000000000000000000000000000002"
+        account = "0x0000000000000000000000000000000000000003"
+
+        [sync]
+        start_block = 1
+        sync_historical = false
+
+        [matching]
+        max_batch_size = 10
+        matching_interval_ms = 1000
+
+        [executor]
+        private_key = "0x0000000000000000000000000000000000000000000000000000000000000001"
+        gas_price_gwei = 1
+        gas_limit = 1000000
+    "#;
+
+    fn record(block_number: u64, tx: u64, log_index: u64, event: ChainEvent) -> ArchiveRecord {
+        let log = DecodedLog {
+            meta: EventMeta {
+                block_number,
+                block_hash: H256::from_low_u64_be(block_number),
+                tx_hash: H256::from_low_u64_be(tx),
+                log_index: U256::from(log_index),
+                block_timestamp: 1_700_000_000 + block_number,
+            },
+            event,
+        };
+        ArchiveRecord::new(&log, false)
+    }
+
+    fn place_order(request_id: u64, price: u64) -> ChainEvent {
+        ChainEvent::Sequencer(SequencerEvents::PlaceOrderRequestedFilter(PlaceOrderRequestedFilter {
+            request_id: U256::from(request_id),
+            order_id: U256::from(request_id),
+            trading_pair: [7; 32],
+            trader: Address::from_low_u64_be(1),
+            order_type: 0,
+            is_ask: true,
+            price: U256::from(price),
+            amount: U256::from(5),
+            timestamp: U256::from(1_700_000_000u64),
+        }))
+    }
+
+    fn processed(request_id: u64) -> ChainEvent {
+        ChainEvent::Sequencer(SequencerEvents::RequestProcessedFilter(RequestProcessedFilter {
+            request_id: U256::from(request_id),
+            request_type: 0,
+        }))
+    }
+
+    fn inserted(order_id: u64, price: u64) -> Vec<ChainEvent> {
+        vec![
+            ChainEvent::OrderBook(OrderBookEvents::PriceLevelCreatedFilter(PriceLevelCreatedFilter {
+                trading_pair: [7; 32],
+                price: U256::from(price),
+                is_ask: true,
+            })),
+            ChainEvent::OrderBook(OrderBookEvents::OrderInsertedFilter(OrderInsertedFilter {
+                trading_pair: [7; 32],
+                order_id: U256::from(order_id),
+                is_ask: true,
+                price: U256::from(price),
+                amount: U256::from(5),
+            })),
+        ]
+    }
+
+    #[tokio::test]
+    async fn test_replay_batches() {
+        let config: Config = toml::from_str(CONFIG).unwrap();
+
+        // 区块 10：请求 1；区块 11：处理请求 1；区块 12：请求 2；
+        // 区块 13：处理请求 2 的交易同时也处理了重放状态中不存在的请求 3
+        let mut records = vec![record(10, 1, 0, place_order(1, 100))];
+        records.extend(inserted(1, 100).into_iter().enumerate().map(|(i, e)| record(11, 2, i as u64, e)));
+        records.push(record(11, 2, 2, processed(1)));
+        records.push(record(12, 3, 0, place_order(2, 110)));
+        records.extend(inserted(2, 110).into_iter().enumerate().map(|(i, e)| record(13, 4, i as u64, e)));
+        records.push(record(13, 4, 2, processed(2)));
+        records.push(record(13, 4, 3, processed(3)));
+        // 重复的日志只处理一次
+        records.push(record(13, 4, 3, processed(3)));
+
+        let replayer = Replayer::new(config, FileSource::from_records(records)).unwrap();
+        let state = replayer.state();
+        let report = replayer.run().await.unwrap();
+
+        assert_eq!(report.blocks, 4);
+        assert_eq!(report.events, 9);
+        assert_eq!(report.batches.len(), 2);
+        assert!(report.batches[0].matches());
+        assert_eq!(report.batches[0].predicted.insert_after_price_levels, vec![U256::zero()]);
+        assert_eq!(report.batches[1].predicted.order_ids, vec![U256::from(2)]);
+        assert_eq!(report.batches[1].processed, vec![U256::from(2), U256::from(3)]);
+        assert_eq!(report.mismatches().count(), 1);
+
+        assert!(state.queue.read().is_empty());
+        assert_eq!(state.clone_orderbook_view(StateView::Head, &[7; 32]).orders.len(), 2);
+        assert_eq!(*state.current_block.read(), 13);
+    }
+
+    #[tokio::test]
+    async fn test_replay_skips_pairs_outside_allow_list() {
+        let config = CONFIG.replace(
+            "start_block = 1",
+            &format!("start_block = 1\ntrading_pairs = [\"0x{}\"]", "08".repeat(32)),
+        );
+        let config: Config = toml::from_str(&config).unwrap();
+
+        let mut records = vec![record(10, 1, 0, place_order(1, 100))];
+        records.extend(inserted(1, 100).into_iter().enumerate().map(|(i, e)| record(11, 2, i as u64, e)));
+
+        let replayer = Replayer::new(config, FileSource::from_records(records)).unwrap();
+        let state = replayer.state();
+        let report = replayer.run().await.unwrap();
+
+        // 队列与链上保持一致，只有订单簿事件被跳过
+        assert_eq!(report.events, 1);
+        assert!(state.queue.read().contains(&U256::from(1)));
+        assert!(state.orderbooks.read().is_empty());
+        assert!(state.confirmed_orderbooks.read().is_empty());
+    }
+}
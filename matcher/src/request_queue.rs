@@ -0,0 +1,159 @@
+//! Sequencer 请求队列镜像
+//!
+//! 与 Sequencer.sol 一致：新请求追加到队列尾部（_createRequest），
+//! batchProcessRequests 从队列头部依次处理（processRequest，发出 RequestProcessed）。
+//! head / confirmed 视图各有一份；链重组时 head 队列由 confirmed 队列加上未确认事件重建。
+
+use crate::types::QueuedRequest;
+use ethers::types::U256;
+use std::collections::HashMap;
+
+/// 请求队列：request_id -> QueuedRequest，按 next_request_id 串成链表
+#[derive(Debug, Clone, Default)]
+pub struct RequestQueue {
+    requests: HashMap<U256, QueuedRequest>,
+    head: U256,
+    tail: U256,
+}
+
+impl RequestQueue {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 队列头部（最早的请求），空队列为 0
+    pub fn head(&self) -> U256 {
+        self.head
+    }
+
+    pub fn len(&self) -> usize {
+        self.requests.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.requests.is_empty()
+    }
+
+    pub fn get(&self, request_id: &U256) -> Option<&QueuedRequest> {
+        self.requests.get(request_id)
+    }
+
+    pub fn contains(&self, request_id: &U256) -> bool {
+        self.requests.contains_key(request_id)
+    }
+
+    /// 全部请求（无序）
+    pub fn iter(&self) -> impl Iterator<Item = &QueuedRequest> {
+        self.requests.values()
+    }
+
+    /// 追加到队列尾部；已在队列中的请求（重复事件）忽略，返回是否追加
+    pub fn push(&mut self, mut request: QueuedRequest) -> bool {
+        let request_id = request.request_id;
+        if self.requests.contains_key(&request_id) {
+            return false;
+        }
+
+        request.next_request_id = U256::zero();
+        match self.requests.get_mut(&self.tail) {
+            Some(tail) => tail.next_request_id = request_id,
+            None => self.head = request_id,
+        }
+        self.tail = request_id;
+        self.requests.insert(request_id, request);
+        true
+    }
+
+    /// 从队列中移除请求并重新连接前后请求；链上只处理头部请求，
+    /// 本地队列与链上不一致时也可能移除中间的请求
+    pub fn remove(&mut self, request_id: &U256) -> Option<QueuedRequest> {
+        let removed = self.requests.remove(request_id)?;
+        let prev_id = self
+            .requests
+            .values()
+            .find(|request| request.next_request_id == *request_id)
+            .map(|request| request.request_id);
+
+        match prev_id.and_then(|prev_id| self.requests.get_mut(&prev_id)) {
+            Some(prev) => prev.next_request_id = removed.next_request_id,
+            None => self.head = removed.next_request_id,
+        }
+        if self.tail == *request_id {
+            self.tail = prev_id.unwrap_or_default();
+        }
+        Some(removed)
+    }
+
+    /// 队列头部的前 n 个请求
+    pub fn head_requests(&self, n: usize) -> Vec<QueuedRequest> {
+        let mut result = Vec::new();
+        let mut current = self.head;
+        while result.len() < n {
+            let Some(request) = self.requests.get(&current) else {
+                break;
+            };
+            result.push(request.clone());
+            current = request.next_request_id;
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixed_point::Price;
+    use crate::types::{BlockStamp, OrderType, RequestType};
+    use ethers::types::Address;
+
+    fn request(request_id: u64) -> QueuedRequest {
+        QueuedRequest {
+            request_id: U256::from(request_id),
+            request_type: RequestType::PlaceOrder,
+            trading_pair: [0; 32],
+            trader: Address::zero(),
+            order_type: OrderType::Limit,
+            is_ask: true,
+            price: Price::zero(),
+            amount: U256::one(),
+            order_id_to_remove: U256::zero(),
+            next_request_id: U256::zero(),
+            requested: BlockStamp::default(),
+        }
+    }
+
+    fn ids(queue: &RequestQueue) -> Vec<u64> {
+        queue
+            .head_requests(usize::MAX)
+            .iter()
+            .map(|request| request.request_id.as_u64())
+            .collect()
+    }
+
+    #[test]
+    fn test_push_and_remove() {
+        let mut queue = RequestQueue::new();
+        for id in 1..=4 {
+            assert!(queue.push(request(id)));
+        }
+        assert!(!queue.push(request(2)));
+        assert_eq!(ids(&queue), vec![1, 2, 3, 4]);
+
+        // 头部、中间、尾部
+        queue.remove(&U256::from(1)).unwrap();
+        queue.remove(&U256::from(3)).unwrap();
+        queue.remove(&U256::from(4)).unwrap();
+        assert_eq!(ids(&queue), vec![2]);
+        assert!(queue.remove(&U256::from(4)).is_none());
+
+        // 尾部被移除后新请求接在剩余的尾部之后
+        queue.push(request(5));
+        assert_eq!(ids(&queue), vec![2, 5]);
+        queue.remove(&U256::from(2)).unwrap();
+        queue.remove(&U256::from(5)).unwrap();
+        assert!(queue.is_empty());
+        assert_eq!(queue.head(), U256::zero());
+        queue.push(request(6));
+        assert_eq!(ids(&queue), vec![6]);
+    }
+}
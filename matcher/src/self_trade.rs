@@ -0,0 +1,234 @@
+//! 自成交检测 - 合约与撮合器都不阻止同一交易者的买卖单互相成交
+//!
+//! 模拟器在 SimEffects.self_trades 中标出预测的自成交，
+//! 匹配引擎按 matching.self_trade_policy 处理并记录到 GlobalState.self_trades。
+
+use crate::orderbook_simulator::SimSelfTrade;
+use ethers::types::{Address, U256};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::time::{Duration, Instant};
+
+/// 队列停滞时重复输出警告的最小间隔
+pub const STALL_REPORT_INTERVAL: Duration = Duration::from_secs(60);
+
+/// 检测到自成交时匹配引擎的处理方式
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SelfTradePolicy {
+    /// 只记录（info 日志），照常处理
+    #[default]
+    Report,
+    /// 记录并输出警告，照常处理
+    Alert,
+    /// 记录并拒绝处理：批次在产生自成交的请求之前截止，该请求留在队列头部
+    ///
+    /// 链上只能从队列头部开始处理，而该请求每个批次都会再次被拒绝：
+    /// 之后整个队列（包括撤销它的 RemoveOrder）都无法处理，直到人工介入
+    /// （如切换策略，或订单簿变化使其不再自成交）。停滞期间按 STALL_REPORT_INTERVAL 输出警告，
+    /// 状态见 SelfTradeLog::stall
+    Refuse,
+}
+
+/// 一次检测到的自成交
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SelfTradeRecord {
+    /// 产生自成交的请求（插入该订单时触发撮合）
+    pub request_id: U256,
+    pub trading_pair: [u8; 32],
+    pub trader: Address,
+    pub buy_order_id: U256,
+    pub sell_order_id: U256,
+    pub price: U256,
+    pub amount: U256,
+    /// 是否因 SelfTradePolicy::Refuse 未被处理
+    pub refused: bool,
+}
+
+impl SelfTradeRecord {
+    pub fn new(request_id: U256, trading_pair: [u8; 32], trade: &SimSelfTrade, refused: bool) -> Self {
+        Self {
+            request_id,
+            trading_pair,
+            trader: trade.trader,
+            buy_order_id: trade.buy_order_id,
+            sell_order_id: trade.sell_order_id,
+            price: trade.price,
+            amount: trade.amount,
+            refused,
+        }
+    }
+}
+
+/// Refuse 策略导致的队列停滞：队列头部的请求被拒绝，批次为空
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct QueueStall {
+    /// 被拒绝的队列头部请求
+    pub request_id: U256,
+    /// 连续停滞的批次数
+    pub ticks: u64,
+    pub since: Instant,
+    last_report: Instant,
+}
+
+/// 自成交记录（按检测顺序）
+///
+/// 请求在被处理前每个批次都会重新模拟，同一请求的同一对订单只记录一次
+#[derive(Debug, Clone, Default)]
+pub struct SelfTradeLog {
+    records: Vec<SelfTradeRecord>,
+    seen: HashSet<(U256, U256, U256)>,
+    stall: Option<QueueStall>,
+}
+
+impl SelfTradeLog {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 添加记录，返回是否为新记录
+    pub fn record(&mut self, record: SelfTradeRecord) -> bool {
+        let key = (record.request_id, record.buy_order_id, record.sell_order_id);
+        if !self.seen.insert(key) {
+            return false;
+        }
+        self.records.push(record);
+        true
+    }
+
+    pub fn records(&self) -> &[SelfTradeRecord] {
+        &self.records
+    }
+
+    /// 指定交易者的自成交
+    pub fn by_trader(&self, trader: Address) -> Vec<SelfTradeRecord> {
+        self.records
+            .iter()
+            .filter(|record| record.trader == trader)
+            .copied()
+            .collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.records.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.records.is_empty()
+    }
+
+    /// 当前的队列停滞（没有停滞时为 None）
+    pub fn stall(&self) -> Option<QueueStall> {
+        self.stall
+    }
+
+    /// 记录一个因头部请求被拒绝而为空的批次；
+    /// 返回需要输出警告的停滞状态（停滞开始时及之后每隔 STALL_REPORT_INTERVAL）
+    pub fn record_stall(&mut self, request_id: U256, now: Instant) -> Option<QueueStall> {
+        match &mut self.stall {
+            Some(stall) if stall.request_id == request_id => {
+                stall.ticks += 1;
+                if now.duration_since(stall.last_report) < STALL_REPORT_INTERVAL {
+                    return None;
+                }
+                stall.last_report = now;
+                Some(*stall)
+            }
+            _ => {
+                let stall = QueueStall {
+                    request_id,
+                    ticks: 1,
+                    since: now,
+                    last_report: now,
+                };
+                self.stall = Some(stall);
+                Some(stall)
+            }
+        }
+    }
+
+    /// 批次不再为空：停滞结束，返回结束前的停滞状态
+    pub fn clear_stall(&mut self) -> Option<QueueStall> {
+        self.stall.take()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::orderbook_simulator::OrderBookSimulator;
+
+    #[test]
+    fn test_simulator_flags_self_trades() {
+        let alice = Address::from_low_u64_be(1);
+        let bob = Address::from_low_u64_be(2);
+        let mut sim = OrderBookSimulator::new();
+        sim.simulate_insert_order(U256::from(1), alice, U256::from(100), U256::from(5), true).unwrap();
+        sim.simulate_insert_order(U256::from(2), bob, U256::from(100), U256::from(5), true).unwrap();
+
+        // alice 的买单先吃到 bob 的卖单（层级头部），再吃到自己的卖单
+        let effects = sim
+            .simulate_insert_order(U256::from(3), alice, U256::from(100), U256::from(8), false)
+            .unwrap();
+        assert_eq!(effects.trades.len(), 2);
+        assert_eq!(
+            effects.self_trades,
+            vec![SimSelfTrade {
+                trader: alice,
+                buy_order_id: U256::from(3),
+                sell_order_id: U256::from(1),
+                price: U256::from(100),
+                amount: U256::from(3),
+            }]
+        );
+
+        // 市价单同样检测；trader 未知（零地址）的订单不计
+        sim.simulate_insert_order(U256::from(4), alice, U256::from(90), U256::from(1), false).unwrap();
+        let effects = sim
+            .simulate_insert_market_order(U256::from(5), alice, U256::from(1), true)
+            .unwrap();
+        assert_eq!(effects.self_trades.len(), 1);
+        sim.simulate_insert_order(U256::from(6), Address::zero(), U256::from(90), U256::from(1), false).unwrap();
+        let effects = sim
+            .simulate_insert_market_order(U256::from(7), Address::zero(), U256::from(1), true)
+            .unwrap();
+        assert!(effects.trades.len() == 1 && effects.self_trades.is_empty());
+    }
+
+    #[test]
+    fn test_log_deduplicates_repeated_detections() {
+        let trader = Address::from_low_u64_be(1);
+        let trade = SimSelfTrade {
+            trader,
+            buy_order_id: U256::from(3),
+            sell_order_id: U256::from(1),
+            price: U256::from(100),
+            amount: U256::from(3),
+        };
+        let mut log = SelfTradeLog::new();
+        assert!(log.record(SelfTradeRecord::new(U256::from(3), [0; 32], &trade, false)));
+        // 下一个批次再次模拟同一请求
+        assert!(!log.record(SelfTradeRecord::new(U256::from(3), [0; 32], &trade, false)));
+        assert_eq!(log.len(), 1);
+        assert_eq!(log.by_trader(trader).len(), 1);
+        assert!(log.by_trader(Address::zero()).is_empty());
+    }
+
+    #[test]
+    fn test_stall_reports_are_rate_limited() {
+        let mut log = SelfTradeLog::new();
+        let start = Instant::now();
+        let request = U256::from(3);
+
+        assert_eq!(log.record_stall(request, start).map(|stall| stall.ticks), Some(1));
+        assert_eq!(log.record_stall(request, start + Duration::from_secs(1)), None);
+        let reported = log.record_stall(request, start + STALL_REPORT_INTERVAL).unwrap();
+        assert_eq!((reported.ticks, reported.since), (3, start));
+        assert_eq!(log.stall().unwrap().ticks, 3);
+
+        // 头部换成另一个被拒绝的请求：重新计数
+        assert_eq!(log.record_stall(U256::from(4), start + STALL_REPORT_INTERVAL).map(|stall| stall.ticks), Some(1));
+        assert_eq!(log.clear_stall().map(|stall| stall.request_id), Some(U256::from(4)));
+        assert_eq!(log.stall(), None);
+    }
+}
@@ -0,0 +1,239 @@
+//! 成交结算模拟 - 对应链上 Account.lockFunds / Account.transferFunds
+//!
+//! 模拟器只跟踪 filled_amount（带 AMOUNT_DECIMALS / PRICE_DECIMALS 精度），
+//! 这里按合约相同的整数运算顺序换算出每笔成交实际转移的代币数量和费用：
+//!
+//! - baseAmount  = amount * 10^baseDecimals / AMOUNT_DECIMALS
+//! - quoteAmount = price * amount * 10^quoteDecimals / (PRICE_DECIMALS * AMOUNT_DECIMALS)
+//! - 买卖双方费用各为 quoteAmount * FEE_RATE / FEE_BASE
+//! - 买方从 locked 支付 quoteAmount + buyerFee，卖方实收 quoteAmount - sellerFee
+//!
+//! 市价买单与限价买单的结算公式相同（成交价为对手卖方价格）。
+
+use crate::constants::{AMOUNT_DECIMALS, FEE_BASE, FEE_RATE, PRICE_DECIMALS};
+use crate::orderbook_simulator::{SimEffects, SimTrade};
+use ethers::types::U256;
+
+/// 交易对两种代币的精度
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PairDecimals {
+    pub base: u8,
+    pub quote: u8,
+}
+
+/// 单笔成交的结算结果（代币最小单位）
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Settlement {
+    pub buy_order_id: U256,
+    pub sell_order_id: U256,
+    /// 成交价格（带 PRICE_DECIMALS 精度）
+    pub price: U256,
+    /// 成交数量（带 AMOUNT_DECIMALS 精度）
+    pub amount: U256,
+    /// 买方收到的基础代币 / 卖方付出的基础代币
+    pub base_amount: U256,
+    /// 成交额（计价代币，不含费用）
+    pub quote_amount: U256,
+    pub buyer_fee: U256,
+    pub seller_fee: U256,
+}
+
+impl Settlement {
+    /// 买方从锁定余额中支付的计价代币（溢出时为 None，链上 revert）
+    pub fn buyer_pays(&self) -> Option<U256> {
+        self.quote_amount.checked_add(self.buyer_fee)
+    }
+
+    /// 卖方实收的计价代币
+    pub fn seller_receives(&self) -> Option<U256> {
+        self.quote_amount.checked_sub(self.seller_fee)
+    }
+
+    /// 本笔成交收取的总费用（计价代币）
+    pub fn total_fee(&self) -> Option<U256> {
+        self.buyer_fee.checked_add(self.seller_fee)
+    }
+}
+
+/// 带 AMOUNT_DECIMALS 精度的数量换算为代币最小单位（溢出时为 None，链上 revert）
+pub fn token_amount(amount: U256, decimals: u8) -> Option<U256> {
+    Some(amount.checked_mul(U256::exp10(decimals as usize))? / AMOUNT_DECIMALS)
+}
+
+/// 成交额：price * amount 换算为计价代币最小单位（溢出时为 None，链上 revert）
+pub fn quote_amount(price: U256, amount: U256, quote_decimals: u8) -> Option<U256> {
+    let scaled = price
+        .checked_mul(amount)?
+        .checked_mul(U256::exp10(quote_decimals as usize))?;
+    Some(scaled / (PRICE_DECIMALS * AMOUNT_DECIMALS))
+}
+
+/// 单边交易费用
+pub fn fee(quote_amount: U256) -> Option<U256> {
+    Some(quote_amount.checked_mul(FEE_RATE)? / FEE_BASE)
+}
+
+/// 下单时锁定的代币数量（对应 Account.lockFunds；溢出时为 None，链上 revert）
+/// - 卖单锁定基础代币
+/// - 买单锁定计价代币并额外锁定费用；市价买单（price = 0）的 amount 为计价代币数量
+pub fn lock_amount(is_ask: bool, price: U256, amount: U256, decimals: PairDecimals) -> Option<U256> {
+    if is_ask {
+        return token_amount(amount, decimals.base);
+    }
+
+    let base_quote_amount = if price.is_zero() {
+        token_amount(amount, decimals.quote)?
+    } else {
+        quote_amount(price, amount, decimals.quote)?
+    };
+    Some(base_quote_amount.checked_mul(FEE_BASE + FEE_RATE)? / FEE_BASE)
+}
+
+/// 结算单笔成交（对应 Account.transferFunds；溢出时为 None，链上 revert）
+pub fn settle_trade(trade: &SimTrade, decimals: PairDecimals) -> Option<Settlement> {
+    let quote = quote_amount(trade.price, trade.amount, decimals.quote)?;
+    let fee = fee(quote)?;
+    Some(Settlement {
+        buy_order_id: trade.buy_order_id,
+        sell_order_id: trade.sell_order_id,
+        price: trade.price,
+        amount: trade.amount,
+        base_amount: token_amount(trade.amount, decimals.base)?,
+        quote_amount: quote,
+        buyer_fee: fee,
+        seller_fee: fee,
+    })
+}
+
+impl SimEffects {
+    /// 结算本次模拟产生的全部成交；任一笔溢出时为 None（链上整笔交易 revert）
+    pub fn settlements(&self, decimals: PairDecimals) -> Option<Vec<Settlement>> {
+        self.trades
+            .iter()
+            .map(|trade| settle_trade(trade, decimals))
+            .collect()
+    }
+
+    /// 本次模拟预计收取的总费用（计价代币），溢出时为 None
+    pub fn total_fees(&self, decimals: PairDecimals) -> Option<U256> {
+        self.settlements(decimals)?
+            .iter()
+            .try_fold(U256::zero(), |acc, settlement| acc.checked_add(settlement.total_fee()?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::orderbook_simulator::OrderBookSimulator;
+    use ethers::types::Address;
+
+    const WETH_USDC: PairDecimals = PairDecimals { base: 18, quote: 6 };
+
+    fn price(whole: u64) -> U256 {
+        U256::from(whole) * PRICE_DECIMALS
+    }
+
+    #[test]
+    fn test_settle_trade() {
+        // 1.5 WETH @ 2000 USDC
+        let trade = SimTrade {
+            buy_order_id: U256::from(1),
+            sell_order_id: U256::from(2),
+            price: price(2000),
+            amount: U256::from(150_000_000u64),
+        };
+        let settlement = settle_trade(&trade, WETH_USDC).unwrap();
+
+        assert_eq!(settlement.base_amount, U256::exp10(18) * 3 / 2);
+        assert_eq!(settlement.quote_amount, U256::from(3_000_000_000u64));
+        assert_eq!(settlement.buyer_fee, U256::from(3_000_000u64));
+        assert_eq!(settlement.buyer_pays(), Some(U256::from(3_003_000_000u64)));
+        assert_eq!(settlement.seller_receives(), Some(U256::from(2_997_000_000u64)));
+        assert_eq!(settlement.total_fee(), Some(U256::from(6_000_000u64)));
+    }
+
+    #[test]
+    fn test_rounding_matches_contract() {
+        // 成交额 999 个最小单位：费用向下取整为 0
+        let trade = SimTrade {
+            buy_order_id: U256::from(1),
+            sell_order_id: U256::from(2),
+            price: U256::from(999),
+            amount: AMOUNT_DECIMALS,
+        };
+        let settlement = settle_trade(&trade, PairDecimals { base: 8, quote: 8 }).unwrap();
+        assert_eq!(settlement.quote_amount, U256::from(999));
+        assert_eq!(settlement.buyer_fee, U256::zero());
+
+        // 数量精度高于代币精度时向下取整
+        assert_eq!(token_amount(U256::from(199), 6), Some(U256::from(1)));
+    }
+
+    #[test]
+    fn test_lock_amount() {
+        // 卖 1 WETH：锁定 1e18
+        assert_eq!(
+            lock_amount(true, price(2000), AMOUNT_DECIMALS, WETH_USDC),
+            Some(U256::exp10(18))
+        );
+        // 限价买 1 WETH @ 2000：锁定 2000e6 * 1001 / 1000
+        assert_eq!(
+            lock_amount(false, price(2000), AMOUNT_DECIMALS, WETH_USDC),
+            Some(U256::from(2_002_000_000u64))
+        );
+        // 市价买花费 100 USDC：锁定 100e6 * 1001 / 1000
+        assert_eq!(
+            lock_amount(false, U256::zero(), U256::from(100) * AMOUNT_DECIMALS, WETH_USDC),
+            Some(U256::from(100_100_000u64))
+        );
+    }
+
+    #[test]
+    fn test_settlements_from_simulation() {
+        let mut sim = OrderBookSimulator::new();
+        sim.simulate_insert_order(U256::from(1), Address::zero(), price(2000), AMOUNT_DECIMALS, true).unwrap();
+        sim.simulate_insert_order(U256::from(2), Address::zero(), price(2100), AMOUNT_DECIMALS, true).unwrap();
+
+        // 买 2 WETH @ 2100，依次按 2000 和 2100 成交
+        let effects =
+            sim.simulate_insert_order(U256::from(3), Address::zero(), price(2100), AMOUNT_DECIMALS * 2, false).unwrap();
+        let settlements = effects.settlements(WETH_USDC).unwrap();
+
+        assert_eq!(settlements.len(), 2);
+        assert_eq!(settlements[0].quote_amount, U256::from(2_000_000_000u64));
+        assert_eq!(settlements[1].quote_amount, U256::from(2_100_000_000u64));
+        assert_eq!(
+            effects.total_fees(WETH_USDC),
+            Some(U256::from(2 * (2_000_000 + 2_100_000u64)))
+        );
+    }
+
+    #[test]
+    fn test_overflow_returns_none() {
+        // price * amount * 10^decimals 超出 U256：链上 revert，这里返回 None 而不是 panic
+        let huge = U256::MAX / 2;
+        let trade = SimTrade {
+            buy_order_id: U256::from(1),
+            sell_order_id: U256::from(2),
+            price: huge,
+            amount: U256::from(3),
+        };
+        assert_eq!(quote_amount(huge, U256::from(3), 6), None);
+        assert_eq!(settle_trade(&trade, WETH_USDC), None);
+        assert_eq!(token_amount(U256::MAX, 18), None);
+        assert_eq!(lock_amount(false, huge, U256::from(3), WETH_USDC), None);
+        assert_eq!(lock_amount(false, U256::zero(), U256::MAX, WETH_USDC), None);
+
+        let effects = SimEffects {
+            trades: vec![trade],
+            ..SimEffects::default()
+        };
+        assert_eq!(effects.settlements(WETH_USDC), None);
+        assert_eq!(effects.total_fees(WETH_USDC), None);
+
+        // 结果恰好不溢出时照常结算
+        let max_price = U256::MAX / U256::exp10(6);
+        assert!(quote_amount(max_price, U256::one(), 6).is_some());
+    }
+}
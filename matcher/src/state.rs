@@ -1,75 +1,135 @@
-use crate::orderbook_simulator::OrderBookSimulator;
+use crate::config::StateView;
+use crate::depth::{BookOrder, DepthSnapshot, TopOfBook};
+use crate::fixed_point::{Amount, Price};
+use crate::ledger::Ledger;
+use crate::settlement::PairDecimals;
+use crate::orderbook_simulator::{MatchLimits, OrderBookSimulator, SimulatorError};
+use crate::quote::{HypotheticalOrder, MarketQuote, OrderPreview};
+use crate::request_queue::RequestQueue;
+use crate::self_trade::SelfTradeLog;
+use crate::trade_history::TradeHistory;
 use crate::types::*;
 use dashmap::DashMap;
 use ethers::types::U256;
+use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
+use tracing::debug;
+
+/// 按交易对划分的订单簿：trading_pair -> OrderBookSimulator
+pub type OrderBooks = HashMap<[u8; 32], OrderBookSimulator>;
 
 /// 全局状态（线程安全）
 #[derive(Clone)]
 pub struct GlobalState {
-    /// Sequencer 请求队列
-    /// request_id -> QueuedRequest
-    pub queued_requests: Arc<DashMap<U256, QueuedRequest>>,
+    /// Sequencer 请求队列（乐观视图）
+    pub queue: Arc<parking_lot::RwLock<RequestQueue>>,
+
+    /// Sequencer 请求队列（确认视图），链重组时用于重建乐观视图的队列
+    pub confirmed_queue: Arc<parking_lot::RwLock<RequestQueue>>,
+
+    /// 下单请求的交易者与提交时间（order_id == request_id）
+    /// OrderInserted 事件不带这些信息，应用事件时从这里查找；订单离开 confirmed 视图后删除
+    pub order_origins: Arc<DashMap<U256, OrderOrigin>>,
 
-    /// Sequencer 队列头部
-    pub queue_head: Arc<parking_lot::RwLock<U256>>,
+    /// OrderBook 模拟器（使用链表结构，与链上一致），每个交易对一份
+    /// 乐观视图：收到事件立即更新
+    pub orderbooks: Arc<parking_lot::RwLock<OrderBooks>>,
 
-    /// OrderBook 模拟器（使用链表结构，与链上一致）
-    pub orderbook: Arc<parking_lot::RwLock<OrderBookSimulator>>,
+    /// 确认视图：只包含已达到确认深度的事件
+    pub confirmed_orderbooks: Arc<parking_lot::RwLock<OrderBooks>>,
 
     /// 当前同步到的区块高度
     pub current_block: Arc<parking_lot::RwLock<u64>>,
+
+    /// confirmed 视图对应的区块高度
+    pub confirmed_block: Arc<parking_lot::RwLock<u64>>,
+
+    /// Account 余额镜像（乐观视图）
+    pub ledger: Arc<parking_lot::RwLock<Ledger>>,
+
+    /// Account 余额镜像（确认视图）
+    pub confirmed_ledger: Arc<parking_lot::RwLock<Ledger>>,
+
+    /// 已加载的交易对
+    /// trading_pair -> TradingPairInfo
+    pub trading_pairs: Arc<DashMap<[u8; 32], TradingPairInfo>>,
+
+    /// 匹配引擎检测到的自成交
+    pub self_trades: Arc<parking_lot::RwLock<SelfTradeLog>>,
+
+    /// 确认后的成交记录与 K 线
+    pub trade_history: Arc<parking_lot::RwLock<TradeHistory>>,
+}
+
+impl Default for GlobalState {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl GlobalState {
     pub fn new() -> Self {
         Self {
-            queued_requests: Arc::new(DashMap::new()),
-            queue_head: Arc::new(parking_lot::RwLock::new(U256::zero())),
-            orderbook: Arc::new(parking_lot::RwLock::new(OrderBookSimulator::new())),
+            queue: Arc::new(parking_lot::RwLock::new(RequestQueue::new())),
+            confirmed_queue: Arc::new(parking_lot::RwLock::new(RequestQueue::new())),
+            order_origins: Arc::new(DashMap::new()),
+            orderbooks: Arc::new(parking_lot::RwLock::new(OrderBooks::new())),
+            confirmed_orderbooks: Arc::new(parking_lot::RwLock::new(OrderBooks::new())),
             current_block: Arc::new(parking_lot::RwLock::new(0)),
+            confirmed_block: Arc::new(parking_lot::RwLock::new(0)),
+            ledger: Arc::new(parking_lot::RwLock::new(Ledger::new())),
+            confirmed_ledger: Arc::new(parking_lot::RwLock::new(Ledger::new())),
+            trading_pairs: Arc::new(DashMap::new()),
+            self_trades: Arc::new(parking_lot::RwLock::new(SelfTradeLog::new())),
+            trade_history: Arc::new(parking_lot::RwLock::new(TradeHistory::new())),
         }
     }
 
     /// 获取队列中的前 N 个请求
     pub fn get_head_requests(&self, n: usize) -> Vec<QueuedRequest> {
-        let mut result = Vec::new();
-        let head = *self.queue_head.read();
+        self.queue.read().head_requests(n)
+    }
 
-        if head.is_zero() {
-            return result;
+    /// 添加请求到队列尾部
+    pub fn add_request(&self, request: QueuedRequest) {
+        if request.request_type == RequestType::PlaceOrder {
+            self.order_origins.insert(
+                request.request_id,
+                OrderOrigin {
+                    trader: request.trader,
+                    requested: request.requested,
+                },
+            );
         }
+        self.queue.write().push(request);
+    }
 
-        let mut current = head;
-        for _ in 0..n {
-            if current.is_zero() {
-                break;
-            }
-
-            if let Some(request) = self.queued_requests.get(&current) {
-                result.push(request.clone());
-                current = request.next_request_id;
-            } else {
-                break;
+    /// 批处理完成：移除已处理的请求（RequestProcessed 事件可能已先移除）
+    pub fn complete_requests(&self, request_ids: &[U256]) {
+        let mut queue = self.queue.write();
+        for request_id in request_ids {
+            if queue.remove(request_id).is_some() {
+                debug!("  Removed request {} from local state", request_id);
             }
         }
-
-        result
     }
 
-    /// 更新队列头部
-    pub fn update_queue_head(&self, new_head: U256) {
-        *self.queue_head.write() = new_head;
+    /// 添加交易对，返回是否为新交易对
+    pub fn add_trading_pair(&self, info: TradingPairInfo) -> bool {
+        self.trading_pairs.insert(info.trading_pair, info).is_none()
     }
 
-    /// 添加请求到队列
-    pub fn add_request(&self, request: QueuedRequest) {
-        self.queued_requests.insert(request.request_id, request);
+    /// 交易对两种代币的精度（交易对未加载或精度未知时返回 None）
+    pub fn pair_decimals(&self, trading_pair: &[u8; 32]) -> Option<PairDecimals> {
+        let info = *self.trading_pairs.get(trading_pair)?;
+        self.ledger
+            .read()
+            .pair_decimals(&info.base_token, &info.quote_token)
     }
 
-    /// 从队列中移除请求
-    pub fn remove_request(&self, request_id: &U256) {
-        self.queued_requests.remove(request_id);
+    /// 已加载的交易对 ID
+    pub fn trading_pair_ids(&self) -> Vec<[u8; 32]> {
+        self.trading_pairs.iter().map(|entry| *entry.key()).collect()
     }
 
     /// 更新当前区块
@@ -77,8 +137,241 @@ impl GlobalState {
         *self.current_block.write() = block;
     }
 
-    /// 克隆当前订单簿状态（用于模拟计算）
-    pub fn clone_orderbook(&self) -> OrderBookSimulator {
-        self.orderbook.read().clone()
+    /// 更新已确认区块
+    pub fn update_confirmed_block(&self, block: u64) {
+        *self.confirmed_block.write() = block;
+    }
+
+    /// 指定视图的订单簿
+    pub fn view_orderbooks(&self, view: StateView) -> &parking_lot::RwLock<OrderBooks> {
+        match view {
+            StateView::Head => &self.orderbooks,
+            StateView::Confirmed => &self.confirmed_orderbooks,
+        }
+    }
+
+    /// 在指定视图的订单簿上直接模拟，结束后回滚全部修改（不复制订单簿）
+    ///
+    /// 闭包拿到该视图全部交易对的订单簿；pairs 中还没有订单簿的交易对先建立空订单簿，模拟结束后删除。
+    /// 模拟期间持有订单簿写锁，同步器的事件处理会等待模拟结束；
+    /// 闭包内不要再访问同一视图的订单簿。闭包 panic 时同样回滚（见 SimulationGuard）
+    pub fn simulate_on_orderbooks<R>(
+        &self,
+        view: StateView,
+        pairs: impl IntoIterator<Item = [u8; 32]>,
+        simulate: impl FnOnce(&mut OrderBooks) -> R,
+    ) -> R {
+        let mut orderbooks = self.view_orderbooks(view).write();
+        let guard = SimulationGuard::new(&mut orderbooks);
+        for pair in pairs {
+            guard.orderbooks.entry(pair).or_default();
+        }
+        simulate(guard.orderbooks)
+    }
+
+    /// 在指定视图中一个交易对的订单簿上模拟（见 simulate_on_orderbooks）
+    pub fn simulate_on_orderbook<R>(
+        &self,
+        view: StateView,
+        trading_pair: [u8; 32],
+        simulate: impl FnOnce(&mut OrderBookSimulator) -> R,
+    ) -> R {
+        self.simulate_on_orderbooks(view, [trading_pair], |orderbooks| {
+            simulate(orderbooks.get_mut(&trading_pair).expect("orderbook created for simulation"))
+        })
+    }
+
+    /// 估算新市价单的成交结果：先在指定视图上模拟 Sequencer 队列中的全部请求，
+    /// 新订单排在它们之后（见 OrderBookSimulator::quote_market）
+    pub fn quote_market(
+        &self,
+        view: StateView,
+        trading_pair: [u8; 32],
+        is_ask: bool,
+        amount: U256,
+    ) -> Result<MarketQuote, SimulatorError> {
+        let queued = self.get_head_requests(usize::MAX);
+        let pairs = queue_pairs(&queued, trading_pair);
+        self.simulate_on_orderbooks(view, pairs, |orderbooks| {
+            simulate_queue(orderbooks, &queued);
+            orderbooks[&trading_pair].quote_market(is_ask, amount)
+        })
+    }
+
+    /// 预估现在提交的订单会怎样：先在指定视图上模拟 Sequencer 队列中的全部请求，
+    /// 再模拟该订单（见 OrderBookSimulator::preview_order）
+    ///
+    /// 订单 id 取队列与全部订单簿中最大的 id + 1，对应它提交后得到的 request_id
+    pub fn preview_order(
+        &self,
+        view: StateView,
+        trading_pair: [u8; 32],
+        order: &HypotheticalOrder,
+    ) -> Result<OrderPreview, SimulatorError> {
+        let queued = self.get_head_requests(usize::MAX);
+        let pairs = queue_pairs(&queued, trading_pair);
+        self.simulate_on_orderbooks(view, pairs, |orderbooks| {
+            simulate_queue(orderbooks, &queued);
+            let last_id = queued
+                .iter()
+                .map(|request| request.request_id)
+                .chain(orderbooks.values().flat_map(|sim| sim.orders.keys().copied()))
+                .max()
+                .unwrap_or_default();
+            let order_id = last_id
+                .checked_add(U256::one())
+                .ok_or(SimulatorError::Overflow { context: "preview order id" })?;
+            orderbooks
+                .get_mut(&trading_pair)
+                .expect("orderbook created for simulation")
+                .preview_order(order_id, order)
+        })
+    }
+
+    /// 在指定视图中一个交易对的订单簿上只读查询（持有读锁，不复制订单簿）
+    ///
+    /// 还没有订单簿的交易对按空订单簿查询
+    pub fn with_orderbook<R>(
+        &self,
+        view: StateView,
+        trading_pair: &[u8; 32],
+        query: impl FnOnce(&OrderBookSimulator) -> R,
+    ) -> R {
+        let orderbooks = self.view_orderbooks(view).read();
+        match orderbooks.get(trading_pair) {
+            Some(sim) => query(sim),
+            None => query(&OrderBookSimulator::new()),
+        }
+    }
+
+    /// 双边聚合深度，每侧最多 levels 个价格层级（None 表示全部）
+    pub fn depth_snapshot(&self, view: StateView, trading_pair: &[u8; 32], levels: Option<usize>) -> DepthSnapshot {
+        self.with_orderbook(view, trading_pair, |sim| sim.depth_snapshot(levels))
+    }
+
+    /// 盘口（买一、卖一、价差、中间价）
+    pub fn top_of_book(&self, view: StateView, trading_pair: &[u8; 32]) -> TopOfBook {
+        self.with_orderbook(view, trading_pair, |sim| sim.top_of_book())
+    }
+
+    /// 某一侧价格不差于 price 的全部挂单量
+    pub fn cumulative_depth(&self, view: StateView, trading_pair: &[u8; 32], is_ask: bool, price: Price) -> Amount {
+        self.with_orderbook(view, trading_pair, |sim| sim.cumulative_depth(is_ask, price))
+    }
+
+    /// 某一侧的全部限价单，按撮合顺序排列
+    pub fn l3_orders(&self, view: StateView, trading_pair: &[u8; 32], is_ask: bool) -> Vec<BookOrder> {
+        self.with_orderbook(view, trading_pair, |sim| sim.l3_orders(is_ask))
+    }
+
+    /// 克隆指定视图中一个交易对的订单簿
+    pub fn clone_orderbook_view(&self, view: StateView, trading_pair: &[u8; 32]) -> OrderBookSimulator {
+        self.with_orderbook(view, trading_pair, OrderBookSimulator::clone)
+    }
+
+    /// 克隆指定视图的全部订单簿
+    pub fn clone_orderbooks(&self, view: StateView) -> OrderBooks {
+        self.view_orderbooks(view).read().clone()
+    }
+}
+
+/// 队列请求涉及的交易对以及要查询的交易对
+fn queue_pairs(queued: &[QueuedRequest], trading_pair: [u8; 32]) -> HashSet<[u8; 32]> {
+    queued
+        .iter()
+        .map(|request| request.trading_pair)
+        .chain(std::iter::once(trading_pair))
+        .collect()
+}
+
+/// 依次在各请求所属交易对的订单簿上模拟队列中的请求；
+/// 与撮合器一样，第一个模拟失败的请求及其后的请求（无论哪个交易对）不计入
+fn simulate_queue(orderbooks: &mut OrderBooks, queued: &[QueuedRequest]) {
+    for request in queued {
+        let Some(sim) = orderbooks.get_mut(&request.trading_pair) else {
+            break;
+        };
+        if sim.simulate_request(request).is_err() {
+            break;
+        }
+    }
+}
+
+/// 模拟用的检查点：离开作用域时（包括闭包 panic 展开时）把每个订单簿回滚到检查点之前，
+/// 恢复 match_limits，并删除模拟期间新建的订单簿，避免半途的模拟修改留在共享订单簿上
+struct SimulationGuard<'a> {
+    orderbooks: &'a mut OrderBooks,
+    /// 模拟前已有的订单簿：检查点深度与 match_limits
+    saved: HashMap<[u8; 32], (usize, MatchLimits)>,
+}
+
+impl<'a> SimulationGuard<'a> {
+    fn new(orderbooks: &'a mut OrderBooks) -> Self {
+        let saved = orderbooks
+            .iter_mut()
+            .map(|(pair, orderbook)| {
+                let saved = (orderbook.checkpoint_depth(), orderbook.match_limits);
+                orderbook.checkpoint();
+                (*pair, saved)
+            })
+            .collect();
+        Self { orderbooks, saved }
+    }
+}
+
+impl Drop for SimulationGuard<'_> {
+    fn drop(&mut self) {
+        let saved = &self.saved;
+        self.orderbooks.retain(|pair, _| saved.contains_key(pair));
+        for (pair, orderbook) in self.orderbooks.iter_mut() {
+            let (depth, match_limits) = saved[pair];
+            // 闭包内未关闭的检查点一并回滚
+            while orderbook.checkpoint_depth() > depth {
+                orderbook.rollback();
+            }
+            orderbook.match_limits = match_limits;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ethers::types::Address;
+    use std::panic::{catch_unwind, AssertUnwindSafe};
+
+    #[test]
+    fn test_simulation_rolls_back_on_panic() {
+        let state = GlobalState::new();
+        let pair = [1; 32];
+        state
+            .orderbooks
+            .write()
+            .entry(pair)
+            .or_default()
+            .simulate_insert_order(U256::from(1), Address::zero(), U256::from(100), U256::from(5), true)
+            .unwrap();
+        let before = state.clone_orderbook_view(StateView::Head, &pair).to_json().unwrap();
+
+        let result = catch_unwind(AssertUnwindSafe(|| {
+            state.simulate_on_orderbooks(StateView::Head, [pair, [2; 32]], |orderbooks| {
+                let sim = orderbooks.get_mut(&pair).unwrap();
+                sim.match_limits.limit_iterations = 0;
+                sim.simulate_insert_order(U256::from(2), Address::zero(), U256::from(100), U256::from(2), false)
+                    .unwrap();
+                sim.checkpoint();
+                sim.simulate_remove_order(U256::from(1), Address::zero()).unwrap();
+                panic!("simulation bug");
+            })
+        }));
+
+        assert!(result.is_err());
+        let orderbooks = state.orderbooks.read();
+        // 模拟期间新建的订单簿被删除
+        assert_eq!(orderbooks.len(), 1);
+        let orderbook = &orderbooks[&pair];
+        assert_eq!(orderbook.checkpoint_depth(), 0);
+        assert_eq!(orderbook.match_limits, MatchLimits::default());
+        assert_eq!(orderbook.to_json().unwrap(), before);
     }
 }
@@ -1,45 +1,87 @@
-use crate::config::Config;
-use crate::contracts::{OrderBook, Sequencer};
-use crate::orderbook_simulator::{SimOrder, SimPriceLevel};
-use crate::state::GlobalState;
+use crate::archive::{ArchiveRecord, EventArchive};
+use crate::chain_source::{ChainSource, LiveSource, LogFetcher, SourceEvent};
+use crate::config::{Config, StateView, SyncConfig, Transport};
+use crate::contracts::account::{AccountEvents, DepositFilter, TradingPairRegisteredFilter};
+use crate::contracts::order_book::OrderBookEvents;
+use crate::contracts::sequencer::SequencerEvents;
+use crate::contracts::{Account, Erc20, OrderBook, Sequencer};
+use crate::events::{decode_log, ChainEvent, ContractAddresses, DecodedLog, EventMeta};
+use crate::finality::ConfirmationTracker;
+use crate::fixed_point::{Amount, OrderSize, Price};
+use crate::ledger::Balance;
+use crate::orderbook_simulator::{OrderBookSimulator, SimOrder, SimPriceLevel, SimulatorError};
+use crate::request_queue::RequestQueue;
+use crate::state::{GlobalState, OrderBooks};
+use crate::trade_history::{TradeHistory, TradeRecord};
+use crate::transport::{self, RpcProvider};
 use crate::types::*;
-use anyhow::{Context, Result};
+use anyhow::{bail, Result};
+use dashmap::DashMap;
 use ethers::prelude::*;
-use futures::stream::StreamExt;
+use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
+use std::time::Duration;
 use tracing::{debug, info, warn};
 
 pub struct StateSynchronizer {
     config: Config,
     state: GlobalState,
-    provider: Arc<Provider<Ws>>,
-    sequencer: Sequencer<Provider<Ws>>,
-    orderbook: OrderBook<Provider<Ws>>,
-    synced_block: u64,
+    provider: Arc<RpcProvider>,
+    transport: Transport,
+    sequencer: Sequencer<RpcProvider>,
+    orderbook: OrderBook<RpcProvider>,
+    account: Account<RpcProvider>,
+    contracts: ContractAddresses,
+    fetcher: LogFetcher,
+    /// 事件处理（去重、归档、视图更新、确认、重组）
+    processor: EventProcessor,
 }
 
+/// 去重缓存保留的区块数
+const RECENT_LOG_BLOCKS: u64 = 128;
+
 impl StateSynchronizer {
     pub async fn new(config: Config) -> Result<Self> {
         // 连接到节点
-        let ws = Ws::connect(&config.network.rpc_url)
-            .await
-            .context("Failed to connect to WebSocket")?;
-        let provider = Arc::new(Provider::new(ws));
+        let (provider, transport) = transport::connect(&config.network).await?;
+        let provider = Arc::new(provider);
+
+        // 快照读取使用要求多节点一致的 provider
+        let snapshot_provider = Arc::new(Provider::new(
+            provider.as_ref().as_ref().with_quorum(config.network.snapshot_quorum),
+        ));
 
         // 创建合约实例
         let sequencer_addr: Address = config.contracts.sequencer.parse()?;
         let orderbook_addr: Address = config.contracts.orderbook.parse()?;
+        let account_addr: Address = config.contracts.account.parse()?;
+
+        let sequencer = Sequencer::new(sequencer_addr, snapshot_provider.clone());
+        let orderbook = OrderBook::new(orderbook_addr, snapshot_provider.clone());
+        let account = Account::new(account_addr, snapshot_provider);
+        let contracts = ContractAddresses {
+            sequencer: sequencer_addr,
+            orderbook: orderbook_addr,
+            account: account_addr,
+        };
+        let fetcher = LogFetcher::new(provider.clone(), contracts, config.sync.max_block_range);
 
-        let sequencer = Sequencer::new(sequencer_addr, provider.clone());
-        let orderbook = OrderBook::new(orderbook_addr, provider.clone());
+        let state = GlobalState::new();
+        *state.trade_history.write() = TradeHistory::open(&config.history)?;
+        let archive = EventArchive::open(&config.archive)?;
+        let processor = EventProcessor::new(&config.sync, state.clone(), archive)?;
 
         Ok(Self {
             config,
-            state: GlobalState::new(),
+            state,
             provider,
+            transport,
             sequencer,
             orderbook,
-            synced_block: 0,
+            account,
+            contracts,
+            fetcher,
+            processor,
         })
     }
 
@@ -56,28 +98,98 @@ impl StateSynchronizer {
             self.sync_historical_state().await?;
         }
 
-        // 第二步：监听事件
-        self.watch_events().await?;
+        // 第二步：监听事件；LiveSource 在连接断开或节点切换后从已同步的区块继续
+        info!(
+            "👀 Watching for OrderBook and Sequencer events from block {} (transport={:?}, confirmations={})",
+            self.processor.synced_block(),
+            self.transport,
+            self.config.sync.confirmations
+        );
+        let mut source = LiveSource::spawn(
+            self.fetcher.clone(),
+            self.transport,
+            Duration::from_millis(self.config.sync.poll_interval_ms),
+            self.processor.synced_block(),
+        );
+        while let Some(event) = source.next_event().await? {
+            self.handle_event(event).await;
+        }
+        bail!("Event source closed")
+    }
+
+    /// 处理一项事件；运行期间新注册的交易对需要读取代币精度，在这里完成
+    async fn handle_event(&mut self, event: SourceEvent) {
+        let Some(log) = self.processor.process(event) else {
+            return;
+        };
 
-        Ok(())
+        // 新注册的交易对订单簿为空，无需加载
+        if let ChainEvent::Account(AccountEvents::TradingPairRegisteredFilter(registered)) = &log.event {
+            if self.processor.is_pair_allowed(&registered.trading_pair) {
+                let info = TradingPairInfo {
+                    trading_pair: registered.trading_pair,
+                    base_token: registered.base_token,
+                    quote_token: registered.quote_token,
+                };
+                if let Err(e) = self.register_trading_pair(info).await {
+                    warn!("Failed to register trading pair: {:#}", e);
+                }
+            }
+        }
     }
 
     /// 同步历史状态
+    ///
+    /// 快照读取在已确认区块（head - confirmations）上进行，
+    /// 之后 (confirmed, head] 区间的日志通过 eth_getLogs 补齐到 head 视图和确认缓存中
     async fn sync_historical_state(&mut self) -> Result<()> {
         // 获取当前区块高度作为同步起点
         let current_block = self.provider.get_block_number().await?.as_u64();
+        let snapshot_block = self
+            .processor
+            .tracker
+            .confirmed_block(current_block)
+            .unwrap_or_default();
 
-        info!("📚 Syncing historical state at block {}", current_block);
+        info!(
+            "📚 Syncing historical state at block {} (head {})",
+            snapshot_block, current_block
+        );
 
         // 同步 Sequencer 状态（使用 RPC 读取所有 pending requests）
-        self.sync_sequencer_state(current_block).await?;
+        self.sync_sequencer_state(snapshot_block).await?;
+
+        // 发现 Account 中注册的交易对
+        self.discover_trading_pairs(snapshot_block).await?;
+
+        // 同步 OrderBook 状态到 GlobalState.orderbooks
+        self.sync_orderbook_state(snapshot_block).await?;
+
+        // 同步 Account 余额到 GlobalState.ledger
+        self.sync_account_state(snapshot_block).await?;
 
-        // 同步 OrderBook 状态到 GlobalState.orderbook
-        self.sync_orderbook_state().await?;
+        // 快照即为已确认状态
+        *self.state.confirmed_orderbooks.write() = self.state.clone_orderbooks(StateView::Head);
+        *self.state.confirmed_ledger.write() = self.state.ledger.read().clone();
+        *self.state.confirmed_queue.write() = self.state.queue.read().clone();
+        self.state.update_confirmed_block(snapshot_block);
+
+        // 补齐尚未确认的区块
+        if snapshot_block < current_block {
+            let events = self.fetcher.fetch(snapshot_block + 1, current_block).await?;
+            info!(
+                "   Replaying {} logs from unconfirmed blocks {}..={}",
+                events.len(),
+                snapshot_block + 1,
+                current_block
+            );
+            for event in events {
+                self.handle_event(event).await;
+            }
+        }
 
         // 记录同步的区块高度，后续 event 监听从这个区块开始
-        self.synced_block = current_block;
-        self.state.update_current_block(current_block);
+        self.processor.process(SourceEvent::NewHead(current_block));
 
         info!("✅ Historical state synced at block {}", current_block);
         info!("   Event monitoring will start from block {}", current_block);
@@ -86,12 +198,11 @@ impl StateSynchronizer {
     }
 
     /// 同步 Sequencer 状态
-    async fn sync_sequencer_state(&self, _from_block: u64) -> Result<()> {
+    async fn sync_sequencer_state(&self, block: u64) -> Result<()> {
         debug!("Syncing Sequencer state...");
 
         // 获取当前队列头部
-        let head_request_id = self.sequencer.queue_head().call().await?;
-        self.state.update_queue_head(head_request_id);
+        let head_request_id = self.sequencer.queue_head().block(block).call().await?;
         debug!("  Queue head: {}", head_request_id);
 
         // 如果队列为空，直接返回
@@ -106,12 +217,17 @@ impl StateSynchronizer {
 
         while !current_id.is_zero() {
             // 调用合约获取请求信息
-            let request_data = self.sequencer.queued_requests(current_id).call().await?;
+            let request_data = self
+                .sequencer
+                .queued_requests(current_id)
+                .block(block)
+                .call()
+                .await?;
 
             let next_id = request_data.7;
 
-            let request_type_u8: u8 = request_data.2.try_into().unwrap_or(0);
-            let order_type_u8: u8 = request_data.3.try_into().unwrap_or(0);
+            let request_type_u8: u8 = request_data.2;
+            let order_type_u8: u8 = request_data.3;
 
             let request = QueuedRequest {
                 request_id: current_id,
@@ -131,10 +247,12 @@ impl StateSynchronizer {
                     _ => OrderType::Limit,
                 },
                 is_ask: request_data.4,
-                price: request_data.5,
+                price: Price::from_raw(request_data.5),
                 amount: request_data.6,
                 order_id_to_remove: if request_type_u8 == 1 { request_data.5 } else { U256::zero() },
                 next_request_id: next_id,
+                // 合约不保存提交时间，启动前已在队列中的请求时间未知
+                requested: BlockStamp::default(),
             };
 
             self.state.add_request(request);
@@ -147,66 +265,187 @@ impl StateSynchronizer {
         Ok(())
     }
 
-    /// 同步 OrderBook 状态到 GlobalState.orderbook
-    async fn sync_orderbook_state(&self) -> Result<()> {
-        debug!("Syncing OrderBook state to GlobalState...");
+    /// 通过 Account.TradingPairRegistered 事件发现交易对，并用 getTradingPair 校验
+    async fn discover_trading_pairs(&self, block: u64) -> Result<()> {
+        debug!("Discovering trading pairs...");
+
+        let filter = Filter::new()
+            .address(self.contracts.account)
+            .topic0(TradingPairRegisteredFilter::signature());
+        let logs = self
+            .fetcher
+            .fetch_raw(&filter, self.config.sync.start_block, block)
+            .await?;
+
+        for log in &logs {
+            let Some(decoded) = decode_log(log, &self.contracts) else {
+                continue;
+            };
+            let ChainEvent::Account(AccountEvents::TradingPairRegisteredFilter(registered)) =
+                decoded.event
+            else {
+                continue;
+            };
 
-        // 从 state 获取已知的交易对（通过请求中的 trading_pair）
-        let trading_pairs: Vec<[u8; 32]> = self.state.queued_requests
-            .iter()
-            .map(|r| r.trading_pair)
-            .collect::<std::collections::HashSet<_>>()
-            .into_iter()
-            .collect();
+            if !self.processor.is_pair_allowed(&registered.trading_pair) {
+                debug!(
+                    "  Skipping trading pair {} (not in allow-list)",
+                    format_pair(&registered.trading_pair)
+                );
+                continue;
+            }
+
+            let (base_token, quote_token, exists) = self
+                .account
+                .get_trading_pair(registered.trading_pair)
+                .block(block)
+                .call()
+                .await?;
+            if !exists {
+                warn!(
+                    "Trading pair {} registered but not found in Account",
+                    format_pair(&registered.trading_pair)
+                );
+                continue;
+            }
 
-        for trading_pair in trading_pairs {
-            self.sync_trading_pair_orderbook(&trading_pair).await?;
+            self.register_trading_pair(TradingPairInfo {
+                trading_pair: registered.trading_pair,
+                base_token,
+                quote_token,
+            })
+            .await?;
+        }
+
+        // 白名单中有、但在 start_block 之后没有注册事件的交易对（如更早注册）直接查询
+        if let Some(allowed) = &self.processor.allowed_pairs {
+            for trading_pair in allowed {
+                if self.state.trading_pairs.contains_key(trading_pair) {
+                    continue;
+                }
+                let (base_token, quote_token, exists) = self
+                    .account
+                    .get_trading_pair(*trading_pair)
+                    .block(block)
+                    .call()
+                    .await?;
+                if exists {
+                    self.register_trading_pair(TradingPairInfo {
+                        trading_pair: *trading_pair,
+                        base_token,
+                        quote_token,
+                    })
+                    .await?;
+                } else {
+                    warn!(
+                        "Allow-listed trading pair {} is not registered",
+                        format_pair(trading_pair)
+                    );
+                }
+            }
+        }
+
+        info!("  Discovered {} trading pairs", self.state.trading_pairs.len());
+        Ok(())
+    }
+
+    /// 记录交易对，并读取两种代币的 decimals 供 ledger 换算成交数量
+    async fn register_trading_pair(&self, info: TradingPairInfo) -> Result<()> {
+        for token in [info.base_token, info.quote_token] {
+            if self.state.ledger.read().decimals(&token).is_some() {
+                continue;
+            }
+            let decimals = Erc20::new(token, self.provider.clone())
+                .decimals()
+                .call()
+                .await?;
+            self.state.ledger.write().set_decimals(token, decimals);
+            self.state.confirmed_ledger.write().set_decimals(token, decimals);
+        }
+
+        if self.state.add_trading_pair(info) {
+            info!(
+                "🪙 Trading pair {}: base={:?}, quote={:?}",
+                format_pair(&info.trading_pair),
+                info.base_token,
+                info.quote_token
+            );
+        }
+        Ok(())
+    }
+
+    /// 同步 OrderBook 状态到 GlobalState.orderbooks（每个交易对一份订单簿）
+    async fn sync_orderbook_state(&self, block: u64) -> Result<()> {
+        debug!("Syncing OrderBook state to GlobalState...");
+
+        // 加载所有已发现的交易对
+        for trading_pair in self.state.trading_pair_ids() {
+            self.sync_trading_pair_orderbook(&trading_pair, block).await?;
         }
 
         Ok(())
     }
 
     /// 同步单个交易对的订单簿到 GlobalState
-    async fn sync_trading_pair_orderbook(&self, trading_pair: &[u8; 32]) -> Result<()> {
+    async fn sync_trading_pair_orderbook(&self, trading_pair: &[u8; 32], block: u64) -> Result<()> {
         // 获取订单簿数据
-        let orderbook_data = self.orderbook.order_books(*trading_pair).call().await?;
+        let orderbook_data = self
+            .orderbook
+            .order_books(*trading_pair)
+            .block(block)
+            .call()
+            .await?;
         let ask_head = orderbook_data.0;
         let ask_tail = orderbook_data.1;
         let bid_head = orderbook_data.2;
         let bid_tail = orderbook_data.3;
 
         info!(
-            "📊 Trading pair: askHead={}, askTail={}, bidHead={}, bidTail={}",
-            ask_head, ask_tail, bid_head, bid_tail
+            "📊 Trading pair {}: askHead={}, askTail={}, bidHead={}, bidTail={}",
+            format_pair(trading_pair),
+            ask_head,
+            ask_tail,
+            bid_head,
+            bid_tail
         );
 
-        // 更新 GlobalState.orderbook 的头尾指针
-        {
-            let mut orderbook = self.state.orderbook.write();
-            orderbook.ask_head = ask_head;
-            orderbook.ask_tail = ask_tail;
-            orderbook.bid_head = bid_head;
-            orderbook.bid_tail = bid_tail;
-        }
+        // 头尾指针
+        let mut orderbook = OrderBookSimulator::new();
+        orderbook.ask_head = ask_head;
+        orderbook.ask_tail = ask_tail;
+        orderbook.bid_head = bid_head;
+        orderbook.bid_tail = bid_tail;
 
         // 同步 Ask 价格层级
-        self.sync_price_levels(ask_head, true).await?;
+        self.sync_price_levels(&mut orderbook, ask_head, true, block).await?;
 
         // 同步 Bid 价格层级
-        self.sync_price_levels(bid_head, false).await?;
+        self.sync_price_levels(&mut orderbook, bid_head, false, block).await?;
 
+        self.state.orderbooks.write().insert(*trading_pair, orderbook);
         Ok(())
     }
 
-    /// 同步价格层级链表到 GlobalState
-    async fn sync_price_levels(&self, head_price: U256, is_ask: bool) -> Result<()> {
+    /// 同步价格层级链表到交易对的订单簿
+    async fn sync_price_levels(
+        &self,
+        orderbook: &mut OrderBookSimulator,
+        head_price: U256,
+        is_ask: bool,
+        block: u64,
+    ) -> Result<()> {
         let mut current_price = head_price;
         let mut level_count = 0;
         let mut order_count = 0;
 
         while !current_price.is_zero() {
             // 获取价格层级数据
-            let level_data = self.orderbook.get_price_level(current_price, is_ask).call().await?;
+            let level_data = self
+                .orderbook
+                .get_price_level(current_price, is_ask)
+                .block(block)
+                .call()
+                .await?;
 
             let sim_level = SimPriceLevel {
                 price: level_data.price,
@@ -218,14 +457,12 @@ impl StateSynchronizer {
             };
 
             // 同步该价格层级的订单
-            let orders_synced = self.sync_orders_at_price_level(&sim_level, is_ask).await?;
+            let orders_synced = self
+                .sync_orders_at_price_level(orderbook, &sim_level, is_ask, block)
+                .await?;
             order_count += orders_synced;
 
-            // 添加到 GlobalState.orderbook
-            {
-                let mut orderbook = self.state.orderbook.write();
-                orderbook.add_existing_price_level(sim_level.clone(), is_ask);
-            }
+            orderbook.add_existing_price_level(sim_level.clone(), is_ask);
 
             level_count += 1;
             current_price = sim_level.next_price;
@@ -243,17 +480,29 @@ impl StateSynchronizer {
         Ok(())
     }
 
-    /// 同步指定价格层级的所有订单到 GlobalState
-    async fn sync_orders_at_price_level(&self, level: &SimPriceLevel, is_ask: bool) -> Result<usize> {
+    /// 同步指定价格层级的所有订单到交易对的订单簿
+    async fn sync_orders_at_price_level(
+        &self,
+        orderbook: &mut OrderBookSimulator,
+        level: &SimPriceLevel,
+        is_ask: bool,
+        block: u64,
+    ) -> Result<usize> {
         let mut current_order_id = level.head_order_id;
         let mut count = 0;
 
         while !current_order_id.is_zero() {
             // 获取订单数据
-            let order_data = self.orderbook.orders(current_order_id).call().await?;
+            let order_data = self
+                .orderbook
+                .orders(current_order_id)
+                .block(block)
+                .call()
+                .await?;
 
             let sim_order = SimOrder {
                 id: order_data.0,
+                trader: order_data.1,
                 amount: order_data.2,
                 filled_amount: order_data.3,
                 is_market_order: order_data.4,
@@ -261,15 +510,13 @@ impl StateSynchronizer {
                 price_level: order_data.5,
                 next_order_id: order_data.6,
                 prev_order_id: order_data.7,
+                requested: BlockStamp::default(),
+                inserted: BlockStamp::default(),
             };
 
             let next_id = sim_order.next_order_id;
 
-            // 添加到 GlobalState.orderbook
-            {
-                let mut orderbook = self.state.orderbook.write();
-                orderbook.add_existing_order(sim_order);
-            }
+            orderbook.add_existing_order(sim_order);
 
             count += 1;
             current_order_id = next_id;
@@ -278,443 +525,788 @@ impl StateSynchronizer {
         Ok(count)
     }
 
-    /// 监听事件
-    async fn watch_events(&self) -> Result<()> {
-        // 使用历史同步时的区块高度，确保不会漏掉事件
-        let from_block = self.synced_block;
-        info!("👀 Watching for OrderBook and Sequencer events from block {}", from_block);
+    /// 同步 Account 余额到 GlobalState.ledger
+    ///
+    /// 合约的 balances 是 mapping，无法枚举：用户来自 Deposit 事件和队列中的请求，
+    /// 代币来自 Deposit 事件和已发现的交易对，对两者的组合逐个读取 balances
+    async fn sync_account_state(&self, block: u64) -> Result<()> {
+        debug!("Syncing Account balances...");
+
+        let filter = Filter::new()
+            .address(self.contracts.account)
+            .topic0(DepositFilter::signature());
+        let logs = self
+            .fetcher
+            .fetch_raw(&filter, self.config.sync.start_block, block)
+            .await?;
+
+        let mut users = HashSet::new();
+        let mut tokens = HashSet::new();
+        for log in &logs {
+            if let Some(DecodedLog {
+                event: ChainEvent::Account(AccountEvents::DepositFilter(deposit)),
+                ..
+            }) = decode_log(log, &self.contracts)
+            {
+                users.insert(deposit.user);
+                tokens.insert(deposit.token);
+            }
+        }
+        users.extend(self.state.queue.read().iter().map(|r| r.trader));
+        for pair in self.state.trading_pairs.iter() {
+            tokens.insert(pair.base_token);
+            tokens.insert(pair.quote_token);
+        }
+
+        let mut count = 0;
+        for &token in &tokens {
+            let fees = self.account.collected_fees(token).block(block).call().await?;
+            self.state.ledger.write().set_collected_fees(token, fees);
+
+            for &user in &users {
+                let (available, locked) = self.account.balances(user, token).block(block).call().await?;
+                if available.is_zero() && locked.is_zero() {
+                    continue;
+                }
+                self.state
+                    .ledger
+                    .write()
+                    .set_balance(user, token, Balance { available, locked });
+                count += 1;
+            }
+        }
+
+        info!(
+            "  Loaded {} balances ({} users, {} tokens)",
+            count,
+            users.len(),
+            tokens.len()
+        );
+        Ok(())
+    }
+}
+
+/// 同步器的事件处理：不访问节点，事件来自 ChainSource（节点或事件归档）
+///
+/// 正常日志作用于 head 视图并进入确认缓存，被重组的日志触发回滚，
+/// 新区块把达到确认深度的事件应用到 confirmed 视图
+pub struct EventProcessor {
+    state: GlobalState,
+    /// 交易对白名单（None = 全部）
+    allowed_pairs: Option<HashSet<[u8; 32]>>,
+    validate_orderbook: bool,
+    synced_block: u64,
+    tracker: ConfirmationTracker,
+    /// 最近处理过的日志 (tx_hash, log_index) -> 区块号
+    /// 重连 / 切换节点后补拉日志时可能与订阅推送重叠，用于去重
+    recent_logs: HashMap<(H256, U256), u64>,
+    /// 事件归档（未配置时为 None）
+    archive: Option<EventArchive>,
+}
+
+impl EventProcessor {
+    pub fn new(config: &SyncConfig, state: GlobalState, archive: Option<EventArchive>) -> Result<Self> {
+        Ok(Self {
+            state,
+            allowed_pairs: config.trading_pair_allow_list()?,
+            validate_orderbook: config.validate_orderbook,
+            synced_block: config.start_block.saturating_sub(1),
+            tracker: ConfirmationTracker::new(config.confirmations),
+            recent_logs: HashMap::new(),
+            archive,
+        })
+    }
+
+    pub fn state(&self) -> &GlobalState {
+        &self.state
+    }
 
-        // 创建 OrderBook 事件监听任务
-        let orderbook_watcher = {
-            let orderbook = self.orderbook.clone();
-            let state = self.state.clone();
+    /// 已处理到的最高区块
+    pub fn synced_block(&self) -> u64 {
+        self.synced_block
+    }
 
-            tokio::spawn(async move {
-                Self::watch_orderbook_events(orderbook, state, from_block).await
-            })
-        };
+    pub fn is_pair_allowed(&self, trading_pair: &[u8; 32]) -> bool {
+        self.allowed_pairs
+            .as_ref()
+            .is_none_or(|allowed| allowed.contains(trading_pair))
+    }
 
-        // 创建 Sequencer 事件监听任务
-        let sequencer_watcher = {
-            let sequencer = self.sequencer.clone();
-            let state = self.state.clone();
+    /// 只跟踪白名单中交易对的订单簿；Sequencer 队列与链上保持一致（链上按队列顺序处理，
+    /// 白名单只影响撮合器选取批次，见 MatchingEngine::compute_batch），Account 事件全部处理
+    fn is_event_allowed(&self, event: &ChainEvent) -> bool {
+        match event {
+            ChainEvent::OrderBook(_) => event
+                .trading_pair()
+                .is_none_or(|trading_pair| self.is_pair_allowed(&trading_pair)),
+            ChainEvent::Sequencer(_) | ChainEvent::Account(_) => true,
+        }
+    }
 
-            tokio::spawn(async move {
-                Self::watch_sequencer_events(sequencer, state, from_block).await
-            })
+    /// 处理一项事件，返回作用到 head 视图的日志（重复、被移除的日志和新区块返回 None）
+    pub fn process(&mut self, event: SourceEvent) -> Option<DecodedLog> {
+        match event {
+            SourceEvent::NewHead(head_block) => {
+                self.advance_head(head_block);
+                None
+            }
+            SourceEvent::Log { log, removed: true } => {
+                self.recent_logs.remove(&(log.meta.tx_hash, log.meta.log_index));
+                self.archive_event(&log, true);
+                if self.is_event_allowed(&log.event) {
+                    self.handle_removed_log(&log);
+                }
+                None
+            }
+            SourceEvent::Log { log, removed: false } => self.handle_log(*log),
+        }
+    }
+
+    fn handle_log(&mut self, log: DecodedLog) -> Option<DecodedLog> {
+        let key = (log.meta.tx_hash, log.meta.log_index);
+        if self.recent_logs.insert(key, log.meta.block_number).is_some() {
+            debug!(
+                "Skipping duplicate log: tx={:?}, index={}",
+                log.meta.tx_hash, log.meta.log_index
+            );
+            return None;
+        }
+
+        self.archive_event(&log, false);
+
+        // 归档保留全部事件，订单簿只跟踪白名单中的交易对
+        if !self.is_event_allowed(&log.event) {
+            debug!(
+                "Skipping event for trading pair outside allow list: tx={:?}, index={}",
+                log.meta.tx_hash, log.meta.log_index
+            );
+            return None;
+        }
+
+        log_event(&log.event);
+        apply_event(&self.state, &log);
+        if matches!(log.event, ChainEvent::OrderBook(_)) {
+            self.check_orderbook_invariants(StateView::Head, &log.event);
+        }
+
+        if self.tracker.confirmations() == 0 {
+            apply_confirmed_event(&self.state, &log);
+            self.state.update_confirmed_block(log.meta.block_number);
+            if matches!(log.event, ChainEvent::OrderBook(_)) {
+                self.check_orderbook_invariants(StateView::Confirmed, &log.event);
+            }
+        } else {
+            self.tracker.push(log.clone());
+        }
+        Some(log)
+    }
+
+    /// 写入事件归档；写入失败只输出警告，不影响同步
+    fn archive_event(&mut self, decoded: &DecodedLog, removed: bool) {
+        if let Some(archive) = self.archive.as_mut() {
+            if let Err(e) = archive.append(&ArchiveRecord::new(decoded, removed)) {
+                warn!("Failed to archive event: {:#}", e);
+            }
+        }
+    }
+
+    /// debug 构建下按配置检查事件所属交易对的订单簿结构不变量，违反项以警告输出
+    fn check_orderbook_invariants(&self, view: StateView, event: &ChainEvent) {
+        if !cfg!(debug_assertions) || !self.validate_orderbook {
+            return;
+        }
+        let Some(trading_pair) = event.trading_pair() else {
+            return;
         };
 
-        // 等待任一任务完成
-        tokio::select! {
-            result = orderbook_watcher => {
-                match result {
-                    Ok(Ok(_)) => info!("OrderBook watcher completed"),
-                    Ok(Err(e)) => warn!("OrderBook watcher error: {}", e),
-                    Err(e) => warn!("OrderBook watcher task error: {}", e),
+        let orderbooks = self.state.view_orderbooks(view).read();
+        let Some(orderbook) = orderbooks.get(&trading_pair) else {
+            return;
+        };
+        for violation in orderbook.validate() {
+            warn!(
+                "⚠️  Orderbook invariant violated ({:?} view, pair {}): {}",
+                view,
+                format_pair(&trading_pair),
+                violation
+            );
+        }
+    }
+
+    /// 链重组：从确认缓存中剔除事件，并用 confirmed 视图 + 剩余缓存重建 head 视图
+    fn handle_removed_log(&mut self, decoded: &DecodedLog) {
+        warn!(
+            "↩️  Log removed by reorg: block={}, tx={:?}, index={}",
+            decoded.meta.block_number, decoded.meta.tx_hash, decoded.meta.log_index
+        );
+
+        if !self.tracker.remove(&decoded.meta) {
+            warn!(
+                "Reorg deeper than {} confirmations, confirmed view may be stale",
+                self.tracker.confirmations()
+            );
+        }
+
+        // 队列、订单簿和余额都由 confirmed 视图加剩余的未确认事件重建：
+        // 被重组的请求离开队列（前后请求重新连接），被重组的 RequestProcessed 让请求回到队列
+        let mut rebuilt_queue = self.state.confirmed_queue.read().clone();
+        let mut rebuilt = self.state.clone_orderbooks(StateView::Confirmed);
+        let mut rebuilt_ledger = self.state.confirmed_ledger.read().clone();
+        for pending in self.tracker.pending() {
+            match &pending.event {
+                ChainEvent::OrderBook(event) => {
+                    if let Some(orderbook) = pair_orderbook(&mut rebuilt, &pending.event) {
+                        report_orderbook_error(
+                    apply_orderbook_event(orderbook, event, &pending.meta, &self.state.order_origins),
+                    &pending.meta,
+                )
+                    }
+                }
+                ChainEvent::Account(event) => rebuilt_ledger.apply_event(event),
+                ChainEvent::Sequencer(event) => apply_queue_event(&mut rebuilt_queue, event, &pending.meta),
+            }
+        }
+        *self.state.queue.write() = rebuilt_queue;
+        *self.state.orderbooks.write() = rebuilt;
+        *self.state.ledger.write() = rebuilt_ledger;
+    }
+
+    /// 新区块：推进 head 高度，并把达到确认深度的事件应用到 confirmed 视图
+    fn advance_head(&mut self, head_block: u64) {
+        self.state.update_current_block(head_block);
+        self.synced_block = self.synced_block.max(head_block);
+        self.recent_logs
+            .retain(|_, block| *block + RECENT_LOG_BLOCKS > head_block);
+
+        let confirmed = self.tracker.drain_confirmed(head_block);
+        if !confirmed.is_empty() {
+            for log in &confirmed {
+                apply_confirmed_event(&self.state, log);
+                if matches!(log.event, ChainEvent::OrderBook(_)) {
+                    self.check_orderbook_invariants(StateView::Confirmed, &log.event);
                 }
             }
-            result = sequencer_watcher => {
-                match result {
-                    Ok(Ok(_)) => info!("Sequencer watcher completed"),
-                    Ok(Err(e)) => warn!("Sequencer watcher error: {}", e),
-                    Err(e) => warn!("Sequencer watcher task error: {}", e),
+            debug!(
+                "  {} events confirmed, {} pending",
+                confirmed.len(),
+                self.tracker.pending_len()
+            );
+        }
+
+        if let Some(confirmed_block) = self.tracker.confirmed_block(head_block) {
+            self.state.update_confirmed_block(confirmed_block);
+        }
+    }
+}
+
+
+/// 打印事件日志（每个事件只打印一次，不随视图重放重复输出）
+fn log_event(event: &ChainEvent) {
+    match event {
+        ChainEvent::Sequencer(SequencerEvents::PlaceOrderRequestedFilter(place_order)) => {
+            info!(
+                "📥 PlaceOrderRequested: requestId={}, price={}, amount={}, isAsk={}",
+                place_order.request_id,
+                Price::from_raw(place_order.price),
+                OrderSize::new(place_order.order_type == 1, place_order.is_ask, place_order.amount),
+                place_order.is_ask
+            );
+        }
+        ChainEvent::Sequencer(SequencerEvents::RemoveOrderRequestedFilter(remove_order)) => {
+            info!(
+                "📥 RemoveOrderRequested: requestId={}, orderIdToRemove={}",
+                remove_order.request_id,
+                remove_order.order_id_to_remove
+            );
+        }
+        ChainEvent::OrderBook(OrderBookEvents::OrderInsertedFilter(inserted)) => {
+            info!(
+                "📦 OrderInserted: orderId={}, price={}, amount={}, isAsk={}",
+                inserted.order_id,
+                Price::from_raw(inserted.price),
+                Amount::from_raw(inserted.amount),
+                inserted.is_ask
+            );
+        }
+        ChainEvent::OrderBook(OrderBookEvents::PriceLevelCreatedFilter(created)) => {
+            info!(
+                "📊 PriceLevelCreated: price={}, isAsk={}",
+                Price::from_raw(created.price),
+                created.is_ask
+            );
+        }
+        ChainEvent::OrderBook(OrderBookEvents::PriceLevelRemovedFilter(removed)) => {
+            info!("🗑️  PriceLevelRemoved: price={}", Price::from_raw(removed.price));
+        }
+        ChainEvent::OrderBook(OrderBookEvents::TradeFilter(trade)) => {
+            info!(
+                "🔄 Trade: buy={}, sell={}, price={}, amount={}",
+                trade.buy_order_id,
+                trade.sell_order_id,
+                Price::from_raw(trade.price),
+                Amount::from_raw(trade.amount)
+            );
+        }
+        ChainEvent::OrderBook(OrderBookEvents::OrderFilledFilter(filled)) => {
+            info!(
+                "✅ OrderFilled: order={}, filled={}, fully_filled={}",
+                filled.order_id,
+                Amount::from_raw(filled.filled_amount),
+                filled.is_fully_filled
+            );
+        }
+        ChainEvent::OrderBook(OrderBookEvents::OrderRemovedFilter(removed)) => {
+            info!("🗑️  OrderRemoved: order={}", removed.order_id);
+        }
+        ChainEvent::Account(AccountEvents::TradingPairRegisteredFilter(registered)) => {
+            info!(
+                "🪙 TradingPairRegistered: pair={}, base={:?}, quote={:?}",
+                format_pair(&registered.trading_pair),
+                registered.base_token,
+                registered.quote_token
+            );
+        }
+        _ => {}
+    }
+}
+
+/// 交易对 ID 的十六进制表示
+fn format_pair(trading_pair: &[u8; 32]) -> String {
+    format!("0x{}", ethers::utils::hex::encode(trading_pair))
+}
+
+/// 事件所属交易对的订单簿，第一次出现的交易对建立空订单簿；没有交易对的事件返回 None
+fn pair_orderbook<'a>(orderbooks: &'a mut OrderBooks, event: &ChainEvent) -> Option<&'a mut OrderBookSimulator> {
+    let trading_pair = event.trading_pair()?;
+    Some(orderbooks.entry(trading_pair).or_default())
+}
+
+/// 应用 OrderBook 事件失败时输出警告，跳过该事件
+fn report_orderbook_error(result: Result<(), SimulatorError>, meta: &EventMeta) {
+    if let Err(e) = result {
+        warn!(
+            "⚠️  Failed to apply orderbook event: block={}, tx={:?}, index={}: {}",
+            meta.block_number, meta.tx_hash, meta.log_index, e
+        );
+    }
+}
+
+/// 将事件作用于 GlobalState 的 head 视图（Sequencer 队列 + 事件所属交易对的乐观订单簿 + 余额）
+pub fn apply_event(state: &GlobalState, log: &DecodedLog) {
+    match &log.event {
+        ChainEvent::Sequencer(event) => apply_sequencer_event(state, event, &log.meta),
+        ChainEvent::OrderBook(event) => {
+            if let Some(orderbook) = pair_orderbook(&mut state.orderbooks.write(), &log.event) {
+                report_orderbook_error(
+                    apply_orderbook_event(orderbook, event, &log.meta, &state.order_origins),
+                    &log.meta,
+                )
+            }
+        }
+        ChainEvent::Account(event) => state.ledger.write().apply_event(event),
+    }
+}
+
+/// 将达到确认深度的事件作用于 confirmed 视图（Sequencer 队列 + 订单簿 + 余额）
+pub fn apply_confirmed_event(state: &GlobalState, log: &DecodedLog) {
+    match &log.event {
+        ChainEvent::OrderBook(event) => {
+            if let Some(orderbook) = pair_orderbook(&mut state.confirmed_orderbooks.write(), &log.event) {
+                report_orderbook_error(
+                    apply_orderbook_event(orderbook, event, &log.meta, &state.order_origins),
+                    &log.meta,
+                );
+            }
+            // 订单已离开 confirmed 视图，不再需要它的 trader 与提交时间
+            match event {
+                OrderBookEvents::OrderRemovedFilter(removed) => {
+                    state.order_origins.remove(&removed.order_id);
                 }
+                OrderBookEvents::OrderFilledFilter(filled) if filled.is_fully_filled => {
+                    state.order_origins.remove(&filled.order_id);
+                }
+                OrderBookEvents::TradeFilter(trade) => {
+                    state.trade_history.write().record(TradeRecord::new(trade, &log.meta));
+                }
+                _ => {}
             }
         }
+        ChainEvent::Account(event) => state.confirmed_ledger.write().apply_event(event),
+        ChainEvent::Sequencer(event) => apply_queue_event(&mut state.confirmed_queue.write(), event, &log.meta),
+    }
+}
 
-        Ok(())
+/// 更新 head 视图的 Sequencer 请求队列，并记录下单请求的 trader 与提交时间
+/// 注意：启动时已通过 RPC 读取了所有 pending requests
+/// 这里只处理新产生的事件，不再使用 RPC 读取 request
+pub fn apply_sequencer_event(state: &GlobalState, event: &SequencerEvents, meta: &EventMeta) {
+    match queued_request(event, meta) {
+        Some(request) => state.add_request(request),
+        None => apply_queue_event(&mut state.queue.write(), event, meta),
     }
+}
 
-    /// 监听 OrderBook 事件并更新 GlobalState
-    async fn watch_orderbook_events(
-        orderbook: OrderBook<Provider<Ws>>,
-        state: GlobalState,
-        from_block: u64,
-    ) -> Result<()> {
-        use crate::contracts::order_book::*;
-
-        info!("📡 Starting OrderBook event listener from block {}", from_block);
-
-        // 创建事件过滤器（从同步的区块开始）
-        // 使用 from_block + 1 避免重复处理已同步的状态
-        let event_start_block = from_block + 1;
-        let trade_filter = orderbook.event::<TradeFilter>().from_block(event_start_block);
-        let order_filled_filter = orderbook.event::<OrderFilledFilter>().from_block(event_start_block);
-        let order_removed_filter = orderbook.event::<OrderRemovedFilter>().from_block(event_start_block);
-        let order_inserted_filter = orderbook.event::<OrderInsertedFilter>().from_block(event_start_block);
-        let price_level_created_filter = orderbook.event::<PriceLevelCreatedFilter>().from_block(event_start_block);
-        let price_level_removed_filter = orderbook.event::<PriceLevelRemovedFilter>().from_block(event_start_block);
-
-        // 创建事件流
-        let mut trade_stream = trade_filter.stream().await?.take(10000);
-        let mut order_filled_stream = order_filled_filter.stream().await?.take(10000);
-        let mut order_removed_stream = order_removed_filter.stream().await?.take(10000);
-        let mut order_inserted_stream = order_inserted_filter.stream().await?.take(10000);
-        let mut price_level_created_stream = price_level_created_filter.stream().await?.take(10000);
-        let mut price_level_removed_stream = price_level_removed_filter.stream().await?.take(10000);
-
-        loop {
-            tokio::select! {
-                Some(event) = order_inserted_stream.next() => {
-                    match event {
-                        Ok(inserted) => {
-                            info!(
-                                "📦 OrderInserted: orderId={}, price={}, amount={}, isAsk={}",
-                                inserted.order_id,
-                                inserted.price,
-                                inserted.amount,
-                                inserted.is_ask
-                            );
-
-                            let mut orderbook = state.orderbook.write();
-                            let level_key = if inserted.is_ask {
-                                inserted.price
-                            } else {
-                                inserted.price | (U256::one() << 255)
-                            };
-
-                            // 先读取需要的信息
-                            let old_tail = orderbook.price_levels.get(&level_key)
-                                .map(|l| l.tail_order_id)
-                                .unwrap_or(U256::zero());
-
-                            // 更新旧尾部订单的 next_order_id
-                            if !old_tail.is_zero() {
-                                if let Some(tail_order) = orderbook.orders.get_mut(&old_tail) {
-                                    tail_order.next_order_id = inserted.order_id;
-                                }
-                            }
-
-                            // 更新价格层级
-                            if let Some(level) = orderbook.price_levels.get_mut(&level_key) {
-                                if old_tail.is_zero() {
-                                    level.head_order_id = inserted.order_id;
-                                }
-                                level.tail_order_id = inserted.order_id;
-                                level.total_volume = level.total_volume + inserted.amount;
-                            }
-
-                            // 创建并插入新订单
-                            let sim_order = SimOrder {
-                                id: inserted.order_id,
-                                amount: inserted.amount,
-                                filled_amount: U256::zero(),
-                                is_market_order: false,
-                                is_ask: inserted.is_ask,
-                                price_level: inserted.price,
-                                next_order_id: U256::zero(),
-                                prev_order_id: old_tail,
-                            };
-                            orderbook.orders.insert(inserted.order_id, sim_order);
-
-                            debug!(
-                                "  Added order {} to simulator (price={}, is_ask={})",
-                                inserted.order_id, inserted.price, inserted.is_ask
-                            );
-                        }
-                        Err(e) => warn!("Error receiving OrderInserted event: {}", e),
-                    }
+/// 将 Sequencer 事件作用于请求队列（head 与 confirmed 视图共用）：
+/// 新请求追加到队列尾部，RequestProcessed 把请求移出队列
+pub fn apply_queue_event(queue: &mut RequestQueue, event: &SequencerEvents, meta: &EventMeta) {
+    if let Some(request) = queued_request(event, meta) {
+        queue.push(request);
+    } else if let SequencerEvents::RequestProcessedFilter(processed) = event {
+        queue.remove(&processed.request_id);
+    }
+}
+
+/// PlaceOrderRequested / RemoveOrderRequested 事件对应的队列请求
+/// meta 为事件所在位置，时间戳取事件中的 block.timestamp
+fn queued_request(event: &SequencerEvents, meta: &EventMeta) -> Option<QueuedRequest> {
+    match event {
+        SequencerEvents::PlaceOrderRequestedFilter(place_order) => Some(QueuedRequest {
+            request_id: place_order.request_id,
+            request_type: RequestType::PlaceOrder,
+            trading_pair: place_order.trading_pair,
+            trader: place_order.trader,
+            order_type: match place_order.order_type {
+                0 => OrderType::Limit,
+                1 => OrderType::Market,
+                _ => OrderType::Limit,
+            },
+            is_ask: place_order.is_ask,
+            price: Price::from_raw(place_order.price),
+            amount: place_order.amount,
+            order_id_to_remove: U256::zero(),
+            next_request_id: U256::zero(), // 追加到队列时设置
+            requested: BlockStamp {
+                timestamp: place_order.timestamp.low_u64(),
+                ..meta.stamp()
+            },
+        }),
+        SequencerEvents::RemoveOrderRequestedFilter(remove_order) => Some(QueuedRequest {
+            request_id: remove_order.request_id,
+            request_type: RequestType::RemoveOrder,
+            trading_pair: remove_order.trading_pair,
+            trader: remove_order.trader,
+            order_type: OrderType::Limit, // RemoveOrder 不关心 orderType
+            is_ask: false, // 将从链上获取
+            price: Price::zero(),
+            amount: U256::zero(),
+            order_id_to_remove: remove_order.order_id_to_remove,
+            next_request_id: U256::zero(),
+            requested: BlockStamp {
+                timestamp: remove_order.timestamp.low_u64(),
+                ..meta.stamp()
+            },
+        }),
+        _ => None,
+    }
+}
+
+/// 将 OrderBook 事件作用于订单簿模拟器（head 与 confirmed 视图共用）
+/// meta: 事件所在位置，作为 OrderInserted 订单的插入时间
+/// origins: order_id -> 下单请求信息，用于补全 OrderInserted 事件中没有的 trader 与提交时间
+/// 数值溢出或链表引用缺失时返回错误，事件未应用（本地订单簿已与链上不一致）
+pub fn apply_orderbook_event(
+    orderbook: &mut OrderBookSimulator,
+    event: &OrderBookEvents,
+    meta: &EventMeta,
+    origins: &DashMap<U256, OrderOrigin>,
+) -> Result<(), SimulatorError> {
+    match event {
+        OrderBookEvents::OrderInsertedFilter(inserted) => {
+            let level_key = if inserted.is_ask {
+                inserted.price
+            } else {
+                inserted.price | (U256::one() << 255)
+            };
+
+            // 先读取需要的信息，并在修改之前检查 total_volume 是否溢出
+            let level = orderbook.price_levels.get(&level_key);
+            let old_tail = level.map(|l| l.tail_order_id).unwrap_or(U256::zero());
+            let total_volume = match level {
+                Some(level) => level
+                    .total_volume
+                    .checked_add(inserted.amount)
+                    .ok_or(SimulatorError::Overflow { context: "price level total_volume" })?,
+                None => U256::zero(),
+            };
+
+            // 更新旧尾部订单的 next_order_id
+            if !old_tail.is_zero() {
+                if let Some(tail_order) = orderbook.orders.get_mut(&old_tail) {
+                    tail_order.next_order_id = inserted.order_id;
                 }
+            }
 
-                Some(event) = price_level_created_stream.next() => {
-                    match event {
-                        Ok(created) => {
-                            info!(
-                                "📊 PriceLevelCreated: price={}, isAsk={}",
-                                created.price,
-                                created.is_ask
-                            );
-
-                            // 创建新的价格层级
-                            let new_level = SimPriceLevel {
-                                price: created.price,
-                                total_volume: U256::zero(),
-                                head_order_id: U256::zero(),
-                                tail_order_id: U256::zero(),
-                                next_price: U256::zero(),
-                                prev_price: U256::zero(),
-                            };
-
-                            let mut orderbook = state.orderbook.write();
-                            orderbook.add_existing_price_level(new_level, created.is_ask);
-
-                            // 更新链表指针 - 需要找到正确的位置插入
-                            // 简化处理：直接更新 head/tail
-                            let level_key = if created.is_ask {
-                                created.price
-                            } else {
-                                created.price | (U256::one() << 255)
-                            };
-
-                            if created.is_ask {
-                                let old_head = orderbook.ask_head;
-                                if old_head.is_zero() || created.price < old_head {
-                                    // 更新旧 head 的 prev_price
-                                    if !old_head.is_zero() {
-                                        let old_head_key = old_head;
-                                        if let Some(old_head_level) = orderbook.price_levels.get_mut(&old_head_key) {
-                                            old_head_level.prev_price = created.price;
-                                        }
-                                        if let Some(new_level) = orderbook.price_levels.get_mut(&level_key) {
-                                            new_level.next_price = old_head;
-                                        }
-                                    }
-                                    orderbook.ask_head = created.price;
-                                }
-                                let old_tail = orderbook.ask_tail;
-                                if old_tail.is_zero() || created.price > old_tail {
-                                    orderbook.ask_tail = created.price;
-                                }
-                            } else {
-                                let old_head = orderbook.bid_head;
-                                if old_head.is_zero() || created.price > old_head {
-                                    // 更新旧 head 的 prev_price
-                                    if !old_head.is_zero() {
-                                        let old_head_key = old_head | (U256::one() << 255);
-                                        if let Some(old_head_level) = orderbook.price_levels.get_mut(&old_head_key) {
-                                            old_head_level.prev_price = created.price;
-                                        }
-                                        if let Some(new_level) = orderbook.price_levels.get_mut(&level_key) {
-                                            new_level.next_price = old_head;
-                                        }
-                                    }
-                                    orderbook.bid_head = created.price;
-                                }
-                                let old_tail = orderbook.bid_tail;
-                                if old_tail.is_zero() || created.price < old_tail {
-                                    orderbook.bid_tail = created.price;
-                                }
-                            }
-
-                            debug!(
-                                "  Created price level {} (is_ask={})",
-                                created.price, created.is_ask
-                            );
-                        }
-                        Err(e) => warn!("Error receiving PriceLevelCreated event: {}", e),
-                    }
+            // 更新价格层级
+            if let Some(level) = orderbook.price_levels.get_mut(&level_key) {
+                if old_tail.is_zero() {
+                    level.head_order_id = inserted.order_id;
                 }
+                level.tail_order_id = inserted.order_id;
+                level.total_volume = total_volume;
+            }
+
+            // 创建并插入新订单
+            let origin = origins.get(&inserted.order_id).map(|origin| *origin);
+            let sim_order = SimOrder {
+                id: inserted.order_id,
+                trader: origin.map(|origin| origin.trader).unwrap_or_default(),
+                amount: inserted.amount,
+                filled_amount: U256::zero(),
+                is_market_order: false,
+                is_ask: inserted.is_ask,
+                price_level: inserted.price,
+                next_order_id: U256::zero(),
+                prev_order_id: old_tail,
+                requested: origin.map(|origin| origin.requested).unwrap_or_default(),
+                inserted: meta.stamp(),
+            };
+            orderbook.add_existing_order(sim_order);
+
+            debug!(
+                "  Added order {} to simulator (price={}, is_ask={})",
+                inserted.order_id, inserted.price, inserted.is_ask
+            );
+        }
+
+        OrderBookEvents::PriceLevelCreatedFilter(created) => {
+            // 创建新的价格层级，按价格顺序接入链表（头部、中间或尾部）
+            orderbook.create_existing_price_level(created.price, created.is_ask)?;
+
+            debug!(
+                "  Created price level {} (is_ask={})",
+                created.price, created.is_ask
+            );
+        }
 
-                Some(event) = price_level_removed_stream.next() => {
-                    match event {
-                        Ok(removed) => {
-                            info!("🗑️  PriceLevelRemoved: price={}", removed.price);
-                            // 从 GlobalState.orderbook 中移除价格层级
-                            // 注意：需要知道 is_ask，但事件中没有这个字段
-                            // 尝试两个 key
-                            let mut orderbook = state.orderbook.write();
-                            let ask_key = removed.price;
-                            let bid_key = removed.price | (U256::one() << 255);
-
-                            if orderbook.price_levels.contains_key(&ask_key) {
-                                // 更新链表指针
-                                if let Some(level) = orderbook.price_levels.get(&ask_key) {
-                                    let prev = level.prev_price;
-                                    let next = level.next_price;
-                                    if !prev.is_zero() {
-                                        if let Some(prev_level) = orderbook.price_levels.get_mut(&prev) {
-                                            prev_level.next_price = next;
-                                        }
-                                    } else {
-                                        orderbook.ask_head = next;
-                                    }
-                                    if !next.is_zero() {
-                                        if let Some(next_level) = orderbook.price_levels.get_mut(&next) {
-                                            next_level.prev_price = prev;
-                                        }
-                                    } else {
-                                        orderbook.ask_tail = prev;
-                                    }
-                                }
-                                orderbook.price_levels.remove(&ask_key);
-                            } else if orderbook.price_levels.contains_key(&bid_key) {
-                                // 更新链表指针
-                                if let Some(level) = orderbook.price_levels.get(&bid_key) {
-                                    let prev = level.prev_price;
-                                    let next = level.next_price;
-                                    let prev_key = prev | (U256::one() << 255);
-                                    let next_key = next | (U256::one() << 255);
-                                    if !prev.is_zero() {
-                                        if let Some(prev_level) = orderbook.price_levels.get_mut(&prev_key) {
-                                            prev_level.next_price = next;
-                                        }
-                                    } else {
-                                        orderbook.bid_head = next;
-                                    }
-                                    if !next.is_zero() {
-                                        if let Some(next_level) = orderbook.price_levels.get_mut(&next_key) {
-                                            next_level.prev_price = prev;
-                                        }
-                                    } else {
-                                        orderbook.bid_tail = prev;
-                                    }
-                                }
-                                orderbook.price_levels.remove(&bid_key);
-                            }
+        OrderBookEvents::PriceLevelRemovedFilter(removed) => {
+            // 从订单簿中移除价格层级
+            // 注意：需要知道 is_ask，但事件中没有这个字段
+            // 尝试两个 key
+            let ask_key = removed.price;
+            let bid_key = removed.price | (U256::one() << 255);
+
+            if orderbook.price_levels.contains_key(&ask_key) {
+                // 更新链表指针
+                if let Some(level) = orderbook.price_levels.get(&ask_key) {
+                    let prev = level.prev_price;
+                    let next = level.next_price;
+                    if !prev.is_zero() {
+                        if let Some(prev_level) = orderbook.price_levels.get_mut(&prev) {
+                            prev_level.next_price = next;
                         }
-                        Err(e) => warn!("Error receiving PriceLevelRemoved event: {}", e),
+                    } else {
+                        orderbook.ask_head = next;
                     }
-                }
-
-                Some(event) = trade_stream.next() => {
-                    match event {
-                        Ok(trade) => {
-                            info!(
-                                "🔄 Trade: buy={}, sell={}, price={}, amount={}",
-                                trade.buy_order_id,
-                                trade.sell_order_id,
-                                trade.price,
-                                trade.amount
-                            );
-                            // Trade 事件后会有 OrderFilled 事件来更新订单状态
+                    if !next.is_zero() {
+                        if let Some(next_level) = orderbook.price_levels.get_mut(&next) {
+                            next_level.prev_price = prev;
                         }
-                        Err(e) => warn!("Error receiving trade event: {}", e),
+                    } else {
+                        orderbook.ask_tail = prev;
                     }
                 }
-
-                Some(event) = order_filled_stream.next() => {
-                    match event {
-                        Ok(filled) => {
-                            info!(
-                                "✅ OrderFilled: order={}, filled={}, fully_filled={}",
-                                filled.order_id,
-                                filled.filled_amount,
-                                filled.is_fully_filled
-                            );
-
-                            // 更新 GlobalState.orderbook 中的订单状态
-                            let mut orderbook = state.orderbook.write();
-                            if filled.is_fully_filled {
-                                // 移除完全成交的订单
-                                orderbook.orders.remove(&filled.order_id);
-                            } else {
-                                // 更新部分成交
-                                if let Some(order) = orderbook.orders.get_mut(&filled.order_id) {
-                                    order.filled_amount = filled.filled_amount;
-                                }
-                            }
+                orderbook.remove_existing_price_level(removed.price, true);
+            } else if orderbook.price_levels.contains_key(&bid_key) {
+                // 更新链表指针
+                if let Some(level) = orderbook.price_levels.get(&bid_key) {
+                    let prev = level.prev_price;
+                    let next = level.next_price;
+                    let prev_key = prev | (U256::one() << 255);
+                    let next_key = next | (U256::one() << 255);
+                    if !prev.is_zero() {
+                        if let Some(prev_level) = orderbook.price_levels.get_mut(&prev_key) {
+                            prev_level.next_price = next;
                         }
-                        Err(e) => warn!("Error receiving order filled event: {}", e),
+                    } else {
+                        orderbook.bid_head = next;
                     }
-                }
-
-                Some(event) = order_removed_stream.next() => {
-                    match event {
-                        Ok(removed) => {
-                            info!("🗑️  OrderRemoved: order={}", removed.order_id);
-                            // 从 GlobalState.orderbook 中移除订单
-                            let mut orderbook = state.orderbook.write();
-                            orderbook.orders.remove(&removed.order_id);
+                    if !next.is_zero() {
+                        if let Some(next_level) = orderbook.price_levels.get_mut(&next_key) {
+                            next_level.prev_price = prev;
                         }
-                        Err(e) => warn!("Error receiving order removed event: {}", e),
+                    } else {
+                        orderbook.bid_tail = prev;
                     }
                 }
+                orderbook.remove_existing_price_level(removed.price, false);
+            }
+        }
 
-                else => {
-                    warn!("All event streams ended, restarting...");
-                    tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
-                    return Ok(());
+        OrderBookEvents::TradeFilter(_) => {
+            // Trade 事件后会有 OrderFilled 事件来更新订单状态
+        }
+
+        OrderBookEvents::OrderFilledFilter(filled) => {
+            // 更新订单簿中的订单状态
+            if filled.is_fully_filled {
+                // 移除完全成交的订单
+                orderbook.remove_existing_order(filled.order_id);
+            } else {
+                // 更新部分成交
+                if let Some(order) = orderbook.orders.get_mut(&filled.order_id) {
+                    order.filled_amount = filled.filled_amount;
                 }
             }
         }
+
+        OrderBookEvents::OrderRemovedFilter(removed) => {
+            // 从订单簿中移除订单
+            orderbook.remove_existing_order(removed.order_id);
+        }
+
+        _ => {}
     }
+    Ok(())
+}
 
-    /// 监听 Sequencer 事件并更新 GlobalState
-    /// 注意：启动时已通过 RPC 读取了所有 pending requests
-    /// 这里只监听新产生的事件，不再使用 RPC 读取 request
-    async fn watch_sequencer_events(
-        sequencer: Sequencer<Provider<Ws>>,
-        state: GlobalState,
-        from_block: u64,
-    ) -> Result<()> {
-        use crate::contracts::sequencer::*;
-
-        info!("📡 Starting Sequencer event listener from block {}", from_block);
-
-        // 创建事件过滤器（从同步的区块之后开始，避免重复处理）
-        // 使用 from_block + 1 因为 from_block 的状态已经通过 RPC 同步了
-        let event_start_block = from_block + 1;
-        let place_order_filter = sequencer.event::<PlaceOrderRequestedFilter>().from_block(event_start_block);
-        let remove_order_filter = sequencer.event::<RemoveOrderRequestedFilter>().from_block(event_start_block);
-
-        // 创建事件流
-        let mut place_order_stream = place_order_filter.stream().await?.take(10000);
-        let mut remove_order_stream = remove_order_filter.stream().await?.take(10000);
-
-        loop {
-            tokio::select! {
-                Some(event) = place_order_stream.next() => {
-                    match event {
-                        Ok(place_order) => {
-                            info!(
-                                "📥 PlaceOrderRequested: requestId={}, price={}, amount={}, isAsk={}",
-                                place_order.request_id,
-                                place_order.price,
-                                place_order.amount,
-                                place_order.is_ask
-                            );
-
-                            // 创建请求并添加到 GlobalState
-                            let request = QueuedRequest {
-                                request_id: place_order.request_id,
-                                request_type: RequestType::PlaceOrder,
-                                trading_pair: place_order.trading_pair,
-                                trader: place_order.trader,
-                                order_type: match place_order.order_type {
-                                    0 => OrderType::Limit,
-                                    1 => OrderType::Market,
-                                    _ => OrderType::Limit,
-                                },
-                                is_ask: place_order.is_ask,
-                                price: place_order.price,
-                                amount: place_order.amount,
-                                order_id_to_remove: U256::zero(),
-                                next_request_id: U256::zero(), // 将在处理时更新
-                            };
-
-                            state.add_request(request);
-                            state.update_queue_head(place_order.request_id);
-                        }
-                        Err(e) => warn!("Error receiving PlaceOrderRequested event: {}", e),
-                    }
-                }
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::contracts::order_book::{OrderInsertedFilter, PriceLevelCreatedFilter};
+    use crate::contracts::sequencer::{PlaceOrderRequestedFilter, RequestProcessedFilter};
+
+    fn processor(confirmations: u64) -> EventProcessor {
+        let config: SyncConfig = toml::from_str(&format!(
+            "start_block = 1\nsync_historical = false\nconfirmations = {}",
+            confirmations
+        ))
+        .unwrap();
+        EventProcessor::new(&config, GlobalState::new(), None).unwrap()
+    }
 
-                Some(event) = remove_order_stream.next() => {
-                    match event {
-                        Ok(remove_order) => {
-                            info!(
-                                "📥 RemoveOrderRequested: requestId={}, orderIdToRemove={}",
-                                remove_order.request_id,
-                                remove_order.order_id_to_remove
-                            );
-
-                            // 创建请求并添加到 GlobalState
-                            let request = QueuedRequest {
-                                request_id: remove_order.request_id,
-                                request_type: RequestType::RemoveOrder,
-                                trading_pair: remove_order.trading_pair,
-                                trader: remove_order.trader,
-                                order_type: OrderType::Limit, // RemoveOrder 不关心 orderType
-                                is_ask: false, // 将从链上获取
-                                price: U256::zero(),
-                                amount: U256::zero(),
-                                order_id_to_remove: remove_order.order_id_to_remove,
-                                next_request_id: U256::zero(),
-                            };
-
-                            state.add_request(request);
-                            state.update_queue_head(remove_order.request_id);
-                        }
-                        Err(e) => warn!("Error receiving RemoveOrderRequested event: {}", e),
-                    }
-                }
+    fn log(block_number: u64, log_index: u64, event: ChainEvent) -> Box<DecodedLog> {
+        Box::new(DecodedLog {
+            meta: EventMeta {
+                block_number,
+                block_hash: H256::from_low_u64_be(block_number),
+                tx_hash: H256::from_low_u64_be(block_number * 1000 + log_index),
+                log_index: U256::from(log_index),
+                block_timestamp: 0,
+            },
+            event,
+        })
+    }
 
-                else => {
-                    warn!("All Sequencer event streams ended, restarting...");
-                    tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
-                    return Ok(());
-                }
+    fn place_order(request_id: u64) -> ChainEvent {
+        ChainEvent::Sequencer(SequencerEvents::PlaceOrderRequestedFilter(PlaceOrderRequestedFilter {
+            request_id: U256::from(request_id),
+            order_id: U256::from(request_id),
+            trading_pair: [1; 32],
+            trader: Address::from_low_u64_be(request_id),
+            order_type: 0,
+            is_ask: true,
+            price: U256::from(100),
+            amount: U256::from(5),
+            timestamp: U256::zero(),
+        }))
+    }
+
+    fn processed(request_id: u64) -> ChainEvent {
+        ChainEvent::Sequencer(SequencerEvents::RequestProcessedFilter(RequestProcessedFilter {
+            request_id: U256::from(request_id),
+            request_type: 0,
+        }))
+    }
+
+    fn queue_ids(state: &GlobalState) -> Vec<u64> {
+        state
+            .get_head_requests(usize::MAX)
+            .iter()
+            .map(|request| request.request_id.as_u64())
+            .collect()
+    }
+
+    #[test]
+    fn test_reorg_rebuilds_request_queue() {
+        let mut processor = processor(2);
+        let events = [
+            log(10, 0, place_order(1)),
+            log(11, 0, place_order(2)),
+            log(11, 1, place_order(3)),
+            log(12, 0, processed(1)),
+        ];
+        for event in &events {
+            processor.process(SourceEvent::Log { log: event.clone(), removed: false });
+        }
+        processor.process(SourceEvent::NewHead(12));
+
+        let state = processor.state().clone();
+        assert_eq!(queue_ids(&state), vec![2, 3]);
+        assert_eq!(state.confirmed_queue.read().head_requests(usize::MAX).len(), 1);
+
+        // 队列中间的请求被重组：前后请求重新连接
+        processor.process(SourceEvent::Log { log: events[1].clone(), removed: true });
+        assert_eq!(queue_ids(&state), vec![3]);
+
+        // 处理请求 1 的交易被重组：请求 1 回到队列头部
+        processor.process(SourceEvent::Log { log: events[3].clone(), removed: true });
+        assert_eq!(queue_ids(&state), vec![1, 3]);
+        assert_eq!(state.queue.read().head(), U256::from(1));
+        assert_eq!(state.queue.read().len(), 2);
+    }
+
+    #[test]
+    fn test_price_levels_created_at_head_middle_and_tail() {
+        let mut orderbook = OrderBookSimulator::new();
+        let meta = log(1, 0, processed(0)).meta;
+        let origins = DashMap::new();
+        let mut order_id = 0;
+        // 先建立 110，再依次在头部、尾部、中间创建层级
+        for (price, is_ask) in [(110, true), (100, true), (120, true), (105, true), (90, false), (95, false), (80, false), (85, false)] {
+            order_id += 1;
+            let events = [
+                OrderBookEvents::PriceLevelCreatedFilter(PriceLevelCreatedFilter {
+                    trading_pair: [1; 32],
+                    price: U256::from(price),
+                    is_ask,
+                }),
+                OrderBookEvents::OrderInsertedFilter(OrderInsertedFilter {
+                    trading_pair: [1; 32],
+                    order_id: U256::from(order_id),
+                    is_ask,
+                    price: U256::from(price),
+                    amount: U256::from(5),
+                }),
+            ];
+            for event in &events {
+                apply_orderbook_event(&mut orderbook, event, &meta, &origins).unwrap();
             }
+            assert!(orderbook.validate().is_empty(), "{:?}", orderbook.validate());
         }
+
+        let prices = |prices: &[u64]| prices.iter().map(|&p| U256::from(p)).collect::<Vec<_>>();
+        assert_eq!(orderbook.get_price_levels(true), prices(&[100, 105, 110, 120]));
+        assert_eq!(orderbook.get_price_levels(false), prices(&[95, 90, 85, 80]));
+        assert_eq!((orderbook.ask_tail, orderbook.bid_tail), (U256::from(120), U256::from(80)));
+    }
+
+    #[test]
+    fn test_order_inserted_volume_overflow_is_reported() {
+        let mut orderbook = OrderBookSimulator::new();
+        let meta = log(1, 0, processed(0)).meta;
+        let origins = DashMap::new();
+        let inserted = |order_id: u64, amount: U256| {
+            OrderBookEvents::OrderInsertedFilter(OrderInsertedFilter {
+                trading_pair: [1; 32],
+                order_id: U256::from(order_id),
+                is_ask: true,
+                price: U256::from(100),
+                amount,
+            })
+        };
+        let created = OrderBookEvents::PriceLevelCreatedFilter(PriceLevelCreatedFilter {
+            trading_pair: [1; 32],
+            price: U256::from(100),
+            is_ask: true,
+        });
+        apply_orderbook_event(&mut orderbook, &created, &meta, &origins).unwrap();
+        apply_orderbook_event(&mut orderbook, &inserted(1, U256::MAX), &meta, &origins).unwrap();
+
+        assert_eq!(
+            apply_orderbook_event(&mut orderbook, &inserted(2, U256::one()), &meta, &origins),
+            Err(SimulatorError::Overflow { context: "price level total_volume" })
+        );
+        assert!(!orderbook.orders.contains_key(&U256::from(2)));
+        assert!(orderbook.validate().is_empty());
     }
 }
@@ -0,0 +1,474 @@
+//! 成交记录与 K 线
+//!
+//! 每个交易对保存确认后的 Trade 事件（按区块号、日志索引排序），并按区块时间戳聚合
+//! 1m / 5m / 1h / 1d 的 OHLCV K 线。配置了 history.trade_file 时，新成交以 JSONL
+//! 追加到文件，启动时从文件加载，重启后成交记录与 K 线不丢失。
+//!
+//! 只记录 confirmed 视图的成交，不受链重组影响；时间戳未知（为 0）的成交不计入 K 线。
+
+use crate::config::HistoryConfig;
+use crate::contracts::order_book::TradeFilter;
+use crate::events::EventMeta;
+use crate::fixed_point::{Amount, Price, QuoteAmount};
+use crate::types::BlockStamp;
+use anyhow::{Context, Result};
+use ethers::types::{Address, H256, U256};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, LineWriter, Write};
+use std::path::Path;
+use tracing::warn;
+
+/// 一笔成交（Trade 事件）
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TradeRecord {
+    pub trading_pair: [u8; 32],
+    pub buy_order_id: U256,
+    pub sell_order_id: U256,
+    pub buyer: Address,
+    pub seller: Address,
+    pub price: Price,
+    pub amount: Amount,
+    /// 成交所在区块与交易
+    pub stamp: BlockStamp,
+    pub log_index: U256,
+}
+
+impl TradeRecord {
+    pub fn new(trade: &TradeFilter, meta: &EventMeta) -> Self {
+        Self {
+            trading_pair: trade.trading_pair,
+            buy_order_id: trade.buy_order_id,
+            sell_order_id: trade.sell_order_id,
+            buyer: trade.buyer,
+            seller: trade.seller,
+            price: Price::from_raw(trade.price),
+            amount: Amount::from_raw(trade.amount),
+            stamp: meta.stamp(),
+            log_index: meta.log_index,
+        }
+    }
+
+    /// 链上顺序
+    fn position(&self) -> (u64, U256) {
+        (self.stamp.block_number, self.log_index)
+    }
+
+    /// 同一条日志
+    fn key(&self) -> (H256, U256) {
+        (self.stamp.tx_hash, self.log_index)
+    }
+
+    /// 成交额（计价代币）
+    pub fn quote_amount(&self) -> QuoteAmount {
+        self.amount.checked_mul_price(self.price).unwrap_or_default()
+    }
+}
+
+/// K 线周期
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum CandleInterval {
+    #[serde(rename = "1m")]
+    OneMinute,
+    #[serde(rename = "5m")]
+    FiveMinutes,
+    #[serde(rename = "1h")]
+    OneHour,
+    #[serde(rename = "1d")]
+    OneDay,
+}
+
+impl CandleInterval {
+    pub const ALL: [CandleInterval; 4] = [
+        CandleInterval::OneMinute,
+        CandleInterval::FiveMinutes,
+        CandleInterval::OneHour,
+        CandleInterval::OneDay,
+    ];
+
+    pub fn seconds(self) -> u64 {
+        match self {
+            CandleInterval::OneMinute => 60,
+            CandleInterval::FiveMinutes => 5 * 60,
+            CandleInterval::OneHour => 60 * 60,
+            CandleInterval::OneDay => 24 * 60 * 60,
+        }
+    }
+
+    /// 时间戳所在 K 线的开始时间（UTC 对齐）
+    pub fn open_time(self, timestamp: u64) -> u64 {
+        timestamp - timestamp % self.seconds()
+    }
+}
+
+/// 一根 K 线
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Candle {
+    /// 开始时间（秒），包含
+    pub open_time: u64,
+    pub open: Price,
+    pub high: Price,
+    pub low: Price,
+    pub close: Price,
+    /// 成交量（基础代币）
+    pub volume: Amount,
+    /// 成交额（计价代币）
+    pub quote_volume: QuoteAmount,
+    pub trade_count: usize,
+}
+
+impl Candle {
+    fn new(open_time: u64, trade: &TradeRecord) -> Self {
+        Self {
+            open_time,
+            open: trade.price,
+            high: trade.price,
+            low: trade.price,
+            close: trade.price,
+            volume: trade.amount,
+            quote_volume: trade.quote_amount(),
+            trade_count: 1,
+        }
+    }
+
+    /// 追加一笔时间上更晚的成交
+    fn push(&mut self, trade: &TradeRecord) {
+        self.high = self.high.max(trade.price);
+        self.low = self.low.min(trade.price);
+        self.close = trade.price;
+        self.volume = self.volume.checked_add(trade.amount).unwrap_or(self.volume);
+        self.quote_volume = self
+            .quote_volume
+            .checked_add(trade.quote_amount())
+            .unwrap_or(self.quote_volume);
+        self.trade_count += 1;
+    }
+}
+
+/// 单个交易对的成交记录与 K 线
+#[derive(Debug, Default)]
+struct PairHistory {
+    /// 按链上顺序
+    trades: VecDeque<TradeRecord>,
+    candles: HashMap<CandleInterval, BTreeMap<u64, Candle>>,
+}
+
+impl PairHistory {
+    /// 插入成交，返回是否追加在末尾
+    fn insert(&mut self, trade: TradeRecord) -> bool {
+        let at_end = self
+            .trades
+            .back()
+            .is_none_or(|last| last.position() <= trade.position());
+        if at_end {
+            self.trades.push_back(trade);
+        } else {
+            let index = self
+                .trades
+                .partition_point(|existing| existing.position() <= trade.position());
+            self.trades.insert(index, trade);
+        }
+        at_end
+    }
+
+    /// 更新 K 线：追加在末尾的成交直接合并，乱序到达的成交重算所在的 K 线
+    /// （确认后的成交按区块顺序到达，乱序只出现在加载文件与实时事件重叠时）
+    fn update_candles(&mut self, trade: &TradeRecord, at_end: bool) {
+        if trade.stamp.timestamp == 0 {
+            return;
+        }
+
+        for interval in CandleInterval::ALL {
+            let open_time = interval.open_time(trade.stamp.timestamp);
+            if at_end {
+                self.candles
+                    .entry(interval)
+                    .or_default()
+                    .entry(open_time)
+                    .and_modify(|candle| candle.push(trade))
+                    .or_insert_with(|| Candle::new(open_time, trade));
+            } else {
+                let candle = self.aggregate(interval, open_time);
+                if let Some(candle) = candle {
+                    self.candles.entry(interval).or_default().insert(open_time, candle);
+                }
+            }
+        }
+    }
+
+    /// 从成交记录重新聚合一根 K 线
+    fn aggregate(&self, interval: CandleInterval, open_time: u64) -> Option<Candle> {
+        let close_time = open_time + interval.seconds();
+        let mut candle: Option<Candle> = None;
+        for trade in self
+            .trades
+            .iter()
+            .filter(|trade| (open_time..close_time).contains(&trade.stamp.timestamp))
+        {
+            match candle.as_mut() {
+                Some(candle) => candle.push(trade),
+                None => candle = Some(Candle::new(open_time, trade)),
+            }
+        }
+        candle
+    }
+}
+
+/// 全部交易对的成交记录与 K 线
+#[derive(Debug, Default)]
+pub struct TradeHistory {
+    pairs: HashMap<[u8; 32], PairHistory>,
+    /// 已记录的成交 (tx_hash, log_index)，用于去重
+    seen: HashSet<(H256, U256)>,
+    /// 每个交易对保留的最近成交数（0 = 不限制），K 线不受影响
+    max_trades_per_pair: usize,
+    /// 追加新成交的 JSONL 文件
+    writer: Option<LineWriter<File>>,
+}
+
+impl TradeHistory {
+    /// 只保存在内存中，不限制条数
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 按配置创建；配置了 trade_file 时先从文件加载，之后的新成交追加到文件
+    pub fn open(config: &HistoryConfig) -> Result<Self> {
+        let mut history = Self {
+            max_trades_per_pair: config.max_trades_per_pair,
+            ..Self::default()
+        };
+
+        if let Some(path) = &config.trade_file {
+            let path = Path::new(path);
+            if path.exists() {
+                history.load(path)?;
+            }
+            let file = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .with_context(|| format!("Failed to open trade file {}", path.display()))?;
+            history.writer = Some(LineWriter::new(file));
+        }
+
+        Ok(history)
+    }
+
+    /// 加载 JSONL 文件；无法解析的行（如写到一半时进程退出）跳过并输出警告
+    fn load(&mut self, path: &Path) -> Result<()> {
+        let file = File::open(path)
+            .with_context(|| format!("Failed to open trade file {}", path.display()))?;
+        for (number, line) in BufReader::new(file).lines().enumerate() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            match serde_json::from_str::<TradeRecord>(&line) {
+                Ok(trade) => {
+                    self.insert(trade);
+                }
+                Err(e) => warn!("Skipping invalid trade at {}:{}: {}", path.display(), number + 1, e),
+            }
+        }
+        Ok(())
+    }
+
+    /// 记录一笔成交，返回是否为新成交（重复的日志忽略）
+    pub fn record(&mut self, trade: TradeRecord) -> bool {
+        if !self.insert(trade) {
+            return false;
+        }
+
+        if let Some(writer) = self.writer.as_mut() {
+            let result = serde_json::to_string(&trade)
+                .map_err(std::io::Error::from)
+                .and_then(|json| writeln!(writer, "{}", json));
+            if let Err(e) = result {
+                warn!("Failed to append trade to file: {}", e);
+            }
+        }
+        true
+    }
+
+    fn insert(&mut self, trade: TradeRecord) -> bool {
+        if !self.seen.insert(trade.key()) {
+            return false;
+        }
+
+        let pair = self.pairs.entry(trade.trading_pair).or_default();
+        let at_end = pair.insert(trade);
+        pair.update_candles(&trade, at_end);
+
+        if self.max_trades_per_pair > 0 {
+            while pair.trades.len() > self.max_trades_per_pair {
+                if let Some(oldest) = pair.trades.pop_front() {
+                    self.seen.remove(&oldest.key());
+                }
+            }
+        }
+        true
+    }
+
+    /// 有成交记录的交易对
+    pub fn trading_pairs(&self) -> Vec<[u8; 32]> {
+        self.pairs.keys().copied().collect()
+    }
+
+    /// 最近 limit 笔成交，按链上顺序
+    pub fn recent_trades(&self, trading_pair: &[u8; 32], limit: usize) -> Vec<TradeRecord> {
+        self.pairs
+            .get(trading_pair)
+            .map(|pair| {
+                let skip = pair.trades.len().saturating_sub(limit);
+                pair.trades.iter().skip(skip).copied().collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// 区块时间戳在 [from, to) 内的成交，按链上顺序
+    pub fn trades_between(&self, trading_pair: &[u8; 32], from: u64, to: u64) -> Vec<TradeRecord> {
+        self.pairs
+            .get(trading_pair)
+            .map(|pair| {
+                pair.trades
+                    .iter()
+                    .filter(|trade| (from..to).contains(&trade.stamp.timestamp))
+                    .copied()
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    pub fn last_trade(&self, trading_pair: &[u8; 32]) -> Option<TradeRecord> {
+        self.pairs.get(trading_pair)?.trades.back().copied()
+    }
+
+    /// 开始时间在 [from, to) 内的 K 线，按时间升序；没有成交的周期不返回
+    pub fn candles(
+        &self,
+        trading_pair: &[u8; 32],
+        interval: CandleInterval,
+        from: u64,
+        to: u64,
+    ) -> Vec<Candle> {
+        self.pairs
+            .get(trading_pair)
+            .and_then(|pair| pair.candles.get(&interval))
+            .map(|candles| candles.range(from..to).map(|(_, candle)| *candle).collect())
+            .unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PAIR: [u8; 32] = [1; 32];
+
+    fn trade(block_number: u64, log_index: u64, timestamp: u64, price: u64, amount: u64) -> TradeRecord {
+        TradeRecord {
+            trading_pair: PAIR,
+            buy_order_id: U256::from(block_number),
+            sell_order_id: U256::from(log_index),
+            buyer: Address::from_low_u64_be(1),
+            seller: Address::from_low_u64_be(2),
+            price: Price::from_units(price),
+            amount: Amount::from_units(amount),
+            stamp: BlockStamp {
+                timestamp,
+                block_number,
+                tx_hash: H256::from_low_u64_be(block_number),
+            },
+            log_index: U256::from(log_index),
+        }
+    }
+
+    #[test]
+    fn test_candles_aggregate_by_block_timestamp() {
+        let mut history = TradeHistory::new();
+        assert!(history.record(trade(1, 0, 60, 100, 1)));
+        assert!(history.record(trade(1, 1, 60, 105, 2)));
+        assert!(history.record(trade(2, 0, 119, 95, 1)));
+        assert!(history.record(trade(3, 0, 125, 98, 3)));
+        // 重复的日志
+        assert!(!history.record(trade(1, 1, 60, 105, 2)));
+
+        let minutes = history.candles(&PAIR, CandleInterval::OneMinute, 0, u64::MAX);
+        assert_eq!(minutes.len(), 2);
+        assert_eq!(minutes[0].open_time, 60);
+        assert_eq!(
+            (minutes[0].open, minutes[0].high, minutes[0].low, minutes[0].close),
+            (Price::from_units(100), Price::from_units(105), Price::from_units(95), Price::from_units(95))
+        );
+        assert_eq!(minutes[0].volume, Amount::from_units(4));
+        assert_eq!(minutes[0].quote_volume, QuoteAmount::from_units(100 + 210 + 95));
+        assert_eq!(minutes[0].trade_count, 3);
+        assert_eq!(minutes[1].open_time, 120);
+
+        let days = history.candles(&PAIR, CandleInterval::OneDay, 0, u64::MAX);
+        assert_eq!(days.len(), 1);
+        assert_eq!((days[0].close, days[0].trade_count), (Price::from_units(98), 4));
+        assert!(history.candles(&PAIR, CandleInterval::OneMinute, 61, 120).is_empty());
+
+        assert_eq!(history.trades_between(&PAIR, 60, 120).len(), 3);
+        assert_eq!(history.last_trade(&PAIR).map(|trade| trade.price), Some(Price::from_units(98)));
+    }
+
+    #[test]
+    fn test_out_of_order_trade_recomputes_candle() {
+        let mut history = TradeHistory::new();
+        history.record(trade(1, 0, 60, 100, 1));
+        history.record(trade(3, 0, 70, 110, 1));
+        // 区块 2 的成交晚到：成为该分钟的中间一笔，收盘价不变
+        history.record(trade(2, 0, 65, 90, 1));
+
+        let ids: Vec<_> = history
+            .recent_trades(&PAIR, 10)
+            .iter()
+            .map(|trade| trade.stamp.block_number)
+            .collect();
+        assert_eq!(ids, vec![1, 2, 3]);
+
+        let candle = history.candles(&PAIR, CandleInterval::OneMinute, 0, u64::MAX)[0];
+        assert_eq!((candle.open, candle.low, candle.close), (
+            Price::from_units(100),
+            Price::from_units(90),
+            Price::from_units(110)
+        ));
+        assert_eq!(candle.trade_count, 3);
+    }
+
+    #[test]
+    fn test_trade_file_roundtrip_and_retention() {
+        let path = std::env::temp_dir().join(format!("matcher-trades-{}.jsonl", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let config = HistoryConfig {
+            max_trades_per_pair: 2,
+            trade_file: Some(path.to_string_lossy().into_owned()),
+        };
+
+        {
+            let mut history = TradeHistory::open(&config).unwrap();
+            for block in 1..=3 {
+                history.record(trade(block, 0, 60 * block, 100 + block, 1));
+            }
+            // 只保留最近 2 笔，K 线仍包含全部成交
+            assert_eq!(history.recent_trades(&PAIR, 10).len(), 2);
+            assert_eq!(history.candles(&PAIR, CandleInterval::OneHour, 0, u64::MAX)[0].trade_count, 3);
+        }
+
+        std::fs::OpenOptions::new()
+            .append(true)
+            .open(&path)
+            .unwrap()
+            .write_all(b"{\"truncated\n")
+            .unwrap();
+
+        let history = TradeHistory::open(&config).unwrap();
+        assert_eq!(history.recent_trades(&PAIR, 1), vec![trade(3, 0, 180, 103, 1)]);
+        assert_eq!(history.candles(&PAIR, CandleInterval::OneMinute, 0, u64::MAX).len(), 3);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,696 @@
+//! RPC 传输层
+//!
+//! `RpcClient` 统一 WebSocket 与 HTTP 两种连接方式，
+//! `MultiClient` 在多个 `RpcClient` 之上提供健康检查、自动切换、快照读取的多节点一致性校验，
+//! 以及交易广播。`StateSynchronizer` / `MatchingEngine` 只依赖 `Provider<MultiClient>`。
+//! WebSocket 支持订阅（eth_subscribe）；HTTP 只能通过 eth_getLogs 轮询。
+
+use crate::config::{NetworkConfig, Transport};
+use anyhow::{bail, Context, Result};
+use async_trait::async_trait;
+use ethers::providers::{
+    Http, HttpClientError, JsonRpcClient, JsonRpcError, Provider, ProviderError, PubsubClient,
+    RpcError, Ws, WsClientError,
+};
+use ethers::types::{U256, U64};
+use futures::future::join_all;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::Value;
+use std::fmt::Debug;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use thiserror::Error;
+use tracing::{debug, info, warn};
+
+/// 单个节点的 RPC 客户端
+#[derive(Debug, Clone)]
+pub enum RpcClient {
+    Ws(Ws),
+    Http(Http),
+}
+
+/// 统一的 RPC 错误
+#[derive(Debug, Error)]
+pub enum RpcClientError {
+    #[error(transparent)]
+    Ws(Box<WsClientError>),
+
+    #[error(transparent)]
+    Http(#[from] HttpClientError),
+
+    #[error(transparent)]
+    Serde(#[from] serde_json::Error),
+
+    #[error("transport does not support subscriptions")]
+    PubsubUnsupported,
+
+    #[error("no RPC endpoint available")]
+    NoEndpoint,
+
+    #[error("quorum not reached: {agreed}/{needed} endpoints agreed")]
+    QuorumNotReached { agreed: usize, needed: usize },
+}
+
+impl From<WsClientError> for RpcClientError {
+    fn from(e: WsClientError) -> Self {
+        RpcClientError::Ws(Box::new(e))
+    }
+}
+
+impl RpcError for RpcClientError {
+    fn as_error_response(&self) -> Option<&JsonRpcError> {
+        match self {
+            RpcClientError::Ws(e) => e.as_error_response(),
+            RpcClientError::Http(e) => e.as_error_response(),
+            _ => None,
+        }
+    }
+
+    fn as_serde_error(&self) -> Option<&serde_json::Error> {
+        match self {
+            RpcClientError::Ws(e) => e.as_serde_error(),
+            RpcClientError::Http(e) => e.as_serde_error(),
+            RpcClientError::Serde(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<RpcClientError> for ProviderError {
+    fn from(e: RpcClientError) -> Self {
+        ProviderError::JsonRpcClientError(Box::new(e))
+    }
+}
+
+#[async_trait]
+impl JsonRpcClient for RpcClient {
+    type Error = RpcClientError;
+
+    async fn request<T, R>(&self, method: &str, params: T) -> Result<R, Self::Error>
+    where
+        T: Debug + Serialize + Send + Sync,
+        R: DeserializeOwned + Send,
+    {
+        match self {
+            RpcClient::Ws(ws) => Ok(JsonRpcClient::request(ws, method, params).await?),
+            RpcClient::Http(http) => Ok(JsonRpcClient::request(http, method, params).await?),
+        }
+    }
+}
+
+impl PubsubClient for RpcClient {
+    type NotificationStream = <Ws as PubsubClient>::NotificationStream;
+
+    fn subscribe<T: Into<U256>>(&self, id: T) -> Result<Self::NotificationStream, Self::Error> {
+        match self {
+            RpcClient::Ws(ws) => Ok(ws.subscribe(id)?),
+            _ => Err(RpcClientError::PubsubUnsupported),
+        }
+    }
+
+    fn unsubscribe<T: Into<U256>>(&self, id: T) -> Result<(), Self::Error> {
+        match self {
+            RpcClient::Ws(ws) => Ok(ws.unsubscribe(id)?),
+            _ => Err(RpcClientError::PubsubUnsupported),
+        }
+    }
+}
+
+/// MultiClient 中单个节点的客户端；生产环境为 RpcClient
+pub trait EndpointClient:
+    JsonRpcClient<Error = RpcClientError>
+    + PubsubClient<NotificationStream = <Ws as PubsubClient>::NotificationStream>
+    + 'static
+{
+}
+
+impl EndpointClient for RpcClient {}
+
+/// 一个 RPC 节点及其健康状态
+#[derive(Debug)]
+struct Endpoint<C> {
+    url: String,
+    client: C,
+    healthy: AtomicBool,
+}
+
+impl<C> Endpoint<C> {
+    fn is_healthy(&self) -> bool {
+        self.healthy.load(Ordering::Relaxed)
+    }
+
+    /// 更新健康状态，状态变化时打印日志
+    fn set_healthy(&self, healthy: bool) {
+        if self.healthy.swap(healthy, Ordering::Relaxed) != healthy {
+            if healthy {
+                info!("💚 RPC endpoint {} is healthy again", self.url);
+            } else {
+                warn!("💔 RPC endpoint {} marked unhealthy", self.url);
+            }
+        }
+    }
+}
+
+/// 多节点 RPC 客户端
+///
+/// - 普通读请求：发往当前节点，传输层错误时切换到下一个健康节点
+/// - quorum > 1 时：同时发往所有健康节点，至少 quorum 个节点结果一致才返回
+/// - eth_sendRawTransaction：广播到所有健康节点
+#[derive(Debug)]
+pub struct MultiClient<C = RpcClient> {
+    endpoints: Arc<Vec<Endpoint<C>>>,
+    active: Arc<AtomicUsize>,
+    quorum: usize,
+}
+
+impl<C> Clone for MultiClient<C> {
+    fn clone(&self) -> Self {
+        Self {
+            endpoints: self.endpoints.clone(),
+            active: self.active.clone(),
+            quorum: self.quorum,
+        }
+    }
+}
+
+impl<C: EndpointClient> MultiClient<C> {
+    fn new(endpoints: Vec<(String, C)>) -> Self {
+        let endpoints = endpoints
+            .into_iter()
+            .map(|(url, client)| Endpoint {
+                url,
+                client,
+                healthy: AtomicBool::new(true),
+            })
+            .collect();
+        Self {
+            endpoints: Arc::new(endpoints),
+            active: Arc::new(AtomicUsize::new(0)),
+            quorum: 1,
+        }
+    }
+
+    /// 共享同一组节点、但读请求要求 quorum 个节点一致的客户端
+    pub fn with_quorum(&self, quorum: usize) -> Self {
+        Self {
+            endpoints: self.endpoints.clone(),
+            active: self.active.clone(),
+            quorum: quorum.max(1),
+        }
+    }
+
+    /// 节点数量
+    pub fn len(&self) -> usize {
+        self.endpoints.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.endpoints.is_empty()
+    }
+
+    /// 当前节点的 URL
+    pub fn active_url(&self) -> &str {
+        &self.endpoints[self.active.load(Ordering::Relaxed)].url
+    }
+
+    /// 请求顺序：从 start（当前节点）开始的健康节点，然后是不健康节点（全部不健康时仍会尝试）
+    fn ordered_endpoints(&self, start: usize) -> Vec<usize> {
+        let n = self.endpoints.len();
+        let (mut healthy, unhealthy): (Vec<usize>, Vec<usize>) = (0..n)
+            .map(|offset| (start + offset) % n)
+            .partition(|&idx| self.endpoints[idx].is_healthy());
+        healthy.extend(unhealthy);
+        healthy
+    }
+
+    /// 参与广播 / quorum 的节点：所有健康节点，没有健康节点时退化为全部节点
+    fn broadcast_endpoints(&self) -> Vec<usize> {
+        let healthy: Vec<usize> = (0..self.endpoints.len())
+            .filter(|&idx| self.endpoints[idx].is_healthy())
+            .collect();
+        if healthy.is_empty() {
+            (0..self.endpoints.len()).collect()
+        } else {
+            healthy
+        }
+    }
+
+    /// 当前节点仍为 previous 时切换到 idx
+    ///
+    /// 并发请求各自从读取到的当前节点开始尝试；其他请求已经切换过时不再覆盖
+    /// （否则较慢的请求会把当前节点改回它自己尝试到的节点，跳过其他请求已确认健康的节点）
+    fn set_active(&self, previous: usize, idx: usize) {
+        if previous == idx {
+            return;
+        }
+        if self
+            .active
+            .compare_exchange(previous, idx, Ordering::AcqRel, Ordering::Acquire)
+            .is_ok()
+        {
+            warn!(
+                "🔀 RPC failover: {} -> {}",
+                self.endpoints[previous].url, self.endpoints[idx].url
+            );
+        }
+    }
+
+    async fn failover_request<T, R>(&self, method: &str, params: T) -> Result<R, RpcClientError>
+    where
+        T: Debug + Serialize + Send + Sync,
+        R: DeserializeOwned + Send,
+    {
+        let mut last_err = None;
+        let start = self.active.load(Ordering::Acquire);
+
+        for idx in self.ordered_endpoints(start) {
+            let endpoint = &self.endpoints[idx];
+            match JsonRpcClient::request(&endpoint.client, method, &params).await {
+                Ok(result) => {
+                    endpoint.set_healthy(true);
+                    self.set_active(start, idx);
+                    return Ok(result);
+                }
+                // 节点正常返回的 JSON-RPC 错误（如 revert）不触发切换
+                Err(e) if e.is_error_response() => return Err(e),
+                Err(e) => {
+                    warn!("RPC {} failed on {}: {}", method, endpoint.url, e);
+                    endpoint.set_healthy(false);
+                    last_err = Some(e);
+                }
+            }
+        }
+
+        Err(last_err.unwrap_or(RpcClientError::NoEndpoint))
+    }
+
+    async fn quorum_request<T, R>(&self, method: &str, params: T) -> Result<R, RpcClientError>
+    where
+        T: Debug + Serialize + Send + Sync,
+        R: DeserializeOwned + Send,
+    {
+        let indices = self.broadcast_endpoints();
+        let results = join_all(indices.iter().map(|&idx| {
+            JsonRpcClient::request::<_, Value>(&self.endpoints[idx].client, method, &params)
+        }))
+        .await;
+
+        let mut votes: Vec<(Value, usize)> = Vec::new();
+        let mut first_err = None;
+        for (&idx, result) in indices.iter().zip(results) {
+            match result {
+                Ok(value) => match votes.iter_mut().find(|(v, _)| *v == value) {
+                    Some((_, count)) => *count += 1,
+                    None => votes.push((value, 1)),
+                },
+                Err(e) => {
+                    debug!("Quorum {} failed on {}: {}", method, self.endpoints[idx].url, e);
+                    first_err.get_or_insert(e);
+                }
+            }
+        }
+
+        let Some((value, agreed)) = votes.into_iter().max_by_key(|(_, count)| *count) else {
+            return Err(first_err.unwrap_or(RpcClientError::NoEndpoint));
+        };
+
+        if agreed < self.quorum {
+            warn!(
+                "Quorum not reached for {}: {}/{} endpoints agreed",
+                method, agreed, self.quorum
+            );
+            return Err(RpcClientError::QuorumNotReached {
+                agreed,
+                needed: self.quorum,
+            });
+        }
+
+        Ok(serde_json::from_value(value)?)
+    }
+
+    async fn broadcast_request<T, R>(&self, method: &str, params: T) -> Result<R, RpcClientError>
+    where
+        T: Debug + Serialize + Send + Sync,
+        R: DeserializeOwned + Send,
+    {
+        let indices = self.broadcast_endpoints();
+        let results = join_all(indices.iter().map(|&idx| {
+            JsonRpcClient::request::<_, Value>(&self.endpoints[idx].client, method, &params)
+        }))
+        .await;
+
+        let mut accepted = None;
+        let mut first_err = None;
+        for (&idx, result) in indices.iter().zip(results) {
+            match result {
+                Ok(value) => {
+                    debug!("Broadcast {} accepted by {}", method, self.endpoints[idx].url);
+                    accepted.get_or_insert(value);
+                }
+                Err(e) => {
+                    debug!("Broadcast {} rejected by {}: {}", method, self.endpoints[idx].url, e);
+                    first_err.get_or_insert(e);
+                }
+            }
+        }
+
+        match accepted {
+            Some(value) => Ok(serde_json::from_value(value)?),
+            None => Err(first_err.unwrap_or(RpcClientError::NoEndpoint)),
+        }
+    }
+
+    /// 对每个节点调用 eth_blockNumber 更新健康状态
+    pub async fn check_health(&self) {
+        let results = join_all(
+            self.endpoints
+                .iter()
+                .map(|endpoint| JsonRpcClient::request::<_, U64>(&endpoint.client, "eth_blockNumber", ())),
+        )
+        .await;
+
+        for (endpoint, result) in self.endpoints.iter().zip(results) {
+            endpoint.set_healthy(result.is_ok());
+        }
+
+        // 当前节点不健康时切换到第一个健康节点
+        let active = self.active.load(Ordering::Acquire);
+        if !self.endpoints[active].is_healthy() {
+            if let Some(idx) = self.endpoints.iter().position(Endpoint::is_healthy) {
+                self.set_active(active, idx);
+            }
+        }
+    }
+
+    /// 后台定期健康检查
+    pub fn spawn_health_check(&self, interval: Duration) {
+        let client = self.clone();
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            loop {
+                ticker.tick().await;
+                client.check_health().await;
+            }
+        });
+    }
+}
+
+#[async_trait]
+impl<C: EndpointClient> JsonRpcClient for MultiClient<C> {
+    type Error = RpcClientError;
+
+    async fn request<T, R>(&self, method: &str, params: T) -> Result<R, Self::Error>
+    where
+        T: Debug + Serialize + Send + Sync,
+        R: DeserializeOwned + Send,
+    {
+        if method == "eth_sendRawTransaction" && self.endpoints.len() > 1 {
+            self.broadcast_request(method, params).await
+        } else if self.quorum > 1 {
+            self.quorum_request(method, params).await
+        } else {
+            self.failover_request(method, params).await
+        }
+    }
+}
+
+/// 订阅使用当前节点（eth_subscribe 请求也是由当前节点处理的）
+impl<C: EndpointClient> PubsubClient for MultiClient<C> {
+    type NotificationStream = <Ws as PubsubClient>::NotificationStream;
+
+    fn subscribe<T: Into<U256>>(&self, id: T) -> Result<Self::NotificationStream, Self::Error> {
+        self.endpoints[self.active.load(Ordering::Relaxed)].client.subscribe(id)
+    }
+
+    fn unsubscribe<T: Into<U256>>(&self, id: T) -> Result<(), Self::Error> {
+        self.endpoints[self.active.load(Ordering::Relaxed)].client.unsubscribe(id)
+    }
+}
+
+pub type RpcProvider = Provider<MultiClient>;
+
+impl Transport {
+    /// 确定传输方式：显式配置优先，否则按 URL scheme 推断
+    pub fn resolve(explicit: Option<Transport>, url: &str) -> Result<Self> {
+        if let Some(transport) = explicit {
+            return Ok(transport);
+        }
+        Self::from_url(url)
+    }
+
+    /// 按 URL scheme 推断传输方式
+    pub fn from_url(url: &str) -> Result<Self> {
+        let scheme = url.split("://").next().unwrap_or_default().to_ascii_lowercase();
+        match scheme.as_str() {
+            "ws" | "wss" => Ok(Transport::Ws),
+            "http" | "https" => Ok(Transport::Http),
+            _ => bail!("Cannot infer transport from rpc_url: {}", url),
+        }
+    }
+}
+
+/// 连接单个节点
+async fn connect_endpoint(url: &str, transport: Transport) -> Result<RpcClient> {
+    Ok(match transport {
+        Transport::Ws => RpcClient::Ws(
+            Ws::connect(url)
+                .await
+                .context("Failed to connect to WebSocket")?,
+        ),
+        Transport::Http => RpcClient::Http(url.parse::<Http>().context("Invalid HTTP rpc_url")?),
+    })
+}
+
+/// 连接到配置的所有 RPC 节点（传输方式必须一致，见 NetworkConfig::resolve_transport），
+/// 返回 provider 和传输方式
+/// 连接失败的备用节点会被跳过；多于一个节点时启动后台健康检查
+pub async fn connect(network: &NetworkConfig) -> Result<(RpcProvider, Transport)> {
+    let transport = network.resolve_transport()?;
+    let mut endpoints = Vec::new();
+
+    for url in network.endpoints() {
+        match connect_endpoint(url, transport).await {
+            Ok(client) => endpoints.push((url.to_string(), client)),
+            Err(e) => warn!("Skipping RPC endpoint {}: {:#}", url, e),
+        }
+    }
+
+    if endpoints.is_empty() {
+        bail!("Failed to connect to any RPC endpoint");
+    }
+
+    let client = MultiClient::new(endpoints);
+    if client.len() > 1 {
+        client.spawn_health_check(Duration::from_millis(network.health_check_interval_ms));
+    }
+
+    Ok((Provider::new(client), transport))
+}
+
+/// 测试用的节点客户端：包装 MockProvider，错误按真实节点的形式转换为 RpcClientError
+#[cfg(test)]
+pub(crate) mod mock {
+    use super::*;
+    use ethers::providers::{MockError, MockProvider};
+
+    #[derive(Debug, Clone)]
+    pub(crate) struct MockClient(MockProvider);
+
+    impl MultiClient<MockClient> {
+        /// 由 MockProvider 组成的多节点客户端，节点名为 mock0、mock1 ...
+        pub(crate) fn mock(mocks: &[MockProvider]) -> Self {
+            Self::new(
+                mocks
+                    .iter()
+                    .enumerate()
+                    .map(|(i, mock)| (format!("mock{}", i), MockClient(mock.clone())))
+                    .collect(),
+            )
+        }
+    }
+
+    impl From<MockError> for RpcClientError {
+        fn from(e: MockError) -> Self {
+            match e {
+                // 节点返回的 JSON-RPC 错误
+                MockError::JsonRpcError(e) => RpcClientError::Http(HttpClientError::JsonRpcError(e)),
+                MockError::SerdeJson(e) => RpcClientError::Serde(e),
+                // 没有预置响应：相当于节点不可用
+                MockError::EmptyRequests | MockError::EmptyResponses => RpcClientError::NoEndpoint,
+            }
+        }
+    }
+
+    #[async_trait]
+    impl JsonRpcClient for MockClient {
+        type Error = RpcClientError;
+
+        async fn request<T, R>(&self, method: &str, params: T) -> Result<R, Self::Error>
+        where
+            T: Debug + Serialize + Send + Sync,
+            R: DeserializeOwned + Send,
+        {
+            Ok(JsonRpcClient::request(&self.0, method, params).await?)
+        }
+    }
+
+    impl PubsubClient for MockClient {
+        type NotificationStream = <Ws as PubsubClient>::NotificationStream;
+
+        fn subscribe<T: Into<U256>>(&self, _id: T) -> Result<Self::NotificationStream, Self::Error> {
+            Err(RpcClientError::PubsubUnsupported)
+        }
+
+        fn unsubscribe<T: Into<U256>>(&self, _id: T) -> Result<(), Self::Error> {
+            Err(RpcClientError::PubsubUnsupported)
+        }
+    }
+
+    impl EndpointClient for MockClient {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::mock::MockClient;
+    use super::*;
+    use ethers::providers::{MockProvider, MockResponse};
+
+    fn mock_client(mocks: &[MockProvider]) -> MultiClient<MockClient> {
+        MultiClient::mock(mocks)
+    }
+
+    #[test]
+    fn test_transport_from_url() {
+        assert_eq!(Transport::from_url("ws://localhost:8545").unwrap(), Transport::Ws);
+        assert_eq!(Transport::from_url("wss://node.example/ws").unwrap(), Transport::Ws);
+        assert_eq!(Transport::from_url("http://127.0.0.1:8545").unwrap(), Transport::Http);
+        assert_eq!(Transport::from_url("HTTPS://node.example").unwrap(), Transport::Http);
+        assert!(Transport::from_url("localhost:8545").is_err());
+    }
+
+    #[test]
+    fn test_explicit_transport_overrides_scheme() {
+        assert_eq!(
+            Transport::resolve(Some(Transport::Http), "ws://localhost:8545").unwrap(),
+            Transport::Http
+        );
+    }
+
+    fn network(rpc_url: &str, fallback_rpc_urls: &[&str], transport: Option<Transport>) -> NetworkConfig {
+        NetworkConfig {
+            rpc_url: rpc_url.to_string(),
+            chain_id: 1,
+            transport,
+            fallback_rpc_urls: fallback_rpc_urls.iter().map(|url| url.to_string()).collect(),
+            health_check_interval_ms: 5000,
+            snapshot_quorum: 1,
+        }
+    }
+
+    #[test]
+    fn test_mixed_transport_endpoints_rejected() {
+        let same = network("wss://a.example", &["ws://b.example"], None);
+        assert_eq!(same.resolve_transport().unwrap(), Transport::Ws);
+
+        // 切换到 HTTP 节点后订阅会失败
+        let mixed = network("wss://a.example", &["https://b.example"], None);
+        assert!(mixed.resolve_transport().is_err());
+
+        // 显式配置时全部按该方式连接
+        let explicit = network("wss://a.example", &["https://b.example"], Some(Transport::Http));
+        assert_eq!(explicit.resolve_transport().unwrap(), Transport::Http);
+    }
+
+    #[test]
+    fn test_snapshot_quorum_exceeding_endpoints_rejected() {
+        let mut config = network("wss://a.example", &["wss://b.example"], None);
+        config.snapshot_quorum = 2;
+        assert_eq!(config.resolve_transport().unwrap(), Transport::Ws);
+
+        config.snapshot_quorum = 3;
+        assert!(config.resolve_transport().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_failover_to_next_endpoint() {
+        // mock0 没有预置响应，相当于节点不可用
+        let mocks = [MockProvider::new(), MockProvider::new()];
+        mocks[1].push(U64::from(42)).unwrap();
+        let client = mock_client(&mocks);
+
+        let block: U64 = client.request("eth_blockNumber", ()).await.unwrap();
+        assert_eq!(block, U64::from(42));
+        assert_eq!(client.active_url(), "mock1");
+        assert!(!client.endpoints[0].is_healthy());
+    }
+
+    #[test]
+    fn test_stale_failover_does_not_override_active() {
+        let mocks = [MockProvider::new(), MockProvider::new(), MockProvider::new()];
+        let client = mock_client(&mocks);
+
+        // 另一请求已从 mock0 切换到 mock2，较慢的请求不能再把当前节点改为 mock1
+        client.set_active(0, 2);
+        client.set_active(0, 1);
+        assert_eq!(client.active_url(), "mock2");
+    }
+
+    #[tokio::test]
+    async fn test_error_response_does_not_failover() {
+        let mocks = [MockProvider::new(), MockProvider::new()];
+        mocks[0].push_response(MockResponse::Error(JsonRpcError {
+            code: 3,
+            message: "execution reverted".to_string(),
+            data: None,
+        }));
+        mocks[1].push(U64::from(1)).unwrap();
+        let client = mock_client(&mocks);
+
+        let result: Result<U64, _> = client.request("eth_call", ()).await;
+        assert!(result.unwrap_err().is_error_response());
+        assert_eq!(client.active_url(), "mock0");
+    }
+
+    #[tokio::test]
+    async fn test_quorum_read() {
+        let mocks = [MockProvider::new(), MockProvider::new(), MockProvider::new()];
+        mocks[0].push(U64::from(7)).unwrap();
+        mocks[1].push(U64::from(7)).unwrap();
+        mocks[2].push(U64::from(8)).unwrap();
+        let client = mock_client(&mocks).with_quorum(2);
+
+        let value: U64 = client.request("eth_call", ()).await.unwrap();
+        assert_eq!(value, U64::from(7));
+
+        // 三个节点结果各不相同
+        mocks[0].push(U64::from(1)).unwrap();
+        mocks[1].push(U64::from(2)).unwrap();
+        mocks[2].push(U64::from(3)).unwrap();
+        let result: Result<U64, _> = client.request("eth_call", ()).await;
+        assert!(matches!(
+            result,
+            Err(RpcClientError::QuorumNotReached { agreed: 1, needed: 2 })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_send_raw_transaction_is_broadcast() {
+        let mocks = [MockProvider::new(), MockProvider::new()];
+        let tx_hash = ethers::types::H256::from_low_u64_be(1);
+        mocks[0].push(tx_hash).unwrap();
+        mocks[1].push(tx_hash).unwrap();
+        let client = mock_client(&mocks);
+
+        let hash: ethers::types::H256 = client
+            .request("eth_sendRawTransaction", ["0x00"])
+            .await
+            .unwrap();
+        assert_eq!(hash, tx_hash);
+
+        // 两个节点都收到了请求
+        mocks[0].assert_request("eth_sendRawTransaction", ["0x00"]).unwrap();
+        mocks[1].assert_request("eth_sendRawTransaction", ["0x00"]).unwrap();
+    }
+}
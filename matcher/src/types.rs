@@ -1,4 +1,5 @@
-use ethers::types::{Address, U256};
+use crate::fixed_point::{OrderSize, Price};
+use ethers::types::{Address, H256, U256};
 use serde::{Deserialize, Serialize};
 use std::collections::BTreeMap;
 
@@ -16,6 +17,26 @@ pub enum OrderType {
     Market = 1,
 }
 
+/// 链上时间与位置：区块时间戳（秒）、区块号、交易哈希
+/// 未知时（如启动时从合约存储加载的数据）为默认值
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BlockStamp {
+    pub timestamp: u64,
+    pub block_number: u64,
+    pub tx_hash: H256,
+}
+
+impl BlockStamp {
+    pub fn is_known(&self) -> bool {
+        self.block_number != 0
+    }
+
+    /// 从该时间到 now 经过的秒数；时间未知时为 None
+    pub fn elapsed(&self, now: u64) -> Option<u64> {
+        (self.timestamp != 0).then(|| now.saturating_sub(self.timestamp))
+    }
+}
+
 /// 排队中的请求
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct QueuedRequest {
@@ -25,10 +46,42 @@ pub struct QueuedRequest {
     pub trader: Address,
     pub order_type: OrderType,
     pub is_ask: bool,
-    pub price: U256,
+    /// 限价单价格（市价单与撤单为 0）
+    pub price: Price,
+    /// 原始数量，含义随订单类型变化，见 size
     pub amount: U256,
     pub order_id_to_remove: U256,
     pub next_request_id: U256,
+    /// 请求提交的时间与位置（PlaceOrderRequested / RemoveOrderRequested）
+    #[serde(default)]
+    pub requested: BlockStamp,
+}
+
+impl QueuedRequest {
+    /// 下单数量（市价买单为要花费的计价代币）
+    pub fn size(&self) -> OrderSize {
+        OrderSize::new(self.order_type == OrderType::Market, self.is_ask, self.amount)
+    }
+
+    /// 在队列中等待的秒数；提交时间未知时为 None
+    pub fn queue_age(&self, now: u64) -> Option<u64> {
+        self.requested.elapsed(now)
+    }
+}
+
+/// 下单请求中订单簿事件不带的信息，应用 OrderInserted 时补全到 SimOrder
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OrderOrigin {
+    pub trader: Address,
+    pub requested: BlockStamp,
+}
+
+/// Account 合约中注册的交易对
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TradingPairInfo {
+    pub trading_pair: [u8; 32],
+    pub base_token: Address,
+    pub quote_token: Address,
 }
 
 /// 价格层级
@@ -79,6 +132,12 @@ pub struct MatchResult {
     pub insert_after_orders: Vec<U256>,
 }
 
+impl Default for MatchResult {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl MatchResult {
     pub fn new() -> Self {
         Self {
@@ -112,6 +171,12 @@ pub struct PriceLevelCache {
     pub levels: BTreeMap<U256, PriceLevel>,
 }
 
+impl Default for PriceLevelCache {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl PriceLevelCache {
     pub fn new() -> Self {
         Self {